@@ -0,0 +1,38 @@
+//! Benchmark for the FAST corner detector.
+//! Compare sequential (`detect`) and parallel (`detect_parallel`) implementation.
+//! Run with `cargo bench --features parallel --bench fast`.
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use rand::{Rng, SeedableRng};
+
+use improc::feat::keypoints::{fast::FASTCornerDetector, KeypointDetector};
+
+const BLOCK_SIZE: u32 = 16;
+
+/// Create image of the blocks filled with random intensity.
+fn create_image<R: Rng>(rng: &mut R, size: u32) -> image::GrayImage {
+    let n_blocks = size / BLOCK_SIZE;
+    let blocks: Vec<u8> = (0..n_blocks * n_blocks).map(|_| rng.gen::<u8>()).collect();
+    image::GrayImage::from_fn(size, size, |x, y| {
+        image::Luma([blocks[((y / BLOCK_SIZE) * n_blocks + x / BLOCK_SIZE) as usize]])
+    })
+}
+
+pub fn bench_fast(c: &mut Criterion) {
+    let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(0);
+    let fast = FASTCornerDetector::new(3, (50 * 50) as f32, 8, 1.2, true);
+    let mut group = c.benchmark_group("fast");
+    group.sample_size(10);
+    for size in [512, 1024, 2048] {
+        let img = create_image(&mut rng, size);
+        group.bench_with_input(BenchmarkId::new("sequential", size), &img, |b, img| {
+            b.iter(|| black_box(fast.detect(img, 0)))
+        });
+        group.bench_with_input(BenchmarkId::new("parallel", size), &img, |b, img| {
+            b.iter(|| black_box(fast.detect_parallel(img, 0)))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_fast);
+criterion_main!(benches);
@@ -0,0 +1,65 @@
+//! Benchmark for the feature detection, description and matching (512x512 gray image and 256
+//! descriptors for the matcher).
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use rand::{Rng, SeedableRng};
+
+use improc::feat::{
+    descriptors::{steered_brief::SteeredBrief, BriefDescriptor, Descriptor, Extractor},
+    keypoints::{fast::FASTCornerDetector, KeyPoint, KeypointDetector},
+    matcher::{brute_force::BruteForceMathcer, Matcher},
+};
+
+const IMAGE_SIZE: u32 = 512;
+const BLOCK_SIZE: u32 = 16;
+
+/// Create image of the blocks filled with random intensity.
+fn create_image<R: Rng>(rng: &mut R) -> image::GrayImage {
+    let n_blocks = IMAGE_SIZE / BLOCK_SIZE;
+    let blocks: Vec<u8> = (0..n_blocks * n_blocks).map(|_| rng.gen::<u8>()).collect();
+    image::GrayImage::from_fn(IMAGE_SIZE, IMAGE_SIZE, |x, y| {
+        image::Luma([blocks[((y / BLOCK_SIZE) * n_blocks + x / BLOCK_SIZE) as usize]])
+    })
+}
+
+fn create_descriptors<R: Rng>(rng: &mut R, n: usize) -> Vec<Descriptor<BriefDescriptor>> {
+    let n_bits = 256;
+    (0..n)
+        .map(|i| {
+            let mut value = BriefDescriptor::new(n_bits);
+            (0..n_bits).for_each(|_| value.push(rng.gen::<bool>()));
+            Descriptor {
+                kpt: KeyPoint::new(i, i, 0.0, 0, 0.0),
+                value,
+            }
+        })
+        .collect()
+}
+
+pub fn bench_feat(c: &mut Criterion) {
+    let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(0);
+    let img = create_image(&mut rng);
+    let fast = FASTCornerDetector::new(3, (50 * 50) as f32, 1, 1.2, true);
+    let brief = SteeredBrief::new(31, 5, 256, 12);
+    let kpts = fast.detect(&img, 0);
+    let matcher = BruteForceMathcer::new(
+        create_descriptors(&mut rng, 256),
+        create_descriptors(&mut rng, 256),
+        false,
+    );
+
+    let mut group = c.benchmark_group("feat");
+    group.sample_size(10);
+    group.bench_function("fast_detect", |b| {
+        b.iter(|| black_box(fast.detect(black_box(&img), 0)))
+    });
+    group.bench_function("steered_brief_compute", |b| {
+        b.iter(|| black_box(brief.compute(black_box(&img), &kpts)))
+    });
+    group.bench_function("brute_force_matcher", |b| {
+        b.iter(|| black_box(matcher.run()))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_feat);
+criterion_main!(benches);
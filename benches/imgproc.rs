@@ -0,0 +1,74 @@
+//! Benchmark for the image processing functions in `imgproc` (512x512 RGB image).
+//! `gaussian_avx2` is compared with `gaussian` if avx2 and fma are enabled
+//! (e.g. `RUSTFLAGS="-C target-cpu=native" cargo bench --bench imgproc`).
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use rand::{Rng, SeedableRng};
+
+use improc::{
+    feat::keypoints::KeyPoint,
+    imgproc::{affine_transform, gaussian, nms, resize},
+    linalg::get_rotation_matrix,
+};
+
+const IMAGE_SIZE: u32 = 512;
+
+fn create_image<R: Rng>(rng: &mut R) -> image::RgbImage {
+    image::RgbImage::from_fn(IMAGE_SIZE, IMAGE_SIZE, |_, _| {
+        image::Rgb(rng.gen::<[u8; 3]>())
+    })
+}
+
+fn create_keypoints<R: Rng>(rng: &mut R, n: usize) -> Vec<KeyPoint> {
+    (0..n)
+        .map(|_| {
+            KeyPoint::new(
+                rng.gen_range(0..IMAGE_SIZE as usize),
+                rng.gen_range(0..IMAGE_SIZE as usize),
+                rng.gen::<f32>(),
+                0,
+                0.0,
+            )
+        })
+        .collect()
+}
+
+pub fn bench_imgproc(c: &mut Criterion) {
+    let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(0);
+    let img = create_image(&mut rng);
+    let kpts = create_keypoints(&mut rng, 2000);
+    let center = (IMAGE_SIZE as f32 / 2.0, IMAGE_SIZE as f32 / 2.0);
+    let affine_mat = get_rotation_matrix(0.3, center, 1.2);
+
+    let mut group = c.benchmark_group("imgproc");
+    group.sample_size(10);
+    group.bench_function("gaussian", |b| {
+        b.iter(|| black_box(gaussian(black_box(&img), 5, 1.0)))
+    });
+    #[cfg(all(
+        target_arch = "x86_64",
+        target_feature = "avx2",
+        target_feature = "fma"
+    ))]
+    group.bench_function("gaussian_avx2", |b| {
+        b.iter(|| {
+            black_box(improc::imgproc::simd::gaussian_avx2(
+                black_box(&img),
+                5,
+                1.0,
+            ))
+        })
+    });
+    group.bench_function("resize", |b| {
+        b.iter(|| black_box(resize(black_box(&img), IMAGE_SIZE / 2, IMAGE_SIZE / 2)))
+    });
+    group.bench_function("nms", |b| {
+        b.iter(|| black_box(nms(black_box(&kpts), 5, 2.0)))
+    });
+    group.bench_function("affine_transform", |b| {
+        b.iter(|| black_box(affine_transform(black_box(&img), &affine_mat)))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_imgproc);
+criterion_main!(benches);
@@ -0,0 +1,46 @@
+//! Benchmark for the brute force matcher.
+//! Compare sequential (`run`) and parallel (`run_parallel`) implementation.
+//! Run with `cargo bench --features parallel --bench matcher`.
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use rand::{Rng, SeedableRng};
+
+use improc::feat::{
+    descriptors::{BriefDescriptor, Descriptor},
+    keypoints::KeyPoint,
+    matcher::{brute_force::BruteForceMathcer, Matcher},
+};
+
+fn create_descriptors<R: Rng>(rng: &mut R, n: usize) -> Vec<Descriptor<BriefDescriptor>> {
+    let n_bits = 256;
+    (0..n)
+        .map(|i| {
+            let mut value = BriefDescriptor::new(n_bits);
+            (0..n_bits).for_each(|_| value.push(rng.gen::<bool>()));
+            Descriptor {
+                kpt: KeyPoint::new(i, i, 0.0, 0, 0.0),
+                value,
+            }
+        })
+        .collect()
+}
+
+pub fn bench_brute_force_matcher(c: &mut Criterion) {
+    let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(0);
+    let mut group = c.benchmark_group("brute_force_matcher");
+    group.sample_size(10);
+    for n in [500, 1000, 2000] {
+        let lhs = create_descriptors(&mut rng, n);
+        let rhs = create_descriptors(&mut rng, n);
+        let matcher = BruteForceMathcer::new(lhs, rhs, false);
+        group.bench_with_input(BenchmarkId::new("sequential", n), &matcher, |b, m| {
+            b.iter(|| black_box(m.run()))
+        });
+        group.bench_with_input(BenchmarkId::new("parallel", n), &matcher, |b, m| {
+            b.iter(|| black_box(m.run_parallel()))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_brute_force_matcher);
+criterion_main!(benches);
@@ -0,0 +1,50 @@
+//! Benchmark for the iterative estimators (`fns` and `renormalization`) of the homography.
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use nalgebra as na;
+use rand::{Rng, SeedableRng};
+
+use improc::{
+    epipolar::homography::HomographyData,
+    optimizer::{fns::fns, taubin::renormalization},
+};
+
+const N_POINTS: usize = 100;
+
+/// Create point pairs [img0_pt0, img1_pt0, ...] related by a homography with uniform noise.
+fn create_data<R: Rng>(rng: &mut R) -> Vec<na::Point2<f64>> {
+    #[rustfmt::skip]
+    let homography = na::Matrix3::new(
+        1.1, 0.05, 0.2,
+        -0.03, 0.95, -0.1,
+        0.05, -0.02, 1.0,
+    );
+    (0..N_POINTS)
+        .flat_map(|_| {
+            let pt = na::Point2::new(rng.gen::<f64>() * 2.0 - 1.0, rng.gen::<f64>() * 2.0 - 1.0);
+            let projected = homography * pt.to_homogeneous();
+            let noise = na::Vector2::new(rng.gen::<f64>() - 0.5, rng.gen::<f64>() - 0.5) * 1e-2;
+            vec![
+                pt,
+                na::Point2::new(projected[0] / projected[2], projected[1] / projected[2]) + noise,
+            ]
+        })
+        .collect()
+}
+
+pub fn bench_optimizer(c: &mut Criterion) {
+    let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(0);
+    let data = create_data(&mut rng);
+
+    let mut group = c.benchmark_group("optimizer");
+    group.sample_size(10);
+    group.bench_function("fns", |b| {
+        b.iter(|| black_box(fns::<HomographyData>(black_box(&data)).unwrap()))
+    });
+    group.bench_function("renormalization", |b| {
+        b.iter(|| black_box(renormalization::<HomographyData>(black_box(&data)).unwrap()))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_optimizer);
+criterion_main!(benches);
@@ -1,9 +1,9 @@
 //! FAST corner detector + brief特徴量 + brute force matchingのsample
 use cgmath::Point3;
-use clap::{AppSettings, Parser};
-use image::{imageops::rotate180, DynamicImage, GenericImageView, GrayImage};
+use clap::{Arg, Command};
+use image::{DynamicImage, GenericImageView, GrayImage};
 use nalgebra::Matrix2x3;
-use std::{cmp::min, path::Path, time::Instant};
+use std::{cmp::min, path::Path};
 
 use improc::{
     feat::{
@@ -18,6 +18,63 @@ use improc::{
     process_dynamic_image, timer,
 };
 
+struct Opts {
+    filename: Option<String>,
+    rot_angle: f32,
+    dx: f32,
+    dy: f32,
+    max_kpts: usize,
+    descriptor: String,
+}
+
+impl Opts {
+    fn parse() -> Self {
+        let matches = Command::new("fast_matching")
+            .about("FAST corner detector + BRIEF descriptor + brute force matching")
+            .arg(Arg::new("filename").help("input image (default : lena.png)"))
+            .arg(
+                Arg::new("rot_angle")
+                    .long("rot-angle")
+                    .takes_value(true)
+                    .default_value("0.0"),
+            )
+            .arg(
+                Arg::new("dx")
+                    .long("dx")
+                    .takes_value(true)
+                    .default_value("0.0"),
+            )
+            .arg(
+                Arg::new("dy")
+                    .long("dy")
+                    .takes_value(true)
+                    .default_value("0.0"),
+            )
+            .arg(
+                Arg::new("max_kpts")
+                    .long("max-kpts")
+                    .takes_value(true)
+                    .default_value("1000"),
+            )
+            .arg(
+                Arg::new("descriptor")
+                    .long("descriptor")
+                    .takes_value(true)
+                    .possible_values(["brief", "sbrief"])
+                    .default_value("brief"),
+            )
+            .get_matches();
+        Opts {
+            filename: matches.value_of("filename").map(|fname| fname.to_string()),
+            rot_angle: matches.value_of_t_or_exit("rot_angle"),
+            dx: matches.value_of_t_or_exit("dx"),
+            dy: matches.value_of_t_or_exit("dy"),
+            max_kpts: matches.value_of_t_or_exit("max_kpts"),
+            descriptor: matches.value_of("descriptor").unwrap().to_string(),
+        }
+    }
+}
+
 fn get_affine_mat(image: &DynamicImage, opts: &Opts) -> Matrix2x3<f32> {
     let (width, height) = (image.width(), image.height());
     let mut mat = get_rotation_matrix(
@@ -0,0 +1,120 @@
+//! Parameters of the algorithms loaded from the configuration file (json).
+use std::path::Path;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::json_writer::read_json;
+
+/// Parameters of `FASTCornerDetector`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FASTConfig {
+    pub radius: u32,
+    pub threshold: f32,
+    pub n_pyramid: u32,
+    pub pyramid_scale: f32,
+    pub use_nms: bool,
+}
+
+impl Default for FASTConfig {
+    fn default() -> Self {
+        FASTConfig {
+            radius: 3,
+            threshold: (50 * 50) as f32,
+            n_pyramid: 8,
+            pyramid_scale: 1.2,
+            use_nms: true,
+        }
+    }
+}
+
+/// Parameters of `Brief` and `SteeredBrief`. `n_discrete` is used only by `SteeredBrief`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BriefConfig {
+    pub patch_size: u32,
+    pub median_kernel_size: u32,
+    pub n_binary_test: u32,
+    pub n_discrete: u32,
+}
+
+impl Default for BriefConfig {
+    fn default() -> Self {
+        BriefConfig {
+            patch_size: 31,
+            median_kernel_size: 5,
+            n_binary_test: 256,
+            n_discrete: 12,
+        }
+    }
+}
+
+/// Parameters of `BruteForceMathcer`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MatcherConfig {
+    pub allow_duplicate: bool,
+}
+
+/// Parameters of all algorithms. Missing entries in the configuration file are filled with
+/// the default values.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub fast: FASTConfig,
+    pub brief: BriefConfig,
+    pub matcher: MatcherConfig,
+}
+
+/// Load `Config` from the json file.
+pub fn load_config(path: &Path) -> Result<Config> {
+    read_json(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_config() {
+        let path = std::env::temp_dir().join("improc_test_load_config.json");
+        std::fs::write(
+            &path,
+            r#"{
+                "fast": {"radius": 5, "threshold": 100.0, "n_pyramid": 4, "pyramid_scale": 1.5,
+                         "use_nms": false},
+                "brief": {"patch_size": 15, "n_binary_test": 128},
+                "matcher": {"allow_duplicate": true}
+            }"#,
+        )
+        .unwrap();
+        let config = load_config(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(config.brief.patch_size, 15);
+        assert_eq!(config.brief.n_binary_test, 128);
+        assert_eq!(config.brief.median_kernel_size, 5);
+        assert_eq!(config.brief.n_discrete, 12);
+        assert!(config.matcher.allow_duplicate);
+        assert_eq!(
+            config.fast,
+            FASTConfig {
+                radius: 5,
+                threshold: 100.0,
+                n_pyramid: 4,
+                pyramid_scale: 1.5,
+                use_nms: false,
+            }
+        );
+    }
+
+    #[test]
+    fn test_default_config() {
+        let path = std::env::temp_dir().join("improc_test_default_config.json");
+        std::fs::write(&path, "{}").unwrap();
+        let config = load_config(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(config, Config::default());
+    }
+}
@@ -5,7 +5,7 @@ mod tests {
             test_utility::test_util::{compare_vecs_without_sign, normalize},
             EllipseData,
         },
-        optimizer::fns::fns,
+        optimizer::fns::{fns, fns_params},
     };
 
     use rand::Rng;
@@ -34,7 +34,7 @@ mod tests {
             na::Point2::new(-2.0 * r60.cos(), 1.0 * r60.sin()),
             na::Point2::new(-2.0 * r60.cos(), -1.0 * r60.sin()),
         ];
-        let params = fns::<EllipseData>(&points).unwrap();
+        let params = fns_params::<EllipseData>(&points).unwrap();
         compare_vecs_without_sign(&ans, params.as_slice(), 1e-5);
     }
 
@@ -66,9 +66,28 @@ mod tests {
                 .collect();
 
             // pred & eval
-            let pred = fns::<EllipseData>(&points).unwrap();
+            let pred = fns_params::<EllipseData>(&points).unwrap();
             let normed = normalize(pred.as_slice());
             compare_vecs_without_sign(&ans, &normed, 1e-2);
         }
     }
+
+    #[test]
+    fn test_fns_diagnostics() {
+        // x^2 + 4 * y^2 - 4 = 0
+        let points: Vec<na::Point2<f64>> = (0..12)
+            .map(|idx| {
+                let rad = std::f64::consts::PI * 2.0 * idx as f64 / 12.0;
+                na::Point2::new(2.0 * rad.cos(), rad.sin())
+            })
+            .collect();
+        let res = fns::<EllipseData>(&points).unwrap();
+        assert!(res.converged, "{:?}", res);
+        assert_eq!(res.history.len(), res.iterations);
+        assert!(res.residual.abs() < 1e-10, "residual = {}", res.residual);
+
+        // 4 points can not determine the ellipse (rank of the moment matrix is 4).
+        let err = fns::<EllipseData>(&points[..4]).unwrap_err();
+        assert!(err.to_string().contains("rank deficient"), "{}", err);
+    }
 }
@@ -5,12 +5,16 @@ mod tests {
             test_utility::test_util::{compare_vecs_without_sign, normalize},
             EllipseData,
         },
-        optimizer::taubin::renormalization,
-        optimizer::taubin::taubin,
+        optimizer::taubin::{
+            renormalization, renormalization_params, renormalization_with_progress, taubin,
+            taubin_params,
+        },
     };
+    use std::sync::{Arc, Mutex};
 
     use nalgebra as na;
-    use rand::Rng;
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha8Rng;
 
     #[test]
     fn test_taubin() {
@@ -35,7 +39,7 @@ mod tests {
             na::Point2::new(-2.0 * r60.cos(), -1.0 * r60.sin()),
         ];
 
-        let params = taubin::<EllipseData>(&points).unwrap();
+        let params = taubin_params::<EllipseData>(&points).unwrap();
         let normed = normalize(params.as_slice());
         println!("{:?}", normed);
         compare_vecs_without_sign(&ans, &normed, 1e-5);
@@ -69,9 +73,72 @@ mod tests {
                 .collect();
 
             // pred & eval
-            let pred = renormalization::<EllipseData>(&points).unwrap();
+            let pred = renormalization_params::<EllipseData>(&points).unwrap();
             let normed = normalize(pred.as_slice());
             compare_vecs_without_sign(&ans, &normed, 1e-2);
         }
     }
+
+    #[test]
+    fn test_renormalization_diagnostics() {
+        // x^2 + 4 * y^2 - 4 = 0
+        let points: Vec<na::Point2<f64>> = (0..12)
+            .map(|idx| {
+                let rad = std::f64::consts::PI * 2.0 * idx as f64 / 12.0;
+                na::Point2::new(2.0 * rad.cos(), rad.sin())
+            })
+            .collect();
+        let res = renormalization::<EllipseData>(&points).unwrap();
+        assert!(res.converged, "{:?}", res);
+        assert_eq!(res.history.len(), res.iterations);
+        assert!(res.residual.abs() < 1e-10, "residual = {}", res.residual);
+
+        let res = taubin::<EllipseData>(&points).unwrap();
+        assert!(res.converged);
+        assert_eq!(res.iterations, 1);
+        assert!(taubin::<EllipseData>(&points[..4]).is_err());
+
+        // 4 points can not determine the ellipse (rank of the moment matrix is 4).
+        let err = renormalization::<EllipseData>(&points[..4]).unwrap_err();
+        assert!(err.to_string().contains("rank deficient"), "{}", err);
+    }
+
+    #[test]
+    fn test_renormalization_progress() {
+        // noisy points for which renormalization does not stop within `max_iteration`.
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        // (x - 0.3)^2 + 4 * (y + 0.2)^2 - 4 = 0 with noise
+        let points: Vec<na::Point2<f64>> = (0..200)
+            .map(|_| {
+                let rad: f64 = rng.gen::<f64>() * std::f64::consts::PI * 2.0;
+                let dx = (rng.gen::<f64>() - 0.5) * 2.0;
+                let dy = (rng.gen::<f64>() - 0.5) * 2.0;
+                na::Point2::new(2.0 * rad.cos() + 0.3 + dx, rad.sin() - 0.2 + dy)
+            })
+            .collect();
+
+        let max_iteration = 4;
+        let calls: Arc<Mutex<Vec<(usize, usize)>>> = Arc::new(Mutex::new(vec![]));
+        let cloned = Arc::clone(&calls);
+        let res = renormalization_with_progress::<EllipseData>(
+            &points,
+            max_iteration,
+            Some(Box::new(move |step, total| {
+                cloned.lock().unwrap().push((step, total))
+            })),
+        )
+        .unwrap();
+        assert!(!res.converged);
+
+        // the callback is called at the start of every iteration (steps 1 ..= max_iteration).
+        // `iterations` includes the initial estimation by taubin method.
+        let calls = calls.lock().unwrap();
+        assert_eq!(calls.len(), max_iteration);
+        assert_eq!(res.iterations, max_iteration + 1);
+        assert!(calls.iter().all(|&(_, total)| total == max_iteration));
+        assert!(calls
+            .iter()
+            .enumerate()
+            .all(|(idx, &(step, _))| step == idx + 1));
+    }
 }
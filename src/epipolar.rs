@@ -1,6 +1,217 @@
+//! Epipolar geometry of two (or three) views.
+//! Point pairs are given as [image0_pt0, image1_pt0, image0_pt1, image1_pt1, ...] and
+//! fundamental matrix `F` satisfies x0^T F x1 = 0.
+use nalgebra as na;
+
+use crate::linalg::matrix::reordered_svd;
+
+pub mod essential_matrix;
 pub mod fundamental_matrix;
 pub mod homography;
 pub mod latent_variable_method;
 pub mod rank_correction;
+pub mod rectification;
 pub mod triangulation;
 pub mod trifocal;
+
+/// Calculate Sampson distance (first order approximation of the geometric distance) of
+/// the point pair (`x0`, `x1`) to the epipolar constraint x0^T `f` x1 = 0.
+/// Return `f64::INFINITY` if the distance is undefined (e.g. both points are at the epipoles).
+pub fn sampson_distance(f: &na::DMatrix<f64>, x0: &na::Point2<f64>, x1: &na::Point2<f64>) -> f64 {
+    let x0 = na::DVector::from_vec(vec![x0[0], x0[1], 1.0]);
+    let x1 = na::DVector::from_vec(vec![x1[0], x1[1], 1.0]);
+    let fx1 = f * &x1;
+    let ftx0 = f.transpose() * &x0;
+    let denom = fx1[0].powi(2) + fx1[1].powi(2) + ftx0[0].powi(2) + ftx0[1].powi(2);
+    // threshold is relative to the scale of `f`, which is defined up to scale.
+    if denom <= f64::EPSILON * f.norm_squared() {
+        return f64::INFINITY;
+    }
+    x0.dot(&fx1).powi(2) / denom
+}
+
+/// Calculate symmetric epipolar distance of the point pair (`x0`, `x1`), that is, sum of the squared
+/// distances of each point to the epipolar line of the other point
+/// (x0^T F x1)^2 (1 / |F x1|_xy^2 + 1 / |F^T x0|_xy^2).
+/// |v|_xy^2 is the squared norm of the first two elements of v.
+pub fn symmetric_epipolar_distance(
+    f: &na::DMatrix<f64>,
+    x0: &na::Point2<f64>,
+    x1: &na::Point2<f64>,
+) -> f64 {
+    let x0 = na::DVector::from_vec(vec![x0[0], x0[1], 1.0]);
+    let x1 = na::DVector::from_vec(vec![x1[0], x1[1], 1.0]);
+    let fx1 = f * &x1;
+    let ftx0 = f.transpose() * &x0;
+    let norm0 = fx1[0].powi(2) + fx1[1].powi(2);
+    let norm1 = ftx0[0].powi(2) + ftx0[1].powi(2);
+    if norm0 < f64::EPSILON || norm1 < f64::EPSILON {
+        return 0.0;
+    }
+    x0.dot(&fx1).powi(2) * (1.0 / norm0 + 1.0 / norm1)
+}
+
+/// Calculate sum of Sampson distances of all point pairs in `data`.
+pub fn sampson_error_total(f: &na::DMatrix<f64>, data: &[na::Point2<f64>]) -> f64 {
+    (0..data.len() / 2).fold(0.0, |acc, idx| {
+        acc + sampson_distance(f, &data[idx * 2], &data[idx * 2 + 1])
+    })
+}
+
+/// Direction of the epipolar line computation by `epipolar_line`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EpipolarDirection {
+    /// l' = F x. Since x0^T F x1 = 0, `x` is a point in image1 and `l'` is a line in image0.
+    Forward,
+    /// l = F^T x'. `x'` is a point in image0 and `l` is a line in image1.
+    Backward,
+}
+
+/// Calculate epipolar line of the point `x` from the fundamental matrix `f`.
+/// Return line coefficients [a, b, c] (ax + by + c = 0).
+pub fn epipolar_line(
+    f: &na::DMatrix<f64>,
+    x: &na::Point2<f64>,
+    direction: EpipolarDirection,
+) -> na::DVector<f64> {
+    let x = na::DVector::from_vec(vec![x[0], x[1], 1.0]);
+    match direction {
+        EpipolarDirection::Forward => f * x,
+        EpipolarDirection::Backward => f.transpose() * x,
+    }
+}
+
+/// Calculate distance between the line `l` ([a, b, c]) and the point `x`.
+pub fn point_to_line_distance(l: &na::DVector<f64>, x: &na::Point2<f64>) -> f64 {
+    (l[0] * x[0] + l[1] * x[1] + l[2]).abs() / (l[0] * l[0] + l[1] * l[1]).sqrt()
+}
+
+/// Calculate epipoles of the fundamental matrix `f` (x0^T F x1 = 0).
+/// Return tuple of (left epipole e' (F^T e' = 0, epipole in image0),
+/// right epipole e (F e = 0, epipole in image1)). Epipoles are homogeneous vectors of norm 1.
+pub fn epipoles(f: &na::DMatrix<f64>) -> (na::DVector<f64>, na::DVector<f64>) {
+    // left singular vector of the smallest singular value of F (F^T) is the null space of F^T (F).
+    // `reordered_svd` never fails because both U and V are calculated.
+    let (u, _, _) = reordered_svd(f.clone()).unwrap();
+    let left = u.column(2).normalize();
+    let (u, _, _) = reordered_svd(f.transpose()).unwrap();
+    let right = u.column(2).normalize();
+    (left, right)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::linalg::vector_cross_matrix;
+
+    /// Return tuple of (fundamental matrix, exact point pairs).
+    fn create_test_data() -> (na::DMatrix<f64>, Vec<na::Point2<f64>>) {
+        let theta: f64 = 0.3;
+        #[rustfmt::skip]
+        let rot = na::DMatrix::from_row_slice(3, 3, &[
+            theta.cos(), 0.0, theta.sin(),
+            0.0, 1.0, 0.0,
+            -theta.sin(), 0.0, theta.cos(),
+        ]);
+        let trans = na::DVector::from_vec(vec![1.0, 0.2, 0.1]);
+        // x1 = R x0 + t -> x0^T (R^T [t]x) x1 = 0
+        let f = rot.transpose() * vector_cross_matrix(&trans);
+
+        let data: Vec<na::Point2<f64>> = [(0.1, 0.2, 3.0), (-0.5, 0.3, 5.0), (0.4, -0.6, 4.0)]
+            .iter()
+            .flat_map(|(x, y, z)| {
+                let pt0 = na::DVector::from_vec(vec![*x, *y, *z]);
+                let pt1 = &rot * &pt0 + &trans;
+                vec![
+                    na::Point2::new(pt0[0] / pt0[2], pt0[1] / pt0[2]),
+                    na::Point2::new(pt1[0] / pt1[2], pt1[1] / pt1[2]),
+                ]
+            })
+            .collect();
+        (f, data)
+    }
+
+    #[test]
+    fn test_sampson_distance() {
+        let (f, data) = create_test_data();
+        (0..data.len() / 2).for_each(|idx| {
+            let dist = sampson_distance(&f, &data[idx * 2], &data[idx * 2 + 1]);
+            assert!(dist.abs() < 1e-15, "dist = {}", dist);
+        });
+        assert!(sampson_error_total(&f, &data) < 1e-15);
+
+        // point off the epipolar line
+        let off = na::Point2::new(data[1][0], data[1][1] + 0.1);
+        assert!(sampson_distance(&f, &data[0], &off) > 0.0);
+        assert!(sampson_error_total(&f, &[data[0], off]) > 0.0);
+
+        // degenerated fundamental matrix
+        let zero = na::DMatrix::<f64>::zeros(3, 3);
+        assert_eq!(sampson_distance(&zero, &data[0], &data[1]), f64::INFINITY);
+        // distance does not depend on the scale of the fundamental matrix.
+        let small = &f * 1e-10;
+        let dist = sampson_distance(&f, &data[0], &off);
+        assert!((sampson_distance(&small, &data[0], &off) - dist).abs() < 1e-12 * dist);
+    }
+
+    #[test]
+    fn test_symmetric_epipolar_distance() {
+        let (f, data) = create_test_data();
+        (0..data.len() / 2).for_each(|idx| {
+            let dist = symmetric_epipolar_distance(&f, &data[idx * 2], &data[idx * 2 + 1]);
+            assert!(dist.abs() < 1e-15, "dist = {}", dist);
+        });
+
+        // sum of the squared point to epipolar line distances of the both images.
+        let off = na::Point2::new(data[1][0] + 0.03, data[1][1] - 0.05);
+        let l0 = epipolar_line(&f, &off, EpipolarDirection::Forward);
+        let l1 = epipolar_line(&f, &data[0], EpipolarDirection::Backward);
+        let expect = point_to_line_distance(&l0, &data[0]).powi(2)
+            + point_to_line_distance(&l1, &off).powi(2);
+        let symmetric = symmetric_epipolar_distance(&f, &data[0], &off);
+        assert!((symmetric - expect).abs() < 1e-12);
+
+        // Sampson distance (first order approximation of the geometric distance) is smaller than
+        // the squared distance to the exact points and the symmetric distance.
+        let sampson = sampson_distance(&f, &data[0], &off);
+        let reprojection = (off - data[1]).norm_squared();
+        assert!(sampson > 0.0);
+        assert!(sampson <= reprojection, "{} vs {}", sampson, reprojection);
+        assert!(sampson <= symmetric, "{} vs {}", sampson, symmetric);
+    }
+
+    #[test]
+    fn test_epipolar_line() {
+        let (f, data) = create_test_data();
+        (0..data.len() / 2).for_each(|idx| {
+            let (x0, x1) = (&data[idx * 2], &data[idx * 2 + 1]);
+            let l0 = epipolar_line(&f, x1, EpipolarDirection::Forward);
+            let l1 = epipolar_line(&f, x0, EpipolarDirection::Backward);
+            assert_eq!(l0.len(), 3);
+            assert!(point_to_line_distance(&l0, x0) < 1e-10);
+            assert!(point_to_line_distance(&l1, x1) < 1e-10);
+        });
+
+        let l = na::DVector::from_vec(vec![3.0, 4.0, -5.0]);
+        let dist = point_to_line_distance(&l, &na::Point2::new(3.0, 4.0));
+        assert!((dist - 4.0).abs() < 1e-10);
+    }
+
+    #[test]
+    fn test_epipoles() {
+        let (f, data) = create_test_data();
+        let (left, right) = epipoles(&f);
+        assert!((&f * &right).norm() < 1e-10);
+        assert!((f.transpose() * &left).norm() < 1e-10);
+        assert!((left.norm() - 1.0).abs() < 1e-10);
+        assert!((right.norm() - 1.0).abs() < 1e-10);
+
+        // all epipolar lines pass through the epipoles.
+        (0..data.len() / 2).for_each(|idx| {
+            let l0 = epipolar_line(&f, &data[idx * 2 + 1], EpipolarDirection::Forward);
+            let l1 = epipolar_line(&f, &data[idx * 2], EpipolarDirection::Backward);
+            assert!(l0.dot(&left).abs() < 1e-10);
+            assert!(l1.dot(&right).abs() < 1e-10);
+        });
+    }
+}
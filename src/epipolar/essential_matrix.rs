@@ -0,0 +1,438 @@
+//! Calculate essential matrix
+use nalgebra as na;
+
+use crate::{
+    ensure,
+    error::{Context, ImprocError, Result},
+    optimizer::fns::fns_params,
+};
+
+use super::{fundamental_matrix::FundamentalMatrixData, triangulation::triangulate_dlt};
+
+/// Estimate essential matrix from observed points.
+/// Returned matrix `E` satisfies x0^T * E * x1 = 0 where x0 and x1 are normalized image
+/// coordinates (K^-1 * [x, y, 1]^T). The norm of `E` is 1.
+/// - `data` : observed points in the two images. [image0_pt0, image1_pt0, image0_pt1, ....].
+/// - `intrinsics` : intrinsic matrix of the camera (common to the two images).
+pub fn estimate_essential_matrix(
+    data: &[na::Point2<f64>],
+    intrinsics: &na::Matrix3<f64>,
+) -> Result<na::DMatrix<f64>> {
+    ensure!(
+        data.len() >= 16,
+        ImprocError::InsufficientData {
+            expected: 16,
+            got: data.len()
+        }
+    );
+    let normalized = normalize_points(data, intrinsics)?;
+    let params = fns_params::<FundamentalMatrixData>(&normalized)?;
+    let matrix = na::DMatrix::from_row_slice(3, 3, params.as_slice());
+    essential_constraint(matrix)
+}
+
+/// Convert image coordinates to normalized image coordinates.
+pub fn normalize_points(
+    data: &[na::Point2<f64>],
+    intrinsics: &na::Matrix3<f64>,
+) -> Result<Vec<na::Point2<f64>>> {
+    let inv = intrinsics
+        .try_inverse()
+        .ok_or(ImprocError::SingularMatrix)?;
+    Ok(data
+        .iter()
+        .map(|pt| {
+            let x = inv * pt.to_homogeneous();
+            na::Point2::new(x[0] / x[2], x[1] / x[2])
+        })
+        .collect())
+}
+
+/// Correct `matrix` to the nearest essential matrix, which has two equal non-zero singular values.
+/// The norm of the returned matrix is 1.
+pub fn essential_constraint(matrix: na::DMatrix<f64>) -> Result<na::DMatrix<f64>> {
+    let svd = matrix.svd(true, true);
+    let u = svd.u.context("Failed to calc svd.")?;
+    let v_t = svd.v_t.context("Failed to calc svd.")?;
+    let (min_idx, _) = svd.singular_values.argmin();
+    let diag =
+        na::DVector::from_iterator(3, (0..3).map(|idx| if idx == min_idx { 0.0 } else { 1.0 }));
+    Ok((u * na::DMatrix::from_diagonal(&diag) * v_t).normalize())
+}
+
+/// Recover relative camera pose (`R`, `t`) from the essential matrix.
+/// Camera matrices of the two images are K [I | 0] and K [R | t] (x1 = R * x0 + t in the camera
+/// coordinates) and `t` is a unit vector.
+/// Four candidates are obtained by decomposing `essential` and the candidate with the most
+/// triangulated points in front of both cameras is selected.
+/// - `essential` : essential matrix satisfying x0^T * E * x1 = 0 (see `estimate_essential_matrix`).
+/// - `pts0`, `pts1` : observed points in image0 and image1. `pts0[i]` and `pts1[i]` are the same point.
+/// - `intrinsics` : intrinsic matrix of the camera.
+pub fn recover_pose(
+    essential: &na::DMatrix<f64>,
+    pts0: &[na::Point2<f64>],
+    pts1: &[na::Point2<f64>],
+    intrinsics: &na::Matrix3<f64>,
+) -> Result<(na::DMatrix<f64>, na::DVector<f64>)> {
+    ensure!(
+        pts0.len() == pts1.len() && !pts0.is_empty(),
+        "Invalid number of points : {} vs {}",
+        pts0.len(),
+        pts1.len()
+    );
+    // x1^T [t]x R x0 = 0 -> [t]x R = E^T
+    let svd = essential.transpose().svd(true, true);
+    let sings = svd.singular_values;
+    let u = svd.u.context("Failed to calc svd.")?;
+    let v_t = svd.v_t.context("Failed to calc svd.")?;
+    // sort singular values in descending order.
+    let mut order = [0, 1, 2];
+    order.sort_by(|l, r| sings[*r].partial_cmp(&sings[*l]).unwrap());
+    let u = na::DMatrix::from_fn(3, 3, |r, c| u[(r, order[c])]);
+    let v_t = na::DMatrix::from_fn(3, 3, |r, c| v_t[(order[r], c)]);
+
+    #[rustfmt::skip]
+    let w = na::DMatrix::from_row_slice(3, 3, &[
+        0.0, -1.0, 0.0,
+        1.0, 0.0, 0.0,
+        0.0, 0.0, 1.0,
+    ]);
+    let trans = u.column(2).clone_owned();
+    let k = na::DMatrix::from_column_slice(3, 3, intrinsics.as_slice());
+    let p0 = k.clone() * na::DMatrix::<f64>::identity(3, 4);
+    let candidates = [&u * &w * &v_t, &u * w.transpose() * &v_t]
+        .iter()
+        .map(|rot| {
+            if rot.determinant() < 0.0 {
+                -rot
+            } else {
+                rot.clone()
+            }
+        })
+        .flat_map(|rot| vec![(rot.clone(), trans.clone()), (rot, -&trans)])
+        .map(|(rot, trans)| {
+            let mut rt = na::DMatrix::<f64>::zeros(3, 4);
+            rt.slice_mut((0, 0), (3, 3)).copy_from(&rot);
+            rt.set_column(3, &trans);
+            let p1 = &k * &rt;
+            let n_front = pts0
+                .iter()
+                .zip(pts1.iter())
+                .filter(|(x0, x1)| {
+                    let pt = triangulate_dlt(&p0, &p1, x0, x1);
+                    pt[2] > 0.0 && (&rot * &pt + &trans)[2] > 0.0
+                })
+                .count();
+            (n_front, rot, trans)
+        })
+        .collect::<Vec<_>>();
+    let (_, rot, trans) = candidates
+        .into_iter()
+        .max_by_key(|(n_front, _, _)| *n_front)
+        .context("Failed to recover pose.")?;
+    Ok((rot, trans))
+}
+
+/// Exponents of monomials (x, y, z) of degree <= 3 in graded reverse lexicographic order.
+/// The first 10 monomials are cubic and the rest are the basis of the quotient ring.
+#[rustfmt::skip]
+const MONOMIALS: [(usize, usize, usize); 20] = [
+    (3, 0, 0), (2, 1, 0), (1, 2, 0), (0, 3, 0), (2, 0, 1),
+    (1, 1, 1), (0, 2, 1), (1, 0, 2), (0, 1, 2), (0, 0, 3),
+    (2, 0, 0), (1, 1, 0), (0, 2, 0), (1, 0, 1), (0, 1, 1),
+    (0, 0, 2), (1, 0, 0), (0, 1, 0), (0, 0, 1), (0, 0, 0),
+];
+
+/// Polynomial of (x, y, z) of degree <= 3. Coefficients are ordered as `MONOMIALS`.
+type Poly = [f64; 20];
+
+fn monomial_index(exp: (usize, usize, usize)) -> usize {
+    MONOMIALS.iter().position(|m| *m == exp).unwrap()
+}
+
+fn poly_mul(lhs: &Poly, rhs: &Poly) -> Poly {
+    let mut res = [0.0; 20];
+    for (i, l) in lhs.iter().enumerate().filter(|(_, l)| **l != 0.0) {
+        for (j, r) in rhs.iter().enumerate().filter(|(_, r)| **r != 0.0) {
+            let (a, b) = (MONOMIALS[i], MONOMIALS[j]);
+            res[monomial_index((a.0 + b.0, a.1 + b.1, a.2 + b.2))] += l * r;
+        }
+    }
+    res
+}
+
+fn poly_add(lhs: &Poly, rhs: &Poly, scale: f64) -> Poly {
+    let mut res = *lhs;
+    res.iter_mut()
+        .zip(rhs.iter())
+        .for_each(|(l, r)| *l += scale * r);
+    res
+}
+
+/// Calculate essential matrices from five point correspondences by the five-point algorithm
+/// (Gröbner basis formulation by Stewenius et al.).
+/// `pts0` and `pts1` are normalized image coordinates (K^-1 * [x, y, 1]^T) and returned matrices
+/// satisfy x0^T * E * x1 = 0. Return all real solutions (at most 10). The norm of each matrix is 1.
+pub fn five_point_essential(
+    pts0: &[na::Point2<f64>; 5],
+    pts1: &[na::Point2<f64>; 5],
+) -> Vec<na::DMatrix<f64>> {
+    // null space of the epipolar constraints
+    let mut q = na::DMatrix::<f64>::zeros(9, 9);
+    pts0.iter()
+        .zip(pts1.iter())
+        .enumerate()
+        .for_each(|(r, (p0, p1))| {
+            let (x0, y0, x1, y1) = (p0[0], p0[1], p1[0], p1[1]);
+            let row = [x0 * x1, x0 * y1, x0, y0 * x1, y0 * y1, y0, x1, y1, 1.0];
+            row.iter().enumerate().for_each(|(c, val)| q[(r, c)] = *val);
+        });
+    let svd = q.svd(false, true);
+    let sings = svd.singular_values;
+    let v_t = match svd.v_t {
+        Some(v_t) => v_t,
+        None => return vec![],
+    };
+    let mut indices: Vec<usize> = (0..9).collect();
+    indices.sort_by(|l, r| sings[*l].partial_cmp(&sings[*r]).unwrap());
+    // E = x * X + y * Y + z * Z + W
+    let basis: Vec<na::DVector<f64>> = indices[..4]
+        .iter()
+        .map(|idx| v_t.row(*idx).transpose())
+        .collect();
+    let e: Vec<Poly> = (0..9)
+        .map(|i| {
+            let mut poly = [0.0; 20];
+            poly[monomial_index((1, 0, 0))] = basis[0][i];
+            poly[monomial_index((0, 1, 0))] = basis[1][i];
+            poly[monomial_index((0, 0, 1))] = basis[2][i];
+            poly[monomial_index((0, 0, 0))] = basis[3][i];
+            poly
+        })
+        .collect();
+    let e_at = |r: usize, c: usize| &e[r * 3 + c];
+
+    // constraints : det(E) = 0 and 2 E E^T E - tr(E E^T) E = 0
+    let mut constraints: Vec<Poly> = Vec::with_capacity(10);
+    let det = (0..3).fold([0.0; 20], |acc, c| {
+        let minor = poly_add(
+            &poly_mul(e_at(1, (c + 1) % 3), e_at(2, (c + 2) % 3)),
+            &poly_mul(e_at(1, (c + 2) % 3), e_at(2, (c + 1) % 3)),
+            -1.0,
+        );
+        poly_add(&acc, &poly_mul(e_at(0, c), &minor), 1.0)
+    });
+    constraints.push(det);
+    let eet: Vec<Poly> = (0..9)
+        .map(|idx| {
+            let (r, c) = (idx / 3, idx % 3);
+            (0..3).fold([0.0; 20], |acc, k| {
+                poly_add(&acc, &poly_mul(e_at(r, k), e_at(c, k)), 1.0)
+            })
+        })
+        .collect();
+    let trace = (0..3).fold([0.0; 20], |acc, k| poly_add(&acc, &eet[k * 4], 1.0));
+    (0..9).for_each(|idx| {
+        let (r, c) = (idx / 3, idx % 3);
+        let eete = (0..3).fold([0.0; 20], |acc, k| {
+            poly_add(&acc, &poly_mul(&eet[r * 3 + k], e_at(k, c)), 1.0)
+        });
+        constraints.push(poly_add(&poly_mul(&trace, e_at(r, c)), &eete, -2.0));
+    });
+
+    // eliminate cubic monomials : cubic = -G * basis
+    let coeffs = na::DMatrix::from_fn(10, 20, |r, c| constraints[r][c]);
+    let g = match coeffs
+        .columns(0, 10)
+        .into_owned()
+        .try_inverse()
+        .map(|inv| inv * coeffs.columns(10, 10))
+    {
+        Some(g) => g,
+        None => return vec![],
+    };
+
+    // action matrix of multiplication by x on the basis [x^2, xy, y^2, xz, yz, z^2, x, y, z, 1]
+    let mut action = na::DMatrix::<f64>::zeros(10, 10);
+    // x * [x^2, xy, y^2, xz, yz, z^2] = [x^3, x^2y, xy^2, x^2z, xyz, xz^2]
+    [0, 1, 2, 4, 5, 7].iter().enumerate().for_each(|(r, gr)| {
+        (0..10).for_each(|c| action[(r, c)] = -g[(*gr, c)]);
+    });
+    // x * [x, y, z, 1] = [x^2, xy, xz, x]
+    [0, 1, 3, 6]
+        .iter()
+        .enumerate()
+        .for_each(|(r, c)| action[(r + 6, *c)] = 1.0);
+
+    action
+        .complex_eigenvalues()
+        .iter()
+        .filter(|val| val.im.abs() < 1e-8 * (1.0 + val.re.abs()))
+        .filter_map(|val| {
+            let svd = (&action - na::DMatrix::<f64>::identity(10, 10) * val.re).svd(false, true);
+            let (idx, _) = svd.singular_values.argmin();
+            let vec = svd.v_t?.row(idx).transpose();
+            if vec[9].abs() < 1e-12 {
+                return None;
+            }
+            let (x, y, z) = (vec[6] / vec[9], vec[7] / vec[9], vec[8] / vec[9]);
+            let params = &basis[0] * x + &basis[1] * y + &basis[2] * z + &basis[3];
+            Some(na::DMatrix::from_row_slice(3, 3, params.as_slice()).normalize())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+pub mod tests {
+    use rand::Rng;
+
+    use crate::{epipolar::sampson_distance, linalg::vector_cross_matrix};
+
+    use super::*;
+
+    /// Create test data.
+    /// Return tuple of (intrinsic matrix, rotation, translation, observed points).
+    /// Second camera's coordinates are x1 = R * x0 + t.
+    pub fn create_test_data() -> (
+        na::Matrix3<f64>,
+        na::DMatrix<f64>,
+        na::DVector<f64>,
+        Vec<na::Point2<f64>>,
+    ) {
+        let mut rng = rand::thread_rng();
+        #[rustfmt::skip]
+        let intrinsics = na::Matrix3::new(
+            800.0, 0.0, 320.0,
+            0.0, 800.0, 240.0,
+            0.0, 0.0, 1.0,
+        );
+        let theta: f64 = (rng.gen::<f64>() - 0.5) * 0.5;
+        #[rustfmt::skip]
+        let rot = na::DMatrix::from_row_slice(3, 3, &[
+            theta.cos(), 0.0, theta.sin(),
+            0.0, 1.0, 0.0,
+            -theta.sin(), 0.0, theta.cos(),
+        ]);
+        let trans = na::DVector::from_vec(vec![
+            -1.0,
+            (rng.gen::<f64>() - 0.5) * 0.2,
+            (rng.gen::<f64>() - 0.5) * 0.2,
+        ])
+        .normalize();
+        let k = na::DMatrix::from_column_slice(3, 3, intrinsics.as_slice());
+        let data = (0..100)
+            .flat_map(|_| {
+                let x0 = na::DVector::from_vec(vec![
+                    (rng.gen::<f64>() - 0.5) * 4.0,
+                    (rng.gen::<f64>() - 0.5) * 4.0,
+                    rng.gen::<f64>() * 5.0 + 5.0,
+                ]);
+                let x1 = &rot * &x0 + &trans;
+                let p0 = &k * x0;
+                let p1 = &k * x1;
+                vec![
+                    na::Point2::new(p0[0] / p0[2], p0[1] / p0[2]),
+                    na::Point2::new(p1[0] / p1[2], p1[1] / p1[2]),
+                ]
+            })
+            .collect();
+        (intrinsics, rot, trans, data)
+    }
+
+    #[test]
+    fn test_estimate_essential_matrix() {
+        let (intrinsics, rot, trans, data) = create_test_data();
+        let essential = estimate_essential_matrix(&data, &intrinsics).unwrap();
+
+        // singular values are (s, s, 0)
+        let mut sings: Vec<f64> = essential
+            .clone()
+            .svd(false, false)
+            .singular_values
+            .as_slice()
+            .to_vec();
+        sings.sort_by(|l, r| r.partial_cmp(l).unwrap());
+        assert!((sings[0] - sings[1]).abs() < 1e-10);
+        assert!(sings[2].abs() < 1e-10);
+
+        let normalized = normalize_points(&data, &intrinsics).unwrap();
+        (0..normalized.len() / 2).for_each(|idx| {
+            let err = sampson_distance(&essential, &normalized[idx * 2], &normalized[idx * 2 + 1]);
+            assert!(err < 1e-5, "err = {}", err);
+        });
+
+        // x1^T [t]x R x0 = 0 -> x0^T (-R^T [t]x) x1 = 0
+        let mut gt = (-rot.transpose() * vector_cross_matrix(&trans)).normalize();
+        if gt.dot(&essential) < 0.0 {
+            gt *= -1.0;
+        }
+        assert!((gt - essential).norm() < 1e-5);
+    }
+
+    #[test]
+    fn test_recover_pose() {
+        let (intrinsics, rot, trans, data) = create_test_data();
+        let essential = estimate_essential_matrix(&data, &intrinsics).unwrap();
+        let pts0: Vec<na::Point2<f64>> = data.iter().step_by(2).copied().collect();
+        let pts1: Vec<na::Point2<f64>> = data.iter().skip(1).step_by(2).copied().collect();
+        let (pred_rot, pred_trans) = recover_pose(&essential, &pts0, &pts1, &intrinsics).unwrap();
+        assert!(
+            (&pred_rot - &rot).norm() < 1e-5,
+            "rot = {:?}",
+            pred_rot.as_slice()
+        );
+        assert!(
+            (&pred_trans - &trans).norm() < 1e-5,
+            "trans = {:?}",
+            pred_trans.as_slice()
+        );
+
+        // sign of the essential matrix does not affect the result.
+        let (pred_rot, pred_trans) = recover_pose(&-essential, &pts0, &pts1, &intrinsics).unwrap();
+        assert!((&pred_rot - &rot).norm() < 1e-5);
+        assert!((&pred_trans - &trans).norm() < 1e-5);
+
+        assert!(recover_pose(&rot, &pts0, &pts1[..10], &intrinsics).is_err());
+    }
+
+    #[test]
+    fn test_five_point_essential() {
+        let (intrinsics, rot, trans, data) = create_test_data();
+        let normalized = normalize_points(&data, &intrinsics).unwrap();
+        let pts0 = [0, 1, 2, 3, 4].map(|idx| normalized[idx * 2]);
+        let pts1 = [0, 1, 2, 3, 4].map(|idx| normalized[idx * 2 + 1]);
+        let essentials = five_point_essential(&pts0, &pts1);
+        assert!(!essentials.is_empty());
+        assert!(essentials.len() <= 10);
+
+        let max_errors: Vec<f64> = essentials
+            .iter()
+            .map(|e| {
+                (0..normalized.len() / 2)
+                    .map(|idx| sampson_distance(e, &normalized[idx * 2], &normalized[idx * 2 + 1]))
+                    .fold(0.0, f64::max)
+            })
+            .collect();
+        let (best, err) = max_errors
+            .iter()
+            .enumerate()
+            .min_by(|l, r| l.1.partial_cmp(r.1).unwrap())
+            .unwrap();
+        assert!(*err < 1e-8, "err = {}", err);
+
+        let mut gt = (-rot.transpose() * vector_cross_matrix(&trans)).normalize();
+        if gt.dot(&essentials[best]) < 0.0 {
+            gt *= -1.0;
+        }
+        assert!((gt - &essentials[best]).norm() < 1e-5);
+    }
+
+    #[test]
+    fn test_essential_constraint() {
+        let matrix = na::DMatrix::from_diagonal(&na::DVector::from_vec(vec![3.0, 1.0, 0.5]));
+        let essential = essential_constraint(matrix).unwrap();
+        let s = 1.0 / 2.0f64.sqrt();
+        let ans = na::DMatrix::from_diagonal(&na::DVector::from_vec(vec![s, s, 0.0]));
+        assert!((ans - essential).norm() < 1e-10);
+    }
+}
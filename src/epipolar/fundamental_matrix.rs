@@ -1,9 +1,9 @@
 //! Calculate fundamental matrix
-use anyhow::Result;
 use nalgebra as na;
 
 use crate::{
-    linalg::{get_identity_mat, get_zero_mat, matrix::pseudo_inverse},
+    error::Result,
+    linalg::{block::kronecker, get_identity_mat, get_zero_mat, matrix::pseudo_inverse_with_rank},
     optimizer::ObservedData,
 };
 
@@ -69,31 +69,18 @@ impl<'a> ObservedData<'a> for FundamentalMatrixData<'a> {
         let d0 = self.delta[data_index * 2];
         let pt1 = self.data[data_index * 2 + 1];
         let d1 = self.delta[data_index * 2 + 1];
-        let (x0, y0) = (pt0[0] + d0[0], pt0[1] + d0[1]);
-        let (x02, y02) = (x0 * x0, y0 * y0);
-        let (x1, y1) = (pt1[0] + d1[0], pt1[1] + d1[1]);
-        let (x12, y12) = (x1 * x1, y1 * y1);
         let f0 = self.scale;
-        let f02 = f0 * f0;
-        let vec_size = self.vec_size();
-        #[rustfmt::skip]
-        let mat = na::DMatrix::<f64>::from_row_slice(vec_size, vec_size, &[
-            x02 + x12, x1 * y1,   f0 * x1, x0 * y0,   0.0,       0.0,     f0 * x0, 0.0,     0.0,
-            x1 * y1,   x02 + y12, f0 * y1, 0.0,       x0 * y0,   0.0,     0.0,     f0 * x1, 0.0,
-            f0 * x1,   f0 * y1,   f02,     0.0,       0.0,       0.0,     0.0,     0.0,     0.0,
-            x0 * y0,   0.0,       0.0,     y02 + x12, x1 * y1,   f0 * x1, f0 * y0, 0.0,     0.0,
-            0.0,       x0 * y0,   0.0,     x1 * y1,   y02 + y12, f0 * y1, 0.0,     f0 * y0, 0.0,
-            0.0,       0.0,       0.0,     f0 * x1,   f0 * y1,   f02,     0.0,     0.0,     0.0,
-            f0 * x0,   0.0,       0.0,     f0 * y0,   0.0,       0.0,     f02,     0.0,     0.0,
-            0.0,       f0 * x0,   0.0,     0.0,       f0 * y0,   0.0,     0.0,     f02,     0.0,
-            0.0,       0.0,       0.0,     0.0,       0.0,       0.0,     0.0,     0.0,     0.0,
-        ]);
-        mat
+        let p0 = na::DVector::from_column_slice(&[pt0[0] + d0[0], pt0[1] + d0[1], f0]);
+        let p1 = na::DVector::from_column_slice(&[pt1[0] + d1[0], pt1[1] + d1[1], f0]);
+        // xi = p0 (x) p1. Covariance of xi is J J^T where J is jacobian of xi with respect to
+        // (x0, y0, x1, y1), that is, E (x) p1 p1^T + p0 p0^T (x) E (E = diag(1, 1, 0)).
+        let e = na::DMatrix::from_diagonal(&na::DVector::from_column_slice(&[1.0, 1.0, 0.0]));
+        kronecker(&e, &(&p1 * p1.transpose())) + kronecker(&(&p0 * p0.transpose()), &e)
     }
 
     fn weights(&self, params: &na::DVector<f64>) -> Vec<f64> {
         if params.as_slice().iter().any(|&val| val.abs() < 1e-5) {
-            return vec![1.0; self.data.len()];
+            return vec![1.0; self.len()];
         }
         (0..self.len())
             .map(|idx| {
@@ -141,14 +128,85 @@ impl<'a> ObservedData<'a> for FundamentalMatrixData<'a> {
     }
 }
 
-const MAX_ITERATION: usize = 10;
-const STOP_THRESHOLD: f64 = 1e-5;
+/// Struct for computing fundamental matrix which minimizes the symmetric epipolar distance
+/// (see `epipolar::symmetric_epipolar_distance`) by `iterative_reweight`.
+/// Same as `FundamentalMatrixData` except for the weights of each data,
+/// 1 / |F x1|_xy^2 + 1 / |F^T x0|_xy^2.
+pub struct SymmetricEpipolarData<'a> {
+    inner: FundamentalMatrixData<'a>,
+}
+
+impl<'a> ObservedData<'a> for SymmetricEpipolarData<'a> {
+    /// `data` format : [image0_pt0, image1_pt0, image0_pt1, image1_pt1, image0_pt2, image1_pt2, ....]
+    fn new(data: &'a [na::Point2<f64>]) -> Self {
+        SymmetricEpipolarData {
+            inner: FundamentalMatrixData::new(data),
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    fn vector(&self, data_index: usize) -> na::DVector<f64> {
+        self.inner.vector(data_index)
+    }
+
+    fn matrix(&self, weight_vector: &[f64]) -> na::DMatrix<f64> {
+        self.inner.matrix(weight_vector)
+    }
+
+    fn variance(&self, data_index: usize) -> na::DMatrix<f64> {
+        self.inner.variance(data_index)
+    }
+
+    fn weights(&self, params: &na::DVector<f64>) -> Vec<f64> {
+        let f = na::Matrix3::from_row_slice(params.as_slice());
+        let data = self.inner.get_data();
+        (0..self.len())
+            .map(|idx| {
+                let x0 = na::Vector3::new(data[idx * 2][0], data[idx * 2][1], self.inner.scale);
+                let x1 =
+                    na::Vector3::new(data[idx * 2 + 1][0], data[idx * 2 + 1][1], self.inner.scale);
+                let fx1 = f * x1;
+                let ftx0 = f.transpose() * x0;
+                let norm0 = (fx1[0].powi(2) + fx1[1].powi(2)).max(f64::EPSILON);
+                let norm1 = (ftx0[0].powi(2) + ftx0[1].powi(2)).max(f64::EPSILON);
+                1.0 / norm0 + 1.0 / norm1
+            })
+            .collect()
+    }
+
+    fn update_delta(&mut self, params: &na::DVector<f64>) -> f64 {
+        self.inner.update_delta(params)
+    }
+
+    fn get_data(&self) -> Vec<na::Point2<f64>> {
+        self.inner.get_data()
+    }
+}
+
+const MAX_ITERATION: usize = 100;
+const STOP_THRESHOLD: f64 = 1e-12;
+const MIN_STEP_SCALE: f64 = 1e-3;
 
-/// optimal correction for fundamental matrix.
+/// Optimal correction of the fundamental matrix `params` (estimated without the rank constraint)
+/// so that det F = 0 (Kanatani's method).
+/// `params` is moved along the direction which increases the Sampson error least while the
+/// covariance of `params` is updated in each iteration. Return corrected params (norm is 1).
 pub fn optimal_correction(
     data: &[na::Point2<f64>],
     params: na::DVector<f64>,
 ) -> Result<na::DVector<f64>> {
+    Ok(optimal_correction_with_history(data, params)?.0)
+}
+
+/// Same as `optimal_correction`, but also return |det F| of each iteration
+/// (including the initial value).
+pub fn optimal_correction_with_history(
+    data: &[na::Point2<f64>],
+    params: na::DVector<f64>,
+) -> Result<(na::DVector<f64>, Vec<f64>)> {
     let data_container = FundamentalMatrixData::new(data);
     let weights = data_container.weights(&params);
     let pers_mat = get_identity_mat(data_container.vec_size()) - &params * params.transpose();
@@ -157,44 +215,80 @@ pub fn optimal_correction(
             let pers = &pers_mat * data_container.vector(idx);
             acc + weights[idx] * &pers * pers.transpose()
         }) / data_container.len() as f64;
-    let mut var_mat: na::DMatrix<f64> = pseudo_inverse(&mat)? / data_container.len() as f64;
+    // `mat` is rank 8 because `params` is in its null space.
+    let mut var_mat: na::DMatrix<f64> =
+        pseudo_inverse_with_rank(&mat, 8)? / data_container.len() as f64;
 
     let mut updated = params;
+    let mut cofactors = cofactor_vector(&updated);
+    let mut history = vec![cofactors.dot(&updated).abs() / 3.0];
     for _ in 0..MAX_ITERATION {
-        let cofactors = na::DVector::<f64>::from_row_slice(&[
-            updated[4] * updated[8] - updated[7] * updated[5],
-            updated[5] * updated[6] - updated[8] * updated[3],
-            updated[3] * updated[7] - updated[6] * updated[4],
-            updated[7] * updated[2] - updated[1] * updated[8],
-            updated[8] * updated[0] - updated[2] * updated[6],
-            updated[6] * updated[1] - updated[0] * updated[7],
-            updated[1] * updated[5] - updated[4] * updated[2],
-            updated[2] * updated[3] - updated[5] * updated[0],
-            updated[0] * updated[4] - updated[3] * updated[1],
-        ]);
-        updated -= (cofactors.transpose() * &updated)[(0, 0)] * &var_mat * &cofactors
-            / (3.0 * cofactors.transpose() * &var_mat * &cofactors)[(0, 0)];
-        updated = updated.normalize();
-        if (cofactors.transpose() * &updated)[(0, 0)].abs() < STOP_THRESHOLD {
+        let step = cofactors.dot(&updated) * &var_mat * &cofactors
+            / (3.0 * cofactors.dot(&(&var_mat * &cofactors)));
+        // The step is the first order approximation and may overshoot when |det F| is large.
+        // Halve the step until |det F| decreases.
+        let prev_det = *history.last().unwrap();
+        // Stop if no step decreases |det F| (e.g. it is already at the rounding error level).
+        let mut scale = 1.0;
+        let (next, det) = loop {
+            let next = (&updated - scale * &step).normalize();
+            // theta^dagger . theta = 3 det F
+            let det = cofactor_vector(&next).dot(&next).abs() / 3.0;
+            if det < prev_det {
+                break (next, det);
+            }
+            scale *= 0.5;
+            if scale < MIN_STEP_SCALE {
+                return Ok((updated, history));
+            }
+        };
+        updated = next;
+        cofactors = cofactor_vector(&updated);
+        history.push(det);
+        if det < STOP_THRESHOLD {
             break;
         }
         let pers_mat = get_identity_mat(data_container.vec_size()) - &updated * updated.transpose();
         var_mat = &pers_mat * var_mat * &pers_mat;
     }
-    Ok(updated)
+    Ok((updated, history))
+}
+
+/// Return cofactor matrix of F (`params` is row major) as the row major vector.
+fn cofactor_vector(params: &na::DVector<f64>) -> na::DVector<f64> {
+    na::DVector::<f64>::from_row_slice(&[
+        params[4] * params[8] - params[7] * params[5],
+        params[5] * params[6] - params[8] * params[3],
+        params[3] * params[7] - params[6] * params[4],
+        params[7] * params[2] - params[1] * params[8],
+        params[8] * params[0] - params[2] * params[6],
+        params[6] * params[1] - params[0] * params[7],
+        params[1] * params[5] - params[4] * params[2],
+        params[2] * params[3] - params[5] * params[0],
+        params[0] * params[4] - params[3] * params[1],
+    ])
 }
 
 #[cfg(test)]
 pub mod tests {
-    use crate::optimizer::{
-        fns::fns,
-        geometric::minimize_geometric_distance,
-        least_square::{iterative_reweight, least_square_fitting},
-        taubin::{renormalization, taubin},
+    use rand_distr::{Distribution, Normal};
+
+    use crate::{
+        epipolar::sampson_error_total,
+        optimizer::{
+            fns::fns_params,
+            geometric::minimize_geometric_distance,
+            least_square::{
+                iterative_reweight, iterative_reweight_with_loss, least_square_fitting,
+            },
+            robust_loss::RobustLoss,
+            taubin::{renormalization_params, taubin_params},
+        },
     };
 
     use super::*;
-    use rand::Rng;
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha8Rng;
 
     const LOOP_NUM: usize = 50;
 
@@ -286,12 +380,61 @@ pub mod tests {
         );
     }
 
+    #[test]
+    fn test_iterative_reweight_symmetric() {
+        let res: usize = (0..LOOP_NUM)
+            .map(|_| {
+                let (_, points) = create_test_data();
+                let res = iterative_reweight::<SymmetricEpipolarData>(&points).unwrap();
+                assert_result(res, points)
+            })
+            .map(|val| if val.abs() < 1e-2 { 1 } else { 0 })
+            .sum();
+
+        assert!(
+            res as f64 > LOOP_NUM as f64 * 0.9,
+            "success : {} / {}",
+            res,
+            LOOP_NUM
+        );
+    }
+
+    #[test]
+    fn test_iterative_reweight_with_huber_loss() {
+        let mut rng = rand::thread_rng();
+        let loss = RobustLoss::Huber(0.05);
+        let (huber, l2) = (0..LOOP_NUM).fold((0.0, 0.0), |acc, _| {
+            let (_, points) = create_test_data();
+            // 20% of the point pairs are replaced by gross outliers.
+            let mut data = points.clone();
+            let n_outliers = data.len() / 2 / 5;
+            (0..n_outliers).for_each(|idx| {
+                data[idx * 2 + 1] = na::Point2::new(
+                    (rng.gen::<f64>() - 0.5) * 10.0,
+                    (rng.gen::<f64>() - 0.5) * 10.0,
+                )
+            });
+            let inliers = &points[n_outliers * 2..];
+            let error = |params: na::DVector<f64>| {
+                sampson_error_total(
+                    &na::DMatrix::from_row_slice(3, 3, params.as_slice()),
+                    inliers,
+                )
+            };
+            let huber =
+                iterative_reweight_with_loss::<FundamentalMatrixData>(&data, Some(&loss)).unwrap();
+            let l2 = iterative_reweight::<FundamentalMatrixData>(&data).unwrap();
+            (acc.0 + error(huber), acc.1 + error(l2))
+        });
+        assert!(huber < l2, "huber = {}, l2 = {}", huber, l2);
+    }
+
     #[test]
     fn test_taubin() {
         let res: usize = (0..LOOP_NUM)
             .map(|_| {
                 let (_, points) = create_test_data();
-                let res = taubin::<FundamentalMatrixData>(&points).unwrap();
+                let res = taubin_params::<FundamentalMatrixData>(&points).unwrap();
                 assert_result(res, points)
             })
             .map(|val| if val.abs() < 1e-2 { 1 } else { 0 })
@@ -310,7 +453,7 @@ pub mod tests {
         let res: usize = (0..LOOP_NUM)
             .map(|_| {
                 let (_, points) = create_test_data();
-                let res = renormalization::<FundamentalMatrixData>(&points).unwrap();
+                let res = renormalization_params::<FundamentalMatrixData>(&points).unwrap();
                 assert_result(res, points)
             })
             .map(|val| if val.abs() < 1e-2 { 1 } else { 0 })
@@ -329,7 +472,7 @@ pub mod tests {
         let res: f64 = (0..20)
             .map(|_| {
                 let (_, points) = create_test_data();
-                let res = fns::<FundamentalMatrixData>(&points).unwrap();
+                let res = fns_params::<FundamentalMatrixData>(&points).unwrap();
                 assert_result(res, points)
             })
             .sum::<f64>()
@@ -342,7 +485,7 @@ pub mod tests {
         let res: f64 = (0..20)
             .map(|_| {
                 let (_, points) = create_test_data();
-                let res = fns::<FundamentalMatrixData>(&points).unwrap();
+                let res = fns_params::<FundamentalMatrixData>(&points).unwrap();
                 let res = optimal_correction(&points, res).unwrap();
                 assert_result(res, points)
             })
@@ -351,6 +494,75 @@ pub mod tests {
         assert!(res.abs() < 1e-2, "res = {}", res);
     }
 
+    #[test]
+    fn test_optimal_correction_convergence() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let std_dev = 0.002;
+        let normal = Normal::new(0.0, std_dev).unwrap();
+        let theta: f64 = 0.3;
+        #[rustfmt::skip]
+        let rot = na::Matrix3::new(
+            theta.cos(), 0.0, theta.sin(),
+            0.0, 1.0, 0.0,
+            -theta.sin(), 0.0, theta.cos(),
+        );
+        let trans = na::Vector3::new(1.0, 0.2, 0.1);
+        (0..20).for_each(|_| {
+            let points: Vec<na::Point2<f64>> = (0..100)
+                .flat_map(|_| {
+                    let pt0 = na::Vector3::new(
+                        (rng.gen::<f64>() - 0.5) * 8.0,
+                        (rng.gen::<f64>() - 0.5) * 8.0,
+                        rng.gen::<f64>() * 3.0 + 3.0,
+                    );
+                    let pt1 = rot * pt0 + trans;
+                    [pt0, pt1]
+                        .iter()
+                        .map(|pt| {
+                            na::Point2::new(
+                                pt[0] / pt[2] + normal.sample(&mut rng),
+                                pt[1] / pt[2] + normal.sample(&mut rng),
+                            )
+                        })
+                        .collect::<Vec<_>>()
+                })
+                .collect();
+            let initial = fns_params::<FundamentalMatrixData>(&points).unwrap();
+            let (corrected, history) =
+                optimal_correction_with_history(&points, initial.clone()).unwrap();
+
+            // rank constraint : det F = 0, i.e. the smallest singular value of F
+            // (= min |F e| for the unit vector e) is 0.
+            let f = na::DMatrix::from_row_slice(3, 3, corrected.as_slice());
+            assert!(f.determinant().abs() < 1e-10, "det = {}", f.determinant());
+            let min_singular_value = f.singular_values().min();
+            assert!(
+                min_singular_value < 1e-10,
+                "sigma_min = {}",
+                min_singular_value
+            );
+            assert!((corrected.norm() - 1.0).abs() < 1e-10);
+
+            // |det F| decreases monotonically.
+            assert!(history.len() >= 2);
+            assert!(
+                history.windows(2).all(|w| w[1] < w[0]),
+                "history = {:?}",
+                history
+            );
+
+            // corrected F still fits the noisy observations.
+            let rms = |f: &na::DMatrix<f64>| (sampson_error_total(f, &points) / 100.0).sqrt();
+            let initial = na::DMatrix::from_row_slice(3, 3, initial.as_slice());
+            assert!(
+                rms(&f) < rms(&initial) + 3.0 * std_dev,
+                "rms : {} -> {}",
+                rms(&initial),
+                rms(&f)
+            );
+        });
+    }
+
     #[test]
     fn test_geometric() {
         let res: f64 = (0..20)
@@ -363,4 +575,25 @@ pub mod tests {
             / 20.0;
         assert!(res.abs() < 1e-2, "res = {}", res);
     }
+
+    #[test]
+    fn test_variance() {
+        let points = [na::Point2::new(0.3, -0.5), na::Point2::new(-0.2, 0.7)];
+        let data = FundamentalMatrixData::new(&points);
+        let (x0, y0, x1, y1) = (0.3, -0.5, -0.2, 0.7);
+        let (x02, y02, x12, y12) = (x0 * x0, y0 * y0, x1 * x1, y1 * y1);
+        #[rustfmt::skip]
+        let expect = na::DMatrix::<f64>::from_row_slice(9, 9, &[
+            x02 + x12, x1 * y1,   x1,  x0 * y0,   0.0,       0.0, x0,  0.0, 0.0,
+            x1 * y1,   x02 + y12, y1,  0.0,       x0 * y0,   0.0, 0.0, x0,  0.0,
+            x1,        y1,        1.0, 0.0,       0.0,       0.0, 0.0, 0.0, 0.0,
+            x0 * y0,   0.0,       0.0, y02 + x12, x1 * y1,   x1,  y0,  0.0, 0.0,
+            0.0,       x0 * y0,   0.0, x1 * y1,   y02 + y12, y1,  0.0, y0,  0.0,
+            0.0,       0.0,       0.0, x1,        y1,        1.0, 0.0, 0.0, 0.0,
+            x0,        0.0,       0.0, y0,        0.0,       0.0, 1.0, 0.0, 0.0,
+            0.0,       x0,        0.0, 0.0,       y0,        0.0, 0.0, 1.0, 0.0,
+            0.0,       0.0,       0.0, 0.0,       0.0,       0.0, 0.0, 0.0, 0.0,
+        ]);
+        assert!((data.variance(0) - expect).norm() < 1e-12);
+    }
 }
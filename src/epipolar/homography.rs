@@ -1,7 +1,16 @@
 //! Homography matrix
 use nalgebra as na;
-
-use crate::{linalg::matrix::pseudo_inverse, optimizer::ObservedData};
+use rand::seq::index::sample;
+
+use crate::{
+    ensure,
+    error::{ImprocError, Result},
+    linalg::{
+        matrix::pseudo_inverse,
+        ransac::{RANSACConfig, RANSAC},
+    },
+    optimizer::{least_square::least_square_fitting, taubin::renormalization_params, ObservedData},
+};
 
 /// Struct for computing homography matrix from observed points in two images.
 /// - `data` is observed points on the two images. [image0_pt0, image1_pt0, image0_pt1, ....].
@@ -253,20 +262,187 @@ impl<'a> ObservedData<'a> for HomographyData<'a> {
     }
 }
 
+/// Estimate homography by the normalized DLT.
+/// Points of each image are normalized by isotropic scaling matrix (`T` for image0 and `T'` for
+/// image1) so that the centroid is the origin and RMS distance from the origin is sqrt(2).
+/// Homography of the normalized points `H~` is estimated by `least_square_fitting` and
+/// denormalized by `T'^-1 * H~ * T`.
+/// Returned matrix `H` maps image0 points to image1 points and the norm of `H` is 1.
+/// - `data` : observed points in the two images. [image0_pt0, image1_pt0, image0_pt1, ....].
+pub fn normalized_dlt(data: &[na::Point2<f64>]) -> Result<na::DMatrix<f64>> {
+    ensure!(
+        data.len() >= 8,
+        ImprocError::InsufficientData {
+            expected: 8,
+            got: data.len()
+        }
+    );
+    let (normalized, t0, t1) = normalize_data(data)?;
+    let params = least_square_fitting::<HomographyData>(&normalized)?;
+    denormalize(&params, &t0, &t1)
+}
+
+/// Estimate homography robustly by RANSAC.
+/// In each iteration, homography is calculated from randomly sampled 4 point pairs by
+/// `normalized_dlt` and a point pair is regarded as inlier if the symmetric transfer error
+/// (sqrt(|x1 - H x0|^2 + |x0 - H^-1 x1|^2)) is smaller than `threshold`.
+/// The homography with the most inliers is refined with all inliers by `renormalization`.
+/// - `data` : observed points in the two images. [image0_pt0, image1_pt0, image0_pt1, ....].
+///
+/// Return tuple of (homography (norm is 1), indices of inlier point pairs).
+pub fn homography_ransac(
+    data: &[na::Point2<f64>],
+    iterations: usize,
+    threshold: f64,
+) -> Result<(na::DMatrix<f64>, Vec<usize>)> {
+    let n_pairs = data.len() / 2;
+    ensure!(
+        n_pairs >= 4,
+        ImprocError::InsufficientData {
+            expected: 8,
+            got: data.len()
+        }
+    );
+
+    let estimator = HomographyRansac { data, threshold };
+    let homography = estimator.run(&RANSACConfig::new(iterations))?;
+    let inliers = estimator.get_inliers(&homography);
+    Ok((homography, inliers))
+}
+
+/// RANSAC estimator of homography used by `homography_ransac`.
+struct HomographyRansac<'a> {
+    data: &'a [na::Point2<f64>],
+    threshold: f64,
+}
+
+impl<'a> HomographyRansac<'a> {
+    fn pairs(&self, indices: &[usize]) -> Vec<na::Point2<f64>> {
+        indices
+            .iter()
+            .flat_map(|idx| vec![self.data[idx * 2], self.data[idx * 2 + 1]])
+            .collect()
+    }
+}
+
+impl<'a> RANSAC<na::DMatrix<f64>, usize> for HomographyRansac<'a> {
+    fn min_sample(&self) -> usize {
+        4
+    }
+
+    fn estimate_from_random_sample(&self) -> Result<na::DMatrix<f64>> {
+        let samples = sample(&mut rand::thread_rng(), self.data.len() / 2, 4).into_vec();
+        normalized_dlt(&self.pairs(&samples))
+    }
+
+    fn get_inliers(&self, homography: &na::DMatrix<f64>) -> Vec<usize> {
+        transfer_inliers(homography, self.data, self.threshold)
+    }
+
+    /// Refine homography with `inputs` by `renormalization`.
+    fn estimate(&self, inputs: &[usize]) -> Result<na::DMatrix<f64>> {
+        let (normalized, t0, t1) = normalize_data(&self.pairs(inputs))?;
+        let params = renormalization_params::<HomographyData>(&normalized)?;
+        denormalize(&params, &t0, &t1)
+    }
+}
+
+/// Return indices of the point pairs whose symmetric transfer error is smaller than `threshold`.
+fn transfer_inliers(
+    homography: &na::DMatrix<f64>,
+    data: &[na::Point2<f64>],
+    threshold: f64,
+) -> Vec<usize> {
+    let h = na::Matrix3::from_row_slice(homography.transpose().as_slice());
+    let h_inv = match h.try_inverse() {
+        Some(inv) => inv,
+        None => return vec![],
+    };
+    let transfer = |m: &na::Matrix3<f64>, from: &na::Point2<f64>, to: &na::Point2<f64>| {
+        match na::Point2::from_homogeneous(m * from.to_homogeneous()) {
+            Some(pt) => (pt - to).norm_squared(),
+            None => f64::INFINITY,
+        }
+    };
+    (0..data.len() / 2)
+        .filter(|idx| {
+            let (x0, x1) = (&data[idx * 2], &data[idx * 2 + 1]);
+            (transfer(&h, x0, x1) + transfer(&h_inv, x1, x0)).sqrt() < threshold
+        })
+        .collect()
+}
+
+/// Tuple of (normalized data, `T` of image0, `T'` of image1).
+type NormalizedData = (Vec<na::Point2<f64>>, na::Matrix3<f64>, na::Matrix3<f64>);
+
+/// Normalize points of each image by `normalization_matrix`.
+fn normalize_data(data: &[na::Point2<f64>]) -> Result<NormalizedData> {
+    let pts0: Vec<na::Point2<f64>> = data.iter().step_by(2).copied().collect();
+    let pts1: Vec<na::Point2<f64>> = data.iter().skip(1).step_by(2).copied().collect();
+    let t0 = normalization_matrix(&pts0)?;
+    let t1 = normalization_matrix(&pts1)?;
+    let normalized = data
+        .iter()
+        .enumerate()
+        .map(|(idx, pt)| {
+            let t = if idx % 2 == 0 { &t0 } else { &t1 };
+            na::Point2::from_homogeneous(t * pt.to_homogeneous()).unwrap()
+        })
+        .collect();
+    Ok((normalized, t0, t1))
+}
+
+/// Convert homography parameters of the normalized points to homography matrix of the original
+/// points (`T'^-1 * H~ * T`). The norm of the returned matrix is 1.
+fn denormalize(
+    params: &na::DVector<f64>,
+    t0: &na::Matrix3<f64>,
+    t1: &na::Matrix3<f64>,
+) -> Result<na::DMatrix<f64>> {
+    let homography = na::Matrix3::from_row_slice(params.as_slice());
+    let t1_inv = t1.try_inverse().ok_or(ImprocError::SingularMatrix)?;
+    let homography = t1_inv * homography * t0;
+    Ok(na::DMatrix::from_row_slice(3, 3, homography.transpose().as_slice()).normalize())
+}
+
+/// Calculate similarity transformation which moves the centroid of `pts` to the origin and scales
+/// RMS distance from the origin to sqrt(2).
+pub(super) fn normalization_matrix(pts: &[na::Point2<f64>]) -> Result<na::Matrix3<f64>> {
+    let n = pts.len() as f64;
+    let centroid = pts
+        .iter()
+        .fold(na::Vector2::zeros(), |acc, pt| acc + pt.coords)
+        / n;
+    let rms = (pts
+        .iter()
+        .map(|pt| (pt.coords - centroid).norm_squared())
+        .sum::<f64>()
+        / n)
+        .sqrt();
+    ensure!(rms > f64::EPSILON, "All points are at the same position.");
+    let s = 2.0f64.sqrt() / rms;
+    #[rustfmt::skip]
+    let t = na::Matrix3::new(
+        s, 0.0, -s * centroid[0],
+        0.0, s, -s * centroid[1],
+        0.0, 0.0, 1.0,
+    );
+    Ok(t)
+}
+
 #[cfg(test)]
 mod tests {
     use crate::{
         optimizer::{
-            fns::fns,
+            fns::fns_params,
             geometric::minimize_geometric_distance,
             least_square::{iterative_reweight, least_square_fitting},
-            taubin::{renormalization, taubin},
+            taubin::{renormalization_params, taubin_params},
         },
         PrintDebug,
     };
 
     use super::*;
-    use anyhow::Result;
 
     use rand::Rng;
 
@@ -380,6 +556,74 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_normalized_dlt() {
+        // 4K-scale coordinates
+        let scale = 1000.0;
+        let s = na::DMatrix::from_diagonal(&na::DVector::from_vec(vec![scale, scale, 1.0]));
+        let s_inv =
+            na::DMatrix::from_diagonal(&na::DVector::from_vec(vec![1.0 / scale, 1.0 / scale, 1.0]));
+        let homo = create_random_homography();
+        let homo = (&s * homo * &s_inv).normalize();
+        let pts: Vec<na::Point2<f64>> = create_random_points(&(&s_inv * &homo * &s))
+            .iter()
+            .map(|pt| pt * scale)
+            .collect();
+
+        let mut res = normalized_dlt(&pts).unwrap();
+        if res[(2, 2)] * homo[(2, 2)] < 0.0 {
+            res *= -1.0;
+        }
+        assert!(
+            (&homo - &res).norm_squared() < 1e-5,
+            "res = {}",
+            (&homo - &res).norm_squared()
+        );
+        assert!(normalized_dlt(&pts[..6]).is_err());
+    }
+
+    #[test]
+    fn test_homography_ransac() {
+        let mut rng = rand::thread_rng();
+        #[rustfmt::skip]
+        let homo = na::Matrix3::new(
+            0.9, -0.1, 30.0,
+            0.1, 1.05, 20.0,
+            1e-4, 5e-5, 1.0,
+        );
+        let n_pairs = 200;
+        let is_inlier: Vec<bool> = (0..n_pairs).map(|idx| idx % 2 == 0).collect();
+        let pts: Vec<na::Point2<f64>> = is_inlier
+            .iter()
+            .flat_map(|inlier| {
+                let x0 = na::Point2::new(rng.gen::<f64>() * 600.0, rng.gen::<f64>() * 400.0);
+                let x1 = if *inlier {
+                    na::Point2::from_homogeneous(homo * x0.to_homogeneous()).unwrap()
+                        + na::Vector2::new(rng.gen::<f64>() - 0.5, rng.gen::<f64>() - 0.5)
+                } else {
+                    na::Point2::new(rng.gen::<f64>() * 600.0, rng.gen::<f64>() * 400.0)
+                };
+                vec![x0, x1]
+            })
+            .collect();
+
+        let (res, inliers) = homography_ransac(&pts, 500, 3.0).unwrap();
+        let n_true = inliers.iter().filter(|idx| is_inlier[**idx]).count();
+        assert!(
+            n_true as f64 > n_pairs as f64 * 0.5 * 0.9,
+            "n_true = {}",
+            n_true
+        );
+        assert!(inliers.len() - n_true < 5, "n_inliers = {}", inliers.len());
+
+        let gt = na::DMatrix::from_row_slice(3, 3, homo.transpose().as_slice()).normalize();
+        let res = if res[(2, 2)] < 0.0 { -res } else { res };
+        let x = na::DVector::from_vec(vec![300.0, 200.0, 1.0]);
+        let (pred, gt) = (&res * &x, &gt * &x);
+        assert!((pred[0] / pred[2] - gt[0] / gt[2]).abs() < 1.0);
+        assert!((pred[1] / pred[2] - gt[1] / gt[2]).abs() < 1.0);
+    }
+
     #[test]
     fn test_least_square_with_noise() {
         let res: usize = (0..LOOP_NUM)
@@ -411,7 +655,7 @@ mod tests {
     #[test]
     fn test_taubin() {
         let res: usize = (0..LOOP_NUM)
-            .map(|_| test_template(|pts| taubin::<HomographyData>(pts)))
+            .map(|_| test_template(|pts| taubin_params::<HomographyData>(pts)))
             .map(|val| if val < 1e-4 { 1 } else { 0 })
             .sum();
         println!("success : {} / {}", res, LOOP_NUM);
@@ -426,7 +670,7 @@ mod tests {
     #[test]
     fn test_renormalization() {
         let res: usize = (0..LOOP_NUM)
-            .map(|_| test_template(|pts| renormalization::<HomographyData>(pts)))
+            .map(|_| test_template(|pts| renormalization_params::<HomographyData>(pts)))
             .map(|val| if val < 1e-4 { 1 } else { 0 })
             .sum();
         println!("success : {} / {}", res, LOOP_NUM);
@@ -441,7 +685,7 @@ mod tests {
     #[test]
     fn test_fns() {
         let res: usize = (0..LOOP_NUM)
-            .map(|_| test_template(|pts| fns::<HomographyData>(pts)))
+            .map(|_| test_template(|pts| fns_params::<HomographyData>(pts)))
             .map(|val| if val < 1e-4 { 1 } else { 0 })
             .sum();
         println!("success : {} / {}", res, LOOP_NUM);
@@ -1,35 +1,16 @@
-use anyhow::{Context, Result};
 use nalgebra as na;
 
 use crate::{
+    error::{Context, Result},
     linalg::{get_rotation_matrix_from_omega, get_zero_mat, matrix::reordered_svd},
-    optimizer::ObservedData,
+    optimizer::{lm::update_damping, ObservedData},
 };
 
-use super::fundamental_matrix::FundamentalMatrixData;
-
-const MAX_ITERATION: usize = 10;
-
-fn sampson_error(data_container: &FundamentalMatrixData, matrix: &na::DMatrix<f64>) -> f64 {
-    let params = na::DVector::from_row_slice(&[
-        matrix[(0, 0)],
-        matrix[(0, 1)],
-        matrix[(0, 2)],
-        matrix[(1, 0)],
-        matrix[(1, 1)],
-        matrix[(1, 2)],
-        matrix[(2, 0)],
-        matrix[(2, 1)],
-        matrix[(2, 2)],
-    ]);
-    (0..data_container.len()).fold(0.0, |acc, idx| {
-        let xi = data_container.vector(idx);
-        let var_mat = data_container.variance(idx);
-        acc + ((xi.transpose() * &params)[(0, 0)].powi(2)
-            / (params.transpose() * var_mat * &params)[(0, 0)])
-            .abs()
-    }) / data_container.len() as f64
-}
+use super::{fundamental_matrix::FundamentalMatrixData, sampson_error_total};
+
+const MAX_ITERATION: usize = 50; // including the rejected steps
+const INITIAL_DAMPING: f64 = 1e-4;
+const STOP_THRESHOLD: f64 = 1e-3;
 
 /// Fundamental matrix optimization.
 /// `matrix` is 3x3 matrix of rank 3. (rank of the matrix is not corrected.)
@@ -41,61 +22,28 @@ pub fn latent_variable_method(
 
     // println!(
     //     "Sampson error before rank correction : {}",
-    //     sampson_error(&data_container, &matrix)
+    //     sampson_error_total(&matrix, data)
     // );
     // rank correction by svd decomposition
     let (mut u, mut diag, mut v) = reordered_svd(matrix)?;
     diag[2] = 0.0;
-    let phi = (diag[0] / (diag[0] * diag[0] + diag[1] * diag[1]).sqrt()).acos();
+    let mut phi = (diag[0] / (diag[0] * diag[0] + diag[1] * diag[1]).sqrt()).acos();
     diag[0] = phi.cos();
     diag[1] = phi.sin();
     let mut matrix = &u * na::DMatrix::from_diagonal(&diag) * v.transpose();
     // println!(
     //     "Sampson error after SVD rank correction : {}",
-    //     sampson_error(&data_container, &matrix)
+    //     sampson_error_total(&matrix, data)
     // );
 
-    let mut j = sampson_error(&data_container, &matrix);
-    let mut c = 1e-4;
+    let n_data = data_container.len() as f64;
+    let mut j = sampson_error_total(&matrix, data);
+    let mut lambda = INITIAL_DAMPING;
+    let mut nu = 2.0;
 
     // LM optimization
     for _ in 0..MAX_ITERATION {
-        #[rustfmt::skip]
-        let f_u = na::DMatrix::from_row_slice(9, 3, &[
-            0.0, matrix[(2, 0)], -matrix[(1, 0)],
-            0.0, matrix[(2, 1)], -matrix[(1, 1)],
-            0.0, matrix[(2, 2)], -matrix[(1, 2)],
-            -matrix[(2, 0)], 0.0, matrix[(0, 0)],
-            -matrix[(2, 1)], 0.0, matrix[(0, 1)],
-            -matrix[(2, 2)], 0.0, matrix[(0, 2)],
-            matrix[(1, 0)], -matrix[(0, 0)], 0.0,
-            matrix[(1, 1)], -matrix[(0, 1)], 0.0,
-            matrix[(1, 2)], -matrix[(0, 2)], 0.0,
-        ]);
-        #[rustfmt::skip]
-        let f_v = na::DMatrix::from_row_slice(9, 3, &[
-            0.0, matrix[(0, 2)], -matrix[(0, 1)],
-            -matrix[(0, 2)], 0.0, matrix[(0, 0)],
-            matrix[(0, 1)], -matrix[(0, 0)], 0.0,
-            0.0, matrix[(1, 2)], -matrix[(1, 1)],
-            -matrix[(1, 2)], 0.0, matrix[(1, 0)],
-            matrix[(1, 1)], -matrix[(1, 0)], 0.0,
-            0.0, matrix[(2, 2)], -matrix[(2, 1)],
-            -matrix[(2, 2)], 0.0, matrix[(2, 0)],
-            matrix[(2, 1)], -matrix[(2, 0)], 0.0,
-        ]);
-        #[rustfmt::skip]
-        let t_phi = na::DVector::from_row_slice(&[
-            diag[0] * u[(0, 1)] * v[(0, 1)] - diag[1] * u[(0, 0)] * v[(0, 0)],
-            diag[0] * u[(0, 1)] * v[(1, 1)] - diag[1] * u[(0, 0)] * v[(1, 0)],
-            diag[0] * u[(0, 1)] * v[(2, 1)] - diag[1] * u[(0, 0)] * v[(2, 0)],
-            diag[0] * u[(1, 1)] * v[(0, 1)] - diag[1] * u[(1, 0)] * v[(0, 0)],
-            diag[0] * u[(1, 1)] * v[(1, 1)] - diag[1] * u[(1, 0)] * v[(1, 0)],
-            diag[0] * u[(1, 1)] * v[(2, 1)] - diag[1] * u[(1, 0)] * v[(2, 0)],
-            diag[0] * u[(2, 1)] * v[(0, 1)] - diag[1] * u[(2, 0)] * v[(0, 0)],
-            diag[0] * u[(2, 1)] * v[(1, 1)] - diag[1] * u[(2, 0)] * v[(1, 0)],
-            diag[0] * u[(2, 1)] * v[(2, 1)] - diag[1] * u[(2, 0)] * v[(2, 0)],
-        ]);
+        let (f_u, f_v, t_phi) = parameter_jacobians(&matrix, &u, &v, &diag);
 
         let params = na::DVector::<f64>::from_row_slice(&[
             matrix[(0, 0)],
@@ -154,55 +102,102 @@ pub fn latent_variable_method(
             du[0], du[1], du[2], dv[0], dv[1], dv[2], dp[0]
         ]);
 
-        #[allow(unused)]
-        let mut f_hat = na::DMatrix::<f64>::from_element(0, 0, 0.0);
-        #[allow(unused)]
-        let mut u_hat = na::DMatrix::<f64>::from_element(0, 0, 0.0);
-        #[allow(unused)]
-        let mut v_hat = na::DMatrix::<f64>::from_element(0, 0, 0.0);
-        #[allow(unused)]
-        let mut p_hat = 0.0;
-        for _ in 0..5 {
-            let delta = (&h + c * &dh)
-                .lu()
-                .solve(&b)
-                .context("Failed to LU decomposition")?;
-            u_hat = get_rotation_matrix_from_omega(&[delta[0], delta[1], delta[2]]) * &u;
-            v_hat = get_rotation_matrix_from_omega(&[delta[3], delta[4], delta[5]]) * &v;
-            p_hat = phi + delta[6];
-            f_hat = &u_hat
-                * na::DMatrix::from_diagonal(&na::DVector::<f64>::from_row_slice(&[
-                    p_hat.cos(),
-                    p_hat.sin(),
-                    0.0,
-                ]))
-                * v_hat.transpose();
-
-            let j_hat = sampson_error(&data_container, &f_hat);
-            if j_hat < j * 1.001 {
-                if (&matrix - &f_hat).lp_norm(2) < 1e-3 {
-                    return Ok(matrix);
-                }
-                j = j_hat;
-                matrix = f_hat;
-                u = u_hat;
-                v = v_hat;
-                diag[0] = p_hat.cos();
-                diag[1] = p_hat.sin();
-                break;
-            }
-            c *= 10.0;
+        let delta = (&h + lambda * &dh)
+            .lu()
+            .solve(&b)
+            .context("Failed to LU decomposition")?;
+        let u_hat = get_rotation_matrix_from_omega(&[delta[0], delta[1], delta[2]]) * &u;
+        let v_hat = get_rotation_matrix_from_omega(&[delta[3], delta[4], delta[5]]) * &v;
+        let p_hat = phi + delta[6];
+        let f_hat = &u_hat
+            * na::DMatrix::from_diagonal(&na::DVector::<f64>::from_row_slice(&[
+                p_hat.cos(),
+                p_hat.sin(),
+                0.0,
+            ]))
+            * v_hat.transpose();
+
+        let j_hat = sampson_error_total(&f_hat, data);
+        // Derivatives are calculated for the mean of the Sampson distances.
+        let predicted = delta.dot(&(lambda * &dh * &delta + &b)) / 2.0;
+        let rho = (j - j_hat) / n_data / predicted;
+        let accepted = predicted > 0.0 && rho > 0.0;
+        let (next_lambda, next_nu) = update_damping(lambda, nu, accepted, rho);
+        lambda = next_lambda;
+        nu = next_nu;
+        if !accepted {
+            continue;
+        }
+        let converged = (&matrix - &f_hat).lp_norm(2) < STOP_THRESHOLD;
+        j = j_hat;
+        matrix = f_hat;
+        u = u_hat;
+        v = v_hat;
+        phi = p_hat;
+        diag[0] = p_hat.cos();
+        diag[1] = p_hat.sin();
+        if converged {
+            break;
         }
-        c /= 10.0;
     }
     Ok(matrix)
 }
 
+/// Return jacobians of the elements of `matrix` (row-major order) with respect to the parameters.
+/// `matrix` = U diag(cos(phi), sin(phi), 0) V^T is updated as U' = R(omega_u) U,
+/// V' = R(omega_v) V and phi' = phi + delta_phi, where R(omega) is the rotation of the small
+/// angle-axis vector omega.
+/// Return tuple of (dF / d(omega_u), dF / d(omega_v), dF / d(phi)).
+fn parameter_jacobians(
+    matrix: &na::DMatrix<f64>,
+    u: &na::DMatrix<f64>,
+    v: &na::DMatrix<f64>,
+    diag: &na::DVector<f64>,
+) -> (na::DMatrix<f64>, na::DMatrix<f64>, na::DVector<f64>) {
+    #[rustfmt::skip]
+    let f_u = na::DMatrix::from_row_slice(9, 3, &[
+        0.0, matrix[(2, 0)], -matrix[(1, 0)],
+        0.0, matrix[(2, 1)], -matrix[(1, 1)],
+        0.0, matrix[(2, 2)], -matrix[(1, 2)],
+        -matrix[(2, 0)], 0.0, matrix[(0, 0)],
+        -matrix[(2, 1)], 0.0, matrix[(0, 1)],
+        -matrix[(2, 2)], 0.0, matrix[(0, 2)],
+        matrix[(1, 0)], -matrix[(0, 0)], 0.0,
+        matrix[(1, 1)], -matrix[(0, 1)], 0.0,
+        matrix[(1, 2)], -matrix[(0, 2)], 0.0,
+    ]);
+    #[rustfmt::skip]
+    let f_v = na::DMatrix::from_row_slice(9, 3, &[
+        0.0, matrix[(0, 2)], -matrix[(0, 1)],
+        -matrix[(0, 2)], 0.0, matrix[(0, 0)],
+        matrix[(0, 1)], -matrix[(0, 0)], 0.0,
+        0.0, matrix[(1, 2)], -matrix[(1, 1)],
+        -matrix[(1, 2)], 0.0, matrix[(1, 0)],
+        matrix[(1, 1)], -matrix[(1, 0)], 0.0,
+        0.0, matrix[(2, 2)], -matrix[(2, 1)],
+        -matrix[(2, 2)], 0.0, matrix[(2, 0)],
+        matrix[(2, 1)], -matrix[(2, 0)], 0.0,
+    ]);
+    #[rustfmt::skip]
+    let t_phi = na::DVector::from_row_slice(&[
+        diag[0] * u[(0, 1)] * v[(0, 1)] - diag[1] * u[(0, 0)] * v[(0, 0)],
+        diag[0] * u[(0, 1)] * v[(1, 1)] - diag[1] * u[(0, 0)] * v[(1, 0)],
+        diag[0] * u[(0, 1)] * v[(2, 1)] - diag[1] * u[(0, 0)] * v[(2, 0)],
+        diag[0] * u[(1, 1)] * v[(0, 1)] - diag[1] * u[(1, 0)] * v[(0, 0)],
+        diag[0] * u[(1, 1)] * v[(1, 1)] - diag[1] * u[(1, 0)] * v[(1, 0)],
+        diag[0] * u[(1, 1)] * v[(2, 1)] - diag[1] * u[(1, 0)] * v[(2, 0)],
+        diag[0] * u[(2, 1)] * v[(0, 1)] - diag[1] * u[(2, 0)] * v[(0, 0)],
+        diag[0] * u[(2, 1)] * v[(1, 1)] - diag[1] * u[(2, 0)] * v[(1, 0)],
+        diag[0] * u[(2, 1)] * v[(2, 1)] - diag[1] * u[(2, 0)] * v[(2, 0)],
+    ]);
+    (f_u, f_v, t_phi)
+}
+
 #[cfg(test)]
 mod tests {
     use crate::{
         epipolar::fundamental_matrix::tests::{assert_result, create_test_data_with_params},
-        optimizer::least_square::least_square_fitting,
+        optimizer::{jacobian::check_jacobian, least_square::least_square_fitting},
     };
 
     use super::*;
@@ -217,4 +212,32 @@ mod tests {
         let r = assert_result(na::DVector::from_fn(9, |i, _| res[(i / 3, i % 3)]), data);
         assert!(r < 1e-1, "res = {}", r);
     }
+
+    #[test]
+    fn test_parameter_jacobians() {
+        let u = get_rotation_matrix_from_omega(&[0.3, -0.8, 1.2]);
+        let v = get_rotation_matrix_from_omega(&[-1.1, 0.4, 0.2]);
+        let phi = 0.6;
+        // F(omega_u, omega_v, phi) in row-major order
+        let f = |x: &na::DVector<f64>| {
+            let diag =
+                na::DVector::from_column_slice(&[(phi + x[6]).cos(), (phi + x[6]).sin(), 0.0]);
+            let mat = get_rotation_matrix_from_omega(&[x[0], x[1], x[2]])
+                * &u
+                * na::DMatrix::from_diagonal(&diag)
+                * (get_rotation_matrix_from_omega(&[x[3], x[4], x[5]]) * &v).transpose();
+            na::DVector::from_fn(9, |i, _| mat[(i / 3, i % 3)])
+        };
+        let j = |_: &na::DVector<f64>| {
+            let diag = na::DVector::from_column_slice(&[phi.cos(), phi.sin(), 0.0]);
+            let matrix = &u * na::DMatrix::from_diagonal(&diag) * v.transpose();
+            let (f_u, f_v, t_phi) = parameter_jacobians(&matrix, &u, &v, &diag);
+            let mut jacobian = na::DMatrix::zeros(9, 7);
+            jacobian.columns_mut(0, 3).copy_from(&f_u);
+            jacobian.columns_mut(3, 3).copy_from(&f_v);
+            jacobian.set_column(6, &t_phi);
+            jacobian
+        };
+        assert!(check_jacobian(f, j, &na::DVector::zeros(7), 1e-6, 1e-6));
+    }
 }
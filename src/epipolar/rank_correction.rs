@@ -1,7 +1,8 @@
 //! Implementations for rank correction algorithms.
-use anyhow::{Context, Result};
 use nalgebra as na;
 
+use crate::error::{Context, Result};
+
 pub fn svd_rank_correction(matrix: na::DMatrix<f64>) -> Result<na::DMatrix<f64>> {
     let mut svd = matrix.svd(true, true);
     let (idx, _) = svd.singular_values.argmin();
@@ -0,0 +1,175 @@
+//! Rectification of stereo images.
+use nalgebra as na;
+
+use super::epipoles;
+
+/// Calculate homographies which rectify the two images so that corresponding epipolar lines
+/// become the same horizontal scanline.
+/// Rectified images are obtained by applying `H0` and `H1` to each image
+/// (e.g. by `imgproc::warp_perspective`).
+/// - `f` : fundamental matrix satisfying x0^T F x1 = 0.
+/// - `p0`, `p1` : 3 x 4 camera matrices of the two images. Left 3 x 3 part must be invertible.
+/// - `img_size` : (width, height) of the images. The center of the rectified images is aligned
+///   to the center of the images.
+///
+/// Return tuple of (`H0`, `H1`).
+pub fn stereo_rectify(
+    f: &na::DMatrix<f64>,
+    p0: &na::DMatrix<f64>,
+    p1: &na::DMatrix<f64>,
+    img_size: (u32, u32),
+) -> (na::DMatrix<f64>, na::DMatrix<f64>) {
+    let m0 = p0.slice((0, 0), (3, 3)).clone_owned();
+    let m1 = p1.slice((0, 0), (3, 3)).clone_owned();
+    let (k0, r0) = rq_decomposition(&m0);
+    let (k1, _) = rq_decomposition(&m1);
+
+    // new x axis is parallel to the baseline. Baseline is the ray through the epipole of image0.
+    let (epipole, _) = epipoles(f);
+    let mut x_axis = m0.clone().pseudo_inverse(1e-10).unwrap() * epipole;
+    if x_axis.dot(&(camera_center(p1) - camera_center(p0))) < 0.0 {
+        x_axis *= -1.0;
+    }
+    let x_axis = x_axis.normalize();
+    let y_axis = r0.row(2).transpose().cross(&x_axis).normalize();
+    let z_axis = x_axis.cross(&y_axis);
+    let mut rot = na::DMatrix::<f64>::zeros(3, 3);
+    rot.set_row(0, &x_axis.transpose());
+    rot.set_row(1, &y_axis.transpose());
+    rot.set_row(2, &z_axis.transpose());
+
+    let k = (k0 + k1) / 2.0;
+    let h0 = &k * &rot * m0.try_inverse().unwrap();
+    let h1 = &k * &rot * m1.try_inverse().unwrap();
+
+    // move center of the images to the center of the rectified images.
+    // Vertical offset must be common to the two images.
+    let center = na::DVector::from_vec(vec![img_size.0 as f64 / 2.0, img_size.1 as f64 / 2.0, 1.0]);
+    let c0 = &h0 * &center;
+    let c1 = &h1 * &center;
+    let dy = center[1] - (c0[1] / c0[2] + c1[1] / c1[2]) / 2.0;
+    let shift =
+        |dx: f64| na::DMatrix::from_row_slice(3, 3, &[1.0, 0.0, dx, 0.0, 1.0, dy, 0.0, 0.0, 1.0]);
+    (
+        shift(center[0] - c0[0] / c0[2]) * h0,
+        shift(center[0] - c1[0] / c1[2]) * h1,
+    )
+}
+
+/// Decompose 3 x 3 matrix `m` into upper triangular matrix `K` (K[2, 2] = 1, positive diagonal)
+/// and rotation matrix `R` (m = s * K * R).
+fn rq_decomposition(m: &na::DMatrix<f64>) -> (na::DMatrix<f64>, na::DMatrix<f64>) {
+    // m = K R -> J m = (J K J) (J R) where J is the reversal permutation.
+    let j = na::DMatrix::from_fn(3, 3, |r, c| if r + c == 2 { 1.0 } else { 0.0 });
+    let qr = (&j * m).transpose().qr();
+    let (q, u) = (qr.q(), qr.r());
+    let mut k = &j * u.transpose() * &j;
+    let mut r = &j * q.transpose();
+    let d = na::DMatrix::from_diagonal(&na::DVector::from_iterator(
+        3,
+        (0..3).map(|i| k[(i, i)].signum()),
+    ));
+    k *= &d;
+    r = &d * r;
+    if r.determinant() < 0.0 {
+        r *= -1.0;
+    }
+    let scale = k[(2, 2)];
+    (k / scale, r)
+}
+
+/// Calculate camera center (inhomogeneous coordinates) of the camera matrix `p`.
+fn camera_center(p: &na::DMatrix<f64>) -> na::DVector<f64> {
+    let c = min_singular_vector(p);
+    na::DVector::from_vec(vec![c[0] / c[3], c[1] / c[3], c[2] / c[3]])
+}
+
+/// Return the right singular vector of the minimum singular value.
+fn min_singular_vector(m: &na::DMatrix<f64>) -> na::DVector<f64> {
+    let m = if m.nrows() < m.ncols() {
+        m.clone().insert_row(m.nrows(), 0.0)
+    } else {
+        m.clone()
+    };
+    let svd = m.svd(false, true);
+    let (idx, _) = svd.singular_values.argmin();
+    svd.v_t.unwrap().row(idx).transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use crate::linalg::vector_cross_matrix;
+
+    use super::*;
+
+    #[test]
+    fn test_stereo_rectify() {
+        let mut rng = rand::thread_rng();
+        #[rustfmt::skip]
+        let k = na::DMatrix::from_row_slice(3, 3, &[
+            800.0, 0.0, 320.0,
+            0.0, 800.0, 240.0,
+            0.0, 0.0, 1.0,
+        ]);
+        let theta: f64 = (rng.gen::<f64>() - 0.5) * 0.3;
+        #[rustfmt::skip]
+        let rot = na::DMatrix::from_row_slice(3, 3, &[
+            theta.cos(), 0.0, theta.sin(),
+            0.0, 1.0, 0.0,
+            -theta.sin(), 0.0, theta.cos(),
+        ]);
+        let trans = na::DVector::from_vec(vec![
+            -1.0,
+            (rng.gen::<f64>() - 0.5) * 0.2,
+            (rng.gen::<f64>() - 0.5) * 0.2,
+        ]);
+        // P0 = K [I | 0], P1 = K [R | t]
+        let p0 = &k * na::DMatrix::<f64>::identity(3, 4);
+        let mut rt = rot.clone().insert_column(3, 0.0);
+        rt.set_column(3, &trans);
+        let p1 = &k * rt;
+        // x1 = R x0 + t -> x0^T K^-T (-R^T [t]x) K^-1 x1 = 0
+        let k_inv = k.clone().try_inverse().unwrap();
+        let f = k_inv.transpose() * (-rot.transpose() * vector_cross_matrix(&trans)) * &k_inv;
+
+        let (h0, h1) = stereo_rectify(&f, &p0, &p1, (640, 480));
+        (0..100).for_each(|_| {
+            let x = na::DVector::from_vec(vec![
+                (rng.gen::<f64>() - 0.5) * 4.0,
+                (rng.gen::<f64>() - 0.5) * 4.0,
+                rng.gen::<f64>() * 5.0 + 5.0,
+                1.0,
+            ]);
+            let r0 = &h0 * (&p0 * &x);
+            let r1 = &h1 * (&p1 * &x);
+            let (y0, y1) = (r0[1] / r0[2], r1[1] / r1[2]);
+            assert!((y0 - y1).abs() < 0.5, "y0 = {}, y1 = {}", y0, y1);
+        });
+
+        // center of the image is mapped near to the center of the rectified image.
+        let c = &h0 * na::DVector::from_vec(vec![320.0, 240.0, 1.0]);
+        assert!((c[0] / c[2] - 320.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_rq_decomposition() {
+        #[rustfmt::skip]
+        let k = na::DMatrix::from_row_slice(3, 3, &[
+            500.0, 1.0, 300.0,
+            0.0, 600.0, 200.0,
+            0.0, 0.0, 1.0,
+        ]);
+        let theta: f64 = 0.4;
+        #[rustfmt::skip]
+        let r = na::DMatrix::from_row_slice(3, 3, &[
+            1.0, 0.0, 0.0,
+            0.0, theta.cos(), -theta.sin(),
+            0.0, theta.sin(), theta.cos(),
+        ]);
+        let (pk, pr) = rq_decomposition(&(&k * &r * 2.0));
+        assert!((pk - k).norm() < 1e-8);
+        assert!((pr - r).norm() < 1e-8);
+    }
+}
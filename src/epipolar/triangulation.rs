@@ -1,11 +1,17 @@
-use anyhow::Result;
 use nalgebra as na;
 
 use crate::{
-    linalg::matrix::le_lstsq,
+    ensure,
+    error::{ImprocError, Result},
+    linalg::{matrix::le_lstsq, vector_cross_matrix},
     optimizer::{geometric::minimize_geometric_distance_impl, ObservedData},
 };
 
+use super::epipoles;
+
+const MAX_ITERATION: usize = 10;
+const STOP_THRESHOLD: f64 = 1e-20;
+
 /// Triangulation by camera matrix. Calculate position of the point in world coordinates.
 /// `p0` and `p1` are camera matrices. `x0` and `x1` are observed point on each image.
 pub fn triangulation(
@@ -33,6 +39,145 @@ pub fn triangulation(
     le_lstsq(&t, &p)
 }
 
+/// Triangulation by the linear DLT method. Calculate position of the point in world coordinates.
+/// `p0` and `p1` are 3 x 4 camera matrices. `x0` and `x1` are observed point on each image.
+/// Homogeneous coordinates of the point is calculated as the minimum singular vector of the 4 x 4
+/// system and returned vector is inhomogeneous coordinates (x, y, z).
+pub fn triangulate_dlt(
+    p0: &na::DMatrix<f64>,
+    p1: &na::DMatrix<f64>,
+    x0: &na::Point2<f64>,
+    x1: &na::Point2<f64>,
+) -> na::DVector<f64> {
+    let mut a = na::DMatrix::<f64>::zeros(4, 4);
+    [(p0, x0), (p1, x1)]
+        .iter()
+        .enumerate()
+        .for_each(|(i, (p, x))| {
+            a.set_row(i * 2, &(x[0] * p.row(2) - p.row(0)));
+            a.set_row(i * 2 + 1, &(x[1] * p.row(2) - p.row(1)));
+        });
+    let svd = a.svd(false, true);
+    let (idx, _) = svd.singular_values.argmin();
+    let x = svd.v_t.unwrap().row(idx).transpose();
+    na::DVector::from_vec(vec![x[0] / x[3], x[1] / x[3], x[2] / x[3]])
+}
+
+/// Triangulation by the Gold Standard method.
+/// Starting from the result of `triangulate_dlt`, the position of the point is iteratively updated
+/// by Gauss-Newton method to minimize the reprojection error in the both images.
+/// See `triangulate_dlt` for arguments and returned value.
+pub fn triangulate_optimal(
+    p0: &na::DMatrix<f64>,
+    p1: &na::DMatrix<f64>,
+    x0: &na::Point2<f64>,
+    x1: &na::Point2<f64>,
+) -> na::DVector<f64> {
+    let mut pt = triangulate_dlt(p0, p1, x0, x1);
+    let mut error = reprojection_error(p0, p1, x0, x1, &pt);
+    for _ in 0..MAX_ITERATION {
+        if error < STOP_THRESHOLD {
+            break;
+        }
+        let homo = pt.clone().insert_row(3, 1.0);
+        let mut jacobian = na::DMatrix::<f64>::zeros(4, 3);
+        let mut residual = na::DVector::<f64>::zeros(4);
+        [(p0, x0), (p1, x1)]
+            .iter()
+            .enumerate()
+            .for_each(|(i, (p, x))| {
+                let proj = *p * &homo;
+                (0..2).for_each(|j| {
+                    residual[i * 2 + j] = proj[j] / proj[2] - x[j];
+                    (0..3).for_each(|k| {
+                        jacobian[(i * 2 + j, k)] =
+                            (p[(j, k)] * proj[2] - proj[j] * p[(2, k)]) / (proj[2] * proj[2]);
+                    });
+                });
+            });
+        let jtj = jacobian.transpose() * &jacobian;
+        let delta = match jtj.try_inverse() {
+            Some(inv) => inv * jacobian.transpose() * residual,
+            None => break,
+        };
+        let updated = &pt - delta;
+        let updated_error = reprojection_error(p0, p1, x0, x1, &updated);
+        if updated_error >= error {
+            break;
+        }
+        pt = updated;
+        error = updated_error;
+    }
+    pt
+}
+
+/// Calculate sum of squared reprojection error of the point `pt` (inhomogeneous coordinates).
+fn reprojection_error(
+    p0: &na::DMatrix<f64>,
+    p1: &na::DMatrix<f64>,
+    x0: &na::Point2<f64>,
+    x1: &na::Point2<f64>,
+    pt: &na::DVector<f64>,
+) -> f64 {
+    let homo = pt.clone().insert_row(3, 1.0);
+    [(p0, x0), (p1, x1)].iter().fold(0.0, |acc, (p, x)| {
+        let proj = *p * &homo;
+        acc + (proj[0] / proj[2] - x[0]).powi(2) + (proj[1] / proj[2] - x[1]).powi(2)
+    })
+}
+
+/// Tuple of (camera matrix of image0, camera matrix of image1, triangulated points).
+type ProjectiveReconstruction = (na::DMatrix<f64>, na::DMatrix<f64>, Vec<na::DVector<f64>>);
+
+/// Projective reconstruction from the fundamental matrix `f` (x0^T F x1 = 0) without camera
+/// intrinsics. Return tuple of (P0, P1, triangulated points).
+/// Camera matrices are the canonical pair P0 = [I | 0] and P1 = [[e1]x F^T | e1], where e1 is the
+/// epipole in image1 (F e1 = 0). Triangulated points are inhomogeneous coordinates (x, y, z) and
+/// the reconstruction is determined up to a projective transformation.
+/// The sign of P1 is selected so that the depths (the third element of P X) in the two views have
+/// the same sign for as many points as possible (chirality).
+/// - `data` : observed points in the two images. [image0_pt0, image1_pt0, image0_pt1, ....].
+pub fn projective_reconstruction(
+    f: &na::DMatrix<f64>,
+    data: &[na::Point2<f64>],
+) -> Result<ProjectiveReconstruction> {
+    ensure!(
+        f.shape() == (3, 3),
+        ImprocError::ShapeMismatch {
+            expected: (3, 3),
+            got: f.shape(),
+        }
+    );
+    ensure!(
+        data.len() >= 2 && data.len().is_multiple_of(2),
+        "Invalid number of points : {}",
+        data.len()
+    );
+    let f = f.normalize();
+    let (_, e1) = epipoles(&f);
+    let p0 = na::DMatrix::<f64>::identity(3, 4);
+    let mut p1 = na::DMatrix::<f64>::zeros(3, 4);
+    p1.slice_mut((0, 0), (3, 3))
+        .copy_from(&(vector_cross_matrix(&e1) * f.transpose()));
+    p1.set_column(3, &e1);
+
+    let pts: Vec<na::DVector<f64>> = data
+        .chunks(2)
+        .map(|pair| triangulate_dlt(&p0, &p1, &pair[0], &pair[1]))
+        .collect();
+    let n_front = pts
+        .iter()
+        .filter(|pt| {
+            let homo = (*pt).clone().insert_row(3, 1.0);
+            (&p0 * &homo)[2] * (&p1 * &homo)[2] > 0.0
+        })
+        .count();
+    if n_front * 2 < pts.len() {
+        p1 = -p1;
+    }
+    Ok((p0, p1, pts))
+}
+
 /// Optimal correction of position of corresponding points.
 pub fn optimal_correction<'a, DataClass: ObservedData<'a>>(
     matrix: &na::DMatrix<f64>,
@@ -47,7 +192,7 @@ pub fn optimal_correction<'a, DataClass: ObservedData<'a>>(
 mod tests {
     use rand::Rng;
 
-    use crate::{epipolar::fundamental_matrix::FundamentalMatrixData, linalg::vector_cross_matrix};
+    use crate::epipolar::fundamental_matrix::FundamentalMatrixData;
 
     use super::*;
 
@@ -84,6 +229,153 @@ mod tests {
         assert!((gpt[2].abs() - pt[2].abs()).abs() < 1e-5);
     }
 
+    /// Camera matrices of the same setup as `sfm::self_calibration::tests::test_self_calibration`.
+    fn create_cameras() -> (na::DMatrix<f64>, na::DMatrix<f64>) {
+        let mut rng = rand::thread_rng();
+        let f0 = 1.0;
+        let f = 2.0;
+        let fh = 3.0;
+        let theta: f64 = rng.gen::<f64>() * std::f64::consts::PI * 2.0;
+        #[rustfmt::skip]
+        let r = na::DMatrix::from_row_slice(3, 3, &[
+            theta.cos(), 0.0, theta.sin(),
+            0.0, 1.0, 0.0,
+            -theta.sin(), 0.0, theta.cos(),
+        ]);
+        let t = na::DVector::from_vec(vec![1.0, 2.0, 3.0]).normalize();
+        let rt = r.transpose() * &t;
+        #[rustfmt::skip]
+        let p0 = na::DMatrix::from_row_slice(3, 4, &[
+            f, 0.0, 0.0, 0.0,
+            0.0, f, 0.0, 0.0,
+            0.0, 0.0, f0, 0.0,
+        ]);
+        #[rustfmt::skip]
+        let p1 = na::DMatrix::from_row_slice(3, 4, &[
+            fh * r[(0, 0)], fh * r[(1, 0)], fh * r[(2, 0)], fh * -rt[0],
+            fh * r[(0, 1)], fh * r[(1, 1)], fh * r[(2, 1)], fh * -rt[1],
+            f0 * r[(0, 2)], f0 * r[(1, 2)], f0 * r[(2, 2)], f0 * -rt[2],
+        ]);
+        (p0, p1)
+    }
+
+    #[test]
+    fn test_triangulate_dlt_and_optimal() {
+        let mut rng = rand::thread_rng();
+        let (p0, p1) = create_cameras();
+        (0..100).for_each(|_| {
+            let gx = na::DVector::from_vec(vec![
+                (rng.gen::<f64>() - 0.5) * 2.0,
+                (rng.gen::<f64>() - 0.5) * 2.0,
+                rng.gen::<f64>() * 2.0 + 0.5,
+                1.0,
+            ]);
+            let x0 = &p0 * &gx;
+            let x1 = &p1 * &gx;
+            let x0 = na::Point2::new(x0[0] / x0[2], x0[1] / x0[2]);
+            let x1 = na::Point2::new(x1[0] / x1[2], x1[1] / x1[2]);
+
+            let dlt = triangulate_dlt(&p0, &p1, &x0, &x1);
+            let optimal = triangulate_optimal(&p0, &p1, &x0, &x1);
+            (0..3).for_each(|i| {
+                assert!((dlt[i] - gx[i]).abs() < 1e-5, "dlt = {:?}", dlt.as_slice());
+                assert!(
+                    (optimal[i] - gx[i]).abs() < 1e-5,
+                    "optimal = {:?}",
+                    optimal.as_slice()
+                );
+            });
+        });
+    }
+
+    #[test]
+    fn test_triangulate_optimal_with_noise() {
+        let mut rng = rand::thread_rng();
+        let (p0, p1) = create_cameras();
+        let gx = na::DVector::from_vec(vec![0.3, -0.2, 1.5, 1.0]);
+        let x0 = &p0 * &gx;
+        let x1 = &p1 * &gx;
+        let scale = 1e-3;
+        let x0 = na::Point2::new(
+            x0[0] / x0[2] + (rng.gen::<f64>() - 0.5) * scale,
+            x0[1] / x0[2] + (rng.gen::<f64>() - 0.5) * scale,
+        );
+        let x1 = na::Point2::new(
+            x1[0] / x1[2] + (rng.gen::<f64>() - 0.5) * scale,
+            x1[1] / x1[2] + (rng.gen::<f64>() - 0.5) * scale,
+        );
+
+        let dlt = triangulate_dlt(&p0, &p1, &x0, &x1);
+        let optimal = triangulate_optimal(&p0, &p1, &x0, &x1);
+        assert!(
+            reprojection_error(&p0, &p1, &x0, &x1, &optimal)
+                <= reprojection_error(&p0, &p1, &x0, &x1, &dlt) + 1e-15
+        );
+        assert!((optimal - gx.rows(0, 3)).norm() < 1e-2);
+    }
+
+    #[test]
+    fn test_projective_reconstruction() {
+        let mut rng = rand::thread_rng();
+        #[rustfmt::skip]
+        let k = na::DMatrix::from_row_slice(3, 3, &[
+            800.0, 0.0, 320.0,
+            0.0, 800.0, 240.0,
+            0.0, 0.0, 1.0,
+        ]);
+        let theta: f64 = 0.2;
+        #[rustfmt::skip]
+        let rot = na::DMatrix::from_row_slice(3, 3, &[
+            theta.cos(), 0.0, theta.sin(),
+            0.0, 1.0, 0.0,
+            -theta.sin(), 0.0, theta.cos(),
+        ]);
+        let trans = na::DVector::from_vec(vec![-1.0, 0.1, 0.2]);
+        // x1 = R x0 + t -> x0^T (K^-T R^T [t]x K^-1) x1 = 0
+        let k_inv = k.clone().try_inverse().unwrap();
+        let f = k_inv.transpose() * rot.transpose() * vector_cross_matrix(&trans) * &k_inv;
+
+        let data: Vec<na::Point2<f64>> = (0..50)
+            .flat_map(|_| {
+                let pt0 = na::DVector::from_vec(vec![
+                    (rng.gen::<f64>() - 0.5) * 4.0,
+                    (rng.gen::<f64>() - 0.5) * 3.0,
+                    rng.gen::<f64>() * 4.0 + 4.0,
+                ]);
+                let pt1 = &rot * &pt0 + &trans;
+                [&k * pt0, &k * pt1]
+                    .iter()
+                    .map(|x| {
+                        na::Point2::new(
+                            x[0] / x[2] + (rng.gen::<f64>() - 0.5) * 0.1,
+                            x[1] / x[2] + (rng.gen::<f64>() - 0.5) * 0.1,
+                        )
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+
+        let (p0, p1, pts) = projective_reconstruction(&f, &data).unwrap();
+        assert_eq!(p0, na::DMatrix::<f64>::identity(3, 4));
+        assert_eq!(pts.len(), 50);
+        pts.iter().enumerate().for_each(|(idx, pt)| {
+            let homo = pt.clone().insert_row(3, 1.0);
+            let (x0, x1) = (&p0 * &homo, &p1 * &homo);
+            assert!(x0[2] * x1[2] > 0.0, "idx = {}", idx);
+            [(x0, &data[idx * 2]), (x1, &data[idx * 2 + 1])]
+                .iter()
+                .for_each(|(proj, x)| {
+                    let err = ((proj[0] / proj[2] - x[0]).powi(2)
+                        + (proj[1] / proj[2] - x[1]).powi(2))
+                    .sqrt();
+                    assert!(err < 0.5, "idx = {}, error = {}", idx, err);
+                });
+        });
+
+        assert!(projective_reconstruction(&f, &data[..3]).is_err());
+        assert!(projective_reconstruction(&na::DMatrix::zeros(3, 4), &data).is_err());
+    }
+
     #[test]
     fn test_optimal_correction() {
         let mut rng = rand::thread_rng();
@@ -1,10 +1,17 @@
-use anyhow::Result;
 use nalgebra as na;
 
-use crate::linalg::matrix::pseudo_inverse_with_rank;
+use crate::{
+    ensure,
+    error::{ImprocError, Result},
+    linalg::matrix::pseudo_inverse_with_rank,
+};
+
+use super::homography::normalization_matrix;
 
 const STOP_THRESH: f64 = 1e-7;
 const MAX_ITER: usize = 50;
+/// Minimum number of the correspondences to estimate trifocal tensor linearly.
+const MIN_CORRESPONDENCES: usize = 7;
 
 pub fn optimal_correction(
     p0: &na::DMatrix<f64>,
@@ -98,6 +105,90 @@ pub fn optimal_correction(
     Ok(vec![x_h, y_h, z_h])
 }
 
+/// Estimate trifocal tensor from the point correspondences of three views by the linear method.
+/// Points of each view are normalized (see `homography::normalized_dlt`) and 4 independent
+/// trilinearities [x1]x (sum_i x0_i T_i) [x2]x = 0 of each correspondence are stacked into the
+/// 4n x 27 system. The tensor is the singular vector of the smallest singular value and
+/// denormalized to the original coordinates.
+/// `pts0[i]`, `pts1[i]` and `pts2[i]` are the same point. At least 7 correspondences are required.
+/// Return [T_0, T_1, T_2] (the norm of the whole tensor is 1).
+pub fn estimate_trifocal_tensor(
+    pts0: &[na::Point2<f64>],
+    pts1: &[na::Point2<f64>],
+    pts2: &[na::Point2<f64>],
+) -> Result<Vec<na::DMatrix<f64>>> {
+    ensure!(
+        pts0.len() == pts1.len() && pts0.len() == pts2.len(),
+        "Invalid number of points : {}, {}, {}",
+        pts0.len(),
+        pts1.len(),
+        pts2.len()
+    );
+    ensure!(
+        pts0.len() >= MIN_CORRESPONDENCES,
+        ImprocError::InsufficientData {
+            expected: MIN_CORRESPONDENCES,
+            got: pts0.len()
+        }
+    );
+    let ts = [
+        normalization_matrix(pts0)?,
+        normalization_matrix(pts1)?,
+        normalization_matrix(pts2)?,
+    ];
+    let normalize = |t: &na::Matrix3<f64>, pt: &na::Point2<f64>| {
+        let x = t * pt.to_homogeneous();
+        na::DVector::from_column_slice(x.as_slice())
+    };
+
+    // Coefficients of the trilinearity are obtained by `calc_t` with the unit tensors.
+    let bases: Vec<Vec<na::DMatrix<f64>>> = (0..27)
+        .map(|idx| {
+            let mut basis = vec![na::DMatrix::<f64>::zeros(3, 3); 3];
+            basis[idx / 9][(idx % 9 / 3, idx % 3)] = 1.0;
+            basis
+        })
+        .collect();
+    let mut a = na::DMatrix::<f64>::zeros(pts0.len() * 4, 27);
+    (0..pts0.len()).for_each(|idx| {
+        let x = normalize(&ts[0], &pts0[idx]);
+        let y = normalize(&ts[1], &pts1[idx]);
+        let z = normalize(&ts[2], &pts2[idx]);
+        bases.iter().enumerate().for_each(|(col, basis)| {
+            let t = calc_t(basis, &x, &y, &z);
+            a[(idx * 4, col)] = t[(0, 0)];
+            a[(idx * 4 + 1, col)] = t[(0, 1)];
+            a[(idx * 4 + 2, col)] = t[(1, 0)];
+            a[(idx * 4 + 3, col)] = t[(1, 1)];
+        });
+    });
+    let svd = a.svd(false, true);
+    let (min_idx, _) = svd.singular_values.argmin();
+    let v_t = svd.v_t.ok_or(ImprocError::SingularMatrix)?;
+    let normalized: Vec<na::Matrix3<f64>> = (0..3)
+        .map(|i| na::Matrix3::from_fn(|r, c| v_t[(min_idx, i * 9 + r * 3 + c)]))
+        .collect();
+
+    // T_i = sum_r T0[r, i] T1^-1 T~_r T2^-T
+    let t1_inv = ts[1].try_inverse().ok_or(ImprocError::SingularMatrix)?;
+    let t2_inv_t = ts[2]
+        .try_inverse()
+        .ok_or(ImprocError::SingularMatrix)?
+        .transpose();
+    let tensor: Vec<na::Matrix3<f64>> = (0..3)
+        .map(|i| {
+            (0..3).fold(na::Matrix3::zeros(), |acc, r| {
+                acc + ts[0][(r, i)] * t1_inv * normalized[r] * t2_inv_t
+            })
+        })
+        .collect();
+    let norm = tensor.iter().map(|t| t.norm_squared()).sum::<f64>().sqrt();
+    Ok(tensor
+        .iter()
+        .map(|t| na::DMatrix::from_fn(3, 3, |r, c| t[(r, c)] / norm))
+        .collect())
+}
+
 fn calc_trifocal_tensor(
     p0: &na::DMatrix<f64>,
     p1: &na::DMatrix<f64>,
@@ -229,6 +320,77 @@ mod tests {
         assert!((t[(0, 0)] - 22.84) < 1e-1);
     }
 
+    #[test]
+    fn test_estimate_trifocal_tensor() {
+        let mut rng = thread_rng();
+        #[rustfmt::skip]
+        let k = na::DMatrix::from_row_slice(3, 3, &[
+            500.0, 0.0, 320.0,
+            0.0, 500.0, 240.0,
+            0.0, 0.0, 1.0,
+        ]);
+        let camera = |theta: f64, tx: f64, ty: f64| {
+            #[rustfmt::skip]
+            let rt = na::DMatrix::from_row_slice(3, 4, &[
+                theta.cos(), 0.0, theta.sin(), tx,
+                0.0, 1.0, 0.0, ty,
+                -theta.sin(), 0.0, theta.cos(), 0.0,
+            ]);
+            &k * rt
+        };
+        let ps = [
+            camera(0.0, 0.0, 0.0),
+            camera(0.1, -1.0, 0.1),
+            camera(-0.15, 1.0, 0.3),
+        ];
+
+        let n_pts = 20;
+        let (mut pts0, mut pts1, mut pts2) = (vec![], vec![], vec![]);
+        (0..n_pts).for_each(|_| {
+            let gx = na::DVector::from_vec(vec![
+                (rng.gen::<f64>() - 0.5) * 4.0,
+                (rng.gen::<f64>() - 0.5) * 4.0,
+                rng.gen::<f64>() * 4.0 + 4.0,
+                1.0,
+            ]);
+            [&mut pts0, &mut pts1, &mut pts2]
+                .iter_mut()
+                .zip(ps.iter())
+                .for_each(|(pts, p)| {
+                    let x = p * &gx;
+                    pts.push(na::Point2::new(x[0] / x[2], x[1] / x[2]));
+                });
+        });
+
+        let tensor = estimate_trifocal_tensor(&pts0, &pts1, &pts2).unwrap();
+        assert_eq!(tensor.len(), 3);
+        (0..n_pts).for_each(|idx| {
+            let t = calc_t(
+                &tensor,
+                &na::DVector::from_vec(vec![pts0[idx][0], pts0[idx][1], 1.0]),
+                &na::DVector::from_vec(vec![pts1[idx][0], pts1[idx][1], 1.0]),
+                &na::DVector::from_vec(vec![pts2[idx][0], pts2[idx][1], 1.0]),
+            );
+            assert!(t.amax() < 1e-5, "idx = {}, t = {}", idx, t);
+        });
+
+        // equal to the tensor calculated from the camera matrices up to scale.
+        let gt = calc_trifocal_tensor(&ps[0], &ps[1], &ps[2]);
+        let gt_norm = gt.iter().map(|t| t.norm_squared()).sum::<f64>().sqrt();
+        let sign = if gt[0].dot(&tensor[0]) < 0.0 {
+            -1.0
+        } else {
+            1.0
+        };
+        (0..3).for_each(|i| {
+            let diff = &gt[i] / gt_norm * sign - &tensor[i];
+            assert!(diff.amax() < 1e-5, "diff = {}", diff);
+        });
+
+        assert!(estimate_trifocal_tensor(&pts0[..6], &pts1[..6], &pts2[..6]).is_err());
+        assert!(estimate_trifocal_tensor(&pts0, &pts1[..10], &pts2).is_err());
+    }
+
     #[test]
     fn test_optimal_correction() {
         #[rustfmt::skip]
@@ -0,0 +1,148 @@
+//! Error type of the numerical modules (`linalg`, `optimizer`, `epipolar` and `sfm`).
+use std::fmt;
+
+#[derive(Debug)]
+pub enum ImprocError {
+    /// Matrix can not be inverted (or decomposed).
+    SingularMatrix,
+    /// Number of the input data is smaller than required.
+    InsufficientData {
+        expected: usize,
+        got: usize,
+    },
+    /// Shape (rows, cols) of the input matrix is invalid.
+    ShapeMismatch {
+        expected: (usize, usize),
+        got: (usize, usize),
+    },
+    IoError(std::io::Error),
+    Other(String),
+}
+
+pub type Result<T> = std::result::Result<T, ImprocError>;
+
+impl fmt::Display for ImprocError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImprocError::SingularMatrix => write!(f, "Matrix is singular."),
+            ImprocError::InsufficientData { expected, got } => {
+                write!(f, "Not enough data : expected {}, got {}", expected, got)
+            }
+            ImprocError::ShapeMismatch { expected, got } => write!(
+                f,
+                "Invalid matrix shape : expected {:?}, got {:?}",
+                expected, got
+            ),
+            ImprocError::IoError(err) => write!(f, "IO error : {}", err),
+            ImprocError::Other(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl std::error::Error for ImprocError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ImprocError::IoError(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for ImprocError {
+    fn from(err: std::io::Error) -> Self {
+        ImprocError::IoError(err)
+    }
+}
+
+impl From<anyhow::Error> for ImprocError {
+    fn from(err: anyhow::Error) -> Self {
+        ImprocError::Other(format!("{:#}", err))
+    }
+}
+
+/// Convert `None` or `Err` to `ImprocError::Other` with the given message
+/// (counterpart of `anyhow::Context`).
+pub trait Context<T> {
+    fn context(self, msg: &str) -> Result<T>;
+    fn with_context<F: FnOnce() -> String>(self, f: F) -> Result<T>;
+}
+
+impl<T> Context<T> for Option<T> {
+    fn context(self, msg: &str) -> Result<T> {
+        self.ok_or_else(|| ImprocError::Other(msg.to_string()))
+    }
+
+    fn with_context<F: FnOnce() -> String>(self, f: F) -> Result<T> {
+        self.ok_or_else(|| ImprocError::Other(f()))
+    }
+}
+
+impl<T, E: fmt::Display> Context<T> for std::result::Result<T, E> {
+    fn context(self, msg: &str) -> Result<T> {
+        self.map_err(|err| ImprocError::Other(format!("{} : {}", msg, err)))
+    }
+
+    fn with_context<F: FnOnce() -> String>(self, f: F) -> Result<T> {
+        self.map_err(|err| ImprocError::Other(format!("{} : {}", f(), err)))
+    }
+}
+
+/// Return `Err` if the condition is not satisfied (counterpart of `anyhow::ensure!`).
+/// Error is either an `ImprocError` or a format string which is converted to
+/// `ImprocError::Other`.
+#[macro_export]
+macro_rules! ensure {
+    ($cond:expr, $fmt:literal $(, $arg:expr)* $(,)?) => {
+        let cond: bool = $cond;
+        if !cond {
+            return Err($crate::error::ImprocError::Other(format!($fmt $(, $arg)*)));
+        }
+    };
+    ($cond:expr, $err:expr $(,)?) => {
+        let cond: bool = $cond;
+        if !cond {
+            return Err($err);
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(len: usize) -> Result<()> {
+        ensure!(
+            len >= 4,
+            ImprocError::InsufficientData {
+                expected: 4,
+                got: len
+            }
+        );
+        ensure!(len != 5, "Invalid length : {}", len);
+        Ok(())
+    }
+
+    #[test]
+    fn test_ensure() {
+        assert!(check(4).is_ok());
+        assert!(matches!(
+            check(2),
+            Err(ImprocError::InsufficientData {
+                expected: 4,
+                got: 2
+            })
+        ));
+        match check(5) {
+            Err(ImprocError::Other(msg)) => assert_eq!(msg, "Invalid length : 5"),
+            res => panic!("Unexpected result : {:?}", res),
+        }
+    }
+
+    #[test]
+    fn test_into_anyhow() {
+        let res: anyhow::Result<()> = (|| Ok(check(2)?))();
+        let err = res.unwrap_err();
+        assert!(err.downcast_ref::<ImprocError>().is_some());
+        assert_eq!(err.to_string(), "Not enough data : expected 4, got 2");
+    }
+}
@@ -2,6 +2,12 @@ use bitvec::prelude::*;
 
 pub trait Distance {
     fn distance(&self, rhs: &Self) -> f32;
+
+    /// Return false if the distance between `self` and `rhs` is not defined
+    /// (e.g. lengths of the descriptors are different).
+    fn is_comparable(&self, _rhs: &Self) -> bool {
+        true
+    }
 }
 
 pub mod descriptors;
@@ -18,6 +24,52 @@ impl Distance for BitVec {
     }
 }
 
+/// L2 distance of the float descriptors.
+/// Distance of the descriptors of the different lengths is `f32::INFINITY`.
+impl Distance for Vec<f32> {
+    fn distance(&self, rhs: &Self) -> f32 {
+        if !self.is_comparable(rhs) {
+            return f32::INFINITY;
+        }
+        self.iter()
+            .zip(rhs)
+            .fold(0.0, |acc, (l, r)| acc + (l - r) * (l - r))
+            .sqrt()
+    }
+
+    fn is_comparable(&self, rhs: &Self) -> bool {
+        self.len() == rhs.len()
+    }
+}
+
+/// Float descriptor compared by the cosine distance (1 - cos(theta)).
+/// Distance of the descriptors of the different lengths is `f32::INFINITY`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CosineVec(pub Vec<f32>);
+
+impl Distance for CosineVec {
+    fn distance(&self, rhs: &Self) -> f32 {
+        if !self.is_comparable(rhs) {
+            return f32::INFINITY;
+        }
+        let (dot, l_norm2, r_norm2) = self
+            .0
+            .iter()
+            .zip(&rhs.0)
+            .fold((0.0, 0.0, 0.0), |(dot, ln, rn), (l, r)| {
+                (dot + l * r, ln + l * l, rn + r * r)
+            });
+        if l_norm2 == 0.0 || r_norm2 == 0.0 {
+            return 1.0;
+        }
+        1.0 - dot / (l_norm2 * r_norm2).sqrt()
+    }
+
+    fn is_comparable(&self, rhs: &Self) -> bool {
+        self.0.len() == rhs.0.len()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -32,4 +84,29 @@ mod tests {
         let dist = lhs.distance(&rhs) as usize;
         assert_eq!(dist, 5);
     }
+
+    #[test]
+    fn test_l2_distance() {
+        let lhs = vec![1.0f32, -2.0, 0.5];
+        assert_eq!(lhs.distance(&lhs.clone()), 0.0);
+        let rhs = vec![4.0f32, 2.0, 0.5];
+        assert_eq!(lhs.distance(&rhs), 5.0);
+
+        let rhs = vec![1.0f32, -2.0];
+        assert!(!lhs.is_comparable(&rhs));
+        assert_eq!(lhs.distance(&rhs), f32::INFINITY);
+    }
+
+    #[test]
+    fn test_cosine_distance() {
+        let lhs = CosineVec(vec![1.0, 2.0, 0.0]);
+        assert!(lhs.distance(&lhs.clone()).abs() < 1e-6);
+        assert!(lhs.distance(&CosineVec(vec![2.0, 4.0, 0.0])).abs() < 1e-6);
+        assert!((lhs.distance(&CosineVec(vec![-2.0, 1.0, 3.0])) - 1.0).abs() < 1e-6);
+        assert!((lhs.distance(&CosineVec(vec![-1.0, -2.0, 0.0])) - 2.0).abs() < 1e-6);
+
+        let rhs = CosineVec(vec![1.0, 2.0]);
+        assert!(!lhs.is_comparable(&rhs));
+        assert_eq!(lhs.distance(&rhs), f32::INFINITY);
+    }
 }
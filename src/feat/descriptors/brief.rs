@@ -2,7 +2,7 @@ use image::{GrayImage, Luma, Pixel};
 use nalgebra::Point2;
 use rand_distr::{Distribution, Normal};
 
-use crate::{feat::keypoints::KeyPoint, imgproc::median_filter};
+use crate::{config::BriefConfig, feat::keypoints::KeyPoint, imgproc::median_filter};
 
 use super::{BriefDescriptor, Descriptor, Extractor};
 
@@ -44,6 +44,10 @@ impl Brief {
         }
     }
 
+    pub fn from_config(cfg: &BriefConfig) -> Self {
+        Self::new(cfg.patch_size, cfg.median_kernel_size, cfg.n_binary_test)
+    }
+
     pub fn calc_brief(
         &self,
         kpt: &KeyPoint,
@@ -0,0 +1,144 @@
+//! HOG (Histogram of Oriented Gradients) descriptor.
+use std::f32::consts::PI;
+
+use image::GrayImage;
+
+use crate::feat::keypoints::KeyPoint;
+
+use super::{Descriptor, Extractor};
+
+const EPS: f32 = 1e-6;
+
+/// Extract HOG descriptor from the square patch centered at each keypoint.
+/// The patch is divided into `block_size` x `block_size` cells of `cell_size` x `cell_size`
+/// pixels. Histogram of the gradient orientation (in [0, 2pi), weighted by the gradient
+/// magnitude) is computed for each cell, and histograms of all cells are concatenated and
+/// L2-normalized. Length of the descriptor is `block_size * block_size * bins`.
+pub struct HOGExtractor {
+    cell_size: u32,
+    block_size: u32,
+    bins: u32,
+}
+
+impl HOGExtractor {
+    /// Args
+    /// - cell_size : number of pixels of the side of the cell.
+    /// - block_size : number of cells of the side of the patch.
+    /// - bins : number of bins of the histogram of each cell.
+    pub fn new(cell_size: u32, block_size: u32, bins: u32) -> Self {
+        HOGExtractor {
+            cell_size,
+            block_size,
+            bins,
+        }
+    }
+
+    fn patch_size(&self) -> u32 {
+        self.cell_size * self.block_size
+    }
+
+    /// Compute descriptor of the patch whose top-left corner is (`left`, `top`).
+    /// Pixels around the patch (1 pixel width) must be inside of `img`.
+    fn calc_hog(&self, img: &GrayImage, left: u32, top: u32) -> Vec<f32> {
+        let bins = self.bins as usize;
+        let bin_width = 2.0 * PI / self.bins as f32;
+        let mut hist = vec![0.0f32; (self.block_size * self.block_size) as usize * bins];
+        let value = |x: u32, y: u32| img.get_pixel(x, y)[0] as f32;
+        for dy in 0..self.patch_size() {
+            for dx in 0..self.patch_size() {
+                let (x, y) = (left + dx, top + dy);
+                let gx = value(x + 1, y) - value(x - 1, y);
+                let gy = value(x, y + 1) - value(x, y - 1);
+                let magnitude = (gx * gx + gy * gy).sqrt();
+                if magnitude < EPS {
+                    continue;
+                }
+                let angle = gy.atan2(gx).rem_euclid(2.0 * PI);
+                // vote to the two nearest bins (centers of the bins are (i + 0.5) * bin_width)
+                let pos = angle / bin_width - 0.5;
+                let lower = pos.floor();
+                let frac = pos - lower;
+                let lower = (lower as i32).rem_euclid(bins as i32) as usize;
+                let upper = (lower + 1) % bins;
+                let cell = ((dy / self.cell_size) * self.block_size + dx / self.cell_size) as usize;
+                hist[cell * bins + lower] += magnitude * (1.0 - frac);
+                hist[cell * bins + upper] += magnitude * frac;
+            }
+        }
+        let norm = hist.iter().map(|val| val * val).sum::<f32>().sqrt();
+        hist.iter().map(|val| val / (norm + EPS)).collect()
+    }
+}
+
+impl Extractor<Vec<f32>> for HOGExtractor {
+    fn compute(&self, img: &GrayImage, kpts: &Vec<KeyPoint>) -> Vec<Descriptor<Vec<f32>>> {
+        let half = self.patch_size() / 2;
+        let mut descriptors = Vec::new();
+        for kpt in kpts {
+            let (cx, cy) = (kpt.x() as u32, kpt.y() as u32);
+            if cx < half + 1
+                || cy < half + 1
+                || cx + self.patch_size() - half + 1 > img.width()
+                || cy + self.patch_size() - half + 1 > img.height()
+            {
+                continue;
+            }
+            descriptors.push(Descriptor {
+                kpt: *kpt,
+                value: self.calc_hog(img, cx - half, cy - half),
+            });
+        }
+        descriptors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_uniform_gradient() {
+        let img = GrayImage::from_fn(64, 64, |x, y| image::Luma([(2 * x + y) as u8]));
+        let hog = HOGExtractor::new(4, 3, 9);
+        let kpts = vec![
+            KeyPoint::new(20, 20, 1.0, 0, 0.0),
+            KeyPoint::new(40, 30, 1.0, 0, 0.0),
+            KeyPoint::new(2, 2, 1.0, 0, 0.0), // out of the image
+        ];
+        let descs = hog.compute(&img, &kpts);
+        assert_eq!(descs.len(), 2);
+        assert_eq!(descs[0].value.len(), 3 * 3 * 9);
+        assert!(descs[0].distance(&descs[1]) < 1e-6);
+        let norm: f32 = descs[0].value.iter().map(|val| val * val).sum();
+        assert!((norm - 1.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_rotate_180() {
+        let (width, height) = (48, 40);
+        let img = GrayImage::from_fn(width, height, |x, y| {
+            let (fx, fy) = (x as f32, y as f32);
+            image::Luma([(128.0 + 60.0 * (fx * 0.3).sin() * (fy * 0.2 + 1.0).cos()) as u8])
+        });
+        let rotated = image::imageops::rotate180(&img);
+        let (cell_size, block_size, bins) = (5, 3, 8);
+        let hog = HOGExtractor::new(cell_size, block_size, bins);
+        let (x, y) = (20, 17);
+        let desc = &hog.compute(&img, &vec![KeyPoint::new(x, y, 1.0, 0, 0.0)])[0];
+        let kpt = KeyPoint::new(width as usize - 1 - x, height as usize - 1 - y, 1.0, 0, 0.0);
+        let rot_desc = &hog.compute(&rotated, &vec![kpt])[0];
+
+        // cell (i, j) moves to (n - 1 - i, n - 1 - j) and orientation is shifted by pi.
+        let n_cells = (block_size * block_size) as usize;
+        let bins = bins as usize;
+        for cell in 0..n_cells {
+            for bin in 0..bins {
+                let rot_cell = n_cells - 1 - cell;
+                let rot_bin = (bin + bins / 2) % bins;
+                let expect = desc.value[cell * bins + bin];
+                let res = rot_desc.value[rot_cell * bins + rot_bin];
+                assert!((expect - res).abs() < 1e-4, "{} vs {}", expect, res);
+            }
+        }
+    }
+}
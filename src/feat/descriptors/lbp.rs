@@ -0,0 +1,155 @@
+//! LBP (Local Binary Pattern) descriptor.
+use image::GrayImage;
+use nalgebra::Vector2;
+
+use crate::feat::keypoints::KeyPoint;
+
+use super::{Descriptor, Extractor};
+
+/// Extract histogram of the rotation invariant LBP codes in the square patch centered at each
+/// keypoint. LBP code of the pixel is a `n_points` bit pattern whose i-th bit is 1 if the
+/// value at the i-th point of the circle of `radius` is larger than the center pixel. The code
+/// is rotated (circular bit shift) so that it becomes minimum, which makes the descriptor
+/// invariant to the rotation of 360 / `n_points` degrees.
+/// Histogram is normalized so that the sum of the bins is 1.
+pub struct LBPExtractor {
+    patch_size: u32,
+    radius: u32,
+    n_points: u32,
+    n_bins: usize,
+    circle_points: Vec<Vector2<i32>>,
+}
+
+impl LBPExtractor {
+    /// Args
+    /// - patch_size : size of the patch where the histogram is calculated.
+    /// - radius : radius of the circle whose points are compared with the center pixel.
+    /// - n_points : number of the points on the circle (<= 32).
+    /// - n_bins : number of bins of the histogram. LBP codes (0 ~ 2^n_points - 1) are
+    ///   quantized uniformly.
+    pub fn new(patch_size: u32, radius: u32, n_points: u32, n_bins: usize) -> Self {
+        assert!(0 < n_points && n_points <= 32);
+        let circle_points = (0..n_points)
+            .map(|i| {
+                let theta = 2.0 * std::f32::consts::PI * i as f32 / n_points as f32;
+                Vector2::new(
+                    (radius as f32 * theta.cos()).round() as i32,
+                    (radius as f32 * theta.sin()).round() as i32,
+                )
+            })
+            .collect();
+        LBPExtractor {
+            patch_size,
+            radius,
+            n_points,
+            n_bins,
+            circle_points,
+        }
+    }
+
+    /// Calculate rotation invariant LBP code of (`x`, `y`).
+    /// All points of the circle must be inside of `img`.
+    fn calc_code(&self, img: &GrayImage, x: u32, y: u32) -> u32 {
+        let center = img.get_pixel(x, y)[0];
+        let code = self
+            .circle_points
+            .iter()
+            .enumerate()
+            .fold(0u32, |acc, (i, pt)| {
+                let val = img.get_pixel((x as i32 + pt.x) as u32, (y as i32 + pt.y) as u32)[0];
+                acc | (((val > center) as u32) << i)
+            });
+        let n = self.n_points;
+        let mask = if n == 32 { u32::MAX } else { (1 << n) - 1 };
+        (0..n)
+            .map(|shift| ((code >> shift) | (code << ((n - shift) % n))) & mask)
+            .min()
+            .unwrap()
+    }
+
+    fn calc_histogram(&self, img: &GrayImage, left: u32, top: u32) -> Vec<f32> {
+        let mut hist = vec![0.0f32; self.n_bins];
+        for y in top..top + self.patch_size {
+            for x in left..left + self.patch_size {
+                let code = self.calc_code(img, x, y) as u64;
+                hist[((code * self.n_bins as u64) >> self.n_points) as usize] += 1.0;
+            }
+        }
+        let n_pixels = (self.patch_size * self.patch_size) as f32;
+        hist.iter().map(|val| val / n_pixels).collect()
+    }
+}
+
+impl Extractor<Vec<f32>> for LBPExtractor {
+    fn compute(&self, img: &GrayImage, kpts: &Vec<KeyPoint>) -> Vec<Descriptor<Vec<f32>>> {
+        let half = self.patch_size / 2;
+        let margin = half + self.radius;
+        let mut descriptors = Vec::new();
+        for kpt in kpts {
+            let (cx, cy) = (kpt.x() as u32, kpt.y() as u32);
+            if cx < margin
+                || cy < margin
+                || cx + self.patch_size - half + self.radius > img.width()
+                || cy + self.patch_size - half + self.radius > img.height()
+            {
+                continue;
+            }
+            descriptors.push(Descriptor {
+                kpt: *kpt,
+                value: self.calc_histogram(img, cx - half, cy - half),
+            });
+        }
+        descriptors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_constant_image() {
+        let img = GrayImage::from_pixel(32, 32, image::Luma([100]));
+        let lbp = LBPExtractor::new(9, 1, 8, 16);
+        (1..31).for_each(|i| assert_eq!(lbp.calc_code(&img, i, 31 - i), 0));
+        let descs = lbp.compute(&img, &vec![KeyPoint::new(16, 16, 1.0, 0, 0.0)]);
+        assert_eq!(descs.len(), 1);
+        assert_eq!(descs[0].value[0], 1.0);
+        assert!(descs[0].value[1..].iter().all(|val| *val == 0.0));
+    }
+
+    #[test]
+    fn test_checkerboard() {
+        let img = GrayImage::from_fn(32, 32, |x, y| {
+            image::Luma([if (x / 2 + y / 2) % 2 == 0 { 200 } else { 50 }])
+        });
+        let lbp = LBPExtractor::new(9, 1, 8, 16);
+        let kpts = vec![
+            KeyPoint::new(16, 16, 1.0, 0, 0.0),
+            KeyPoint::new(3, 3, 1.0, 0, 0.0), // out of the image
+        ];
+        let descs = lbp.compute(&img, &kpts);
+        assert_eq!(descs.len(), 1);
+        let hist = &descs[0].value;
+        assert!((hist.iter().sum::<f32>() - 1.0).abs() < 1e-5);
+        assert!(hist.iter().filter(|val| **val > 0.0).count() > 1);
+        assert!(hist[0] < 1.0);
+    }
+
+    #[test]
+    fn test_rotation_invariance() {
+        let (width, height) = (40, 30);
+        let img = GrayImage::from_fn(width, height, |x, y| {
+            image::Luma([((x * 37 + y * 91 + x * y * 13) % 251) as u8])
+        });
+        let rotated = image::imageops::rotate90(&img);
+        let lbp = LBPExtractor::new(11, 2, 8, 32);
+        let (x, y) = (18, 14);
+        let desc = &lbp.compute(&img, &vec![KeyPoint::new(x, y, 1.0, 0, 0.0)])[0];
+        // (x, y) moves to (height - 1 - y, x) by the rotation
+        let kpt = KeyPoint::new(height as usize - 1 - y, x, 1.0, 0, 0.0);
+        let rot_desc = &lbp.compute(&rotated, &vec![kpt])[0];
+        assert_eq!(desc.value, rot_desc.value);
+        assert!(desc.value.iter().filter(|val| **val > 0.0).count() > 1);
+    }
+}
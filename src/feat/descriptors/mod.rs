@@ -1,9 +1,14 @@
 use image::GrayImage;
+use serde::{Deserialize, Serialize};
 use std::ops::Index;
 
 use super::{keypoints::KeyPoint, Distance};
 
 pub mod brief;
+pub mod hog;
+pub mod lbp;
+#[cfg(feature = "pca")]
+pub mod pca;
 pub mod steered_brief;
 
 /// Feature Descriptor
@@ -33,7 +38,7 @@ where
     fn compute(&self, img: &GrayImage, kpts: &Vec<KeyPoint>) -> Vec<Descriptor<T>>;
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct BriefDescriptor {
     pub bits: Vec<u64>,
     values: Vec<bool>,
@@ -70,7 +75,17 @@ impl BriefDescriptor {
 }
 
 impl Distance for BriefDescriptor {
+    /// Hamming distance. Return `f32::INFINITY` if the numbers of bits (`n_bits` of `new`) of
+    /// the descriptors are different (panic in debug build).
     fn distance(&self, rhs: &Self) -> f32 {
+        debug_assert_eq!(
+            self.values.len(),
+            rhs.values.len(),
+            "Numbers of bits of the descriptors are different"
+        );
+        if !self.is_comparable(rhs) {
+            return f32::INFINITY;
+        }
         let dist = self
             .bits
             .iter()
@@ -78,6 +93,10 @@ impl Distance for BriefDescriptor {
             .fold(0, |acc, (l, r)| acc + (l ^ r).count_ones());
         dist as f32
     }
+
+    fn is_comparable(&self, rhs: &Self) -> bool {
+        self.values.len() == rhs.values.len()
+    }
 }
 
 impl Index<usize> for BriefDescriptor {
@@ -130,4 +149,15 @@ mod tests {
         (0..n_bits).for_each(|i| rhs.push(i % 2 == 0));
         assert_eq!(lhs.distance(&rhs) as usize, 128);
     }
+
+    #[test]
+    #[cfg_attr(debug_assertions, should_panic)]
+    fn test_brief_bit_vec_distance_mismatch() {
+        let mut lhs = BriefDescriptor::new(64);
+        let mut rhs = BriefDescriptor::new(128);
+        (0..64).for_each(|_| lhs.push(false));
+        (0..128).for_each(|_| rhs.push(false));
+        assert!(!lhs.is_comparable(&rhs));
+        assert_eq!(lhs.distance(&rhs), f32::INFINITY);
+    }
 }
@@ -0,0 +1,102 @@
+//! Dimensionality reduction of the float descriptors by PCA.
+use nalgebra as na;
+
+/// Project descriptors onto the principal components of the training descriptors.
+pub struct PCAProjector {
+    mean: na::DVector<f32>,
+    /// Principal components (n_components x dimension of the descriptor). Each row is a unit
+    /// vector and rows are sorted in descending order of the variance.
+    components: na::DMatrix<f32>,
+}
+
+impl PCAProjector {
+    /// Compute principal components of `descriptors`.
+    /// Panic if `descriptors` is empty or `n_components` is larger than the dimension.
+    pub fn fit(descriptors: &[Vec<f32>], n_components: usize) -> Self {
+        assert!(!descriptors.is_empty(), "No descriptors to fit");
+        let dim = descriptors[0].len();
+        assert!(n_components <= dim);
+        let n = descriptors.len();
+        let data = na::DMatrix::from_fn(n, dim, |r, c| descriptors[r][c]);
+        let mean = na::DVector::from_fn(dim, |r, _| data.column(r).mean());
+        let centered = na::DMatrix::from_fn(n, dim, |r, c| data[(r, c)] - mean[c]);
+        let covariance = centered.transpose() * &centered / n as f32;
+
+        let eigen = covariance.symmetric_eigen();
+        let mut indices: Vec<usize> = (0..dim).collect();
+        indices.sort_by(|&lhs, &rhs| {
+            eigen.eigenvalues[rhs]
+                .partial_cmp(&eigen.eigenvalues[lhs])
+                .unwrap()
+        });
+        let components = na::DMatrix::from_fn(n_components, dim, |r, c| {
+            eigen.eigenvectors[(c, indices[r])]
+        });
+        PCAProjector { mean, components }
+    }
+
+    pub fn n_components(&self) -> usize {
+        self.components.nrows()
+    }
+
+    /// Project `desc` onto the principal components.
+    pub fn project(&self, desc: &[f32]) -> Vec<f32> {
+        let centered = na::DVector::from_column_slice(desc) - &self.mean;
+        (&self.components * centered).as_slice().to_vec()
+    }
+
+    /// Reconstruct the descriptor from the projected descriptor (inverse of `project`).
+    pub fn reconstruct(&self, projected: &[f32]) -> Vec<f32> {
+        let desc =
+            self.components.transpose() * na::DVector::from_column_slice(projected) + &self.mean;
+        desc.as_slice().to_vec()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{Rng, SeedableRng};
+
+    use super::*;
+
+    /// Create descriptors which are (mostly) in the subspace of `n_latent` dimension.
+    fn create_descriptors(n: usize, dim: usize, n_latent: usize) -> Vec<Vec<f32>> {
+        let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(0);
+        let basis = na::DMatrix::<f32>::from_fn(dim, n_latent, |_, _| rng.gen_range(-1.0..1.0));
+        (0..n)
+            .map(|_| {
+                let latent = na::DVector::<f32>::from_fn(n_latent, |_, _| rng.gen_range(-1.0..1.0));
+                let noise = na::DVector::<f32>::from_fn(dim, |_, _| rng.gen_range(-0.1..0.1));
+                (&basis * latent + noise).as_slice().to_vec()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_orthonormal_components() {
+        let descs = create_descriptors(300, 256, 32);
+        let pca = PCAProjector::fit(&descs, 64);
+        assert_eq!(pca.n_components(), 64);
+        let gram = &pca.components * pca.components.transpose();
+        assert!((gram - na::DMatrix::identity(64, 64)).abs().max() < 1e-3);
+    }
+
+    #[test]
+    fn test_preserve_variance() {
+        let descs = create_descriptors(300, 256, 32);
+        let pca = PCAProjector::fit(&descs, 64);
+        let (total, preserved) = descs.iter().fold((0.0, 0.0), |(total, preserved), desc| {
+            let projected = pca.project(desc);
+            assert_eq!(projected.len(), 64);
+            let reconstructed = pca.reconstruct(&projected);
+            let diff = |vec: &[f32]| -> f32 {
+                vec.iter()
+                    .zip(pca.mean.iter())
+                    .map(|(v, m)| (v - m) * (v - m))
+                    .sum()
+            };
+            (total + diff(desc), preserved + diff(&reconstructed))
+        });
+        assert!(preserved / total > 0.9, "ratio = {}", preserved / total);
+    }
+}
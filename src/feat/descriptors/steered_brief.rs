@@ -2,6 +2,7 @@ use image::{GrayImage, Luma, Pixel};
 use nalgebra::Point2;
 
 use crate::{
+    config::BriefConfig,
     feat::keypoints::KeyPoint,
     imgproc::median_filter,
     linalg::{get_rotation_matrix, warp_point},
@@ -49,6 +50,15 @@ impl SteeredBrief {
             rotated_binary_pairs,
         }
     }
+
+    pub fn from_config(cfg: &BriefConfig) -> Self {
+        Self::new(
+            cfg.patch_size,
+            cfg.median_kernel_size,
+            cfg.n_binary_test,
+            cfg.n_discrete,
+        )
+    }
 }
 
 impl Extractor<BriefDescriptor> for SteeredBrief {
@@ -1,8 +1,10 @@
 //! Implementation of FAST corner detector.
+use std::ops::Range;
+
 use image::GrayImage;
 use nalgebra::Point2;
 
-use crate::imgproc::nms;
+use crate::{config::FASTConfig, imgproc::nms};
 
 use super::{KeyPoint, KeypointDetector};
 
@@ -74,6 +76,16 @@ impl FASTCornerDetector {
         }
     }
 
+    pub fn from_config(cfg: &FASTConfig) -> Self {
+        Self::new(
+            cfg.radius,
+            cfg.threshold,
+            cfg.n_pyramid,
+            cfg.pyramid_scale,
+            cfg.use_nms,
+        )
+    }
+
     /// calc the keypoint's direction in radians.
     fn calc_direction(&self, raw: &Vec<u8>, w: usize, cx: usize, cy: usize) -> f32 {
         let mut m10 = 0;
@@ -90,26 +102,98 @@ impl FASTCornerDetector {
     }
 }
 
-impl KeypointDetector for FASTCornerDetector {
-    fn detect(&self, image: &GrayImage, level: u32) -> Vec<KeyPoint> {
-        let mut key_points = Vec::<KeyPoint>::new();
-        let raw = image.as_raw();
+/// Builder of `FASTCornerDetector`. Parameters not set are the same as `FASTConfig::default()`.
+pub struct FASTBuilder {
+    radius: u32,
+    threshold: f32,
+    n_pyramid: u32,
+    pyramid_scale: f32,
+    use_nms: bool,
+}
+
+impl Default for FASTBuilder {
+    fn default() -> Self {
+        let cfg = FASTConfig::default();
+        FASTBuilder {
+            radius: cfg.radius,
+            threshold: cfg.threshold,
+            n_pyramid: cfg.n_pyramid,
+            pyramid_scale: cfg.pyramid_scale,
+            use_nms: cfg.use_nms,
+        }
+    }
+}
 
-        if level + 1 < self.n_pyramid {
-            let resized_w = (image.width() as f32 / self.pyramid_scale) as u32;
-            let resized_h = (image.height() as f32 / self.pyramid_scale) as u32;
-            let resized_raw = crate::imgproc::resize(&image, resized_w, resized_h);
-            let resized_image =
-                image::GrayImage::from_raw(resized_w, resized_h, resized_raw).unwrap();
-            let mut kpts = self.detect(&resized_image, level + 1);
-            key_points.append(&mut kpts);
+impl FASTBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Radius of the circle on which pixels are compared with the center pixel.
+    pub fn radius(mut self, radius: u32) -> Self {
+        self.radius = radius;
+        self
+    }
+
+    /// Threshold of the corner response.
+    pub fn threshold(mut self, threshold: f32) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Number of pyramid levels in which corners are detected.
+    pub fn pyramid_levels(mut self, n_pyramid: u32) -> Self {
+        self.n_pyramid = n_pyramid;
+        self
+    }
+
+    /// Scale factor between the adjacent pyramid levels.
+    pub fn pyramid_scale(mut self, pyramid_scale: f32) -> Self {
+        self.pyramid_scale = pyramid_scale;
+        self
+    }
+
+    /// Apply non maximum suppression to the detected corners.
+    pub fn with_nms(mut self, use_nms: bool) -> Self {
+        self.use_nms = use_nms;
+        self
+    }
+
+    pub fn build(self) -> FASTCornerDetector {
+        FASTCornerDetector::new(
+            self.radius,
+            self.threshold,
+            self.n_pyramid,
+            self.pyramid_scale,
+            self.use_nms,
+        )
+    }
+}
+
+impl FASTCornerDetector {
+    /// Detect corners in the pyramid image of `level + 1` (if exists) by `detect_func`.
+    fn detect_next_level<F>(&self, image: &GrayImage, level: u32, detect_func: F) -> Vec<KeyPoint>
+    where
+        F: Fn(&GrayImage, u32) -> Vec<KeyPoint>,
+    {
+        if level + 1 >= self.n_pyramid {
+            return vec![];
         }
+        let resized_w = (image.width() as f32 / self.pyramid_scale) as u32;
+        let resized_h = (image.height() as f32 / self.pyramid_scale) as u32;
+        let resized_raw = crate::imgproc::resize(image, resized_w, resized_h);
+        let resized_image = image::GrayImage::from_raw(resized_w, resized_h, resized_raw).unwrap();
+        detect_func(&resized_image, level + 1)
+    }
 
+    /// Detect corners whose y coordinates are in `rows`.
+    fn detect_rows(&self, image: &GrayImage, level: u32, rows: Range<usize>) -> Vec<KeyPoint> {
+        let mut key_points = Vec::<KeyPoint>::new();
+        let raw = image.as_raw();
         let w = image.width() as usize;
-        let h = image.height() as usize;
         let radius = self.radius as usize;
         let pt_offset = self.circle_points.len() / 2;
-        for y in radius..h - radius {
+        for y in rows {
             for x in radius..w - radius {
                 let c = raw[(y * w + x) as usize] as f32;
                 // rough test
@@ -143,19 +227,59 @@ impl KeypointDetector for FASTCornerDetector {
                 }
             }
         }
+        key_points
+    }
+
+    /// Apply NMS (if `use_nms`) or sort `key_points` by the corner response.
+    fn suppress(&self, mut key_points: Vec<KeyPoint>) -> Vec<KeyPoint> {
         if self.use_nms {
-            let key_points = nms(&key_points, self.radius * 2 + 1);
-            return key_points;
+            return nms(&key_points, self.radius * 2 + 1, self.pyramid_scale);
         }
         key_points.sort_by(|lhs, rhs| lhs.crf().partial_cmp(&rhs.crf()).unwrap());
         key_points
     }
 }
 
+#[cfg(feature = "parallel")]
+impl FASTCornerDetector {
+    /// Parallel version of `detect`. Rows of the image are split into chunks and each chunk is
+    /// processed in parallel. Return the same keypoints as `detect`.
+    pub fn detect_parallel(&self, image: &GrayImage, level: u32) -> Vec<KeyPoint> {
+        use rayon::prelude::*;
+
+        let mut key_points =
+            self.detect_next_level(image, level, |img, lv| self.detect_parallel(img, lv));
+        let radius = self.radius as usize;
+        let rows: Vec<usize> = (radius..image.height() as usize - radius).collect();
+        let chunk_size = (rows.len() / rayon::current_num_threads()).max(1);
+        let mut kpts: Vec<KeyPoint> = rows
+            .par_chunks(chunk_size)
+            .flat_map_iter(|chunk| {
+                self.detect_rows(image, level, chunk[0]..chunk[chunk.len() - 1] + 1)
+            })
+            .collect();
+        key_points.append(&mut kpts);
+        self.suppress(key_points)
+    }
+}
+
+impl KeypointDetector for FASTCornerDetector {
+    fn detect(&self, image: &GrayImage, level: u32) -> Vec<KeyPoint> {
+        let mut key_points = self.detect_next_level(image, level, |img, lv| self.detect(img, lv));
+        let radius = self.radius as usize;
+        let mut kpts = self.detect_rows(image, level, radius..image.height() as usize - radius);
+        key_points.append(&mut kpts);
+        self.suppress(key_points)
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{calc_crf, FASTCornerDetector};
-    use crate::feat::keypoints::KeypointDetector;
+    use super::{calc_crf, create_circle, FASTBuilder, FASTCornerDetector};
+    use crate::{
+        config::{load_config, FASTConfig},
+        feat::keypoints::KeypointDetector,
+    };
 
     #[test]
     fn fast_detect() {
@@ -352,4 +476,78 @@ mod tests {
             dir
         );
     }
+
+    #[test]
+    fn test_from_config() {
+        let path = std::env::temp_dir().join("improc_test_fast_from_config.json");
+        std::fs::write(
+            &path,
+            r#"{"fast": {"radius": 5, "threshold": 100.0, "n_pyramid": 4, "use_nms": false}}"#,
+        )
+        .unwrap();
+        let config = load_config(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let fast = FASTCornerDetector::from_config(&config.fast);
+        assert_eq!(fast.radius, 5);
+        assert_eq!(fast.threshold, 100.0);
+        assert_eq!(fast.n_pyramid, 4);
+        assert_eq!(fast.pyramid_scale, FASTConfig::default().pyramid_scale);
+        assert!(!fast.use_nms);
+        assert_eq!(fast.circle_points, create_circle(5));
+    }
+
+    #[test]
+    fn test_builder() {
+        let default = FASTConfig::default();
+        let fast = FASTBuilder::new().threshold(20.0).build();
+        assert_eq!(fast.threshold, 20.0);
+        assert_eq!(fast.radius, default.radius);
+        assert_eq!(fast.n_pyramid, default.n_pyramid);
+        assert_eq!(fast.pyramid_scale, default.pyramid_scale);
+        assert_eq!(fast.use_nms, default.use_nms);
+
+        let fast = FASTBuilder::new()
+            .radius(5)
+            .pyramid_levels(2)
+            .pyramid_scale(2.0)
+            .with_nms(false)
+            .build();
+        assert_eq!(fast.radius, 5);
+        assert_eq!(fast.threshold, default.threshold);
+        assert_eq!(fast.n_pyramid, 2);
+        assert_eq!(fast.pyramid_scale, 2.0);
+        assert!(!fast.use_nms);
+        assert_eq!(fast.circle_points, create_circle(5));
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_detect_parallel() {
+        let img = image::GrayImage::from_fn(200, 160, |x, y| {
+            if ((x / 20) + (y / 20)) % 2 == 0 {
+                image::Luma([255u8])
+            } else {
+                image::Luma([0u8])
+            }
+        });
+        let fast = FASTBuilder::new()
+            .threshold(10.0)
+            .pyramid_levels(3)
+            .with_nms(false)
+            .build();
+        let sort_key = |kpt: &crate::feat::keypoints::KeyPoint| {
+            (kpt.loc.x as usize, kpt.loc.y as usize, kpt.level())
+        };
+        let mut expect = fast.detect(&img, 0);
+        let mut res = fast.detect_parallel(&img, 0);
+        assert!(!expect.is_empty());
+        expect.sort_by_key(sort_key);
+        res.sort_by_key(sort_key);
+        assert_eq!(res.len(), expect.len());
+        res.iter().zip(expect.iter()).for_each(|(lhs, rhs)| {
+            assert_eq!(sort_key(lhs), sort_key(rhs));
+            assert_eq!(lhs.crf(), rhs.crf());
+        });
+    }
 }
@@ -8,7 +8,6 @@ pub mod fast;
 pub struct KeyPoint {
     loc: Point2<f32>,
     cornerness: f32,
-    #[allow(dead_code)]
     image_pyramid_level: u32,
     direction: f32,
 }
@@ -37,6 +36,11 @@ impl KeyPoint {
         self.cornerness
     }
 
+    /// return level of the image pyramid where the key point is detected
+    pub fn level(&self) -> u32 {
+        self.image_pyramid_level
+    }
+
     /// return direction of the key point in radian
     pub fn direction(&self) -> f32 {
         self.direction
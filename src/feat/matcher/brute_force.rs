@@ -1,4 +1,7 @@
-use crate::feat::{descriptors::Descriptor, Distance};
+use crate::{
+    config::MatcherConfig,
+    feat::{descriptors::Descriptor, Distance},
+};
 
 use super::{Match, Matcher};
 
@@ -24,34 +27,47 @@ where
             allow_duplicate,
         }
     }
-}
 
-impl<T> Matcher<T> for BruteForceMathcer<T>
-where
-    T: Distance + Clone,
-{
-    fn run(&self) -> Vec<Match<T>> {
+    pub fn from_config(
+        lhs_descs: Vec<Descriptor<T>>,
+        rhs_descs: Vec<Descriptor<T>>,
+        cfg: &MatcherConfig,
+    ) -> Self {
+        Self::new(lhs_descs, rhs_descs, cfg.allow_duplicate)
+    }
+
+    /// Same as `run` but return pairs of (lhs index, rhs index) instead of `Match`.
+    /// Pairs of the descriptors which are not comparable (see `Distance::is_comparable`) are
+    /// never matched.
+    pub fn run_indices(&self) -> Vec<(usize, usize)> {
         let lhs_descs = &self.descriptors.0;
         let rhs_descs = &self.descriptors.1;
 
         // vector of tuple : (distance, lhs_idx, rhs_idx)
         let mut dists: Vec<(f32, usize, usize)> =
             Vec::with_capacity(lhs_descs.len() * rhs_descs.len());
-        for li in 0..lhs_descs.len() {
-            for ri in 0..rhs_descs.len() {
-                let dist = lhs_descs[li].distance(&rhs_descs[ri]);
-                dists.push((dist, li, ri));
+        for (li, lhs) in lhs_descs.iter().enumerate() {
+            for (ri, rhs) in rhs_descs.iter().enumerate() {
+                if !lhs.value.is_comparable(&rhs.value) {
+                    continue;
+                }
+                dists.push((lhs.distance(rhs), li, ri));
             }
         }
+        self.select_indices(dists)
+    }
+
+    /// Select matches from `dists` (vector of tuple : (distance, lhs_idx, rhs_idx)).
+    fn select_indices(&self, mut dists: Vec<(f32, usize, usize)>) -> Vec<(usize, usize)> {
         dists.sort_by(|l, r| l.0.partial_cmp(&r.0).unwrap());
 
-        let mut matches = Vec::new();
-        let mut lflag: Vec<bool> = vec![true; lhs_descs.len()];
-        let mut rflag: Vec<bool> = vec![true; rhs_descs.len()];
+        let mut indices = Vec::new();
+        let mut lflag: Vec<bool> = vec![true; self.descriptors.0.len()];
+        let mut rflag: Vec<bool> = vec![true; self.descriptors.1.len()];
         for m in dists {
             // println!("lhs_idx = {}, rhs_idx = {}", m.1, m.2);
             if lflag[m.1] && rflag[m.2] {
-                matches.push(Match::new(&lhs_descs[m.1], &rhs_descs[m.2]));
+                indices.push((m.1, m.2));
                 if self.allow_duplicate {
                     lflag[m.1] = false;
                     rflag[m.2] = false;
@@ -62,7 +78,50 @@ where
                 rflag[m.2] = false;
             }
         }
-        matches
+        indices
+    }
+
+    fn to_matches(&self, indices: &[(usize, usize)]) -> Vec<Match<T>> {
+        indices
+            .iter()
+            .map(|(li, ri)| Match::new(&self.descriptors.0[*li], &self.descriptors.1[*ri]))
+            .collect()
+    }
+}
+
+#[cfg(feature = "parallel")]
+impl<T> BruteForceMathcer<T>
+where
+    T: Distance + Clone + Send + Sync,
+{
+    /// Parallel version of `run`. Distances from each lhs descriptor are calculated in parallel.
+    /// Return the same matches in the same order as `run`.
+    pub fn run_parallel(&self) -> Vec<Match<T>> {
+        use rayon::prelude::*;
+
+        let lhs_descs = &self.descriptors.0;
+        let rhs_descs = &self.descriptors.1;
+        let dists: Vec<(f32, usize, usize)> = lhs_descs
+            .par_iter()
+            .enumerate()
+            .flat_map_iter(|(li, lhs)| {
+                rhs_descs
+                    .iter()
+                    .enumerate()
+                    .filter(move |(_, rhs)| lhs.value.is_comparable(&rhs.value))
+                    .map(move |(ri, rhs)| (lhs.distance(rhs), li, ri))
+            })
+            .collect();
+        self.to_matches(&self.select_indices(dists))
+    }
+}
+
+impl<T> Matcher<T> for BruteForceMathcer<T>
+where
+    T: Distance + Clone,
+{
+    fn run(&self) -> Vec<Match<T>> {
+        self.to_matches(&self.run_indices())
     }
 }
 
@@ -70,7 +129,7 @@ where
 mod tests {
     use bitvec::prelude::*;
 
-    use crate::feat::keypoints::KeyPoint;
+    use crate::feat::{descriptors::BriefDescriptor, keypoints::KeyPoint};
 
     use super::*;
 
@@ -128,4 +187,71 @@ mod tests {
         assert_eq!(matches[2].matche.1.kpt.x() as usize, 2);
         assert_eq!(matches[2].matche.1.kpt.y() as usize, 2);
     }
+
+    #[test]
+    fn test_float_descriptor_matcher() {
+        let create_descs = |values: &[[f32; 2]]| -> Vec<Descriptor<Vec<f32>>> {
+            values
+                .iter()
+                .enumerate()
+                .map(|(i, val)| Descriptor {
+                    kpt: KeyPoint::new(i, i, 0.0f32, 0, 0.0),
+                    value: val.to_vec(),
+                })
+                .collect()
+        };
+        let lhs_descs = create_descs(&[[0.0, 0.0], [10.0, 0.0], [0.0, 10.0]]);
+        let rhs_descs = create_descs(&[[0.5, 9.0], [0.1, -0.2], [9.0, 1.0]]);
+        let matcher = BruteForceMathcer::new(lhs_descs, rhs_descs, false);
+        let mut indices = matcher.run_indices();
+        indices.sort_unstable();
+        assert_eq!(indices, vec![(0, 1), (1, 2), (2, 0)]);
+    }
+
+    #[test]
+    fn test_mismatched_descriptor_length() {
+        let create_desc = |i: usize, n_bits: usize| {
+            let mut value = BriefDescriptor::new(n_bits);
+            (0..n_bits).for_each(|_| value.push(true));
+            Descriptor {
+                kpt: KeyPoint::new(i, i, 0.0f32, 0, 0.0),
+                value,
+            }
+        };
+        let lhs_descs = vec![create_desc(0, 64), create_desc(1, 128)];
+        let rhs_descs = vec![create_desc(0, 128), create_desc(1, 256)];
+        let matcher = BruteForceMathcer::new(lhs_descs, rhs_descs, false);
+        assert_eq!(matcher.run_indices(), vec![(1, 0)]);
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_brute_force_matcher_parallel() {
+        use rand::Rng;
+
+        let mut rng = rand::thread_rng();
+        let n_bits = 64;
+        let mut create_descs = |n: usize| -> Vec<Descriptor<BitVec>> {
+            (0..n)
+                .map(|i| Descriptor::<BitVec> {
+                    kpt: KeyPoint::new(i, i, 0.0f32, 0, 0.0),
+                    value: (0..n_bits).map(|_| rng.gen::<bool>()).collect(),
+                })
+                .collect()
+        };
+        let lhs_descs = create_descs(200);
+        let rhs_descs = create_descs(150);
+
+        for allow_duplicate in [false, true] {
+            let matcher =
+                BruteForceMathcer::new(lhs_descs.clone(), rhs_descs.clone(), allow_duplicate);
+            let matches = matcher.run();
+            let par_matches = matcher.run_parallel();
+            assert_eq!(matches.len(), par_matches.len());
+            matches.iter().zip(par_matches.iter()).for_each(|(m, pm)| {
+                assert_eq!(m.matche.0.kpt.x(), pm.matche.0.kpt.x());
+                assert_eq!(m.matche.1.kpt.x(), pm.matche.1.kpt.x());
+            });
+        }
+    }
 }
@@ -0,0 +1,190 @@
+//! Approximate nearest neighbor search of the binary descriptors by clustering.
+use rand::seq::index::sample;
+
+use crate::feat::{descriptors::BriefDescriptor, Distance};
+
+const MAX_ITERATION: usize = 20;
+/// Number of the nearest clusters searched in `query`.
+const N_PROBE: usize = 3;
+
+/// Index of the binary descriptors clustered by k-means (k-majority) with the hamming distance.
+/// Nearest neighbors are searched only in the clusters whose centers are near to the query.
+pub struct DescriptorIndex {
+    centers: Vec<BriefDescriptor>,
+    /// Indices of the descriptors belonging to each cluster.
+    bins: Vec<Vec<usize>>,
+    descriptors: Vec<BriefDescriptor>,
+}
+
+impl DescriptorIndex {
+    /// Cluster `descs` into `n_clusters` clusters (see `k_majority`).
+    pub fn build(descs: &[BriefDescriptor], n_clusters: usize) -> Self {
+        let refs: Vec<&BriefDescriptor> = descs.iter().collect();
+        let (centers, assignments) = k_majority(&refs, n_clusters);
+        let mut bins = vec![vec![]; centers.len()];
+        assignments
+            .iter()
+            .enumerate()
+            .for_each(|(idx, &ci)| bins[ci].push(idx));
+        DescriptorIndex {
+            centers,
+            bins,
+            descriptors: descs.to_vec(),
+        }
+    }
+
+    /// Return indices of the (approximate) `k` nearest neighbors of `query` sorted by distance.
+    /// `N_PROBE` nearest clusters are searched (more clusters are searched if they contain less
+    /// than `k` descriptors).
+    pub fn query(&self, query: &BriefDescriptor, k: usize) -> Vec<usize> {
+        let mut candidates: Vec<(f32, usize)> = Vec::new();
+        for (n_probed, ci) in nearest_centers(&self.centers, query, self.centers.len())
+            .into_iter()
+            .enumerate()
+        {
+            if n_probed >= N_PROBE && candidates.len() >= k {
+                break;
+            }
+            candidates.extend(
+                self.bins[ci]
+                    .iter()
+                    .map(|&idx| (self.descriptors[idx].distance(query), idx)),
+            );
+        }
+        candidates.sort_by(|lhs, rhs| lhs.partial_cmp(rhs).unwrap());
+        candidates.iter().take(k).map(|(_, idx)| *idx).collect()
+    }
+}
+
+/// Cluster `descs` into `n_clusters` clusters by k-majority (k-means with the hamming distance).
+/// Centers are initialized by the randomly selected descriptors and updated by the bitwise
+/// majority vote of the members.
+/// Return tuple of (centers, index of the cluster of each descriptor).
+pub(super) fn k_majority(
+    descs: &[&BriefDescriptor],
+    n_clusters: usize,
+) -> (Vec<BriefDescriptor>, Vec<usize>) {
+    if descs.is_empty() {
+        return (vec![], vec![]);
+    }
+    let n_clusters = n_clusters.clamp(1, descs.len());
+    let mut rng = rand::thread_rng();
+    let mut centers: Vec<BriefDescriptor> = sample(&mut rng, descs.len(), n_clusters)
+        .iter()
+        .map(|idx| descs[idx].clone())
+        .collect();
+
+    let mut assignments = vec![usize::MAX; descs.len()];
+    for _ in 0..MAX_ITERATION {
+        let new_assignments: Vec<usize> = descs
+            .iter()
+            .map(|desc| nearest_centers(&centers, desc, 1)[0])
+            .collect();
+        if new_assignments == assignments {
+            break;
+        }
+        assignments = new_assignments;
+        let mut members: Vec<Vec<&BriefDescriptor>> = vec![vec![]; centers.len()];
+        descs
+            .iter()
+            .zip(&assignments)
+            .for_each(|(desc, &ci)| members[ci].push(desc));
+        centers
+            .iter_mut()
+            .zip(&members)
+            .filter(|(_, members)| !members.is_empty())
+            .for_each(|(center, members)| *center = majority(members));
+    }
+    (centers, assignments)
+}
+
+/// Return indices of the `n` nearest centers to `desc`.
+pub(super) fn nearest_centers(
+    centers: &[BriefDescriptor],
+    desc: &BriefDescriptor,
+    n: usize,
+) -> Vec<usize> {
+    let mut dists: Vec<(f32, usize)> = centers
+        .iter()
+        .enumerate()
+        .map(|(idx, center)| (center.distance(desc), idx))
+        .collect();
+    dists.sort_by(|lhs, rhs| lhs.partial_cmp(rhs).unwrap());
+    dists.iter().take(n).map(|(_, idx)| *idx).collect()
+}
+
+/// Bitwise majority vote of `members`.
+fn majority(members: &[&BriefDescriptor]) -> BriefDescriptor {
+    let n_bits = members[0].len();
+    let mut center = BriefDescriptor::new(n_bits);
+    (0..n_bits).for_each(|bit| {
+        let n_ones = members.iter().filter(|desc| desc[bit]).count();
+        center.push(n_ones * 2 > members.len());
+    });
+    center
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use super::*;
+
+    const N_BITS: usize = 256;
+
+    fn create_descriptor<R: Rng>(rng: &mut R, base: &[bool], flip_prob: f64) -> BriefDescriptor {
+        let mut desc = BriefDescriptor::new(N_BITS);
+        base.iter()
+            .for_each(|&bit| desc.push(bit ^ rng.gen_bool(flip_prob)));
+        desc
+    }
+
+    #[test]
+    fn test_query() {
+        let mut rng = rand::thread_rng();
+        let prototypes: Vec<Vec<bool>> = (0..20)
+            .map(|_| (0..N_BITS).map(|_| rng.gen::<bool>()).collect())
+            .collect();
+        let descs: Vec<BriefDescriptor> = (0..1000)
+            .map(|i| create_descriptor(&mut rng, &prototypes[i % prototypes.len()], 0.1))
+            .collect();
+        let index = DescriptorIndex::build(&descs, 20);
+        assert_eq!(
+            index.bins.iter().map(|bin| bin.len()).sum::<usize>(),
+            descs.len()
+        );
+
+        let n_query = 200;
+        let n_correct = (0..n_query)
+            .filter(|i| {
+                let query = create_descriptor(&mut rng, &prototypes[i % prototypes.len()], 0.1);
+                let nearest = descs
+                    .iter()
+                    .map(|desc| desc.distance(&query))
+                    .fold(f32::MAX, f32::min);
+                let res = index.query(&query, 3);
+                assert_eq!(res.len(), 3);
+                descs[res[0]].distance(&query) == nearest
+            })
+            .count();
+        assert!(
+            n_correct as f32 > 0.95 * n_query as f32,
+            "n_correct = {}",
+            n_correct
+        );
+    }
+
+    #[test]
+    fn test_query_small() {
+        let mut rng = rand::thread_rng();
+        let base: Vec<bool> = (0..N_BITS).map(|_| rng.gen::<bool>()).collect();
+        let descs: Vec<BriefDescriptor> = (0..5)
+            .map(|_| create_descriptor(&mut rng, &base, 0.3))
+            .collect();
+        // number of clusters is larger than the number of descriptors
+        let index = DescriptorIndex::build(&descs, 10);
+        assert_eq!(index.centers.len(), 5);
+        assert_eq!(index.query(&descs[2], 1), vec![2]);
+        assert_eq!(index.query(&descs[2], 10).len(), 5);
+    }
+}
@@ -0,0 +1,191 @@
+//! Accumulate feature correspondences across frames.
+use crate::feat::{
+    descriptors::{BriefDescriptor, Descriptor},
+    keypoints::KeyPoint,
+    Distance,
+};
+
+use super::brute_force::BruteForceMathcer;
+
+/// Observations of the same feature in consecutive frames.
+#[derive(Clone)]
+pub struct Track {
+    /// Vector of tuple : (frame index, keypoint, descriptor). Sorted by frame index.
+    pub observations: Vec<(usize, KeyPoint, BriefDescriptor)>,
+}
+
+impl Track {
+    fn new(frame_idx: usize, kpt: KeyPoint, desc: BriefDescriptor) -> Self {
+        Track {
+            observations: vec![(frame_idx, kpt, desc)],
+        }
+    }
+
+    /// Return the number of frames in which the feature is observed.
+    pub fn age(&self) -> usize {
+        self.observations.len()
+    }
+
+    /// Return the frame index of the latest observation.
+    pub fn last_frame(&self) -> usize {
+        self.observations.last().unwrap().0
+    }
+
+    /// Return the latest observation.
+    pub fn last(&self) -> &(usize, KeyPoint, BriefDescriptor) {
+        self.observations.last().unwrap()
+    }
+}
+
+/// Maintain feature tracks over a sliding window of `max_age` frames.
+pub struct FeatureTracker {
+    max_age: usize,
+    max_distance: f32,
+    tracks: Vec<Track>,
+}
+
+impl FeatureTracker {
+    /// - `max_age` : size of the sliding window. Observations older than `max_age` frames are
+    ///   removed and tracks which are not observed in the window are pruned.
+    /// - `max_distance` : maximum descriptor distance to extend a track.
+    pub fn new(max_age: usize, max_distance: f32) -> Self {
+        FeatureTracker {
+            max_age: max_age.max(1),
+            max_distance,
+            tracks: vec![],
+        }
+    }
+
+    pub fn tracks(&self) -> &[Track] {
+        &self.tracks
+    }
+
+    /// Add observations of the frame `frame_idx`. `kpts[i]` and `descs[i]` must be the same feature.
+    /// Features matched with the tracks observed in the most recent frame extend these tracks and
+    /// the others create new tracks.
+    pub fn update(
+        &mut self,
+        frame_idx: usize,
+        kpts: &[KeyPoint],
+        descs: &[Descriptor<BriefDescriptor>],
+    ) -> &[Track] {
+        assert_eq!(kpts.len(), descs.len());
+
+        // tracks observed in the most recent frame.
+        let recent_frame = self.tracks.iter().map(|track| track.last_frame()).max();
+        let track_indices: Vec<usize> = match recent_frame {
+            Some(recent) => (0..self.tracks.len())
+                .filter(|idx| self.tracks[*idx].last_frame() == recent)
+                .collect(),
+            None => vec![],
+        };
+        let prev_descs: Vec<Descriptor<BriefDescriptor>> = track_indices
+            .iter()
+            .map(|idx| {
+                let (_, kpt, value) = self.tracks[*idx].last();
+                Descriptor {
+                    kpt: *kpt,
+                    value: value.clone(),
+                }
+            })
+            .collect();
+
+        let mut is_matched = vec![false; descs.len()];
+        if !prev_descs.is_empty() && !descs.is_empty() {
+            let matcher = BruteForceMathcer::new(prev_descs, descs.to_vec(), true);
+            for (ti, di) in matcher.run_indices() {
+                let track = &mut self.tracks[track_indices[ti]];
+                if track.last().2.distance(&descs[di].value) > self.max_distance {
+                    continue;
+                }
+                track
+                    .observations
+                    .push((frame_idx, kpts[di], descs[di].value.clone()));
+                is_matched[di] = true;
+            }
+        }
+        (0..descs.len())
+            .filter(|idx| !is_matched[*idx])
+            .for_each(|idx| {
+                self.tracks
+                    .push(Track::new(frame_idx, kpts[idx], descs[idx].value.clone()))
+            });
+
+        // remove observations out of the window and prune lost tracks.
+        let oldest = (frame_idx + 1).saturating_sub(self.max_age);
+        self.tracks.iter_mut().for_each(|track| {
+            track.observations.retain(|(fidx, _, _)| *fidx >= oldest);
+        });
+        self.tracks.retain(|track| !track.observations.is_empty());
+        &self.tracks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use super::*;
+
+    fn create_frame(n_features: usize) -> (Vec<KeyPoint>, Vec<Descriptor<BriefDescriptor>>) {
+        let mut rng = rand::thread_rng();
+        let kpts: Vec<KeyPoint> = (0..n_features)
+            .map(|_| KeyPoint::new(rng.gen_range(0..640), rng.gen_range(0..480), 0.0, 0, 0.0))
+            .collect();
+        let descs = kpts
+            .iter()
+            .map(|kpt| {
+                let mut value = BriefDescriptor::new(256);
+                (0..256).for_each(|_| value.push(rng.gen::<bool>()));
+                Descriptor { kpt: *kpt, value }
+            })
+            .collect();
+        (kpts, descs)
+    }
+
+    #[test]
+    fn test_identical_frames() {
+        let n_features = 30;
+        let (kpts, descs) = create_frame(n_features);
+        let mut tracker = FeatureTracker::new(5, 10.0);
+        for frame_idx in 0..5 {
+            let tracks = tracker.update(frame_idx, &kpts, &descs);
+            assert_eq!(tracks.len(), n_features);
+        }
+        tracker.tracks().iter().for_each(|track| {
+            assert_eq!(track.age(), 5);
+            let (_, kpt, desc) = &track.observations[0];
+            track.observations.iter().for_each(|(_, k, d)| {
+                assert_eq!(k.x(), kpt.x());
+                assert_eq!(k.y(), kpt.y());
+                assert_eq!(d.distance(desc), 0.0);
+            });
+        });
+
+        // sliding window : age is limited by `max_age`.
+        tracker.update(5, &kpts, &descs);
+        assert_eq!(tracker.tracks().len(), n_features);
+        tracker.tracks().iter().for_each(|track| {
+            assert_eq!(track.age(), 5);
+            assert_eq!(track.observations[0].0, 1);
+        });
+    }
+
+    #[test]
+    fn test_new_and_lost_tracks() {
+        let (kpts0, descs0) = create_frame(20);
+        let (kpts1, descs1) = create_frame(10);
+        let mut tracker = FeatureTracker::new(3, 10.0);
+        tracker.update(0, &kpts0, &descs0);
+        // unrelated features create new tracks.
+        let tracks = tracker.update(1, &kpts1, &descs1);
+        assert_eq!(tracks.len(), 30);
+        assert!(tracks.iter().all(|track| track.age() == 1));
+
+        // tracks of the frame 0 are pruned after they get out of the window.
+        tracker.update(2, &kpts1, &descs1);
+        let tracks = tracker.update(3, &kpts1, &descs1);
+        assert_eq!(tracks.len(), 10);
+        assert!(tracks.iter().all(|track| track.age() == 3));
+    }
+}
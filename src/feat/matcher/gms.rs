@@ -0,0 +1,189 @@
+//! Grid-based Motion Statistics (GMS) filter of matches.
+//! See Bian et al., "GMS: Grid-based Motion Statistics for Fast, Ultra-robust Feature Correspondence".
+use crate::{ensure, error::Result, feat::descriptors::BriefDescriptor};
+
+use super::Match;
+
+/// Remove matches which are not supported by the neighboring matches.
+/// Both images are divided into `grid_cells` (= (cols, rows)) cells and matches are counted for
+/// each pair of (lhs cell, rhs cell). The score of a match is the number of matches in the 3x3
+/// neighborhood of its cell pair (the neighbors are shifted by the same offset in both images)
+/// divided by the expected number of matches when matches are random.
+/// A match is kept if the score exceeds `threshold`.
+/// - `img0_size`, `img1_size` : (width, height) of lhs and rhs images (pyramid level 0).
+/// - `scale_factor` : scale between the adjacent pyramid levels. Keypoint coordinates are in the
+///   image of their pyramid level and are scaled to level 0.
+///
+/// Return error if `grid_cells` has zero component.
+pub fn gms_filter(
+    matches: &[Match<BriefDescriptor>],
+    img0_size: (u32, u32),
+    img1_size: (u32, u32),
+    grid_cells: (u32, u32),
+    scale_factor: f32,
+    threshold: f32,
+) -> Result<Vec<&Match<BriefDescriptor>>> {
+    ensure!(
+        grid_cells.0 > 0 && grid_cells.1 > 0,
+        "Number of grid cells must be positive : {:?}",
+        grid_cells
+    );
+    let (cols, rows) = (grid_cells.0 as usize, grid_cells.1 as usize);
+    let n_cells = cols * rows;
+    let cell_index = |x: f32, y: f32, size: (u32, u32)| -> (usize, usize) {
+        let cx = (x / size.0 as f32 * cols as f32).floor().max(0.0) as usize;
+        let cy = (y / size.1 as f32 * rows as f32).floor().max(0.0) as usize;
+        (cx.min(cols - 1), cy.min(rows - 1))
+    };
+    let cells: Vec<((usize, usize), (usize, usize))> = matches
+        .iter()
+        .map(|m| {
+            let (lhs, rhs) = (&m.matche.0.kpt, &m.matche.1.kpt);
+            let (s0, s1) = (
+                scale_factor.powi(lhs.level() as i32),
+                scale_factor.powi(rhs.level() as i32),
+            );
+            (
+                cell_index(lhs.x() * s0, lhs.y() * s0, img0_size),
+                cell_index(rhs.x() * s1, rhs.y() * s1, img1_size),
+            )
+        })
+        .collect();
+
+    // number of matches of each cell pair and of each cell.
+    let mut pair_counts = vec![0usize; n_cells * n_cells];
+    let mut lhs_counts = vec![0usize; n_cells];
+    let mut rhs_counts = vec![0usize; n_cells];
+    cells.iter().for_each(|((x0, y0), (x1, y1))| {
+        let (c0, c1) = (y0 * cols + x0, y1 * cols + x1);
+        pair_counts[c0 * n_cells + c1] += 1;
+        lhs_counts[c0] += 1;
+        rhs_counts[c1] += 1;
+    });
+
+    let n_matches = matches.len() as f32;
+    let inliers = matches
+        .iter()
+        .zip(cells.iter())
+        .filter(|(_, ((x0, y0), (x1, y1)))| {
+            let mut count = 0;
+            let mut expected = 0.0;
+            for dy in -1..=1 {
+                for dx in -1..=1 {
+                    let shift = |x: &usize, y: &usize| -> Option<usize> {
+                        let (x, y) = (*x as i32 + dx, *y as i32 + dy);
+                        if x < 0 || y < 0 || x >= cols as i32 || y >= rows as i32 {
+                            None
+                        } else {
+                            Some(y as usize * cols + x as usize)
+                        }
+                    };
+                    if let (Some(c0), Some(c1)) = (shift(x0, y0), shift(x1, y1)) {
+                        count += pair_counts[c0 * n_cells + c1];
+                        expected += (lhs_counts[c0] * rhs_counts[c1]) as f32 / n_matches;
+                    }
+                }
+            }
+            count as f32 / expected > threshold
+        })
+        .map(|(m, _)| m)
+        .collect();
+    Ok(inliers)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use crate::feat::{descriptors::Descriptor, keypoints::KeyPoint};
+
+    use super::*;
+
+    fn create_match(x0: f32, y0: f32, x1: f32, y1: f32) -> Match<BriefDescriptor> {
+        create_match_with_level(x0, y0, x1, y1, 0)
+    }
+
+    /// Rhs keypoint is detected in the pyramid level `level1` (scale factor is 2).
+    fn create_match_with_level(
+        x0: f32,
+        y0: f32,
+        x1: f32,
+        y1: f32,
+        level1: u32,
+    ) -> Match<BriefDescriptor> {
+        let create_desc = |x: f32, y: f32, level: u32| Descriptor {
+            kpt: KeyPoint::new(x.round() as usize, y.round() as usize, 0.0, level, 0.0),
+            value: BriefDescriptor::new(256),
+        };
+        let s1 = 2.0f32.powi(level1 as i32);
+        Match::new(
+            &create_desc(x0, y0, 0),
+            &create_desc(x1 / s1, y1 / s1, level1),
+        )
+    }
+
+    #[test]
+    fn test_gms_filter() {
+        let mut rng = rand::thread_rng();
+        let (width, height) = (640.0, 480.0);
+        let (n_true, n_false) = (2000, 500);
+        let (scale, theta, tx, ty) = (0.9f32, 0.05f32, 40.0, 20.0);
+
+        let mut matches = vec![];
+        while matches.len() < n_true {
+            let (x0, y0) = (rng.gen::<f32>() * width, rng.gen::<f32>() * height);
+            let x1 = scale * (theta.cos() * x0 - theta.sin() * y0) + tx + rng.gen::<f32>() - 0.5;
+            let y1 = scale * (theta.sin() * x0 + theta.cos() * y0) + ty + rng.gen::<f32>() - 0.5;
+            if x1 >= 0.0 && y1 >= 0.0 && x1 < width && y1 < height {
+                let level1 = (matches.len() % 2) as u32;
+                matches.push(create_match_with_level(x0, y0, x1, y1, level1));
+            }
+        }
+        (0..n_false).for_each(|_| {
+            matches.push(create_match(
+                rng.gen::<f32>() * width,
+                rng.gen::<f32>() * height,
+                rng.gen::<f32>() * width,
+                rng.gen::<f32>() * height,
+            ));
+        });
+
+        let inliers = gms_filter(
+            &matches,
+            (width as u32, height as u32),
+            (width as u32, height as u32),
+            (10, 10),
+            2.0,
+            5.0,
+        )
+        .unwrap();
+        let n_kept_true = inliers
+            .iter()
+            .filter(|m| matches[..n_true].iter().any(|t| std::ptr::eq(t, **m)))
+            .count();
+        let n_kept_false = inliers.len() - n_kept_true;
+        assert!(
+            n_kept_true as f32 > 0.9 * n_true as f32,
+            "true matches kept : {}",
+            n_kept_true
+        );
+        assert!(
+            (n_kept_false as f32) < 0.2 * n_false as f32,
+            "false matches kept : {}",
+            n_kept_false
+        );
+    }
+
+    #[test]
+    fn test_gms_filter_empty() {
+        let inliers = gms_filter(&[], (640, 480), (640, 480), (10, 10), 2.0, 5.0).unwrap();
+        assert!(inliers.is_empty());
+    }
+
+    #[test]
+    fn test_gms_filter_zero_grid_cells() {
+        let matches = vec![create_match(10.0, 10.0, 20.0, 20.0)];
+        assert!(gms_filter(&matches, (640, 480), (640, 480), (0, 10), 2.0, 5.0).is_err());
+        assert!(gms_filter(&matches, (640, 480), (640, 480), (10, 0), 2.0, 5.0).is_err());
+    }
+}
@@ -0,0 +1,177 @@
+//! Hierarchical k-means tree for the approximate nearest neighbor search of the binary
+//! descriptors.
+use std::{cmp::Reverse, collections::BinaryHeap};
+
+use crate::feat::{descriptors::BriefDescriptor, Distance};
+
+use super::descriptor_index::k_majority;
+
+/// Number of the children of each node.
+const BRANCHING: usize = 16;
+/// Node which has descriptors less than or equal to this value becomes a leaf.
+const MAX_LEAF_SIZE: usize = 32;
+
+enum HKMNode {
+    /// Indices of the descriptors.
+    Leaf(Vec<usize>),
+    Branch {
+        centers: Vec<BriefDescriptor>,
+        children: Vec<HKMNode>,
+    },
+}
+
+impl HKMNode {
+    /// Build the subtree of the descriptors of `indices`. Return tuple of (node, depth).
+    fn build(descs: &[BriefDescriptor], indices: Vec<usize>, branching: usize) -> (Self, usize) {
+        if indices.len() <= MAX_LEAF_SIZE.max(branching) {
+            return (HKMNode::Leaf(indices), 1);
+        }
+        let refs: Vec<&BriefDescriptor> = indices.iter().map(|&idx| &descs[idx]).collect();
+        let (centers, assignments) = k_majority(&refs, branching);
+        let mut groups = vec![vec![]; centers.len()];
+        indices
+            .iter()
+            .zip(&assignments)
+            .for_each(|(&idx, &ci)| groups[ci].push(idx));
+        if groups.iter().filter(|group| !group.is_empty()).count() < 2 {
+            // descriptors can not be divided (e.g. all descriptors are the same)
+            return (HKMNode::Leaf(indices), 1);
+        }
+
+        let (centers, groups): (Vec<BriefDescriptor>, Vec<Vec<usize>>) = centers
+            .into_iter()
+            .zip(groups)
+            .filter(|(_, group)| !group.is_empty())
+            .unzip();
+        let (children, depths): (Vec<HKMNode>, Vec<usize>) = groups
+            .into_iter()
+            .map(|group| HKMNode::build(descs, group, branching))
+            .unzip();
+        let depth = depths.into_iter().max().unwrap() + 1;
+        (HKMNode::Branch { centers, children }, depth)
+    }
+}
+
+/// Tree whose nodes are clustered recursively by k-majority (k-means with the hamming distance).
+pub struct HKMTree {
+    root: HKMNode,
+    branching: usize,
+    depth: usize,
+    descriptors: Vec<BriefDescriptor>,
+}
+
+impl HKMTree {
+    pub fn build(descs: &[BriefDescriptor]) -> Self {
+        let (root, depth) = HKMNode::build(descs, (0..descs.len()).collect(), BRANCHING);
+        HKMTree {
+            root,
+            branching: BRANCHING,
+            depth,
+            descriptors: descs.to_vec(),
+        }
+    }
+
+    pub fn branching(&self) -> usize {
+        self.branching
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Search nearest neighbors of `query` by best-bin-first traversal.
+    /// Traversal stops when the number of the compared descriptors reaches `checks`.
+    /// Return indices of the compared descriptors sorted by the distance to `query`
+    /// (the first element is the approximate nearest neighbor).
+    pub fn search(&self, query: &BriefDescriptor, checks: usize) -> Vec<usize> {
+        let mut candidates: Vec<(u32, usize)> = Vec::new();
+        // branches not visited yet : (distance to the center, node)
+        let mut queue: BinaryHeap<Reverse<(u32, usize)>> = BinaryHeap::new();
+        let mut nodes: Vec<&HKMNode> = vec![&self.root];
+        let mut node = Some(&self.root);
+        while let Some(current) = node {
+            match current {
+                HKMNode::Leaf(indices) => {
+                    candidates.extend(
+                        indices
+                            .iter()
+                            .map(|&idx| (self.descriptors[idx].distance(query) as u32, idx)),
+                    );
+                    if candidates.len() >= checks {
+                        break;
+                    }
+                    node = queue.pop().map(|Reverse((_, ni))| nodes[ni]);
+                }
+                HKMNode::Branch { centers, children } => {
+                    // go down to the nearest child and keep the others in the queue
+                    let dists: Vec<u32> = centers
+                        .iter()
+                        .map(|center| center.distance(query) as u32)
+                        .collect();
+                    let nearest = (0..dists.len()).min_by_key(|&ci| dists[ci]).unwrap();
+                    children.iter().enumerate().for_each(|(ci, child)| {
+                        if ci != nearest {
+                            queue.push(Reverse((dists[ci], nodes.len())));
+                            nodes.push(child);
+                        }
+                    });
+                    node = Some(&children[nearest]);
+                }
+            }
+        }
+        candidates.sort_unstable();
+        candidates.iter().map(|(_, idx)| *idx).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use super::*;
+
+    fn create_descriptors(n: usize) -> Vec<BriefDescriptor> {
+        let mut rng = rand::thread_rng();
+        (0..n)
+            .map(|_| {
+                let mut desc = BriefDescriptor::new(256);
+                (0..256).for_each(|_| desc.push(rng.gen::<bool>()));
+                desc
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_search() {
+        let descs = create_descriptors(10000);
+        let tree = HKMTree::build(&descs);
+        assert!(tree.depth() > 1);
+        assert_eq!(tree.branching(), BRANCHING);
+
+        let n_correct = descs
+            .iter()
+            .enumerate()
+            .filter(|(idx, desc)| {
+                let res = tree.search(desc, 64);
+                assert!(res.len() >= 64);
+                res[0] == *idx
+            })
+            .count();
+        assert!(
+            n_correct as f32 > 0.9 * descs.len() as f32,
+            "recall = {}",
+            n_correct as f32 / descs.len() as f32
+        );
+    }
+
+    #[test]
+    fn test_small_tree() {
+        let descs = create_descriptors(10);
+        let tree = HKMTree::build(&descs);
+        assert_eq!(tree.depth(), 1);
+        let res = tree.search(&descs[3], 1);
+        assert_eq!(res.len(), 10);
+        assert_eq!(res[0], 3);
+        assert!(HKMTree::build(&[]).search(&descs[0], 1).is_empty());
+    }
+}
@@ -16,6 +16,10 @@ where
             matche: (lhs_desc.clone(), rhs_desc.clone()),
         }
     }
+
+    pub fn distance(&self) -> f32 {
+        self.matche.0.distance(&self.matche.1)
+    }
 }
 
 pub trait Matcher<T>
@@ -25,4 +29,267 @@ where
     fn run(&self) -> Vec<Match<T>>;
 }
 
+/// Number of matches rejected in each stage of `MatchFilter`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FilterStats {
+    pub n_input: usize,
+    pub ratio_test: usize,
+    pub cross_check: usize,
+    pub distance: usize,
+    pub homography: usize,
+}
+
+/// Outlier rejection pipeline of matches.
+/// Enabled stages are applied in the order of
+/// ratio test -> cross check -> distance cutoff -> homography RANSAC.
+/// All stages are disabled by default.
+#[derive(Clone, Debug, Default)]
+pub struct MatchFilter {
+    ratio: Option<f32>,
+    cross_check: bool,
+    max_distance: Option<f32>,
+    homography: Option<(f32, usize)>,
+}
+
+impl MatchFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reject a match if its distance is not smaller than `ratio` * (distance to the second nearest
+    /// rhs descriptor).
+    pub fn ratio_test(mut self, ratio: f32) -> Self {
+        self.ratio = Some(ratio);
+        self
+    }
+
+    /// Reject a match if the descriptors are not the nearest neighbor of each other.
+    pub fn cross_check(mut self, enable: bool) -> Self {
+        self.cross_check = enable;
+        self
+    }
+
+    /// Reject a match whose distance is larger than `max_distance`.
+    pub fn max_distance(mut self, max_distance: f32) -> Self {
+        self.max_distance = Some(max_distance);
+        self
+    }
+
+    /// Reject a match whose transfer error of the homography estimated by RANSAC is larger than
+    /// `threshold_px`. See `ransac::filter_by_homography`.
+    pub fn homography_ransac(mut self, threshold_px: f32, iterations: usize) -> Self {
+        self.homography = Some((threshold_px, iterations));
+        self
+    }
+
+    /// Apply enabled stages to `matches`.
+    /// `lhs_descs` and `rhs_descs` are all descriptors used for matching (used in ratio test and
+    /// cross check). Return tuple of (inlier matches, inlier mask of `matches`).
+    pub fn filter<T: Distance + Clone>(
+        &self,
+        matches: &[Match<T>],
+        lhs_descs: &[Descriptor<T>],
+        rhs_descs: &[Descriptor<T>],
+    ) -> (Vec<Match<T>>, Vec<bool>) {
+        let (inliers, mask, _) = self.filter_with_stats(matches, lhs_descs, rhs_descs);
+        (inliers, mask)
+    }
+
+    /// Same as `filter` but also return the number of rejected matches in each stage.
+    pub fn filter_with_stats<T: Distance + Clone>(
+        &self,
+        matches: &[Match<T>],
+        lhs_descs: &[Descriptor<T>],
+        rhs_descs: &[Descriptor<T>],
+    ) -> (Vec<Match<T>>, Vec<bool>, FilterStats) {
+        let mut stats = FilterStats {
+            n_input: matches.len(),
+            ..Default::default()
+        };
+        let mut mask = vec![true; matches.len()];
+        let dists: Vec<f32> = matches.iter().map(|m| m.distance()).collect();
+
+        if let Some(ratio) = self.ratio {
+            stats.ratio_test = reject(&mut mask, |idx| {
+                let mut rhs_dists: Vec<f32> = rhs_descs
+                    .iter()
+                    .map(|desc| matches[idx].matche.0.distance(desc))
+                    .collect();
+                rhs_dists.sort_by(|l, r| l.partial_cmp(r).unwrap());
+                rhs_dists.len() < 2 || dists[idx] < ratio * rhs_dists[1]
+            });
+        }
+        if self.cross_check {
+            stats.cross_check = reject(&mut mask, |idx| {
+                let (lhs, rhs) = &matches[idx].matche;
+                lhs_descs
+                    .iter()
+                    .all(|desc| dists[idx] <= desc.distance(rhs))
+                    && rhs_descs
+                        .iter()
+                        .all(|desc| dists[idx] <= lhs.distance(desc))
+            });
+        }
+        if let Some(max_distance) = self.max_distance {
+            stats.distance = reject(&mut mask, |idx| dists[idx] <= max_distance);
+        }
+        if let Some((threshold_px, iterations)) = self.homography {
+            let indices: Vec<usize> = (0..matches.len()).filter(|idx| mask[*idx]).collect();
+            let candidates: Vec<Match<T>> = indices
+                .iter()
+                .map(|idx| Match::new(&matches[*idx].matche.0, &matches[*idx].matche.1))
+                .collect();
+            let (inliers, _) = ransac::homography_inliers(&candidates, threshold_px, iterations);
+            let mut is_inlier = vec![false; matches.len()];
+            inliers
+                .iter()
+                .for_each(|idx| is_inlier[indices[*idx]] = true);
+            stats.homography = reject(&mut mask, |idx| is_inlier[idx]);
+        }
+
+        let inliers = (0..matches.len())
+            .filter(|idx| mask[*idx])
+            .map(|idx| Match::new(&matches[idx].matche.0, &matches[idx].matche.1))
+            .collect();
+        (inliers, mask, stats)
+    }
+}
+
+/// Set `mask[idx]` to false if `is_valid(idx)` is false. Only indices where `mask[idx]` is true are
+/// checked. Return the number of rejected indices.
+fn reject<F: Fn(usize) -> bool>(mask: &mut [bool], is_valid: F) -> usize {
+    let mut n_rejected = 0;
+    for (idx, m) in mask.iter_mut().enumerate() {
+        if *m && !is_valid(idx) {
+            *m = false;
+            n_rejected += 1;
+        }
+    }
+    n_rejected
+}
+
 pub mod brute_force;
+pub mod descriptor_index;
+pub mod feature_tracker;
+pub mod gms;
+pub mod hkm_tree;
+pub mod ransac;
+
+#[cfg(test)]
+mod tests {
+    use nalgebra as na;
+    use rand::{seq::index::sample, Rng};
+
+    use crate::feat::{descriptors::BriefDescriptor, keypoints::KeyPoint};
+
+    use super::*;
+
+    const N_BITS: usize = 256;
+
+    fn create_descriptor(bits: &[bool], x: f64, y: f64) -> Descriptor<BriefDescriptor> {
+        let mut value = BriefDescriptor::new(N_BITS);
+        bits.iter().for_each(|b| value.push(*b));
+        Descriptor {
+            kpt: KeyPoint::new(x.round() as usize, y.round() as usize, 0.0, 0, 0.0),
+            value,
+        }
+    }
+
+    fn flip_bits(bits: &[bool], n_flip: usize) -> Vec<bool> {
+        let mut rng = rand::thread_rng();
+        let mut bits = bits.to_vec();
+        sample(&mut rng, N_BITS, n_flip)
+            .iter()
+            .for_each(|idx| bits[idx] = !bits[idx]);
+        bits
+    }
+
+    #[test]
+    fn test_match_filter() {
+        let mut rng = rand::thread_rng();
+        #[rustfmt::skip]
+        let homography = na::Matrix3::new(
+            1.1, 0.05, 20.0,
+            -0.05, 0.95, 30.0,
+            0.0, 0.0, 1.0,
+        );
+        let n_pts = 100;
+        let lhs_bits: Vec<Vec<bool>> = (0..n_pts)
+            .map(|_| (0..N_BITS).map(|_| rng.gen::<bool>()).collect())
+            .collect();
+        let lhs_locs: Vec<(f64, f64)> = (0..n_pts)
+            .map(|_| (rng.gen::<f64>() * 400.0, rng.gen::<f64>() * 400.0))
+            .collect();
+        let mut lhs_descs: Vec<Descriptor<BriefDescriptor>> = lhs_bits
+            .iter()
+            .zip(lhs_locs.iter())
+            .map(|(bits, (x, y))| create_descriptor(bits, *x, *y))
+            .collect();
+        let mut rhs_descs: Vec<Descriptor<BriefDescriptor>> = (0..n_pts)
+            .map(|idx| {
+                // [40, 45) : far in descriptor space.
+                let n_flip = if (40..45).contains(&idx) { 20 } else { 5 };
+                let bits = flip_bits(&lhs_bits[idx], n_flip);
+                let (x, y) = if idx >= 90 {
+                    // [90, 100) : geometric outliers.
+                    (rng.gen::<f64>() * 400.0, rng.gen::<f64>() * 400.0)
+                } else {
+                    let pt = homography * na::Vector3::new(lhs_locs[idx].0, lhs_locs[idx].1, 1.0);
+                    (pt[0] / pt[2], pt[1] / pt[2])
+                };
+                create_descriptor(&bits, x, y)
+            })
+            .collect();
+        // [0, 20) : ambiguous matches rejected by ratio test.
+        (0..20).for_each(|idx| {
+            let bits = flip_bits(&lhs_bits[idx], 6);
+            rhs_descs.push(create_descriptor(&bits, 0.0, 0.0));
+        });
+        // [30, 40) : rhs descriptors have nearer lhs descriptors.
+        (30..40).for_each(|idx| {
+            let desc = rhs_descs[idx].clone();
+            lhs_descs.push(desc);
+        });
+
+        let mut matches: Vec<Match<BriefDescriptor>> = (0..n_pts)
+            .map(|idx| Match::new(&lhs_descs[idx], &rhs_descs[idx]))
+            .collect();
+        // wrong matches rejected by ratio test.
+        (50..80).for_each(|idx| matches.push(Match::new(&lhs_descs[idx], &rhs_descs[idx + 1])));
+
+        let filter = MatchFilter::new()
+            .ratio_test(0.7)
+            .cross_check(true)
+            .max_distance(10.0)
+            .homography_ransac(3.0, 100);
+        let (inliers, mask, stats) = filter.filter_with_stats(&matches, &lhs_descs, &rhs_descs);
+
+        assert_eq!(
+            stats,
+            FilterStats {
+                n_input: 130,
+                ratio_test: 50,
+                cross_check: 10,
+                distance: 5,
+                homography: 10,
+            }
+        );
+        assert_eq!(inliers.len(), 55);
+        assert_eq!(mask.iter().filter(|m| **m).count(), 55);
+        mask.iter().enumerate().for_each(|(idx, m)| {
+            let expected = (20..30).contains(&idx) || (45..90).contains(&idx);
+            assert_eq!(*m, expected, "idx = {}", idx);
+        });
+    }
+
+    #[test]
+    fn test_match_filter_default() {
+        let bits = vec![true; N_BITS];
+        let lhs = vec![create_descriptor(&bits, 0.0, 0.0)];
+        let rhs = vec![create_descriptor(&flip_bits(&bits, 3), 1.0, 1.0)];
+        let matches = vec![Match::new(&lhs[0], &rhs[0])];
+        let (inliers, mask) = MatchFilter::new().filter(&matches, &lhs, &rhs);
+        assert_eq!(inliers.len(), 1);
+        assert_eq!(mask, vec![true]);
+    }
+}
@@ -0,0 +1,224 @@
+//! Outlier rejection of matches by RANSAC.
+use nalgebra as na;
+use rand::seq::index::sample;
+
+use crate::{
+    ensure,
+    epipolar::homography::HomographyData,
+    error::Result,
+    feat::{descriptors::BriefDescriptor, Distance},
+    linalg::ransac::{RANSACConfig, RANSAC},
+    optimizer::least_square::least_square_fitting,
+};
+
+use super::Match;
+
+/// Minimum number of matches to calculate homography.
+const MIN_SAMPLE: usize = 4;
+
+/// Remove outlier matches by fitting homography with RANSAC.
+/// Homography is fitted by `least_square_fitting::<HomographyData>` and a match is regarded as inlier
+/// if the transfer error (|H * lhs - rhs|) is smaller than `threshold_px`.
+/// Return tuple of (inlier matches, estimated homography). Estimated homography maps
+/// lhs keypoints to rhs keypoints. If homography can not be estimated, all matches are regarded as
+/// outliers and the identity matrix is returned.
+pub fn filter_by_homography(
+    matches: &[Match<BriefDescriptor>],
+    threshold_px: f32,
+    iterations: usize,
+) -> (Vec<Match<BriefDescriptor>>, na::Matrix3<f32>) {
+    let (inliers, homography) = homography_inliers(matches, threshold_px, iterations);
+    let inlier_matches = inliers
+        .iter()
+        .map(|idx| Match::new(&matches[*idx].matche.0, &matches[*idx].matche.1))
+        .collect();
+    (inlier_matches, homography)
+}
+
+/// Return tuple of (indices of inlier matches, estimated homography).
+/// See `filter_by_homography` for details.
+pub(super) fn homography_inliers<T: Distance + Clone>(
+    matches: &[Match<T>],
+    threshold_px: f32,
+    iterations: usize,
+) -> (Vec<usize>, na::Matrix3<f32>) {
+    if matches.len() < MIN_SAMPLE {
+        return (vec![], na::Matrix3::identity());
+    }
+    let (data, scale) = matches_to_points(matches);
+    let threshold = threshold_px as f64 / scale;
+
+    let estimator = HomographyRansac {
+        data: &data,
+        threshold,
+    };
+    let homography = match estimator.run(&RANSACConfig::new(iterations)) {
+        Ok(h) => h,
+        Err(_) => return (vec![], na::Matrix3::identity()),
+    };
+    let inliers = estimator.get_inliers(&homography);
+
+    // convert homography from normalized coordinates to image coordinates.
+    let s = na::Matrix3::new(scale, 0.0, 0.0, 0.0, scale, 0.0, 0.0, 0.0, 1.0);
+    let s_inv = na::Matrix3::new(1.0 / scale, 0.0, 0.0, 0.0, 1.0 / scale, 0.0, 0.0, 0.0, 1.0);
+    let homography = s * homography * s_inv;
+    let homography = homography / homography[(2, 2)];
+    (inliers, homography.cast::<f32>())
+}
+
+/// Convert matches to the points ([lhs0, rhs0, lhs1, rhs1, ...]) for `HomographyData`.
+/// Points are scaled to [-1, 1] for numerical stability. Return tuple of (points, scale).
+fn matches_to_points<T: Distance + Clone>(matches: &[Match<T>]) -> (Vec<na::Point2<f64>>, f64) {
+    let data: Vec<na::Point2<f64>> = matches
+        .iter()
+        .flat_map(|m| {
+            let (lhs, rhs) = (&m.matche.0.kpt, &m.matche.1.kpt);
+            vec![
+                na::Point2::new(lhs.x() as f64, lhs.y() as f64),
+                na::Point2::new(rhs.x() as f64, rhs.y() as f64),
+            ]
+        })
+        .collect();
+    let scale = data
+        .iter()
+        .fold(1.0f64, |acc, pt| acc.max(pt.x.abs()).max(pt.y.abs()));
+    let data = data.iter().map(|pt| pt / scale).collect();
+    (data, scale)
+}
+
+/// RANSAC estimator of homography used by `homography_inliers`.
+struct HomographyRansac<'a> {
+    data: &'a [na::Point2<f64>],
+    threshold: f64,
+}
+
+impl<'a> HomographyRansac<'a> {
+    fn fit_homography(&self, indices: &[usize]) -> Result<na::Matrix3<f64>> {
+        let data: Vec<na::Point2<f64>> = indices
+            .iter()
+            .flat_map(|idx| vec![self.data[idx * 2], self.data[idx * 2 + 1]])
+            .collect();
+        let params = least_square_fitting::<HomographyData>(&data)?;
+        let homography = na::Matrix3::from_row_slice(params.as_slice());
+        ensure!(
+            homography.iter().all(|val| val.is_finite()),
+            "Homography is not finite"
+        );
+        Ok(homography)
+    }
+}
+
+impl<'a> RANSAC<na::Matrix3<f64>, usize> for HomographyRansac<'a> {
+    fn min_sample(&self) -> usize {
+        MIN_SAMPLE
+    }
+
+    fn estimate_from_random_sample(&self) -> Result<na::Matrix3<f64>> {
+        let samples = sample(&mut rand::thread_rng(), self.data.len() / 2, MIN_SAMPLE);
+        self.fit_homography(&samples.into_vec())
+    }
+
+    fn get_inliers(&self, homography: &na::Matrix3<f64>) -> Vec<usize> {
+        get_inliers(homography, self.data, self.threshold)
+    }
+
+    fn estimate(&self, inputs: &[usize]) -> Result<na::Matrix3<f64>> {
+        self.fit_homography(inputs)
+    }
+}
+
+/// Return indices of the point pairs whose transfer error is smaller than `threshold`.
+fn get_inliers(
+    homography: &na::Matrix3<f64>,
+    data: &[na::Point2<f64>],
+    threshold: f64,
+) -> Vec<usize> {
+    (0..data.len() / 2)
+        .filter(|idx| {
+            let pt = homography * data[idx * 2].to_homogeneous();
+            if pt[2].abs() < 1e-10 {
+                return false;
+            }
+            let (x, y) = (pt[0] / pt[2], pt[1] / pt[2]);
+            let rhs = data[idx * 2 + 1];
+            ((x - rhs.x).powi(2) + (y - rhs.y).powi(2)).sqrt() < threshold
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use crate::feat::{descriptors::Descriptor, keypoints::KeyPoint};
+
+    use super::*;
+
+    fn create_match(x0: f64, y0: f64, x1: f64, y1: f64) -> Match<BriefDescriptor> {
+        let desc = |x: f64, y: f64| Descriptor {
+            kpt: KeyPoint::new(x.round() as usize, y.round() as usize, 0.0, 0, 0.0),
+            value: BriefDescriptor::new(256),
+        };
+        Match::new(&desc(x0, y0), &desc(x1, y1))
+    }
+
+    #[test]
+    fn test_filter_by_homography() {
+        let mut rng = rand::thread_rng();
+        #[rustfmt::skip]
+        let homography = na::Matrix3::new(
+            0.9, -0.1, 30.0,
+            0.1, 1.05, 20.0,
+            1e-4, 5e-5, 1.0,
+        );
+        let n_matches = 200;
+        let is_inlier: Vec<bool> = (0..n_matches).map(|_| rng.gen::<f64>() > 0.3).collect();
+        let matches: Vec<Match<BriefDescriptor>> = is_inlier
+            .iter()
+            .map(|inlier| {
+                let x0 = rng.gen::<f64>() * 400.0 + 50.0;
+                let y0 = rng.gen::<f64>() * 400.0 + 50.0;
+                if *inlier {
+                    let pt = homography * na::Vector3::new(x0, y0, 1.0);
+                    create_match(x0, y0, pt[0] / pt[2], pt[1] / pt[2])
+                } else {
+                    let x1 = rng.gen::<f64>() * 500.0;
+                    let y1 = rng.gen::<f64>() * 500.0;
+                    create_match(x0, y0, x1, y1)
+                }
+            })
+            .collect();
+
+        let (inliers, pred) = filter_by_homography(&matches, 3.0, 200);
+        let n_true = is_inlier.iter().filter(|val| **val).count();
+        let n_recovered = inliers
+            .iter()
+            .filter(|m| {
+                let pt = homography
+                    * na::Vector3::new(m.matche.0.kpt.x() as f64, m.matche.0.kpt.y() as f64, 1.0);
+                let dx = pt[0] / pt[2] - m.matche.1.kpt.x() as f64;
+                let dy = pt[1] / pt[2] - m.matche.1.kpt.y() as f64;
+                (dx * dx + dy * dy).sqrt() < 1.5
+            })
+            .count();
+        assert!(
+            n_recovered as f64 > n_true as f64 * 0.9,
+            "recovered : {} / {}",
+            n_recovered,
+            n_true
+        );
+
+        let pt = pred * na::Vector3::new(200.0, 300.0, 1.0);
+        let gt = homography * na::Vector3::new(200.0, 300.0, 1.0);
+        assert!((pt[0] / pt[2] - (gt[0] / gt[2]) as f32).abs() < 2.0);
+        assert!((pt[1] / pt[2] - (gt[1] / gt[2]) as f32).abs() < 2.0);
+    }
+
+    #[test]
+    fn test_filter_by_homography_few_matches() {
+        let matches = vec![create_match(0.0, 0.0, 1.0, 1.0)];
+        let (inliers, homography) = filter_by_homography(&matches, 3.0, 10);
+        assert!(inliers.is_empty());
+        assert_eq!(homography, na::Matrix3::identity());
+    }
+}
@@ -1,29 +1,55 @@
 use std::ops::Deref;
 
-use image::{ColorType, ImageBuffer, Pixel};
-use nalgebra::{Matrix2x3, Point2};
-use num_traits::ToPrimitive;
+use image::{ColorType, GenericImageView, ImageBuffer, Pixel};
+use nalgebra::{Matrix2x3, Matrix3, Point2, Vector3};
+use num_traits::{Bounded, ToPrimitive};
 
-use crate::feat::keypoints::KeyPoint;
+use crate::{feat::keypoints::KeyPoint, sfm::distortion::DistortionModel};
 
 use super::{linalg, linalg::inv_affine_mat};
 
-/// affine transformation (linear interpolation)
-/// `affine_mat` is projection from source points to destination points
-pub fn affine_transform<P, Container>(
-    img: &ImageBuffer<P, Container>,
-    affine_mat: &Matrix2x3<f32>,
-) -> Vec<u8>
+pub mod pipeline;
+pub mod roi;
+pub use roi::ImageRoi;
+#[cfg(all(
+    target_arch = "x86_64",
+    target_feature = "avx2",
+    target_feature = "fma"
+))]
+pub mod simd;
+
+const UNDISTORT_MAX_ITERATION: usize = 10;
+
+/// Image whose rows are contiguous in memory.
+/// Image processing functions read pixels from the raw rows instead of
+/// `GenericImageView::get_pixel`, which is much slower.
+pub trait RawImageView: GenericImageView {
+    /// Return the sub pixels of the row `y`.
+    fn raw_row(&self, y: u32) -> &[<Self::Pixel as Pixel>::Subpixel];
+}
+
+impl<P, Container> RawImageView for ImageBuffer<P, Container>
 where
     P: Pixel + 'static,
     P::Subpixel: 'static,
     Container: Deref<Target = [P::Subpixel]>,
+{
+    fn raw_row(&self, y: u32) -> &[P::Subpixel] {
+        let y_stride = self.width() as usize * P::CHANNEL_COUNT as usize;
+        &self.as_raw()[y as usize * y_stride..(y as usize + 1) * y_stride]
+    }
+}
+
+/// affine transformation (linear interpolation)
+/// `affine_mat` is projection from source points to destination points
+pub fn affine_transform<I>(img: &I, affine_mat: &Matrix2x3<f32>) -> Vec<u8>
+where
+    I: RawImageView,
+    I::Pixel: 'static,
 {
     let inv_affine_mat = inv_affine_mat(&affine_mat);
-    let data = img.as_raw();
-    let mut transformed: Vec<u8> = Vec::with_capacity(data.len());
-    let x_stride = P::CHANNEL_COUNT as usize;
-    let y_stride = x_stride * img.width() as usize;
+    let x_stride = I::Pixel::CHANNEL_COUNT as usize;
+    let mut transformed: Vec<u8> = Vec::with_capacity(data_len(img));
 
     for y in 0..img.height() {
         for x in 0..img.width() {
@@ -50,11 +76,7 @@ where
                 fy = 1.0f32;
             }
             for c in 0..x_stride {
-                let offset = iy as usize * y_stride + ix as usize * x_stride + c;
-                let val = (1.0f32 - fx) * (1.0f32 - fy) * data[offset].to_f32().unwrap()
-                    + fx * (1.0f32 - fy) * data[offset + x_stride].to_f32().unwrap()
-                    + (1.0f32 - fx) * fy * data[offset + y_stride].to_f32().unwrap()
-                    + fx * fy * data[offset + y_stride + x_stride].to_f32().unwrap();
+                let val = interpolate(img, ix as u32, iy as u32, fx, fy, c);
                 transformed.push(val as u8);
             }
         }
@@ -62,22 +84,171 @@ where
     transformed
 }
 
+/// perspective transformation (linear interpolation)
+/// `homography` is projection from source points to destination points.
+/// Pixels projected from outside of the source image are filled with 0.
+pub fn warp_perspective<I>(img: &I, homography: &Matrix3<f32>) -> Vec<u8>
+where
+    I: RawImageView,
+    I::Pixel: 'static,
+{
+    let inv = match homography.try_inverse() {
+        Some(inv) => inv,
+        None => return vec![0; data_len(img)],
+    };
+    remap(img, |x, y| {
+        let pt = inv * Vector3::new(x, y, 1.0);
+        if pt[2].abs() < f32::EPSILON {
+            return None;
+        }
+        Some((pt[0] / pt[2], pt[1] / pt[2]))
+    })
+}
+
+/// Direction of the lens distortion conversion of `warp_perspective_with_distortion`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DistortionMode {
+    /// Source image is undistorted and destination image is distorted.
+    Apply,
+    /// Source image is distorted and destination image is undistorted.
+    Remove,
+}
+
+/// perspective transformation with lens distortion (linear interpolation)
+/// `homography` is projection from (undistorted) source points to (undistorted) destination
+/// points. Pass identity matrix to only apply or remove the distortion.
+/// - `intrinsics` : intrinsic matrix of the camera.
+/// - `distortion` : distortion parameters of the camera.
+/// - `mode` : see `DistortionMode`.
+pub fn warp_perspective_with_distortion<I>(
+    img: &I,
+    homography: &Matrix3<f32>,
+    intrinsics: &Matrix3<f32>,
+    distortion: &DistortionModel,
+    mode: DistortionMode,
+) -> Vec<u8>
+where
+    I: RawImageView,
+    I::Pixel: 'static,
+{
+    let (inv, k_inv) = match (homography.try_inverse(), intrinsics.try_inverse()) {
+        (Some(inv), Some(k_inv)) => (inv, k_inv),
+        _ => return vec![0; data_len(img)],
+    };
+    // convert point in the image coordinates through the normalized image coordinates.
+    let convert = |pt: Vector3<f32>, func: &dyn Fn(&Point2<f64>) -> Point2<f64>| {
+        let n = k_inv * pt;
+        let n = func(&Point2::new((n[0] / n[2]) as f64, (n[1] / n[2]) as f64));
+        intrinsics * Vector3::new(n[0] as f32, n[1] as f32, 1.0)
+    };
+    remap(img, |x, y| {
+        let pt = match mode {
+            DistortionMode::Apply => {
+                inv * convert(Vector3::new(x, y, 1.0), &|pt| {
+                    distortion.undistort(pt, UNDISTORT_MAX_ITERATION)
+                })
+            }
+            DistortionMode::Remove => {
+                convert(inv * Vector3::new(x, y, 1.0), &|pt| distortion.distort(pt))
+            }
+        };
+        if pt[2].abs() < f32::EPSILON {
+            return None;
+        }
+        Some((pt[0] / pt[2], pt[1] / pt[2]))
+    })
+}
+
+/// Create image by sampling `img` at the point `func(x, y)` for each destination pixel (x, y)
+/// (linear interpolation). Pixels mapped to `None` or outside of `img` are filled with 0.
+/// Images narrower or lower than 2 pixels can not be interpolated and are filled with 0.
+fn remap<I, F>(img: &I, func: F) -> Vec<u8>
+where
+    I: RawImageView,
+    I::Pixel: 'static,
+    F: Fn(f32, f32) -> Option<(f32, f32)>,
+{
+    let x_stride = I::Pixel::CHANNEL_COUNT as usize;
+    let y_stride = x_stride * img.width() as usize;
+    let mut transformed: Vec<u8> = vec![0; data_len(img)];
+    if img.width() < 2 || img.height() < 2 {
+        return transformed;
+    }
+
+    for y in 0..img.height() as usize {
+        for x in 0..img.width() as usize {
+            let (px, py) = match func(x as f32, y as f32) {
+                Some(pt) => pt,
+                None => continue,
+            };
+            if px < 0.0
+                || py < 0.0
+                || px > (img.width() - 1) as f32
+                || py > (img.height() - 1) as f32
+            {
+                continue;
+            }
+            let ix = (px.floor() as usize).min(img.width() as usize - 2);
+            let iy = (py.floor() as usize).min(img.height() as usize - 2);
+            let (fx, fy) = (px - ix as f32, py - iy as f32);
+            let dst = y * y_stride + x * x_stride;
+            for c in 0..x_stride {
+                let val = interpolate(img, ix as u32, iy as u32, fx, fy, c);
+                transformed[dst + c] = val.round() as u8;
+            }
+        }
+    }
+    transformed
+}
+
+/// Number of the elements of the raw data of `img`.
+fn data_len<I: RawImageView>(img: &I) -> usize {
+    (img.width() * img.height()) as usize * I::Pixel::CHANNEL_COUNT as usize
+}
+
+/// Return the value of the channel `c` of the pixel (x, y).
+fn pixel_value<I: RawImageView>(img: &I, x: u32, y: u32, c: usize) -> f32 {
+    img.raw_row(y)[x as usize * I::Pixel::CHANNEL_COUNT as usize + c]
+        .to_f32()
+        .unwrap()
+}
+
+/// Linear interpolation of the channel `c` at (ix + fx, iy + fy).
+/// Pixels (ix + 1, iy) and (ix, iy + 1) must be inside of `img`.
+fn interpolate<I: RawImageView>(img: &I, ix: u32, iy: u32, fx: f32, fy: f32, c: usize) -> f32 {
+    let x_stride = I::Pixel::CHANNEL_COUNT as usize;
+    let offset = ix as usize * x_stride + c;
+    let (row0, row1) = (img.raw_row(iy), img.raw_row(iy + 1));
+    (1.0f32 - fx) * (1.0f32 - fy) * row0[offset].to_f32().unwrap()
+        + fx * (1.0f32 - fy) * row0[offset + x_stride].to_f32().unwrap()
+        + (1.0f32 - fx) * fy * row1[offset].to_f32().unwrap()
+        + fx * fy * row1[offset + x_stride].to_f32().unwrap()
+}
+
 /// Non-Maximum Supression (NMS)
+/// Coordinates of the key points are in the image of their pyramid level and `scale_factor` is
+/// the scale between the adjacent pyramid levels. Key points are compared in the coordinates of
+/// level 0, where the suppression radius (`kernel_size / 2`) is scaled by the pyramid scale of
+/// the key point.
 // とりあえず、O(n^2)で実装してみて高速化を検討する
-pub fn nms(kpts: &Vec<KeyPoint>, kernel_size: u32) -> Vec<KeyPoint> {
-    if kpts.len() == 0 {
+pub fn nms(kpts: &[KeyPoint], kernel_size: u32, scale_factor: f32) -> Vec<KeyPoint> {
+    if kpts.is_empty() {
         return Vec::<KeyPoint>::new();
     }
     let half = kernel_size as f32 / 2.0;
-    let mut kpts = kpts.clone();
+    let scale = |kpt: &KeyPoint| scale_factor.powf(kpt.level() as f32);
+    let mut kpts = kpts.to_vec();
     kpts.sort_unstable_by(|a, b| a.crf().partial_cmp(&b.crf()).unwrap());
 
     let mut supressed: Vec<KeyPoint> = Vec::new();
     // println!("len = {}", kpts.len());
     'outer: for i in (0..kpts.len()).rev() {
         // println!("{}", kpts[i].crf());
+        let (si, xi, yi) = (scale(&kpts[i]), kpts[i].x(), kpts[i].y());
         for kpt in &supressed {
-            if (kpt.x() - kpts[i].x()).abs() < half && (kpt.y() - kpts[i].y()).abs() < half {
+            let s = scale(kpt);
+            let radius = half * s.max(si);
+            if (kpt.x() * s - xi * si).abs() < radius && (kpt.y() * s - yi * si).abs() < radius {
                 continue 'outer;
             }
         }
@@ -89,20 +260,19 @@ pub fn nms(kpts: &Vec<KeyPoint>, kernel_size: u32) -> Vec<KeyPoint> {
 /// gaussian filter
 // TODO: dftによる高速化
 // : http://signalprocess.binarized.work/2019/04/01/optimize_any_fir_filter_calculation_by_dft/
-pub fn gaussian<P, Container>(
-    img: &ImageBuffer<P, Container>,
+pub fn gaussian<I>(
+    img: &I,
     kernel_size: u32,
     sigma: f32, // stddev
 ) -> Vec<u8>
 where
-    P: Pixel + 'static,
-    P::Subpixel: 'static,
-    Container: Deref<Target = [P::Subpixel]>,
+    I: RawImageView,
+    I::Pixel: 'static,
 {
     let (width, height) = (img.width() as usize, img.height() as usize);
     // let data = img.as_raw();
     let data = padding(img, kernel_size as usize / 2);
-    let x_stride = P::CHANNEL_COUNT as usize; //
+    let x_stride = I::Pixel::CHANNEL_COUNT as usize; //
     let y_stride = (width + kernel_size as usize / 2 * 2) * x_stride;
     let mut res: Vec<u8> = Vec::with_capacity(height * y_stride);
     let kernel = create_gauss_kernel(kernel_size, sigma);
@@ -116,7 +286,7 @@ where
                     let offset = y_off + (x + dx) * x_stride;
                     let kval = kernel[dy * kernel_size as usize + dx];
                     for c in 0..x_stride {
-                        sums[c] += kval * data[offset + c].to_f32().unwrap();
+                        sums[c] += kval * data[offset + c] as f32;
                     }
                 }
             }
@@ -146,122 +316,85 @@ fn create_gauss_kernel(kernel_size: u32, sigma: f32) -> Vec<f32> {
     kernel
 }
 
-fn padding<P, Container>(img: &ImageBuffer<P, Container>, pad_size: usize) -> Vec<u8>
+/// Pad `img` with `pad_size` pixels on each side. Padded pixels are filled with the nearest
+/// edge pixel.
+fn padding<I>(img: &I, pad_size: usize) -> Vec<u8>
 where
-    P: Pixel + 'static,
-    P::Subpixel: 'static,
-    Container: Deref<Target = [P::Subpixel]>,
+    I: RawImageView,
+    I::Pixel: 'static,
 {
-    let (width, height) = (img.width() as usize, img.height() as usize);
-    let data = img.as_raw();
-    let x_stride = P::CHANNEL_COUNT as usize;
-    let src_y_stride = width * x_stride;
-    let dst_y_stride = (width + pad_size * 2) * x_stride;
-    let mut res: Vec<u8> = vec![0; (height + pad_size * 2) * dst_y_stride];
-
-    let lt: Vec<u8> = (0..x_stride)
-        .map(|c| data[0 + c].to_u8().unwrap())
-        .collect();
-    let rt: Vec<u8> = (0..x_stride)
-        .map(|c| data[src_y_stride - x_stride + c].to_u8().unwrap())
-        .collect();
-    let lb: Vec<u8> = (0..x_stride)
-        .map(|c| data[(height - 1) * src_y_stride + c].to_u8().unwrap())
-        .collect();
-    let rb: Vec<u8> = (0..x_stride)
-        .map(|c| data[data.len() - x_stride + c].to_u8().unwrap())
-        .collect();
-    for y in 0..pad_size {
-        for x in 0..pad_size {
-            for c in 0..x_stride {
-                res[y * dst_y_stride + x * x_stride + c] = lt[c];
-                res[y * dst_y_stride + (x + width + pad_size) * x_stride + c] = rt[c];
-                res[(y + height + pad_size) * dst_y_stride + x * x_stride + c] = lb[c];
-                res[(y + height + pad_size) * dst_y_stride
-                    + (x + width + pad_size) * x_stride
-                    + c] = rb[c];
-            }
-        }
-        let dst_y_off = y * dst_y_stride;
-        for x in 0..width {
-            for c in 0..x_stride {
-                res[dst_y_off + (x + pad_size) * x_stride + c] =
-                    data[x * x_stride + c].to_u8().unwrap();
-            }
-        }
-        let src_y_off = (height - 1) * src_y_stride;
-        let dst_y_off = (y + height + pad_size) * dst_y_stride;
-        for x in 0..width {
-            for c in 0..x_stride {
-                res[dst_y_off + (x + pad_size) * x_stride + c] =
-                    data[src_y_off + x * x_stride + c].to_u8().unwrap();
-            }
-        }
-    }
-
-    for y in 0..height {
-        let src_y_off = y * src_y_stride;
-        let dst_y_off = (y + pad_size) * dst_y_stride;
-        for x in 0..width {
-            let src_off = src_y_off + x * x_stride;
-            let dst_off = dst_y_off + (x + pad_size) * x_stride;
-            for c in 0..x_stride {
-                res[dst_off + c] = data[src_off + c].to_u8().unwrap();
-            }
-        }
-        for x in 0..pad_size {
-            let dst_off0 = dst_y_off + x * x_stride;
-            let dst_off1 = dst_y_off + (x + width + pad_size) * x_stride;
-            for c in 0..x_stride {
-                res[dst_off0 + c] = data[src_y_off + c].to_u8().unwrap();
-                res[dst_off1 + c] = data[src_y_off + src_y_stride - x_stride + c]
-                    .to_u8()
-                    .unwrap();
-            }
+    let (width, height) = (img.width() as isize, img.height() as isize);
+    let x_stride = I::Pixel::CHANNEL_COUNT as usize;
+    let pad = pad_size as isize;
+    let mut res: Vec<u8> =
+        Vec::with_capacity((height + pad * 2) as usize * (width + pad * 2) as usize * x_stride);
+    for y in -pad..height + pad {
+        let row = img.raw_row(y.clamp(0, height - 1) as u32);
+        for x in -pad..width + pad {
+            let offset = x.clamp(0, width - 1) as usize * x_stride;
+            res.extend(
+                row[offset..offset + x_stride]
+                    .iter()
+                    .map(|val| val.to_u8().unwrap()),
+            );
         }
     }
     res
 }
 
 /// convert to gray scale.
-pub fn gray<P, Container>(img: &ImageBuffer<P, Container>) -> Vec<u8>
+/// Pixel values are normalized to [0, 255] (e.g. divided by 257 for 16bit images).
+/// Alpha channel is ignored.
+pub fn gray<I>(img: &I) -> Vec<u8>
 where
-    P: Pixel + 'static,
-    P::Subpixel: 'static,
-    Container: Deref<Target = [P::Subpixel]>,
+    I: RawImageView,
+    I::Pixel: 'static,
 {
-    let x_stride = P::CHANNEL_COUNT as usize;
-    assert!(x_stride == 3 || x_stride == 4);
-
-    let (width, height) = (img.width() as usize, img.height() as usize);
-    let y_stride = width * x_stride;
-    let data = img.as_raw();
-    let mut gray: Vec<u8> = Vec::with_capacity(width * height);
+    let n_channels = I::Pixel::CHANNEL_COUNT as usize;
+    assert!((1..=4).contains(&n_channels));
+    let scale = 255.0 / <I::Pixel as Pixel>::Subpixel::max_value().to_f32().unwrap();
+
+    let (width, height) = img.dimensions();
+    let mut gray: Vec<u8> = Vec::with_capacity((width * height) as usize);
+    if n_channels <= 2 {
+        // already gray scale
+        for y in 0..height {
+            let row = img.raw_row(y);
+            gray.extend(
+                row.iter()
+                    .step_by(n_channels)
+                    .map(|val| (val.to_f32().unwrap() * scale).round() as u8),
+            );
+        }
+        return gray;
+    }
     let mut factor: Vec<f32> = vec![0.299, 0.587, 0.114];
-    if P::COLOR_TYPE == ColorType::Bgr8 || P::COLOR_TYPE == ColorType::Bgra8 {
+    if I::Pixel::COLOR_TYPE == ColorType::Bgr8 || I::Pixel::COLOR_TYPE == ColorType::Bgra8 {
         factor = vec![factor[2], factor[1], factor[0]];
     }
 
     for y in 0..height {
-        let off_y = y_stride * y;
-        for x in 0..width {
-            let off = off_y + x * x_stride;
-            let val = (factor[0] * data[off].to_f32().unwrap()
-                + factor[1] * data[off + 1].to_f32().unwrap()
-                + factor[2] * data[off + 2].to_f32().unwrap()) as u8;
-            gray.push(val);
+        for data in img.raw_row(y).chunks_exact(n_channels) {
+            let val = factor[0] * data[0].to_f32().unwrap()
+                + factor[1] * data[1].to_f32().unwrap()
+                + factor[2] * data[2].to_f32().unwrap();
+            // 8-bit luminance is truncated, wider values are rounded after normalization.
+            if scale == 1.0 {
+                gray.push(val as u8);
+            } else {
+                gray.push((val * scale).round() as u8);
+            }
         }
     }
     gray
 }
 
-pub fn median_filter<P, Container>(img: &ImageBuffer<P, Container>, kernel_size: u32) -> Vec<u8>
+pub fn median_filter<I>(img: &I, kernel_size: u32) -> Vec<u8>
 where
-    P: Pixel + 'static,
-    P::Subpixel: 'static,
-    Container: Deref<Target = [P::Subpixel]>,
+    I: RawImageView,
+    I::Pixel: 'static,
 {
-    let x_stride = P::CHANNEL_COUNT as usize;
+    let x_stride = I::Pixel::CHANNEL_COUNT as usize;
     let padded = padding(img, kernel_size as usize / 2 + 1);
     let width = (img.width() + (kernel_size / 2 + 1) * 2) as usize;
     let height = (img.height() + (kernel_size / 2 + 1) * 2) as usize;
@@ -314,34 +447,30 @@ where
 }
 
 /// resize `img` to size (width, height).
-pub fn resize<P, Container>(img: &ImageBuffer<P, Container>, width: u32, height: u32) -> Vec<u8>
+pub fn resize<I>(img: &I, width: u32, height: u32) -> Vec<u8>
 where
-    P: Pixel + 'static,
-    P::Subpixel: 'static,
-    Container: Deref<Target = [P::Subpixel]>,
+    I: RawImageView,
+    I::Pixel: 'static,
 {
-    let (width, height) = (width as usize, height as usize);
-    let x_stride = P::CHANNEL_COUNT as usize;
-    let data = img.as_raw();
-    let mut resized: Vec<u8> = Vec::with_capacity(width * height * x_stride);
+    let x_stride = I::Pixel::CHANNEL_COUNT as usize;
+    let mut resized: Vec<u8> = Vec::with_capacity((width * height) as usize * x_stride);
 
     let x_scale = img.width() as f32 / width as f32;
     let y_scale = img.height() as f32 / height as f32;
-    let y_stride = img.width() as usize * x_stride;
+    let (max_x, max_y) = (img.width() - 1, img.height() - 1);
 
     for y in 0..height {
         for x in 0..width {
             let (fx, fy) = (x as f32 * x_scale, y as f32 * y_scale);
             let (dx, dy) = (fx.fract(), fy.fract());
-            let (ix, iy) = (fx.floor() as usize, fy.floor() as usize);
-            let off = iy * y_stride + ix * x_stride;
+            let (ix, iy) = (fx.floor() as u32, fy.floor() as u32);
+            let (ix1, iy1) = ((ix + 1).min(max_x), (iy + 1).min(max_y));
             for c in 0..x_stride {
                 resized.push(
-                    ((1.0f32 - dx) * (1.0f32 - dy) * data[off + c].to_f32().unwrap()
-                        + dx * (1.0f32 - dy) * data[off + x_stride + c].to_f32().unwrap()
-                        + (1.0f32 - dx) * dy * data[off + y_stride + c].to_f32().unwrap()
-                        + dx * dy * data[off + y_stride + x_stride + c].to_f32().unwrap())
-                        as u8,
+                    ((1.0f32 - dx) * (1.0f32 - dy) * pixel_value(img, ix, iy, c)
+                        + dx * (1.0f32 - dy) * pixel_value(img, ix1, iy, c)
+                        + (1.0f32 - dx) * dy * pixel_value(img, ix, iy1, c)
+                        + dx * dy * pixel_value(img, ix1, iy1, c)) as u8,
                 );
             }
         }
@@ -350,6 +479,25 @@ where
     resized
 }
 
+/// binarize each channel of `img`. Values larger than `thresh` are set to 255, otherwise 0.
+pub fn threshold<I>(img: &I, thresh: u8) -> Vec<u8>
+where
+    I: RawImageView,
+    I::Pixel: 'static,
+{
+    let mut res: Vec<u8> = Vec::with_capacity(data_len(img));
+    for y in 0..img.height() {
+        res.extend(img.raw_row(y).iter().map(|val| {
+            if val.to_u8().unwrap() > thresh {
+                255
+            } else {
+                0
+            }
+        }));
+    }
+    res
+}
+
 #[cfg(test)]
 mod tests {
     use nalgebra::matrix;
@@ -385,6 +533,113 @@ mod tests {
         assert_eq!(res[res.len() - 1], (length - 4) as u8);
     }
 
+    #[test]
+    fn test_warp_perspective() {
+        let length = 10;
+        let img = image::RgbImage::from_fn(length, length, |x, y| {
+            image::Rgb([(x + y) as u8, x as u8, y as u8])
+        });
+        #[rustfmt::skip]
+        let homography = matrix![
+            1.0, 0.0, 2.0;
+            0.0, 1.0, 3.0;
+            0.0, 0.0, 1.0;
+        ];
+        let res = warp_perspective(&img, &homography);
+        assert_eq!(res.len(), (length * length * 3) as usize);
+        for y in 0..length {
+            for x in 0..length {
+                let offset = ((y * length + x) * 3) as usize;
+                if x < 2 || y < 3 {
+                    assert_eq!(res[offset..offset + 3], [0, 0, 0], "x = {}, y = {}", x, y);
+                } else {
+                    assert_eq!(res[offset], (x + y - 5) as u8, "x = {}, y = {}", x, y);
+                    assert_eq!(res[offset + 1], (x - 2) as u8, "x = {}, y = {}", x, y);
+                    assert_eq!(res[offset + 2], (y - 3) as u8, "x = {}, y = {}", x, y);
+                }
+            }
+        }
+
+        // homography is defined up to scale
+        #[rustfmt::skip]
+        let homography = matrix![
+            2.0, 0.0, 0.0;
+            0.0, 2.0, 0.0;
+            0.0, 0.0, 2.0;
+        ];
+        let res = warp_perspective(&img, &homography);
+        assert_eq!(res, img.as_raw().to_vec());
+
+        // images too small to be interpolated
+        for (width, height) in [(0, 0), (1, 1), (1, length), (length, 1)] {
+            let img = image::RgbImage::from_pixel(width, height, image::Rgb([1, 2, 3]));
+            let res = warp_perspective(&img, &Matrix3::identity());
+            assert_eq!(res, vec![0; (width * height * 3) as usize]);
+        }
+    }
+
+    #[test]
+    fn test_warp_perspective_with_distortion() {
+        let length = 64;
+        let img = image::GrayImage::from_fn(length, length, |x, y| {
+            image::Luma([((x * 3 + y * 2) % 256) as u8])
+        });
+        #[rustfmt::skip]
+        let intrinsics = matrix![
+            64.0, 0.0, 32.0;
+            0.0, 64.0, 32.0;
+            0.0, 0.0, 1.0;
+        ];
+        #[rustfmt::skip]
+        let homography = matrix![
+            1.0, 0.0, 2.0;
+            0.0, 1.0, 3.0;
+            0.0, 0.0, 1.0;
+        ];
+
+        // without distortion, the result is the same as `warp_perspective`
+        let expected = warp_perspective(&img, &homography);
+        [DistortionMode::Apply, DistortionMode::Remove]
+            .iter()
+            .for_each(|mode| {
+                let res = warp_perspective_with_distortion(
+                    &img,
+                    &homography,
+                    &intrinsics,
+                    &DistortionModel::default(),
+                    *mode,
+                );
+                assert_eq!(res, expected);
+            });
+
+        // removing the applied distortion restores the image (except for interpolation error)
+        let distortion = DistortionModel::new(-0.2, 0.05, 0.0, 0.0);
+        let identity = Matrix3::identity();
+        let distorted = warp_perspective_with_distortion(
+            &img,
+            &identity,
+            &intrinsics,
+            &distortion,
+            DistortionMode::Apply,
+        );
+        assert_ne!(distorted, img.as_raw().to_vec());
+        let distorted = image::GrayImage::from_raw(length, length, distorted).unwrap();
+        let restored = warp_perspective_with_distortion(
+            &distorted,
+            &identity,
+            &intrinsics,
+            &distortion,
+            DistortionMode::Remove,
+        );
+        for y in 16..48 {
+            for x in 16..48 {
+                let idx = (y * length + x) as usize;
+                let diff = (restored[idx] as i32 - img.as_raw()[idx] as i32).abs();
+                assert!(diff <= 2, "x = {}, y = {}, diff = {}", x, y, diff);
+            }
+        }
+    }
+
     #[test]
     fn test_gaussian() {
         let length = 10;
@@ -490,7 +745,7 @@ mod tests {
             KeyPoint::new(5, 4, 11.5, 1, 0.0),
             KeyPoint::new(3, 2, 8.0, 1, 0.0),
         ];
-        let supressed = nms(&kpts, 3);
+        let supressed = nms(&kpts, 3, 2.0);
         assert_eq!(supressed.len(), 4);
         assert!((supressed[0].crf() - 12.5).abs() < 1e-5);
         assert!((supressed[1].crf() - 11.8).abs() < 1e-5);
@@ -498,6 +753,35 @@ mod tests {
         assert!((supressed[3].crf() - 8.0).abs() < 1e-5);
     }
 
+    #[test]
+    fn test_nms_pyramid() {
+        // (5, 5) in level 1 is (10, 10) in level 0.
+        let kpts = vec![
+            KeyPoint::new(10, 10, 10.0, 0, 0.0),
+            KeyPoint::new(5, 5, 12.0, 1, 0.0),
+        ];
+        let supressed = nms(&kpts, 3, 2.0);
+        assert_eq!(supressed.len(), 1);
+        assert_eq!(supressed[0].level(), 1);
+
+        let kpts = vec![
+            KeyPoint::new(10, 10, 12.0, 0, 0.0),
+            KeyPoint::new(5, 5, 10.0, 1, 0.0),
+        ];
+        let supressed = nms(&kpts, 3, 2.0);
+        assert_eq!(supressed.len(), 1);
+        assert_eq!(supressed[0].level(), 0);
+
+        // (6, 5) in level 1 is (12, 10) in level 0, which is inside of the scaled radius (3).
+        let kpts = vec![
+            KeyPoint::new(10, 10, 12.0, 0, 0.0),
+            KeyPoint::new(6, 5, 10.0, 1, 0.0),
+        ];
+        assert_eq!(nms(&kpts, 3, 2.0).len(), 1);
+        // without the pyramid scale, key points are compared in the same coordinates.
+        assert_eq!(nms(&kpts, 3, 1.0).len(), 2);
+    }
+
     #[test]
     fn test_gray() {
         let length = 256;
@@ -518,6 +802,46 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_gray_16bit() {
+        let (width, height) = (64, 32);
+        let test_image: image::ImageBuffer<image::Luma<u16>, Vec<u16>> =
+            image::ImageBuffer::from_fn(width, height, |x, y| image::Luma([(x * 1000 + y) as u16]));
+        let res = gray(&test_image);
+        assert_eq!(res.len(), (width * height) as usize);
+        for y in 0..height {
+            for x in 0..width {
+                let val = (x * 1000 + y) as f32 / 257.0;
+                assert_eq!(res[(y * width + x) as usize], val.round() as u8);
+            }
+        }
+        let white: image::ImageBuffer<image::LumaA<u16>, Vec<u16>> =
+            image::ImageBuffer::from_pixel(2, 2, image::LumaA([u16::MAX, 0]));
+        assert_eq!(gray(&white), vec![255; 4]);
+
+        let test_image: image::ImageBuffer<image::Rgb<u16>, Vec<u16>> =
+            image::ImageBuffer::from_fn(width, height, |x, y| {
+                image::Rgb([(x * 1000) as u16, (y * 2000) as u16, 40000])
+            });
+        let res = gray(&test_image);
+        for y in 0..height {
+            for x in 0..width {
+                let val = (0.299 * (x * 1000) as f32 + 0.587 * (y * 2000) as f32 + 0.114 * 40000.0)
+                    / 257.0;
+                assert_eq!(
+                    res[(y * width + x) as usize],
+                    val.round() as u8,
+                    "val = {}",
+                    val
+                );
+            }
+        }
+
+        let dynamic = image::DynamicImage::ImageRgb16(test_image);
+        assert_eq!(crate::process_dynamic_image!(&dynamic, gray), res);
+        assert_eq!(crate::process_dynamic_image!(dynamic, gray), res);
+    }
+
     #[test]
     fn test_median_filter() {
         let length: u32 = 10;
@@ -0,0 +1,161 @@
+//! Pipeline of the image processing operations.
+use std::{marker::PhantomData, ops::Deref};
+
+use image::{ImageBuffer, Luma, Pixel};
+
+use super::{gaussian, gray, median_filter, resize, threshold};
+
+/// Operation applied to the raw image data of size (width, height).
+type Operation = Box<dyn Fn(&[u8], u32, u32) -> Vec<u8>>;
+
+fn to_image<P: Pixel<Subpixel = u8> + 'static>(
+    data: &[u8],
+    width: u32,
+    height: u32,
+) -> ImageBuffer<P, &[u8]> {
+    ImageBuffer::from_raw(width, height, data).unwrap()
+}
+
+/// Add the operation `$func(&image, $arg, ...)`.
+/// Image is interpreted as `Luma<u8>` after `gray` is applied, otherwise as `P`.
+macro_rules! push_operation {
+    ($self:ident, $size:expr, $func:ident $(, $arg:expr)*) => {
+        if $self.is_gray {
+            $self.push(
+                move |data, w, h| $func(&to_image::<Luma<u8>>(data, w, h) $(, $arg)*),
+                $size,
+            )
+        } else {
+            $self.push(move |data, w, h| $func(&to_image::<P>(data, w, h) $(, $arg)*), $size)
+        }
+    };
+}
+
+/// Sequence of the image processing operations (functions in `imgproc`).
+/// Operations are applied to the image in the order they are added.
+pub struct Pipeline<P, Container> {
+    /// Pairs of (operation, image size after the operation if changed).
+    operations: Vec<(Operation, Option<(u32, u32)>)>,
+    is_gray: bool,
+    _marker: PhantomData<(P, Container)>,
+}
+
+impl<P, Container> Default for Pipeline<P, Container>
+where
+    P: Pixel<Subpixel = u8> + 'static,
+    Container: Deref<Target = [u8]>,
+{
+    fn default() -> Self {
+        Pipeline {
+            operations: vec![],
+            is_gray: P::CHANNEL_COUNT == 1,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<P, Container> Pipeline<P, Container>
+where
+    P: Pixel<Subpixel = u8> + 'static,
+    Container: Deref<Target = [u8]>,
+{
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn push<F>(&mut self, func: F, size: Option<(u32, u32)>) -> &mut Self
+    where
+        F: Fn(&[u8], u32, u32) -> Vec<u8> + 'static,
+    {
+        self.operations.push((Box::new(func), size));
+        self
+    }
+
+    /// See `imgproc::gaussian`.
+    pub fn gaussian(&mut self, kernel_size: u32, sigma: f32) -> &mut Self {
+        push_operation!(self, None, gaussian, kernel_size, sigma)
+    }
+
+    /// See `imgproc::median_filter`.
+    pub fn median(&mut self, kernel_size: u32) -> &mut Self {
+        push_operation!(self, None, median_filter, kernel_size)
+    }
+
+    /// See `imgproc::resize`.
+    pub fn resize(&mut self, width: u32, height: u32) -> &mut Self {
+        push_operation!(self, Some((width, height)), resize, width, height)
+    }
+
+    /// See `imgproc::threshold`.
+    pub fn threshold(&mut self, thresh: u8) -> &mut Self {
+        push_operation!(self, None, threshold, thresh)
+    }
+
+    /// See `imgproc::gray`. Following operations are applied to the gray scale image.
+    pub fn gray(&mut self) -> &mut Self {
+        push_operation!(self, None, gray);
+        self.is_gray = true;
+        self
+    }
+
+    /// Apply all operations to `img` and return the raw data of the resulting image.
+    pub fn run(self, img: &ImageBuffer<P, Container>) -> Vec<u8> {
+        let (mut width, mut height) = img.dimensions();
+        let mut data = img.as_raw().to_vec();
+        for (operation, size) in &self.operations {
+            data = operation(&data, width, height);
+            if let Some((w, h)) = size {
+                width = *w;
+                height = *h;
+            }
+        }
+        data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{GrayImage, RgbImage};
+
+    use super::*;
+
+    fn create_image() -> RgbImage {
+        RgbImage::from_fn(100, 80, |x, y| {
+            image::Rgb([(x * 2) as u8, (y * 3) as u8, ((x + y) % 256) as u8])
+        })
+    }
+
+    #[test]
+    fn test_pipeline() {
+        let img = create_image();
+        let mut pipeline = Pipeline::new();
+        pipeline.gaussian(5, 1.0).resize(64, 64).gray();
+        let res = pipeline.run(&img);
+
+        let blurred = RgbImage::from_raw(100, 80, super::gaussian(&img, 5, 1.0)).unwrap();
+        let resized = RgbImage::from_raw(64, 64, super::resize(&blurred, 64, 64)).unwrap();
+        let expect = super::gray(&resized);
+        assert_eq!(res, expect);
+    }
+
+    #[test]
+    fn test_pipeline_after_gray() {
+        let img = create_image();
+        let mut pipeline = Pipeline::new();
+        pipeline.gray().median(3).threshold(100);
+        let res = pipeline.run(&img);
+
+        let gray_img = GrayImage::from_raw(100, 80, super::gray(&img)).unwrap();
+        let median = GrayImage::from_raw(100, 80, median_filter(&gray_img, 3)).unwrap();
+        let expect = threshold(&median, 100);
+        assert_eq!(res.len(), 100 * 80);
+        assert_eq!(res, expect);
+    }
+
+    #[test]
+    fn test_empty_pipeline() {
+        let img = create_image();
+        let res = Pipeline::new().run(&img);
+        assert_eq!(res, img.as_raw().to_vec());
+    }
+}
@@ -0,0 +1,129 @@
+//! Region of interest (ROI) of an image without copying pixels.
+use std::ops::Deref;
+
+use image::{GenericImageView, ImageBuffer, Pixel};
+
+use super::RawImageView;
+
+/// View of the rectangle region of `image` whose top-left corner is (`x`, `y`).
+/// Pixel (x, y) of the view is the pixel (`x` + x, `y` + y) of `image`.
+pub struct ImageRoi<'a, P: Pixel, Container> {
+    image: &'a ImageBuffer<P, Container>,
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+impl<'a, P, Container> ImageRoi<'a, P, Container>
+where
+    P: Pixel + 'static,
+    P::Subpixel: 'static,
+    Container: Deref<Target = [P::Subpixel]>,
+{
+    /// Panic if the region is not inside of `image`.
+    pub fn new(
+        image: &'a ImageBuffer<P, Container>,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    ) -> Self {
+        assert!(
+            x as u64 + width as u64 <= image.width() as u64
+                && y as u64 + height as u64 <= image.height() as u64,
+            "ROI ({}, {}, {}, {}) is out of the image ({}, {})",
+            x,
+            y,
+            width,
+            height,
+            image.width(),
+            image.height()
+        );
+        ImageRoi {
+            image,
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+}
+
+impl<'a, P, Container> GenericImageView for ImageRoi<'a, P, Container>
+where
+    P: Pixel + 'static,
+    P::Subpixel: 'static,
+    Container: Deref<Target = [P::Subpixel]>,
+{
+    type Pixel = P;
+    type InnerImageView = ImageBuffer<P, Container>;
+
+    fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    fn bounds(&self) -> (u32, u32, u32, u32) {
+        (self.x, self.y, self.width, self.height)
+    }
+
+    fn get_pixel(&self, x: u32, y: u32) -> P {
+        *self.image.get_pixel(self.x + x, self.y + y)
+    }
+
+    fn inner(&self) -> &Self::InnerImageView {
+        self.image
+    }
+}
+
+impl<'a, P, Container> RawImageView for ImageRoi<'a, P, Container>
+where
+    P: Pixel + 'static,
+    P::Subpixel: 'static,
+    Container: Deref<Target = [P::Subpixel]>,
+{
+    fn raw_row(&self, y: u32) -> &[P::Subpixel] {
+        let x_stride = P::CHANNEL_COUNT as usize;
+        let row = self.image.raw_row(self.y + y);
+        &row[self.x as usize * x_stride..(self.x + self.width) as usize * x_stride]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::RgbImage;
+
+    use super::*;
+    use crate::imgproc::{gaussian, gray};
+
+    fn create_image() -> RgbImage {
+        RgbImage::from_fn(40, 30, |x, y| {
+            image::Rgb([(x * 5) as u8, (y * 7) as u8, ((x * y) % 256) as u8])
+        })
+    }
+
+    #[test]
+    fn test_gray_roi() {
+        let img = create_image();
+        let roi = ImageRoi::new(&img, 0, 0, 20, 15);
+        let cropped = RgbImage::from_fn(20, 15, |x, y| *img.get_pixel(x, y));
+        assert_eq!(roi.dimensions(), (20, 15));
+        assert_eq!(gray(&roi), gray(&cropped));
+    }
+
+    #[test]
+    fn test_gaussian_roi() {
+        let img = create_image();
+        let roi = ImageRoi::new(&img, 10, 5, 20, 15);
+        let cropped = RgbImage::from_fn(20, 15, |x, y| *img.get_pixel(x + 10, y + 5));
+        assert_eq!(roi.get_pixel(0, 0), *img.get_pixel(10, 5));
+        assert_eq!(gaussian(&roi, 5, 1.0), gaussian(&cropped, 5, 1.0));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_roi_out_of_image() {
+        let img = create_image();
+        ImageRoi::new(&img, 30, 0, 20, 15);
+    }
+}
@@ -1,4 +1,4 @@
-use anyhow::{Context, Result};
+use anyhow::{ensure, Context, Result};
 use nalgebra as na;
 use std::{
     fs::{self, File},
@@ -6,9 +6,14 @@ use std::{
     path::Path,
 };
 
-use serde::Serialize;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
-use crate::feat::{keypoints::KeyPoint, matcher::Match, Distance};
+use crate::feat::{
+    descriptors::{BriefDescriptor, Descriptor},
+    keypoints::KeyPoint,
+    matcher::Match,
+    Distance,
+};
 
 pub struct ViewerWriter {
     filename: String,
@@ -142,3 +147,324 @@ impl ViewerWriter {
         Ok(output_str)
     }
 }
+
+/// Read UTF-8 json file of `path` and deserialize it into `T`.
+pub fn read_json<T: DeserializeOwned>(path: &Path) -> Result<T> {
+    let json_str =
+        fs::read_to_string(path).with_context(|| format!("Failed to read {:?}", path))?;
+    from_json_str(&json_str).with_context(|| format!("Failed to parse {:?}", path))
+}
+
+/// Deserialize json string `s` into `T`.
+pub fn from_json_str<T: DeserializeOwned>(s: &str) -> Result<T> {
+    serde_json::from_str(s).context("Invalid json string")
+}
+
+/// Serialize `data` by bincode and write it to `path`.
+#[cfg(feature = "bincode")]
+pub fn write_binary<T: Serialize>(path: &Path, data: &T) -> Result<()> {
+    let bytes = bincode::serialize(data).context("Failed to serialize data")?;
+    fs::write(path, bytes).with_context(|| format!("Failed to write {:?}", path))
+}
+
+/// Read binary file of `path` written by `write_binary` and deserialize it into `T`.
+#[cfg(feature = "bincode")]
+pub fn read_binary<T: DeserializeOwned>(path: &Path) -> Result<T> {
+    let bytes = fs::read(path).with_context(|| format!("Failed to read {:?}", path))?;
+    bincode::deserialize(&bytes).with_context(|| format!("Failed to parse {:?}", path))
+}
+
+/// Serialized form of the keypoint. `scale` is the level of the image pyramid.
+#[derive(Serialize, Deserialize)]
+struct KeyPointData {
+    x: f32,
+    y: f32,
+    scale: u32,
+    direction: f32,
+    cornerness: f32,
+}
+
+impl KeyPointData {
+    fn new(kpt: &KeyPoint) -> Self {
+        KeyPointData {
+            x: kpt.x(),
+            y: kpt.y(),
+            scale: kpt.level(),
+            direction: kpt.direction(),
+            cornerness: kpt.crf(),
+        }
+    }
+}
+
+/// Serialized form of the keypoints and descriptors written by `write_features`.
+#[derive(Serialize, Deserialize)]
+struct FeatureData {
+    keypoints: Vec<KeyPointData>,
+    descriptors: Vec<String>,
+}
+
+/// Write `keypoints` and `descriptors` to json file of `path`.
+/// Output is an object with `keypoints` (array of {x, y, scale, direction, cornerness})
+/// and `descriptors` (array of base64-encoded bits of the descriptors in little endian).
+/// `descriptors[i]` must be the descriptor of `keypoints[i]`.
+pub fn write_features(
+    path: &Path,
+    keypoints: &[KeyPoint],
+    descriptors: &[Descriptor<BriefDescriptor>],
+) -> Result<()> {
+    ensure!(
+        keypoints.len() == descriptors.len(),
+        "Number of keypoints ({}) and descriptors ({}) are mismatched.",
+        keypoints.len(),
+        descriptors.len()
+    );
+    let data = FeatureData {
+        keypoints: keypoints.iter().map(KeyPointData::new).collect(),
+        descriptors: descriptors
+            .iter()
+            .map(|desc| {
+                let n_bytes = desc.value.len().div_ceil(8);
+                let bytes: Vec<u8> = desc
+                    .value
+                    .bits
+                    .iter()
+                    .flat_map(|val| val.to_le_bytes())
+                    .take(n_bytes)
+                    .collect();
+                base64::encode(&bytes)
+            })
+            .collect(),
+    };
+    let json_str = serde_json::to_string_pretty(&data)?;
+    fs::write(path, json_str).with_context(|| format!("Failed to write {:?}", path))
+}
+
+/// Read keypoints and descriptors written by `write_features`.
+/// Number of bits of the descriptors is 8 * (number of the decoded bytes).
+pub fn read_features(path: &Path) -> Result<(Vec<KeyPoint>, Vec<Descriptor<BriefDescriptor>>)> {
+    let data: FeatureData = read_json(path)?;
+    ensure!(
+        data.keypoints.len() == data.descriptors.len(),
+        "Number of keypoints ({}) and descriptors ({}) are mismatched.",
+        data.keypoints.len(),
+        data.descriptors.len()
+    );
+    let keypoints: Vec<KeyPoint> = data
+        .keypoints
+        .iter()
+        .map(|kpt| {
+            KeyPoint::new(
+                kpt.x as usize,
+                kpt.y as usize,
+                kpt.cornerness,
+                kpt.scale,
+                kpt.direction,
+            )
+        })
+        .collect();
+    let descriptors = keypoints
+        .iter()
+        .zip(data.descriptors.iter())
+        .map(|(kpt, encoded)| {
+            let bytes = base64::decode(encoded)
+                .with_context(|| format!("Invalid base64 descriptor : {}", encoded))?;
+            let mut value = BriefDescriptor::new(bytes.len() * 8);
+            (0..bytes.len() * 8).for_each(|i| value.push((bytes[i / 8] >> (i % 8)) & 1 == 1));
+            Ok(Descriptor { kpt: *kpt, value })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok((keypoints, descriptors))
+}
+
+/// Serialized form of the match written by `write_matches`.
+#[derive(Serialize, Deserialize)]
+struct MatchData {
+    lhs_kpt: KeyPointData,
+    rhs_kpt: KeyPointData,
+    distance: f32,
+}
+
+/// Match read by `read_matches`. Descriptors are not stored.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StoredMatch {
+    pub lhs_kpt: na::Point2<f32>,
+    pub rhs_kpt: na::Point2<f32>,
+    pub distance: f32,
+}
+
+/// Write `matches` to json file of `path`.
+/// Output is an array of {lhs_kpt, rhs_kpt, distance}, where keypoints are written in the same
+/// format as `write_features`.
+pub fn write_matches<T: Distance + Clone>(path: &Path, matches: &[Match<T>]) -> Result<()> {
+    let data: Vec<MatchData> = matches
+        .iter()
+        .map(|m| MatchData {
+            lhs_kpt: KeyPointData::new(&m.matche.0.kpt),
+            rhs_kpt: KeyPointData::new(&m.matche.1.kpt),
+            distance: m.distance(),
+        })
+        .collect();
+    let json_str = serde_json::to_string_pretty(&data)?;
+    fs::write(path, json_str).with_context(|| format!("Failed to write {:?}", path))
+}
+
+/// Read matches written by `write_matches`.
+pub fn read_matches(path: &Path) -> Result<Vec<StoredMatch>> {
+    let data: Vec<MatchData> = read_json(path)?;
+    Ok(data
+        .iter()
+        .map(|m| StoredMatch {
+            lhs_kpt: na::Point2::new(m.lhs_kpt.x, m.lhs_kpt.y),
+            rhs_kpt: na::Point2::new(m.rhs_kpt.x, m.rhs_kpt.y),
+            distance: m.distance,
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use rand::Rng;
+
+    use super::*;
+
+    #[test]
+    fn test_read_json() {
+        let mut data: HashMap<String, Vec<f64>> = HashMap::new();
+        data.insert("x".to_string(), vec![0.1, -2.5, 1e-10]);
+        data.insert("y".to_string(), vec![]);
+        let path = std::env::temp_dir().join("improc_test_read_json.json");
+        fs::write(&path, serde_json::to_string_pretty(&data).unwrap()).unwrap();
+        let loaded: HashMap<String, Vec<f64>> = read_json(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, data);
+
+        let loaded: HashMap<String, Vec<f64>> =
+            from_json_str(r#"{"x": [1.0, 2.0], "y": [3.5]}"#).unwrap();
+        assert_eq!(loaded["x"], vec![1.0, 2.0]);
+        assert_eq!(loaded["y"], vec![3.5]);
+    }
+
+    #[test]
+    fn test_read_malformed_json() {
+        let path = std::env::temp_dir().join("improc_test_read_malformed_json.json");
+        fs::write(&path, r#"{"x": [1.0, 2.0"#).unwrap();
+        let res: Result<HashMap<String, Vec<f64>>> = read_json(&path);
+        fs::remove_file(&path).unwrap();
+        let err = format!("{:#}", res.unwrap_err());
+        assert!(err.contains("Failed to parse"), "{}", err);
+        assert!(err.contains("EOF"), "{}", err);
+
+        let missing: Result<HashMap<String, Vec<f64>>> =
+            read_json(Path::new("/nonexistent/improc.json"));
+        assert!(format!("{:#}", missing.unwrap_err()).contains("Failed to read"));
+    }
+
+    #[cfg(feature = "bincode")]
+    #[test]
+    fn test_binary_roundtrip() {
+        let mut rng = rand::thread_rng();
+        let descs: Vec<BriefDescriptor> = (0..100)
+            .map(|_| {
+                let mut desc = BriefDescriptor::new(256);
+                (0..256).for_each(|_| desc.push(rng.gen::<bool>()));
+                desc
+            })
+            .collect();
+        let path = std::env::temp_dir().join("improc_test_binary.bin");
+        write_binary(&path, &descs).unwrap();
+        let binary_size = fs::metadata(&path).unwrap().len() as usize;
+        let loaded: Vec<BriefDescriptor> = read_binary(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.len(), descs.len());
+        loaded.iter().zip(descs.iter()).for_each(|(lhs, rhs)| {
+            assert_eq!(lhs.bits, rhs.bits);
+            assert_eq!(lhs.len(), rhs.len());
+        });
+        let json_size = serde_json::to_string(&descs).unwrap().len();
+        assert!(
+            binary_size < json_size,
+            "binary = {}, json = {}",
+            binary_size,
+            json_size
+        );
+    }
+
+    #[test]
+    fn test_write_and_read_features() {
+        let mut rng = rand::thread_rng();
+        let keypoints: Vec<KeyPoint> = (0..10)
+            .map(|i| KeyPoint::new(i * 10, i * 7 + 3, rng.gen(), i as u32 % 3, rng.gen()))
+            .collect();
+        let descriptors: Vec<Descriptor<BriefDescriptor>> = keypoints
+            .iter()
+            .map(|kpt| {
+                let mut value = BriefDescriptor::new(256);
+                (0..256).for_each(|_| value.push(rng.gen::<bool>()));
+                Descriptor { kpt: *kpt, value }
+            })
+            .collect();
+        let path = std::env::temp_dir().join("improc_test_features.json");
+        write_features(&path, &keypoints, &descriptors).unwrap();
+        let (loaded_kpts, loaded_descs) = read_features(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded_kpts.len(), keypoints.len());
+        assert_eq!(loaded_descs.len(), descriptors.len());
+        keypoints
+            .iter()
+            .zip(loaded_kpts.iter())
+            .for_each(|(lhs, rhs)| {
+                assert_eq!(lhs.x(), rhs.x());
+                assert_eq!(lhs.y(), rhs.y());
+                assert_eq!(lhs.level(), rhs.level());
+                assert_eq!(lhs.direction(), rhs.direction());
+                assert_eq!(lhs.crf(), rhs.crf());
+            });
+        descriptors
+            .iter()
+            .zip(loaded_descs.iter())
+            .for_each(|(lhs, rhs)| {
+                assert_eq!(lhs.value.len(), rhs.value.len());
+                assert_eq!(lhs.value.bits, rhs.value.bits);
+                assert_eq!(lhs.distance(rhs), 0.0);
+                assert_eq!(lhs.kpt.x(), rhs.kpt.x());
+            });
+
+        assert!(write_features(&path, &keypoints[..3], &descriptors).is_err());
+    }
+
+    #[test]
+    fn test_write_and_read_matches() {
+        let mut rng = rand::thread_rng();
+        let mut create_desc = |x: usize, y: usize| {
+            let mut value = BriefDescriptor::new(256);
+            (0..256).for_each(|_| value.push(rng.gen::<bool>()));
+            Descriptor {
+                kpt: KeyPoint::new(x, y, 1.0, 0, 0.0),
+                value,
+            }
+        };
+        let matches: Vec<Match<BriefDescriptor>> = (0..20)
+            .map(|i| Match {
+                matche: (create_desc(i * 3, i * 5), create_desc(i * 4 + 1, 100 - i)),
+            })
+            .collect();
+        let path = std::env::temp_dir().join("improc_test_matches.json");
+        write_matches(&path, &matches).unwrap();
+        let loaded = read_matches(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.len(), matches.len());
+        matches.iter().zip(loaded.iter()).for_each(|(lhs, rhs)| {
+            let (lhs_desc, rhs_desc) = &lhs.matche;
+            assert!((lhs_desc.kpt.x() - rhs.lhs_kpt.x).abs() < 1e-5);
+            assert!((lhs_desc.kpt.y() - rhs.lhs_kpt.y).abs() < 1e-5);
+            assert!((rhs_desc.kpt.x() - rhs.rhs_kpt.x).abs() < 1e-5);
+            assert!((rhs_desc.kpt.y() - rhs.rhs_kpt.y).abs() < 1e-5);
+            assert_eq!(lhs.distance(), rhs.distance);
+        });
+    }
+}
@@ -1,9 +1,11 @@
 // independent from other module
+pub mod error;
 pub mod linalg;
 pub mod optimizer;
 pub mod utility;
 
 // depend on the other module
+pub mod config;
 pub mod ellipse;
 pub mod epipolar;
 pub mod feat;
@@ -17,38 +19,47 @@ use nalgebra as na;
 #[macro_export]
 macro_rules! process_dynamic_image {
     ($e:expr, $i:expr) => {
-        match $e {
-            image::DynamicImage::ImageLuma8(img) => $i(&img),
-            image::DynamicImage::ImageLumaA8(img) => $i(&img),
-            image::DynamicImage::ImageRgb8(img) => $i(&img),
-            image::DynamicImage::ImageRgba8(img) => $i(&img),
-            image::DynamicImage::ImageBgr8(img) => $i(&img),
-            image::DynamicImage::ImageBgra8(img) => $i(&img),
-            image::DynamicImage::ImageLuma16(img) => $i(&img),
-            image::DynamicImage::ImageLumaA16(img) => $i(&img),
-            image::DynamicImage::ImageRgb16(img) => $i(&img),
-            image::DynamicImage::ImageRgba16(img) => $i(&img),
+        // `img` is bound by reference whether `$e` is an image or a reference to it.
+        match &$e {
+            image::DynamicImage::ImageLuma8(img) => $i(img),
+            image::DynamicImage::ImageLumaA8(img) => $i(img),
+            image::DynamicImage::ImageRgb8(img) => $i(img),
+            image::DynamicImage::ImageRgba8(img) => $i(img),
+            image::DynamicImage::ImageBgr8(img) => $i(img),
+            image::DynamicImage::ImageBgra8(img) => $i(img),
+            image::DynamicImage::ImageLuma16(img) => $i(img),
+            image::DynamicImage::ImageLumaA16(img) => $i(img),
+            image::DynamicImage::ImageRgb16(img) => $i(img),
+            image::DynamicImage::ImageRgba16(img) => $i(img),
         }
     };
 }
 
 pub trait PrintDebug {
-    fn print(&self);
+    /// Return the string printed by `print`.
+    fn to_debug_string(&self) -> String;
+
+    fn print(&self) {
+        println!("{}", self.to_debug_string());
+    }
 }
 
+/// Each row is printed in one line with 6 decimal places (elements are separated by ", ").
+/// This covers the dynamic types (`na::DVector` and `na::DMatrix`) as well.
 impl<T: na::Scalar + std::fmt::Display, R: na::Dim, C: na::Dim, S: na::RawStorage<T, R, C>>
     PrintDebug for na::Matrix<T, R, C, S>
 {
-    fn print(&self) {
-        (0..self.nrows()).for_each(|row_idx| {
-            let row: Vec<T> = self.row(row_idx).iter().cloned().collect();
-            println!(
-                "{}",
-                row.as_slice()
+    fn to_debug_string(&self) -> String {
+        (0..self.nrows())
+            .map(|row_idx| {
+                self.row(row_idx)
                     .iter()
-                    .fold(String::from(""), |acc, val| format!("{}{:.3}, ", acc, val))
-            );
-        });
+                    .map(|val| format!("{:.6}", val))
+                    .collect::<Vec<String>>()
+                    .join(", ")
+                    + "\n"
+            })
+            .collect()
     }
 }
 
@@ -60,5 +71,25 @@ mod tests {
     fn test_print() {
         let mat = na::Matrix3::new(1.0, 1.111111, 2.000, 3.0, 4.0, 5.0, 6.0, 7.0, 9.0);
         mat.print();
+        assert_eq!(
+            mat.to_debug_string(),
+            "1.000000, 1.111111, 2.000000\n3.000000, 4.000000, 5.000000\n6.000000, 7.000000, 9.000000\n"
+        );
+    }
+
+    #[test]
+    fn test_print_dynamic() {
+        let mat = na::DMatrix::from_row_slice(2, 3, &[1.5, -2.25, 0.123456, 3.0, 1e-3, -7.0]);
+        mat.print();
+        let rows: Vec<Vec<f64>> = mat
+            .to_debug_string()
+            .lines()
+            .map(|line| line.split(", ").map(|val| val.parse().unwrap()).collect())
+            .collect();
+        let res = na::DMatrix::from_fn(rows.len(), rows[0].len(), |r, c| rows[r][c]);
+        assert_eq!(res, mat);
+
+        let vec = na::DVector::from_column_slice(&[0.5, -1.0]);
+        assert_eq!(vec.to_debug_string(), "0.500000\n-1.000000\n");
     }
 }
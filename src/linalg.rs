@@ -2,9 +2,16 @@ use nalgebra as na;
 
 pub mod affine;
 pub use affine::{get_rotation_matrix, inv_affine_mat, merge_affine_transforms, warp_point};
+pub mod block;
+pub mod conjugate_gradient;
+pub mod homogeneous;
 pub mod homography;
 pub mod matrix;
+pub mod quaternion;
 pub mod ransac;
+pub mod se3;
+
+const SMALL_ANGLE: f64 = 1e-4;
 
 pub fn get_identity_mat(size: usize) -> na::DMatrix<f64> {
     na::DMatrix::from_diagonal_element(size, size, 1.0)
@@ -14,22 +21,56 @@ pub fn get_zero_mat(size: usize) -> na::DMatrix<f64> {
     na::DMatrix::from_diagonal_element(size, size, 0.0)
 }
 
+/// Convert angle-axis vector `omega` (rotation of the angle |omega| around the axis
+/// omega / |omega|) to the rotation matrix by the Rodrigues formula.
+/// R = I + (sin(theta) / theta) * [omega]x + ((1 - cos(theta)) / theta^2) * [omega]x^2
 pub fn get_rotation_matrix_from_omega(omega: &[f64]) -> na::DMatrix<f64> {
-    let norm = (omega[0] * omega[0] + omega[1] * omega[1] + omega[2] * omega[2]).sqrt();
-    let l1 = omega[0] / norm;
-    let l2 = omega[1] / norm;
-    let l3 = omega[2] / norm;
-    let cos = norm.cos();
-    let sin = norm.sin();
-    #[rustfmt::skip]
-    let rot = na::DMatrix::<f64>::from_row_slice(3, 3, &[
-        cos + l1 * l1 * (1.0 - cos), l1 * l1 * (1.0 - cos) - l3 * sin, l1 * l1 * (1.0 - cos) + l2 * sin,
-        l2 * l1 * (1.0 - cos) + l3 * sin, cos + l2 * l2 * (1.0 - cos), l2 * l3 * (1.0 - cos) - l1 * sin,
-        l3 * l1 * (1.0 - cos) - l2 * sin, l3 * l2 * (1.0 - cos) + l1 * sin, cos + l3 * l3 * (1.0 - cos),
+    let omega = na::DVector::from_column_slice(&omega[..3]);
+    let theta2 = omega.norm_squared();
+    let theta = theta2.sqrt();
+    // Taylor expansion is used for small angles to avoid the division by zero.
+    let (a, b) = if theta < SMALL_ANGLE {
+        (1.0 - theta2 / 6.0, 0.5 - theta2 / 24.0)
+    } else {
+        (theta.sin() / theta, (1.0 - theta.cos()) / theta2)
+    };
+    let cross = vector_cross_matrix(&omega);
+    get_identity_mat(3) + a * &cross + b * &cross * &cross
+}
+
+/// Convert rotation matrix `r` to the angle-axis vector (inverse of
+/// `get_rotation_matrix_from_omega`) by the matrix logarithm.
+/// Angle of the returned vector is in [0, pi].
+pub fn rotation_matrix_to_axis_angle(r: &na::DMatrix<f64>) -> na::DVector<f64> {
+    let cos = ((r.trace() - 1.0) / 2.0).clamp(-1.0, 1.0);
+    let theta = cos.acos();
+    // log(R) = theta / (2 sin(theta)) * (R - R^T)
+    let vee = na::DVector::from_column_slice(&[
+        r[(2, 1)] - r[(1, 2)],
+        r[(0, 2)] - r[(2, 0)],
+        r[(1, 0)] - r[(0, 1)],
     ]);
-    rot
+    if theta < SMALL_ANGLE {
+        return vee * (0.5 + theta * theta / 12.0);
+    }
+    if std::f64::consts::PI - theta > SMALL_ANGLE {
+        return vee * (theta / (2.0 * theta.sin()));
+    }
+    // theta is close to pi : axis n is calculated from
+    // (R + R^T) / 2 = cos(theta) * I + (1 - cos(theta)) * n * n^T
+    let idx = (0..3)
+        .max_by(|lhs, rhs| r[(*lhs, *lhs)].partial_cmp(&r[(*rhs, *rhs)]).unwrap())
+        .unwrap();
+    let mut axis = (r.column(idx) + r.transpose().column(idx)) / 2.0;
+    axis[idx] -= cos;
+    axis.normalize_mut();
+    if axis.dot(&vee) < 0.0 {
+        axis = -axis;
+    }
+    axis * theta
 }
 
+/// Calculate a . (b x c) (determinant of the matrix [a b c]).
 pub fn scalar_triple_product(
     a: &na::DVector<f64>,
     b: &na::DVector<f64>,
@@ -38,6 +79,12 @@ pub fn scalar_triple_product(
     a.dot(&b.cross(c))
 }
 
+/// Calculate cross product a x b of 3D vectors.
+pub fn cross_product(a: &na::DVector<f64>, b: &na::DVector<f64>) -> na::DVector<f64> {
+    a.cross(b)
+}
+
+/// Return the skew-symmetric matrix [v]x which satisfies [v]x w = v x w.
 pub fn vector_cross_matrix(from: &na::DVector<f64>) -> na::DMatrix<f64> {
     #[rustfmt::skip]
     let mat = na::DMatrix::from_row_slice(3, 3, &[
@@ -47,3 +94,101 @@ pub fn vector_cross_matrix(from: &na::DVector<f64>) -> na::DMatrix<f64> {
     ]);
     mat
 }
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use super::*;
+
+    #[test]
+    fn test_rodrigues_roundtrip() {
+        let mut rng = rand::thread_rng();
+        (0..100).for_each(|_| {
+            let axis = na::Vector3::new(rng.gen::<f64>() - 0.5, rng.gen(), rng.gen::<f64>() - 0.5)
+                .normalize();
+            let angle = rng.gen::<f64>() * std::f64::consts::PI;
+            let omega = axis * angle;
+            let rot = get_rotation_matrix_from_omega(omega.as_slice());
+            let gt = na::Rotation3::from_scaled_axis(omega);
+            assert!((&rot - gt.matrix()).norm() < 1e-9, "rot = {}", rot);
+            assert!((rot.determinant() - 1.0).abs() < 1e-9);
+
+            let res = rotation_matrix_to_axis_angle(&rot);
+            assert!((res.clone() - omega).norm() < 1e-6, "{} vs {}", res, omega);
+        });
+
+        // angle close to pi
+        let omega = na::Vector3::new(0.6, -0.8, 0.0) * (std::f64::consts::PI - 1e-6);
+        let res = rotation_matrix_to_axis_angle(&get_rotation_matrix_from_omega(omega.as_slice()));
+        assert!((res.clone() - omega).norm() < 1e-5, "{} vs {}", res, omega);
+    }
+
+    #[test]
+    fn test_rodrigues_small_angle() {
+        let rot = get_rotation_matrix_from_omega(&[0.0, 0.0, 0.0]);
+        assert_eq!(rot, get_identity_mat(3));
+        assert_eq!(rotation_matrix_to_axis_angle(&rot), na::DVector::zeros(3));
+
+        let omega = [1e-10, -2e-9, 3e-12];
+        let rot = get_rotation_matrix_from_omega(&omega);
+        assert!(rot.iter().all(|val| val.is_finite()));
+        let res = rotation_matrix_to_axis_angle(&rot);
+        assert!(res.iter().all(|val| val.is_finite()));
+        assert!((res - na::DVector::from_column_slice(&omega)).norm() < 1e-15);
+
+        // continuity around the threshold of the Taylor expansion
+        let lhs = get_rotation_matrix_from_omega(&[0.0, SMALL_ANGLE * 0.999, 0.0]);
+        let rhs = get_rotation_matrix_from_omega(&[0.0, SMALL_ANGLE * 1.001, 0.0]);
+        assert!((lhs - rhs).norm() < 1e-6);
+    }
+
+    #[test]
+    fn test_scalar_triple_product() {
+        let a = na::DVector::from_column_slice(&[1.0, 0.0, 0.0]);
+        let b = na::DVector::from_column_slice(&[0.0, 1.0, 0.0]);
+        let c = na::DVector::from_column_slice(&[0.0, 0.0, 1.0]);
+        assert_eq!(scalar_triple_product(&a, &b, &c), 1.0);
+        assert_eq!(scalar_triple_product(&b, &a, &c), -1.0);
+
+        let mut rng = rand::thread_rng();
+        let mat = na::DMatrix::<f64>::from_fn(3, 3, |_, _| rng.gen_range(-1.0..1.0));
+        let res = scalar_triple_product(
+            &mat.column(0).clone_owned(),
+            &mat.column(1).clone_owned(),
+            &mat.column(2).clone_owned(),
+        );
+        assert!((res - mat.determinant()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_cross_product() {
+        let mut rng = rand::thread_rng();
+        (0..10).for_each(|_| {
+            let v = na::DVector::from_fn(3, |_, _| rng.gen_range(-1.0..1.0));
+            let w = na::DVector::from_fn(3, |_, _| rng.gen_range(-1.0..1.0));
+            let res = cross_product(&v, &w);
+            assert!((vector_cross_matrix(&v) * &w - &res).norm() < 1e-12);
+            assert!(res.dot(&v).abs() < 1e-12);
+            assert!(res.dot(&w).abs() < 1e-12);
+        });
+
+        let i = na::DVector::from_column_slice(&[1.0, 0.0, 0.0]);
+        let j = na::DVector::from_column_slice(&[0.0, 1.0, 0.0]);
+        assert_eq!(
+            cross_product(&i, &j),
+            na::DVector::from_column_slice(&[0.0, 0.0, 1.0])
+        );
+    }
+
+    #[test]
+    fn test_zero_vector() {
+        let zero = na::DVector::<f64>::zeros(3);
+        let v = na::DVector::from_column_slice(&[1.0, -2.0, 3.0]);
+        assert_eq!(vector_cross_matrix(&zero), get_zero_mat(3));
+        assert_eq!(cross_product(&zero, &v), zero);
+        assert_eq!(cross_product(&v, &zero), zero);
+        assert_eq!(scalar_triple_product(&zero, &v, &v), 0.0);
+        assert_eq!(scalar_triple_product(&v, &zero, &v), 0.0);
+    }
+}
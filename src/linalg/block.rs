@@ -0,0 +1,129 @@
+//! Construction and extraction of the block matrices.
+use nalgebra as na;
+
+/// Create block diagonal matrix diag(blocks[0], blocks[1], ...).
+pub fn block_diagonal(blocks: &[na::DMatrix<f64>]) -> na::DMatrix<f64> {
+    let rows = blocks.iter().map(|b| b.nrows()).sum();
+    let cols = blocks.iter().map(|b| b.ncols()).sum();
+    let mut mat = na::DMatrix::zeros(rows, cols);
+    let (mut r, mut c) = (0, 0);
+    blocks.iter().for_each(|block| {
+        mat.slice_mut((r, c), block.shape()).copy_from(block);
+        r += block.nrows();
+        c += block.ncols();
+    });
+    mat
+}
+
+/// Create block upper triangular matrix [[a, b], [0, c]].
+/// Panic if the rows of `a` and `b` or the columns of `b` and `c` do not match.
+pub fn block_upper_tri(
+    a: &na::DMatrix<f64>,
+    b: &na::DMatrix<f64>,
+    c: &na::DMatrix<f64>,
+) -> na::DMatrix<f64> {
+    assert_eq!(a.nrows(), b.nrows(), "Rows of `a` and `b` do not match");
+    assert_eq!(b.ncols(), c.ncols(), "Columns of `b` and `c` do not match");
+    let mut mat = na::DMatrix::zeros(a.nrows() + c.nrows(), a.ncols() + b.ncols());
+    mat.slice_mut((0, 0), a.shape()).copy_from(a);
+    mat.slice_mut((0, a.ncols()), b.shape()).copy_from(b);
+    mat.slice_mut((a.nrows(), a.ncols()), c.shape())
+        .copy_from(c);
+    mat
+}
+
+/// Calculate Kronecker product of `a` and `b`. (i, j) block of the result is a_ij * b.
+pub fn kronecker(a: &na::DMatrix<f64>, b: &na::DMatrix<f64>) -> na::DMatrix<f64> {
+    let (rows, cols) = b.shape();
+    let mut mat = na::DMatrix::zeros(a.nrows() * rows, a.ncols() * cols);
+    for r in 0..a.nrows() {
+        for c in 0..a.ncols() {
+            mat.slice_mut((r * rows, c * cols), (rows, cols))
+                .copy_from(&(a[(r, c)] * b));
+        }
+    }
+    mat
+}
+
+/// Copy the `rows` x `cols` block of `m` whose top-left corner is (`row_start`, `col_start`).
+/// Panic if the block is out of `m`.
+pub fn extract_block(
+    m: &na::DMatrix<f64>,
+    row_start: usize,
+    col_start: usize,
+    rows: usize,
+    cols: usize,
+) -> na::DMatrix<f64> {
+    m.slice((row_start, col_start), (rows, cols)).clone_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_block_diagonal() {
+        let res = block_diagonal(&[na::DMatrix::identity(2, 2), na::DMatrix::identity(3, 3)]);
+        assert_eq!(res, na::DMatrix::identity(5, 5));
+
+        let a = na::DMatrix::from_row_slice(1, 2, &[1.0, 2.0]);
+        let b = na::DMatrix::from_row_slice(2, 1, &[3.0, 4.0]);
+        #[rustfmt::skip]
+        let expect = na::DMatrix::from_row_slice(3, 3, &[
+            1.0, 2.0, 0.0,
+            0.0, 0.0, 3.0,
+            0.0, 0.0, 4.0,
+        ]);
+        assert_eq!(block_diagonal(&[a, b]), expect);
+    }
+
+    #[test]
+    fn test_block_upper_tri() {
+        let a = na::DMatrix::from_row_slice(1, 2, &[1.0, 2.0]);
+        let b = na::DMatrix::from_row_slice(1, 1, &[3.0]);
+        let c = na::DMatrix::from_row_slice(2, 1, &[4.0, 5.0]);
+        #[rustfmt::skip]
+        let expect = na::DMatrix::from_row_slice(3, 3, &[
+            1.0, 2.0, 3.0,
+            0.0, 0.0, 4.0,
+            0.0, 0.0, 5.0,
+        ]);
+        let res = block_upper_tri(&a, &b, &c);
+        assert_eq!(res, expect);
+        assert_eq!(extract_block(&res, 0, 0, 1, 2), a);
+        assert_eq!(extract_block(&res, 0, 2, 1, 1), b);
+        assert_eq!(extract_block(&res, 1, 2, 2, 1), c);
+    }
+
+    #[test]
+    fn test_kronecker() {
+        let a = na::DMatrix::from_row_slice(2, 3, &[1.0, -2.0, 3.0, 0.5, 4.0, -1.0]);
+        let identity = na::DMatrix::identity(2, 2);
+        assert_eq!(
+            kronecker(&identity, &a),
+            block_diagonal(&[a.clone(), a.clone()])
+        );
+
+        let a = na::DMatrix::from_row_slice(2, 2, &[1.0, 2.0, 3.0, 4.0]);
+        #[rustfmt::skip]
+        let expect = na::DMatrix::from_row_slice(4, 4, &[
+            1.0, 0.0, 2.0, 0.0,
+            0.0, 1.0, 0.0, 2.0,
+            3.0, 0.0, 4.0, 0.0,
+            0.0, 3.0, 0.0, 4.0,
+        ]);
+        assert_eq!(kronecker(&a, &identity), expect);
+        assert_eq!(kronecker(&a, &identity), a.kronecker(&identity));
+    }
+
+    #[test]
+    fn test_extract_block() {
+        let m = na::DMatrix::from_fn(4, 5, |r, c| (r * 5 + c) as f64);
+        #[rustfmt::skip]
+        let expect = na::DMatrix::from_row_slice(2, 3, &[
+            6.0, 7.0, 8.0,
+            11.0, 12.0, 13.0,
+        ]);
+        assert_eq!(extract_block(&m, 1, 1, 2, 3), expect);
+    }
+}
@@ -0,0 +1,143 @@
+//! Conjugate gradient method for the linear equation with the positive definite matrix.
+use nalgebra as na;
+
+use crate::{
+    ensure,
+    error::{ImprocError, Result},
+};
+
+/// Solve Ax = b by the conjugate gradient method.
+/// - `a` : symmetric positive definite matrix.
+/// - `x0` : initial value of x.
+/// - `max_iter` : maximum number of the iterations.
+/// - `tol` : iteration stops when the norm of the residual |b - Ax| is smaller than `tol`.
+///
+/// Return `Err` if the residual does not converge within `max_iter` iterations.
+pub fn conjugate_gradient(
+    a: &na::DMatrix<f64>,
+    b: &na::DVector<f64>,
+    x0: na::DVector<f64>,
+    max_iter: usize,
+    tol: f64,
+) -> Result<na::DVector<f64>> {
+    conjugate_gradient_impl(a, b, x0, None, max_iter, tol)
+}
+
+/// Solve Ax = b by the preconditioned conjugate gradient method.
+/// - `precond` : preconditioner M which approximates A^-1 (e.g. inverse of the diagonal of A).
+///
+/// See `conjugate_gradient` for the other arguments.
+pub fn preconditioned_cg(
+    a: &na::DMatrix<f64>,
+    b: &na::DVector<f64>,
+    x0: na::DVector<f64>,
+    precond: &na::DMatrix<f64>,
+    max_iter: usize,
+    tol: f64,
+) -> Result<na::DVector<f64>> {
+    ensure!(
+        precond.shape() == a.shape(),
+        ImprocError::ShapeMismatch {
+            expected: a.shape(),
+            got: precond.shape()
+        }
+    );
+    conjugate_gradient_impl(a, b, x0, Some(precond), max_iter, tol)
+}
+
+fn conjugate_gradient_impl(
+    a: &na::DMatrix<f64>,
+    b: &na::DVector<f64>,
+    mut x: na::DVector<f64>,
+    precond: Option<&na::DMatrix<f64>>,
+    max_iter: usize,
+    tol: f64,
+) -> Result<na::DVector<f64>> {
+    ensure!(
+        a.is_square() && a.nrows() == b.nrows() && a.nrows() == x.nrows(),
+        ImprocError::ShapeMismatch {
+            expected: (b.nrows(), b.nrows()),
+            got: a.shape()
+        }
+    );
+    let apply_precond = |r: &na::DVector<f64>| match precond {
+        Some(m) => m * r,
+        None => r.clone(),
+    };
+
+    let mut r = b - a * &x;
+    let mut z = apply_precond(&r);
+    let mut p = z.clone();
+    let mut rz = r.dot(&z);
+    for _ in 0..max_iter {
+        if r.norm() < tol {
+            return Ok(x);
+        }
+        let ap = a * &p;
+        let pap = p.dot(&ap);
+        ensure!(
+            pap > 0.0,
+            "Matrix is not positive definite (p^T A p = {})",
+            pap
+        );
+        let alpha = rz / pap;
+        x += alpha * &p;
+        r -= alpha * ap;
+        z = apply_precond(&r);
+        let rz_next = r.dot(&z);
+        p = &z + (rz_next / rz) * p;
+        rz = rz_next;
+    }
+    ensure!(
+        r.norm() < tol,
+        "Conjugate gradient did not converge : residual = {}",
+        r.norm()
+    );
+    Ok(x)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use super::*;
+    use crate::linalg::matrix::le_lstsq;
+
+    fn create_problem(n: usize) -> (na::DMatrix<f64>, na::DVector<f64>) {
+        let mut rng = rand::thread_rng();
+        let m = na::DMatrix::from_fn(n, n, |_, _| rng.gen_range(-1.0..1.0));
+        let a = &m * m.transpose() + na::DMatrix::identity(n, n);
+        let b = na::DVector::from_fn(n, |_, _| rng.gen_range(-1.0..1.0));
+        (a, b)
+    }
+
+    #[test]
+    fn test_conjugate_gradient() {
+        let n = 100;
+        let tol = 1e-8;
+        let (a, b) = create_problem(n);
+        let ans = le_lstsq(&a, &b).unwrap();
+
+        let res = conjugate_gradient(&a, &b, na::DVector::zeros(n), 1000, tol).unwrap();
+        assert!(
+            (&res - &ans).norm() < tol,
+            "diff = {}",
+            (&res - &ans).norm()
+        );
+
+        let precond = na::DMatrix::from_diagonal(&a.diagonal().map(|val| 1.0 / val));
+        let res = preconditioned_cg(&a, &b, na::DVector::zeros(n), &precond, 1000, tol).unwrap();
+        assert!(
+            (&res - &ans).norm() < tol,
+            "diff = {}",
+            (&res - &ans).norm()
+        );
+    }
+
+    #[test]
+    fn test_not_converged() {
+        let n = 100;
+        let (a, b) = create_problem(n);
+        assert!(conjugate_gradient(&a, &b, na::DVector::zeros(n), 2, 1e-8).is_err());
+    }
+}
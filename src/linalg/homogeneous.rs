@@ -0,0 +1,112 @@
+//! Conversion between the euclidean and the homogeneous coordinates.
+use nalgebra as na;
+
+use crate::{
+    ensure,
+    error::{Context, Result},
+};
+
+/// Append 1 to the last of `pt`.
+pub fn to_homogeneous(pt: &na::DVector<f64>) -> na::DVector<f64> {
+    pt.push(1.0)
+}
+
+/// Divide `pt` by the last component and remove it.
+/// Return `Err` if the last component is zero (point at infinity).
+pub fn from_homogeneous(pt: &na::DVector<f64>) -> Result<na::DVector<f64>> {
+    let n = pt.nrows();
+    ensure!(n > 0, "Empty vector can not be converted");
+    let w = pt[n - 1];
+    ensure!(
+        w != 0.0,
+        "Point at infinity can not be converted : {:?}",
+        pt.as_slice()
+    );
+    Ok(pt.rows(0, n - 1) / w)
+}
+
+/// Scale `pt` so that the last component is 1.
+/// Point at infinity (last component is zero) is normalized to the unit vector instead.
+pub fn normalize_homogeneous(pt: &na::DVector<f64>) -> na::DVector<f64> {
+    match pt.as_slice().last() {
+        Some(&w) if w != 0.0 => pt / w,
+        _ => pt.normalize(),
+    }
+}
+
+pub fn to_homogeneous_2d(pt: &na::Point2<f64>) -> na::Vector3<f64> {
+    pt.to_homogeneous()
+}
+
+/// See `from_homogeneous`.
+pub fn from_homogeneous_2d(pt: &na::Vector3<f64>) -> Result<na::Point2<f64>> {
+    na::Point2::from_homogeneous(*pt).with_context(|| {
+        format!(
+            "Point at infinity can not be converted : {:?}",
+            pt.as_slice()
+        )
+    })
+}
+
+pub fn to_homogeneous_3d(pt: &na::Point3<f64>) -> na::Vector4<f64> {
+    pt.to_homogeneous()
+}
+
+/// See `from_homogeneous`.
+pub fn from_homogeneous_3d(pt: &na::Vector4<f64>) -> Result<na::Point3<f64>> {
+    na::Point3::from_homogeneous(*pt).with_context(|| {
+        format!(
+            "Point at infinity can not be converted : {:?}",
+            pt.as_slice()
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() {
+        let pt = na::DVector::from_column_slice(&[2.0, -4.0, 0.5]);
+        let homo = to_homogeneous(&pt);
+        assert_eq!(homo, na::DVector::from_column_slice(&[2.0, -4.0, 0.5, 1.0]));
+        assert_eq!(from_homogeneous(&homo).unwrap(), pt);
+
+        let homo = na::DVector::from_column_slice(&[2.0, -4.0, 0.5, 2.0]);
+        let res = to_homogeneous(&from_homogeneous(&homo).unwrap());
+        assert_eq!(res, normalize_homogeneous(&homo));
+        assert_eq!(res, homo / 2.0);
+
+        let pt = na::Point2::new(3.0, -1.0);
+        assert_eq!(from_homogeneous_2d(&to_homogeneous_2d(&pt)).unwrap(), pt);
+        assert_eq!(
+            from_homogeneous_2d(&na::Vector3::new(6.0, -2.0, 2.0)).unwrap(),
+            pt
+        );
+        let pt = na::Point3::new(3.0, -1.0, 0.25);
+        assert_eq!(from_homogeneous_3d(&to_homogeneous_3d(&pt)).unwrap(), pt);
+    }
+
+    #[test]
+    fn test_normalize_homogeneous() {
+        let pt = na::DVector::from_column_slice(&[3.0, -6.0, -3.0]);
+        let res = normalize_homogeneous(&pt);
+        assert_eq!(res[2], 1.0);
+        assert_eq!(res, na::DVector::from_column_slice(&[-1.0, 2.0, 1.0]));
+
+        // point at infinity
+        let pt = na::DVector::from_column_slice(&[3.0, 4.0, 0.0]);
+        assert_eq!(
+            normalize_homogeneous(&pt),
+            na::DVector::from_column_slice(&[0.6, 0.8, 0.0])
+        );
+    }
+
+    #[test]
+    fn test_point_at_infinity() {
+        assert!(from_homogeneous(&na::DVector::from_column_slice(&[1.0, 2.0, 0.0])).is_err());
+        assert!(from_homogeneous_2d(&na::Vector3::new(1.0, 2.0, 0.0)).is_err());
+        assert!(from_homogeneous_3d(&na::Vector4::new(1.0, 2.0, 3.0, 0.0)).is_err());
+    }
+}
@@ -1,23 +1,39 @@
 //! Solver for eigenvalue problems
-use anyhow::{ensure, Context, Result};
 use nalgebra as na;
 
-use crate::linalg::get_identity_mat;
+use crate::{
+    ensure,
+    error::{Context, ImprocError, Result},
+    linalg::get_identity_mat,
+};
 
 /// calculate least square solution of linear equation.
 /// Find x which minimize |Ax - b|.
 pub fn le_lstsq(matrix: &na::DMatrix<f64>, params: &na::DVector<f64>) -> Result<na::DVector<f64>> {
-    Ok(pseudo_inverse(matrix).context("Failed to calc pseudo inverse.")? * params)
+    Ok(pseudo_inverse(matrix)? * params)
 }
 
 /// calculate least square solution of eigenvalue problem.
 /// Minimize |Ax| subject to |x| = 1.
 pub fn lstsq(matrix: &na::DMatrix<f64>) -> Result<na::DVector<f64>> {
+    Ok(lstsq_with_condition(matrix)?.0)
+}
+
+/// Same as `lstsq`, but also return the condition number of `matrix` excluding the null space
+/// of the solution (ratio of the largest singular value to the second smallest one).
+/// Return `f64::INFINITY` as the condition number if the solution is not unique.
+pub fn lstsq_with_condition(matrix: &na::DMatrix<f64>) -> Result<(na::DVector<f64>, f64)> {
     let svd = matrix.clone().svd(false, true);
     let v_t: na::DMatrix<f64> = svd.v_t.context("Failed to get SVD value")?;
     let (row, _) = svd.singular_values.argmin();
-    // println!("singular values : {:?}", svd.singular_values.as_slice());
-    Ok(v_t.row(row).transpose().clone_owned())
+    let mut singular_values: Vec<f64> = svd.singular_values.iter().copied().collect();
+    singular_values.sort_by(|lhs, rhs| rhs.total_cmp(lhs));
+    let cond = match singular_values.len() {
+        0 | 1 => 1.0,
+        n if singular_values[n - 2] == 0.0 => f64::INFINITY,
+        n => singular_values[0] / singular_values[n - 2],
+    };
+    Ok((v_t.row(row).transpose().clone_owned(), cond))
 }
 
 /// calculate least square solution of a generalized eigenvalue problem.
@@ -28,7 +44,10 @@ pub fn constrained_lstsq(
 ) -> Result<na::DVector<f64>> {
     ensure!(
         matrix.ncols() == constrained.ncols(),
-        "Invalid matrix size."
+        ImprocError::ShapeMismatch {
+            expected: (constrained.nrows(), matrix.ncols()),
+            got: constrained.shape()
+        }
     );
     let svd = constrained.clone().svd(false, true);
     let sing_vals = svd.singular_values;
@@ -64,7 +83,7 @@ pub fn constrained_lstsq(
     }
 
     let a_hat2: na::DMatrix<f64> = na::Matrix::from_columns(&a_hat2_vec);
-    let a_hat2_inv = pseudo_inverse(&a_hat2).context("Failed to calculate pseudo inverse.")?;
+    let a_hat2_inv = pseudo_inverse(&a_hat2)?;
     // A'' = (A'_2 * A'_2^+ - I) * A'_1 D_1^-1
     let a_hhat: na::DMatrix<f64> = (a_hat2 * a_hat2_inv.clone() - get_identity_mat(matrix.nrows()))
         * a_hat1.clone()
@@ -80,36 +99,128 @@ pub fn constrained_lstsq(
 }
 
 /// Calculate pseudo inverse of a given matrix.
+/// Singular values smaller than 1e-5 are regarded as zero.
+/// Return zero matrix if all singular values are (nearly) zero.
 pub fn pseudo_inverse(matrix: &na::DMatrix<f64>) -> Result<na::DMatrix<f64>> {
-    pseudo_inverse_with_rank(matrix, matrix.nrows())
+    pseudo_inverse_with_tolerance(matrix, 1e-5)
+}
+
+/// Calculate pseudo inverse of a given matrix.
+/// Singular values smaller than `tol` are regarded as zero.
+pub fn pseudo_inverse_with_tolerance(
+    matrix: &na::DMatrix<f64>,
+    tol: f64,
+) -> Result<na::DMatrix<f64>> {
+    pseudo_inverse_impl(matrix, tol, matrix.nrows().min(matrix.ncols()))
 }
 
+/// Calculate pseudo inverse of a given matrix using only the top `rank` singular values.
+/// Singular values smaller than 1e-5 are regarded as zero.
 pub fn pseudo_inverse_with_rank(
     matrix: &na::DMatrix<f64>,
     rank: usize,
+) -> Result<na::DMatrix<f64>> {
+    pseudo_inverse_impl(matrix, 1e-5, rank)
+}
+
+fn pseudo_inverse_impl(
+    matrix: &na::DMatrix<f64>,
+    tol: f64,
+    rank: usize,
 ) -> Result<na::DMatrix<f64>> {
     let svd = matrix.clone().svd(true, true);
-    let mut inv_d = na::Matrix::from_diagonal(&na::DVector::from_vec(
-        svd.singular_values
-            .iter()
-            .map(|val| if *val < 1e-5 { 0.0 } else { 1.0 / val })
-            .collect::<Vec<f64>>(),
-    ));
-    for idx in rank..inv_d.nrows() {
-        inv_d[idx] = 0.0;
-    }
+    let singular_values = svd.singular_values.as_slice();
+    // singular values of `svd` are not necessarily sorted.
+    let mut indices: Vec<usize> = (0..singular_values.len()).collect();
+    indices.sort_by(|&lhs, &rhs| {
+        singular_values[rhs]
+            .partial_cmp(&singular_values[lhs])
+            .unwrap()
+    });
+    let mut inv_d = na::DVector::<f64>::zeros(singular_values.len());
+    indices
+        .iter()
+        .take(rank)
+        .filter(|&&idx| singular_values[idx] >= tol)
+        .for_each(|&idx| inv_d[idx] = 1.0 / singular_values[idx]);
     Ok(svd.v_t.context("Failed to get SVD value")?.transpose()
-        * inv_d
+        * na::DMatrix::from_diagonal(&inv_d)
         * svd.u.context("Failed to get SVD value")?.transpose())
 }
 
+/// Return true if `matrix` is symmetric positive definite (Cholesky decomposition succeeds).
+pub fn is_positive_definite(matrix: &na::DMatrix<f64>) -> bool {
+    matrix.is_square() && matrix.clone().cholesky().is_some()
+}
+
+/// Solve the linear equation Ax = b where `a` is symmetric positive definite.
+/// `a` is decomposed to L L^T, and x is calculated by forward and backward substitution.
+pub fn cholesky_solve(a: &na::DMatrix<f64>, b: &na::DVector<f64>) -> Result<na::DVector<f64>> {
+    ensure!(
+        a.is_square() && a.nrows() == b.nrows(),
+        ImprocError::ShapeMismatch {
+            expected: (b.nrows(), b.nrows()),
+            got: a.shape()
+        }
+    );
+    let l = a
+        .clone()
+        .cholesky()
+        .context("Matrix is not positive definite")?
+        .unpack();
+    let y = l
+        .solve_lower_triangular(b)
+        .ok_or(ImprocError::SingularMatrix)?;
+    l.transpose()
+        .solve_upper_triangular(&y)
+        .ok_or(ImprocError::SingularMatrix)
+}
+
+/// Return the largest and smallest singular values of `matrix`.
+fn singular_value_range(matrix: &na::DMatrix<f64>) -> (f64, f64) {
+    let singular_values = matrix.singular_values();
+    (singular_values.max(), singular_values.min())
+}
+
+/// Calculate condition number (ratio of the largest singular value to the smallest one).
+/// Return `f64::INFINITY` if the smallest singular value is zero.
+pub fn condition_number(matrix: &na::DMatrix<f64>) -> f64 {
+    let (max, min) = singular_value_range(matrix);
+    if min == 0.0 {
+        return f64::INFINITY;
+    }
+    max / min
+}
+
+/// Calculate log10 of the condition number.
+/// Return `f64::INFINITY` if the smallest singular value is zero.
+pub fn log_condition_number(matrix: &na::DMatrix<f64>) -> f64 {
+    let (max, min) = singular_value_range(matrix);
+    if min == 0.0 {
+        return f64::INFINITY;
+    }
+    max.log10() - min.log10()
+}
+
 /// apply SVD decomposition to `matrix`.
 /// Rows or columns of the resulting matrices is ordered by singular value.
+/// Return tuple of (U, singular values, V) which satisfies `matrix = U diag(singular values) V^T`.
 pub fn reordered_svd(
     matrix: na::DMatrix<f64>,
 ) -> Result<(na::DMatrix<f64>, na::DVector<f64>, na::DMatrix<f64>)> {
     let svd = matrix.svd(true, true);
-    let singular_values = svd.singular_values.as_slice();
+    let u: na::DMatrix<f64> = svd.u.context("Failed to calc svd.")?;
+    let v_t: na::DMatrix<f64> = svd.v_t.context("Failed to calc svd.")?;
+    Ok(reorder_singular_values(&u, &svd.singular_values, &v_t))
+}
+
+/// Sort singular values in descending order and reorder columns of `u` and `v` accordingly.
+/// Return tuple of (U, singular values, V).
+fn reorder_singular_values(
+    u: &na::DMatrix<f64>,
+    singular_values: &na::DVector<f64>,
+    v_t: &na::DMatrix<f64>,
+) -> (na::DMatrix<f64>, na::DVector<f64>, na::DMatrix<f64>) {
     let mut indices: Vec<usize> = (0..singular_values.len()).collect();
     indices.sort_by(|&lhs, &rhs| {
         singular_values[rhs]
@@ -120,15 +231,16 @@ pub fn reordered_svd(
         indices.len(),
         indices.iter().map(|&idx| singular_values[idx]),
     );
-    let u: na::DMatrix<f64> = svd.u.context("Failed to calc svd.")?;
     let u = na::DMatrix::<f64>::from_fn(u.nrows(), u.ncols(), |r, c| u[(r, indices[c])]);
-    let v_t: na::DMatrix<f64> = svd.v_t.context("Failed to calc svd.")?;
-    let v = na::DMatrix::<f64>::from_fn(v_t.ncols(), v_t.nrows(), |r, c| v_t[(c, indices[r])]);
-    Ok((u, diag, v))
+    // column c of V is row indices[c] of V^T
+    let v = na::DMatrix::<f64>::from_fn(v_t.ncols(), v_t.nrows(), |r, c| v_t[(indices[c], r)]);
+    (u, diag, v)
 }
 
 #[cfg(test)]
 mod tests {
+    use rand::Rng;
+
     use crate::ellipse::test_utility::test_util::{compare_matrix, compare_vector};
 
     use super::*;
@@ -168,6 +280,95 @@ mod tests {
         compare_matrix(&ans, &res);
     }
 
+    fn random_rotation<R: Rng>(rng: &mut R) -> na::DMatrix<f64> {
+        let rot = na::Rotation3::from_euler_angles(
+            rng.gen_range(-3.0..3.0),
+            rng.gen_range(-1.5..1.5),
+            rng.gen_range(-3.0..3.0),
+        );
+        na::DMatrix::from_column_slice(3, 3, rot.matrix().as_slice())
+    }
+
+    fn diag(values: &[f64]) -> na::DMatrix<f64> {
+        na::DMatrix::from_diagonal(&na::DVector::from_column_slice(values))
+    }
+
+    #[test]
+    fn test_pseudo_inverse_with_rank() {
+        let mut rng = rand::thread_rng();
+        let u = random_rotation(&mut rng);
+        let v = random_rotation(&mut rng);
+        let ans = &v * diag(&[1.0 / 3.0, 0.0, 0.5]) * u.transpose();
+
+        // one singular value is zero
+        let mat = &u * diag(&[3.0, 0.0, 2.0]) * v.transpose();
+        compare_matrix(&ans, &pseudo_inverse_with_rank(&mat, 2).unwrap());
+
+        // the smallest singular value is truncated
+        let mat = &u * diag(&[3.0, 0.5, 2.0]) * v.transpose();
+        compare_matrix(&ans, &pseudo_inverse_with_rank(&mat, 2).unwrap());
+    }
+
+    #[test]
+    fn test_pseudo_inverse_with_tolerance() {
+        let mut rng = rand::thread_rng();
+        let u = random_rotation(&mut rng);
+        let v = random_rotation(&mut rng);
+        let mat = &u * diag(&[3.0, 1e-2, 2.0]) * v.transpose();
+
+        let ans = &v * diag(&[1.0 / 3.0, 0.0, 0.5]) * u.transpose();
+        compare_matrix(&ans, &pseudo_inverse_with_tolerance(&mat, 0.1).unwrap());
+
+        let ans = mat.clone().try_inverse().unwrap();
+        compare_matrix(&ans, &pseudo_inverse_with_tolerance(&mat, 1e-3).unwrap());
+    }
+
+    #[test]
+    fn test_pseudo_inverse_zero_matrix() {
+        let res = pseudo_inverse(&na::DMatrix::zeros(3, 2)).unwrap();
+        assert_eq!(res, na::DMatrix::zeros(2, 3));
+    }
+
+    #[test]
+    fn test_cholesky_solve() {
+        let mut rng = rand::thread_rng();
+        let m = na::DMatrix::from_fn(5, 5, |_, _| rng.gen_range(-1.0..1.0));
+        let a = &m * m.transpose() + na::DMatrix::identity(5, 5);
+        let x = na::DVector::from_fn(5, |_, _| rng.gen_range(-1.0..1.0));
+        assert!(is_positive_definite(&a));
+        compare_vector(&x, &cholesky_solve(&a, &(&a * &x)).unwrap());
+
+        let a = diag(&[1.0, -2.0, 3.0]);
+        assert!(!is_positive_definite(&a));
+        assert!(cholesky_solve(&a, &na::DVector::from_element(3, 1.0)).is_err());
+        assert!(is_positive_definite(&na::DMatrix::identity(3, 3)));
+    }
+
+    #[test]
+    fn test_lstsq_with_condition() {
+        let (x, cond) = lstsq_with_condition(&diag(&[4.0, 0.0, 2.0])).unwrap();
+        assert!((x[1].abs() - 1.0).abs() < 1e-10);
+        assert!((cond - 2.0).abs() < 1e-10);
+
+        let (_, cond) = lstsq_with_condition(&diag(&[4.0, 0.0, 0.0])).unwrap();
+        assert_eq!(cond, f64::INFINITY);
+    }
+
+    #[test]
+    fn test_condition_number() {
+        let identity = na::DMatrix::<f64>::identity(4, 4);
+        assert_eq!(condition_number(&identity), 1.0);
+        assert_eq!(log_condition_number(&identity), 0.0);
+
+        let mat = diag(&[4.0, 0.5, 2.0]);
+        assert!((condition_number(&mat) - 8.0).abs() < 1e-10);
+        assert!((log_condition_number(&mat) - 8f64.log10()).abs() < 1e-10);
+
+        let mat = diag(&[4.0, 0.0, 2.0]);
+        assert_eq!(condition_number(&mat), f64::INFINITY);
+        assert_eq!(log_condition_number(&mat), f64::INFINITY);
+    }
+
     #[test]
     fn test_constrained_lstsq() {
         // identity matrix case (normal eigenvalue problem)
@@ -192,6 +393,23 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_reorder_svd_non_monotone() {
+        // decomposition whose singular values are not sorted
+        let mut rng = rand::thread_rng();
+        let u = random_rotation(&mut rng);
+        let v = random_rotation(&mut rng);
+        let singular_values = na::DVector::from_column_slice(&[1.0, 3.0, 2.0]);
+        let mat = &u * diag(singular_values.as_slice()) * v.transpose();
+
+        let (res_u, res_d, res_v) = reorder_singular_values(&u, &singular_values, &v.transpose());
+        assert_eq!(res_d, na::DVector::from_column_slice(&[3.0, 2.0, 1.0]));
+        compare_vector(&u.column(1).clone_owned(), &res_u.column(0).clone_owned());
+        compare_vector(&v.column(1).clone_owned(), &res_v.column(0).clone_owned());
+        compare_vector(&v.column(0).clone_owned(), &res_v.column(2).clone_owned());
+        compare_matrix(&mat, &(res_u * diag(res_d.as_slice()) * res_v.transpose()));
+    }
+
     #[test]
     fn test_reorder_svd() {
         let mat =
@@ -199,9 +417,7 @@ mod tests {
         let (u, d, v) = reordered_svd(mat.clone()).unwrap();
 
         let res = u * na::DMatrix::from_diagonal(&d) * v.transpose();
-        println!("{:?}", d);
-        println!("{:?}", mat);
-        println!("{:?}", res);
         compare_matrix(&mat, &res);
+        assert!(d[0] >= d[1] && d[1] >= d[2]);
     }
 }
@@ -0,0 +1,262 @@
+//! Unit quaternion representing the rotation in 3D space.
+use nalgebra as na;
+
+const EPS: f64 = 1e-12;
+const SLERP_THRESHOLD: f64 = 1.0 - 1e-9; // linear interpolation is used above this cos(angle)
+
+/// Unit quaternion q = w + xi + yj + zk (Hamilton convention).
+/// Rotation of the angle theta around the unit axis n is q = cos(theta / 2) + sin(theta / 2) n.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct UnitQuaternion {
+    w: f64,
+    x: f64,
+    y: f64,
+    z: f64,
+}
+
+impl UnitQuaternion {
+    /// Create quaternion from the elements. Quaternion is normalized.
+    pub fn new(w: f64, x: f64, y: f64, z: f64) -> Self {
+        UnitQuaternion { w, x, y, z }.normalize()
+    }
+
+    pub fn identity() -> Self {
+        UnitQuaternion {
+            w: 1.0,
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+        }
+    }
+
+    pub fn w(&self) -> f64 {
+        self.w
+    }
+
+    pub fn x(&self) -> f64 {
+        self.x
+    }
+
+    pub fn y(&self) -> f64 {
+        self.y
+    }
+
+    pub fn z(&self) -> f64 {
+        self.z
+    }
+
+    /// Create quaternion of the rotation of `angle` (radian) around `axis`.
+    /// `axis` is normalized in this function. Identity is returned if `axis` is zero vector.
+    pub fn from_axis_angle(axis: &na::Vector3<f64>, angle: f64) -> Self {
+        let norm = axis.norm();
+        if norm < EPS {
+            return Self::identity();
+        }
+        let (sin, cos) = (angle / 2.0).sin_cos();
+        let axis = axis * (sin / norm);
+        UnitQuaternion {
+            w: cos,
+            x: axis[0],
+            y: axis[1],
+            z: axis[2],
+        }
+    }
+
+    /// Create quaternion from rotation matrix `r` (Shepperd's method).
+    pub fn from_rotation_matrix(r: &na::Matrix3<f64>) -> Self {
+        let trace = r.trace();
+        let (w, x, y, z) = if trace > r[(0, 0)] && trace > r[(1, 1)] && trace > r[(2, 2)] {
+            let s = 2.0 * (1.0 + trace).sqrt(); // s = 4w
+            (
+                s / 4.0,
+                (r[(2, 1)] - r[(1, 2)]) / s,
+                (r[(0, 2)] - r[(2, 0)]) / s,
+                (r[(1, 0)] - r[(0, 1)]) / s,
+            )
+        } else if r[(0, 0)] > r[(1, 1)] && r[(0, 0)] > r[(2, 2)] {
+            let s = 2.0 * (1.0 + r[(0, 0)] - r[(1, 1)] - r[(2, 2)]).sqrt(); // s = 4x
+            (
+                (r[(2, 1)] - r[(1, 2)]) / s,
+                s / 4.0,
+                (r[(0, 1)] + r[(1, 0)]) / s,
+                (r[(0, 2)] + r[(2, 0)]) / s,
+            )
+        } else if r[(1, 1)] > r[(2, 2)] {
+            let s = 2.0 * (1.0 - r[(0, 0)] + r[(1, 1)] - r[(2, 2)]).sqrt(); // s = 4y
+            (
+                (r[(0, 2)] - r[(2, 0)]) / s,
+                (r[(0, 1)] + r[(1, 0)]) / s,
+                s / 4.0,
+                (r[(1, 2)] + r[(2, 1)]) / s,
+            )
+        } else {
+            let s = 2.0 * (1.0 - r[(0, 0)] - r[(1, 1)] + r[(2, 2)]).sqrt(); // s = 4z
+            (
+                (r[(1, 0)] - r[(0, 1)]) / s,
+                (r[(0, 2)] + r[(2, 0)]) / s,
+                (r[(1, 2)] + r[(2, 1)]) / s,
+                s / 4.0,
+            )
+        };
+        Self::new(w, x, y, z)
+    }
+
+    pub fn to_rotation_matrix(&self) -> na::Matrix3<f64> {
+        let (w, x, y, z) = (self.w, self.x, self.y, self.z);
+        #[rustfmt::skip]
+        let rot = na::Matrix3::new(
+            1.0 - 2.0 * (y * y + z * z), 2.0 * (x * y - w * z), 2.0 * (x * z + w * y),
+            2.0 * (x * y + w * z), 1.0 - 2.0 * (x * x + z * z), 2.0 * (y * z - w * x),
+            2.0 * (x * z - w * y), 2.0 * (y * z + w * x), 1.0 - 2.0 * (x * x + y * y),
+        );
+        rot
+    }
+
+    /// Return quaternion product `self` * `other`, which applies `other` first and then `self`.
+    pub fn compose(&self, other: &Self) -> Self {
+        UnitQuaternion {
+            w: self.w * other.w - self.x * other.x - self.y * other.y - self.z * other.z,
+            x: self.w * other.x + self.x * other.w + self.y * other.z - self.z * other.y,
+            y: self.w * other.y - self.x * other.z + self.y * other.w + self.z * other.x,
+            z: self.w * other.z + self.x * other.y - self.y * other.x + self.z * other.w,
+        }
+    }
+
+    /// Return inverse rotation (conjugate of the unit quaternion).
+    pub fn inverse(&self) -> Self {
+        UnitQuaternion {
+            w: self.w,
+            x: -self.x,
+            y: -self.y,
+            z: -self.z,
+        }
+    }
+
+    /// Spherical linear interpolation between `self` (`t` = 0) and `other` (`t` = 1).
+    /// Interpolation is performed along the shortest path.
+    pub fn slerp(&self, other: &Self, t: f64) -> Self {
+        let mut cos = self.dot(other);
+        let mut other = *other;
+        if cos < 0.0 {
+            // q and -q represent the same rotation.
+            cos = -cos;
+            other = other.scale(-1.0);
+        }
+        let (lhs, rhs) = if cos > SLERP_THRESHOLD {
+            (1.0 - t, t)
+        } else {
+            let theta = cos.acos();
+            let sin = theta.sin();
+            (((1.0 - t) * theta).sin() / sin, (t * theta).sin() / sin)
+        };
+        let res = UnitQuaternion {
+            w: lhs * self.w + rhs * other.w,
+            x: lhs * self.x + rhs * other.x,
+            y: lhs * self.y + rhs * other.y,
+            z: lhs * self.z + rhs * other.z,
+        };
+        res.normalize()
+    }
+
+    /// Return quaternion scaled to the unit norm. Identity is returned if the norm is zero.
+    pub fn normalize(&self) -> Self {
+        let norm = self.dot(self).sqrt();
+        if norm < EPS {
+            return Self::identity();
+        }
+        self.scale(1.0 / norm)
+    }
+
+    fn dot(&self, other: &Self) -> f64 {
+        self.w * other.w + self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    fn scale(&self, val: f64) -> Self {
+        UnitQuaternion {
+            w: self.w * val,
+            x: self.x * val,
+            y: self.y * val,
+            z: self.z * val,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use super::*;
+
+    /// Return true if `lhs` and `rhs` represent the same rotation.
+    fn is_same_rotation(lhs: &UnitQuaternion, rhs: &UnitQuaternion) -> bool {
+        (lhs.dot(rhs).abs() - 1.0).abs() < 1e-9
+    }
+
+    fn random_quaternion() -> UnitQuaternion {
+        let mut rng = rand::thread_rng();
+        let axis = na::Vector3::new(
+            rng.gen::<f64>() - 0.5,
+            rng.gen::<f64>() - 0.5,
+            rng.gen::<f64>() - 0.5,
+        );
+        UnitQuaternion::from_axis_angle(&axis, rng.gen::<f64>() * 2.0 * std::f64::consts::PI)
+    }
+
+    #[test]
+    fn test_rotation_matrix() {
+        (0..100).for_each(|_| {
+            let q = random_quaternion();
+            let rot = q.to_rotation_matrix();
+            let gt = na::UnitQuaternion::from_quaternion(na::Quaternion::new(q.w, q.x, q.y, q.z));
+            assert!((rot - gt.to_rotation_matrix().matrix()).norm() < 1e-9);
+            let res = UnitQuaternion::from_rotation_matrix(&rot);
+            assert!(is_same_rotation(&res, &q), "{:?} vs {:?}", res, q);
+        });
+        let axis = na::Vector3::new(0.0, 0.0, 1.0);
+        let q = UnitQuaternion::from_axis_angle(&axis, std::f64::consts::PI);
+        let res = UnitQuaternion::from_rotation_matrix(&q.to_rotation_matrix());
+        assert!(is_same_rotation(&res, &q), "{:?} vs {:?}", res, q);
+    }
+
+    #[test]
+    fn test_compose_and_inverse() {
+        (0..100).for_each(|_| {
+            let (q0, q1) = (random_quaternion(), random_quaternion());
+            assert!(is_same_rotation(
+                &q0.compose(&q0.inverse()),
+                &UnitQuaternion::identity()
+            ));
+            let rot = q0.compose(&q1).to_rotation_matrix();
+            assert!((rot - q0.to_rotation_matrix() * q1.to_rotation_matrix()).norm() < 1e-9);
+        });
+    }
+
+    #[test]
+    fn test_slerp() {
+        (0..100).for_each(|_| {
+            let (q0, q1) = (random_quaternion(), random_quaternion());
+            assert!(is_same_rotation(&q0.slerp(&q1, 0.0), &q0));
+            assert!(is_same_rotation(&q0.slerp(&q1, 1.0), &q1));
+            assert!(is_same_rotation(&q0.slerp(&q0, 0.3), &q0));
+        });
+        let axis = na::Vector3::new(1.0, 2.0, -1.0);
+        let q0 = UnitQuaternion::from_axis_angle(&axis, 0.2);
+        let q1 = UnitQuaternion::from_axis_angle(&axis, 1.0);
+        let res = q0.slerp(&q1, 0.25);
+        assert!(is_same_rotation(
+            &res,
+            &UnitQuaternion::from_axis_angle(&axis, 0.4)
+        ));
+    }
+
+    #[test]
+    fn test_normalize() {
+        let q = UnitQuaternion::new(1.0, 2.0, -2.0, 4.0);
+        assert!((q.dot(&q) - 1.0).abs() < 1e-12);
+        assert!((q.w() - 0.2).abs() < 1e-12);
+        assert_eq!(
+            UnitQuaternion::new(0.0, 0.0, 0.0, 0.0),
+            UnitQuaternion::identity()
+        );
+    }
+}
@@ -1,36 +1,67 @@
+//! Generic RANSAC (random sample consensus) loop.
+use crate::{ensure, error::Result};
+
+/// Configuration of `RANSAC::run`.
+/// - `max_iter` : number of the hypotheses estimated from random samples.
+/// - `threshold` : the loop stops early if the number of inliers exceeds this value.
 pub struct RANSACConfig {
-    max_iter: u32,
+    max_iter: usize,
     threshold: usize,
 }
 
+impl RANSACConfig {
+    /// Create config which runs `max_iter` iterations without early stop.
+    pub fn new(max_iter: usize) -> Self {
+        RANSACConfig {
+            max_iter,
+            threshold: usize::MAX,
+        }
+    }
+
+    /// Stop the loop when the number of inliers exceeds `threshold`.
+    pub fn with_threshold(mut self, threshold: usize) -> Self {
+        self.threshold = threshold;
+        self
+    }
+}
+
+/// Robust estimation of a model `T` from data contaminated by outliers.
+/// `S` is the element returned as inlier (e.g. index of the data).
 pub trait RANSAC<T, S> {
-    fn run(&self, config: &RANSACConfig) -> Option<T> {
-        let mut best_estimated = Option::<T>::None;
-        let mut best_num_inliers = 0;
+    /// Return the model estimated from the inliers of the hypothesis with the most inliers.
+    /// Hypotheses which fail to be estimated (e.g. degenerate samples) are skipped.
+    /// Return error if the number of the inliers is smaller than `min_sample`.
+    fn run(&self, config: &RANSACConfig) -> Result<T> {
+        let mut best_inliers: Vec<S> = vec![];
         for _ in 0..config.max_iter {
-            let estimated = self.estimate_from_random_sample();
-            let num_inliers = self.get_inliers(&estimated).len();
-            if num_inliers > best_num_inliers {
-                best_estimated = Some(estimated);
-                best_num_inliers = num_inliers;
-                if best_num_inliers > config.threshold {
+            let estimated = match self.estimate_from_random_sample() {
+                Ok(estimated) => estimated,
+                Err(_) => continue,
+            };
+            let inliers = self.get_inliers(&estimated);
+            if inliers.len() > best_inliers.len() {
+                best_inliers = inliers;
+                if best_inliers.len() > config.threshold {
                     break;
                 }
             }
         }
-
-        match best_estimated {
-            Some(estimated) => {
-                let inliers = self.get_inliers(&estimated);
-                Some(self.estimate(&inliers))
-            }
-            None => Option::<T>::None,
-        }
+        ensure!(
+            best_inliers.len() >= self.min_sample(),
+            "Failed to find enough inliers : {}",
+            best_inliers.len()
+        );
+        self.estimate(&best_inliers)
     }
 
-    fn estimate_from_random_sample(&self) -> T;
+    /// Minimum number of the data to estimate the model.
+    fn min_sample(&self) -> usize;
+
+    /// Estimate model from `min_sample` randomly sampled data.
+    fn estimate_from_random_sample(&self) -> Result<T>;
 
     fn get_inliers(&self, estimated: &T) -> Vec<S>;
 
-    fn estimate(&self, inputs: &Vec<S>) -> T;
+    /// Estimate model from `inputs`.
+    fn estimate(&self, inputs: &[S]) -> Result<T>;
 }
@@ -0,0 +1,144 @@
+//! Rigid body transformation in 3D space.
+use nalgebra as na;
+
+use crate::{ensure, error::Result};
+
+const EPS: f64 = 1e-6;
+
+/// Rigid body transformation x' = R * x + t.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SE3 {
+    rotation: na::Matrix3<f64>,
+    translation: na::Vector3<f64>,
+}
+
+impl SE3 {
+    /// `rotation` must be a rotation matrix (orthogonal and its determinant is 1).
+    pub fn new(rotation: na::Matrix3<f64>, translation: na::Vector3<f64>) -> Self {
+        SE3 {
+            rotation,
+            translation,
+        }
+    }
+
+    pub fn identity() -> Self {
+        Self::new(na::Matrix3::identity(), na::Vector3::zeros())
+    }
+
+    pub fn rotation(&self) -> &na::Matrix3<f64> {
+        &self.rotation
+    }
+
+    pub fn translation(&self) -> &na::Vector3<f64> {
+        &self.translation
+    }
+
+    /// Return transformation which applies `other` first and then `self`.
+    pub fn compose(&self, other: &SE3) -> SE3 {
+        SE3::new(
+            self.rotation * other.rotation,
+            self.rotation * other.translation + self.translation,
+        )
+    }
+
+    pub fn inverse(&self) -> SE3 {
+        let rot_t = self.rotation.transpose();
+        SE3::new(rot_t, -(rot_t * self.translation))
+    }
+
+    pub fn transform_point(&self, p: &na::Point3<f64>) -> na::Point3<f64> {
+        na::Point3::from(self.rotation * p.coords + self.translation)
+    }
+
+    /// Return 4 x 4 homogeneous transformation matrix.
+    pub fn to_matrix4x4(&self) -> na::DMatrix<f64> {
+        let mut mat = na::DMatrix::<f64>::identity(4, 4);
+        mat.slice_mut((0, 0), (3, 3)).copy_from(&self.rotation);
+        mat.slice_mut((0, 3), (3, 1)).copy_from(&self.translation);
+        mat
+    }
+
+    /// Create transformation from 4 x 4 homogeneous transformation matrix or 3 x 4 matrix [R | t].
+    pub fn from_matrix(m: &na::DMatrix<f64>) -> Result<SE3> {
+        ensure!(
+            (m.nrows() == 3 || m.nrows() == 4) && m.ncols() == 4,
+            "Invalid matrix size : ({}, {})",
+            m.nrows(),
+            m.ncols()
+        );
+        if m.nrows() == 4 {
+            ensure!(
+                (m.row(3) - na::RowDVector::from_row_slice(&[0.0, 0.0, 0.0, 1.0])).norm() < EPS,
+                "Last row of the matrix must be [0, 0, 0, 1] : {}",
+                m.row(3)
+            );
+        }
+        let rotation: na::Matrix3<f64> = m.fixed_slice::<3, 3>(0, 0).into_owned();
+        ensure!(
+            (rotation.transpose() * rotation - na::Matrix3::identity()).norm() < EPS
+                && rotation.determinant() > 0.0,
+            "Invalid rotation matrix : {}",
+            rotation
+        );
+        let translation: na::Vector3<f64> = m.fixed_slice::<3, 1>(0, 3).into_owned();
+        Ok(SE3::new(rotation, translation))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_transform() -> SE3 {
+        let rot = na::Rotation3::from_euler_angles(0.3, -1.1, 2.0);
+        SE3::new(*rot.matrix(), na::Vector3::new(1.0, -2.0, 0.5))
+    }
+
+    #[test]
+    fn test_compose_and_inverse() {
+        let trans = create_transform();
+        let identity = trans.compose(&trans.inverse());
+        assert!((identity.rotation() - na::Matrix3::identity()).norm() < 1e-12);
+        assert!(identity.translation().norm() < 1e-12);
+        let identity = trans.inverse().compose(&trans);
+        assert!((identity.to_matrix4x4() - na::DMatrix::identity(4, 4)).norm() < 1e-12);
+
+        let other = SE3::new(
+            *na::Rotation3::from_euler_angles(-0.5, 0.2, 0.1).matrix(),
+            na::Vector3::new(0.0, 3.0, -1.0),
+        );
+        let composed = trans.compose(&other).to_matrix4x4();
+        assert!((composed - trans.to_matrix4x4() * other.to_matrix4x4()).norm() < 1e-12);
+    }
+
+    #[test]
+    fn test_transform_point() {
+        let trans = create_transform();
+        let pt = na::Point3::new(0.4, 5.0, -3.0);
+        let res = trans.transform_point(&pt);
+        let gt = trans.to_matrix4x4() * na::DVector::from_column_slice(&[pt.x, pt.y, pt.z, 1.0]);
+        assert!((res.coords - gt.rows(0, 3)).norm() < 1e-12);
+        assert_eq!(SE3::identity().transform_point(&pt), pt);
+    }
+
+    #[test]
+    fn test_from_matrix() {
+        let trans = create_transform();
+        let mat = trans.to_matrix4x4();
+        let res = SE3::from_matrix(&mat).unwrap();
+        assert!((res.to_matrix4x4() - &mat).norm() < 1e-12);
+        let res = SE3::from_matrix(&mat.rows(0, 3).into_owned()).unwrap();
+        assert!((res.to_matrix4x4() - &mat).norm() < 1e-12);
+
+        assert!(SE3::from_matrix(&na::DMatrix::identity(3, 3)).is_err());
+        let mut invalid = mat.clone();
+        invalid[(3, 0)] = 1.0;
+        assert!(SE3::from_matrix(&invalid).is_err());
+        let mut invalid = mat;
+        invalid[(0, 0)] *= 2.0;
+        assert!(SE3::from_matrix(&invalid).is_err());
+        let reflection =
+            na::DMatrix::from_diagonal(&na::DVector::from_column_slice(&[-1.0, 1.0, 1.0, 1.0]));
+        assert!(SE3::from_matrix(&reflection).is_err());
+    }
+}
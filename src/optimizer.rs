@@ -1,11 +1,41 @@
 //! Trait definitions for optimization problems.
 use nalgebra as na;
 
+use crate::{ensure, error::Result};
+
+pub mod bundle_adjustment;
+pub mod constrained;
 pub mod fns;
 pub mod geometric;
+pub mod jacobian;
 pub mod least_square;
+pub mod lm;
+pub mod ransac;
+pub mod robust_loss;
 pub mod taubin;
 
+const RANK_TOLERANCE: f64 = 1e-12;
+
+/// Callback called at the start of each iteration with (current step, total steps).
+/// Step starts from 1. Iteration may stop before the total steps when converged.
+pub type ProgressCallback = Box<dyn Fn(usize, usize) + Send>;
+
+/// Result of the iterative optimization (`fns`, `renormalization` and `taubin`).
+/// - `params` : optimized parameters.
+/// - `iterations` : number of the iterations.
+/// - `residual` : algebraic residual (theta^T M theta) of `params` with the unit weights.
+/// - `converged` : false if the iteration is stopped by the maximum iterations or the increase
+///   of the residual.
+/// - `history` : residual of each iteration (including the initial value).
+#[derive(Clone, Debug)]
+pub struct OptimizeResult {
+    pub params: na::DVector<f64>,
+    pub iterations: usize,
+    pub residual: f64,
+    pub converged: bool,
+    pub history: Vec<f64>,
+}
+
 /// Data trait definition
 pub trait ObservedData<'a> {
     /// constructor
@@ -31,3 +61,27 @@ pub trait ObservedData<'a> {
     /// Return all data
     fn get_data(&self) -> Vec<na::Point2<f64>>;
 }
+
+/// Return error if the solution of the data is not unique, i.e. null space of the moment matrix
+/// M (with the unit weights) is more than one dimension.
+fn check_rank<'a, DataClass: ObservedData<'a>>(data_container: &DataClass) -> Result<()> {
+    let matrix = data_container.matrix(&vec![
+        1.0;
+        data_container.len()
+            * data_container.num_equation().pow(2)
+    ]);
+    let singular_values = matrix.singular_values();
+    let threshold = singular_values.max() * RANK_TOLERANCE;
+    let rank = singular_values
+        .iter()
+        .filter(|val| **val > threshold)
+        .count();
+    let required = data_container.vec_size() - 1;
+    ensure!(
+        rank >= required,
+        "Data is rank deficient : rank of the moment matrix is {} (required {}).",
+        rank,
+        required
+    );
+    Ok(())
+}
@@ -0,0 +1,334 @@
+//! Implementation of bundle adjustment.
+use nalgebra as na;
+
+use crate::linalg::matrix::cholesky_solve;
+
+const STOP_THRESHOLD: f64 = 1e-20;
+const MAX_DAMPING: f64 = 1e10;
+
+type CameraJacobian = na::SMatrix<f64, 2, 12>;
+type PointJacobian = na::SMatrix<f64, 2, 3>;
+type CrossBlock = na::SMatrix<f64, 12, 3>;
+
+/// Refine camera matrices and 3D points by minimizing the reprojection error.
+/// Parameters are updated by Gauss-Newton method (with Levenberg-Marquardt damping). In each
+/// iteration, the normal equation is solved by the Schur complement trick: the 3D points
+/// (structure) are eliminated first and the reduced system of the camera parameters is solved,
+/// then the points are updated by back substitution.
+/// The first camera matrix is fixed to remove the ambiguity of the coordinate system.
+/// - `camera_matrices` : 3 x 4 camera matrices. Image point is calculated as
+///   `x = (P X)[0..2] / (P X)[2]`.
+/// - `points_3d` : 3D points.
+/// - `observations` : tuple of (camera index, point index, observed image point).
+/// - `iterations` : maximum number of the iterations.
+///
+/// Return RMS of the reprojection error after the refinement.
+pub fn bundle_adjustment(
+    camera_matrices: &mut [na::DMatrix<f64>],
+    points_3d: &mut [na::Vector3<f64>],
+    observations: &[(usize, usize, na::Point2<f64>)],
+    iterations: usize,
+) -> f64 {
+    let mut obs_of_points: Vec<Vec<usize>> = vec![vec![]; points_3d.len()];
+    observations
+        .iter()
+        .enumerate()
+        .for_each(|(idx, (_, pt_idx, _))| obs_of_points[*pt_idx].push(idx));
+
+    let mut error = reprojection_error(camera_matrices, points_3d, observations);
+    let mut lambda = 1e-3;
+    for _ in 0..iterations {
+        if error * error < STOP_THRESHOLD {
+            break;
+        }
+
+        let normal_eq = NormalEquation::new(camera_matrices, points_3d, observations);
+        let mut updated = false;
+        while lambda < MAX_DAMPING {
+            let (delta_cams, delta_pts) =
+                match normal_eq.solve(observations, &obs_of_points, lambda) {
+                    Some(delta) => delta,
+                    None => {
+                        lambda *= 10.0;
+                        continue;
+                    }
+                };
+            let mut cams = camera_matrices.to_vec();
+            cams.iter_mut().skip(1).enumerate().for_each(|(i, p)| {
+                (0..12).for_each(|k| p[(k / 4, k % 4)] -= delta_cams[i * 12 + k]);
+            });
+            let pts: Vec<na::Vector3<f64>> = points_3d
+                .iter()
+                .zip(delta_pts.iter())
+                .map(|(pt, delta)| pt - delta)
+                .collect();
+            let updated_error = reprojection_error(&cams, &pts, observations);
+            if updated_error < error {
+                camera_matrices.clone_from_slice(&cams);
+                points_3d.copy_from_slice(&pts);
+                error = updated_error;
+                lambda /= 10.0;
+                updated = true;
+                break;
+            }
+            lambda *= 10.0;
+        }
+        if !updated {
+            break;
+        }
+    }
+    error
+}
+
+/// Blocks of the normal equation of bundle adjustment.
+/// ```text
+/// [ U   W ] [dc]   [gc]
+/// [ W^T V ] [dp] = [gp]
+/// ```
+/// `dc` and `dp` are updates of the camera parameters and the points. `V` is block diagonal
+/// (3 x 3 block for each point) and `W` is stored for each observation.
+struct NormalEquation {
+    u: na::DMatrix<f64>,
+    gc: na::DVector<f64>,
+    v: Vec<na::Matrix3<f64>>,
+    gp: Vec<na::Vector3<f64>>,
+    w: Vec<CrossBlock>,
+}
+
+impl NormalEquation {
+    fn new(
+        camera_matrices: &[na::DMatrix<f64>],
+        points_3d: &[na::Vector3<f64>],
+        observations: &[(usize, usize, na::Point2<f64>)],
+    ) -> Self {
+        let n_cam_params = camera_matrices.len().saturating_sub(1) * 12;
+        let mut u = na::DMatrix::<f64>::zeros(n_cam_params, n_cam_params);
+        let mut gc = na::DVector::<f64>::zeros(n_cam_params);
+        let mut v = vec![na::Matrix3::<f64>::zeros(); points_3d.len()];
+        let mut gp = vec![na::Vector3::<f64>::zeros(); points_3d.len()];
+        let w = observations
+            .iter()
+            .map(|(cam_idx, pt_idx, x)| {
+                let (a, b, residual) = jacobian(&camera_matrices[*cam_idx], &points_3d[*pt_idx], x);
+                v[*pt_idx] += b.transpose() * b;
+                gp[*pt_idx] += b.transpose() * residual;
+                if *cam_idx == 0 {
+                    return CrossBlock::zeros();
+                }
+                let offset = (cam_idx - 1) * 12;
+                let mut block = u.slice_mut((offset, offset), (12, 12));
+                block += a.transpose() * a;
+                let mut grad = gc.rows_mut(offset, 12);
+                grad += a.transpose() * residual;
+                a.transpose() * b
+            })
+            .collect();
+        NormalEquation { u, gc, v, gp, w }
+    }
+
+    /// Solve the damped normal equation by eliminating the points.
+    /// Return tuple of (update of the camera parameters, update of the points).
+    fn solve(
+        &self,
+        observations: &[(usize, usize, na::Point2<f64>)],
+        obs_of_points: &[Vec<usize>],
+        lambda: f64,
+    ) -> Option<(na::DVector<f64>, Vec<na::Vector3<f64>>)> {
+        let mut schur = self.u.clone();
+        (0..schur.nrows()).for_each(|i| schur[(i, i)] *= 1.0 + lambda);
+        let mut rhs = self.gc.clone();
+
+        let v_inv: Vec<na::Matrix3<f64>> = self
+            .v
+            .iter()
+            .map(|vj| {
+                let mut damped = *vj;
+                (0..3).for_each(|i| damped[(i, i)] *= 1.0 + lambda);
+                damped.try_inverse().unwrap_or_else(na::Matrix3::zeros)
+            })
+            .collect();
+
+        // S = U - W V^-1 W^T, rhs = gc - W V^-1 gp
+        obs_of_points.iter().enumerate().for_each(|(pt_idx, obs)| {
+            obs.iter()
+                .filter(|a| observations[**a].0 > 0)
+                .for_each(|a| {
+                    let wv = self.w[*a] * v_inv[pt_idx];
+                    let row = (observations[*a].0 - 1) * 12;
+                    let mut r = rhs.rows_mut(row, 12);
+                    r -= wv * self.gp[pt_idx];
+                    obs.iter()
+                        .filter(|b| observations[**b].0 > 0)
+                        .for_each(|b| {
+                            let col = (observations[*b].0 - 1) * 12;
+                            let mut block = schur.slice_mut((row, col), (12, 12));
+                            block -= wv * self.w[*b].transpose();
+                        });
+                });
+        });
+
+        let delta_cams = if schur.nrows() > 0 {
+            cholesky_solve(&schur, &rhs).ok()?
+        } else {
+            rhs
+        };
+
+        // back substitution : dp = V^-1 (gp - W^T dc)
+        let delta_pts = obs_of_points
+            .iter()
+            .enumerate()
+            .map(|(pt_idx, obs)| {
+                let g = obs.iter().filter(|a| observations[**a].0 > 0).fold(
+                    self.gp[pt_idx],
+                    |acc, a| {
+                        let row = (observations[*a].0 - 1) * 12;
+                        acc - self.w[*a].transpose() * delta_cams.rows(row, 12)
+                    },
+                );
+                v_inv[pt_idx] * g
+            })
+            .collect();
+        Some((delta_cams, delta_pts))
+    }
+}
+
+/// Calculate jacobian of the projected point with respect to the camera matrix (row-major) and
+/// the 3D point. Return tuple of (camera jacobian, point jacobian, residual).
+fn jacobian(
+    p: &na::DMatrix<f64>,
+    pt: &na::Vector3<f64>,
+    x: &na::Point2<f64>,
+) -> (CameraJacobian, PointJacobian, na::Vector2<f64>) {
+    let homo = pt.insert_row(3, 1.0);
+    let proj = p * homo;
+    let mut a = CameraJacobian::zeros();
+    let mut b = PointJacobian::zeros();
+    let mut residual = na::Vector2::zeros();
+    (0..2).for_each(|j| {
+        let u = proj[j] / proj[2];
+        residual[j] = u - x[j];
+        (0..4).for_each(|k| {
+            a[(j, j * 4 + k)] = homo[k] / proj[2];
+            a[(j, 8 + k)] = -u * homo[k] / proj[2];
+        });
+        (0..3).for_each(|k| b[(j, k)] = (p[(j, k)] - u * p[(2, k)]) / proj[2]);
+    });
+    (a, b, residual)
+}
+
+/// Calculate RMS of the reprojection error.
+pub fn reprojection_error(
+    camera_matrices: &[na::DMatrix<f64>],
+    points_3d: &[na::Vector3<f64>],
+    observations: &[(usize, usize, na::Point2<f64>)],
+) -> f64 {
+    if observations.is_empty() {
+        return 0.0;
+    }
+    let sum: f64 = observations
+        .iter()
+        .map(|(cam_idx, pt_idx, x)| {
+            let proj = &camera_matrices[*cam_idx] * points_3d[*pt_idx].insert_row(3, 1.0);
+            (proj[0] / proj[2] - x[0]).powi(2) + (proj[1] / proj[2] - x[1]).powi(2)
+        })
+        .sum();
+    (sum / observations.len() as f64).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use crate::linalg::get_rotation_matrix_from_omega;
+
+    use super::*;
+
+    #[test]
+    fn test_bundle_adjustment() {
+        let mut rng = rand::thread_rng();
+        let focal_length = 500.0;
+        let k = na::DMatrix::from_diagonal(&na::DVector::from_vec(vec![
+            focal_length,
+            focal_length,
+            1.0,
+        ]));
+        let gt_cams: Vec<na::DMatrix<f64>> = (0..3)
+            .map(|idx| {
+                let angle = idx as f64 * 0.1;
+                let rot = get_rotation_matrix_from_omega(&[0.0, angle + 1e-9, 0.0]);
+                let trans = na::DVector::from_vec(vec![-(idx as f64) * 0.5, 0.1 * idx as f64, 0.0]);
+                let mut motion = na::DMatrix::<f64>::zeros(3, 4);
+                motion.slice_mut((0, 0), (3, 3)).copy_from(&rot);
+                motion.set_column(3, &trans);
+                &k * motion
+            })
+            .collect();
+        let gt_pts: Vec<na::Vector3<f64>> = (0..30)
+            .map(|_| {
+                na::Vector3::new(
+                    (rng.gen::<f64>() - 0.5) * 4.0,
+                    (rng.gen::<f64>() - 0.5) * 4.0,
+                    4.0 + rng.gen::<f64>() * 4.0,
+                )
+            })
+            .collect();
+        let observations: Vec<(usize, usize, na::Point2<f64>)> = gt_pts
+            .iter()
+            .enumerate()
+            .flat_map(|(pt_idx, pt)| {
+                gt_cams
+                    .iter()
+                    .enumerate()
+                    .map(|(cam_idx, p)| {
+                        let proj = p * pt.insert_row(3, 1.0);
+                        (
+                            cam_idx,
+                            pt_idx,
+                            na::Point2::new(proj[0] / proj[2], proj[1] / proj[2]),
+                        )
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+
+        // perturb cameras (except the first one) and points
+        let mut cams = gt_cams.clone();
+        cams.iter_mut().skip(1).for_each(|p| {
+            let scale = p.norm() * 1e-3;
+            p.iter_mut()
+                .for_each(|val| *val += (rng.gen::<f64>() - 0.5) * scale);
+        });
+        let mut pts: Vec<na::Vector3<f64>> = gt_pts
+            .iter()
+            .map(|pt| pt.map(|val| val + (rng.gen::<f64>() - 0.5) * 0.1))
+            .collect();
+
+        let initial_error = reprojection_error(&cams, &pts, &observations);
+        let error = bundle_adjustment(&mut cams, &mut pts, &observations, 20);
+        assert!(
+            error < initial_error * 0.1,
+            "error = {} (initial = {})",
+            error,
+            initial_error
+        );
+        assert_eq!(
+            error,
+            reprojection_error(&cams, &pts, &observations),
+            "returned value must be the error of the refined parameters"
+        );
+    }
+
+    #[test]
+    fn test_bundle_adjustment_converged() {
+        let p = na::DMatrix::<f64>::identity(3, 4);
+        let mut cams = vec![p.clone(), p];
+        let mut pts = vec![na::Vector3::new(1.0, 2.0, 4.0)];
+        let observations = vec![
+            (0, 0, na::Point2::new(0.25, 0.5)),
+            (1, 0, na::Point2::new(0.25, 0.5)),
+        ];
+        let error = bundle_adjustment(&mut cams, &mut pts, &observations, 10);
+        assert!(error < 1e-12);
+        assert_eq!(pts[0], na::Vector3::new(1.0, 2.0, 4.0));
+    }
+}
@@ -0,0 +1,93 @@
+//! Optimization with the equality constraint by the method of Lagrange multipliers.
+use nalgebra as na;
+
+use crate::linalg::matrix::pseudo_inverse;
+
+/// Compute one step of Newton's method of minimizing f(x) subject to the constraint c(x) = 0.
+/// Step `delta` and Lagrange multiplier `lambda` are the solution of the KKT system
+/// ```text
+/// | H    a | | delta  |   | -g |
+/// | a^T  0 | | lambda | = |  0 |
+/// ```
+/// where `g` and `H` are gradient and hessian of f(x) and `a` is gradient of c(x).
+/// `x` is assumed to satisfy the constraint, so that the step is taken in the tangent space of
+/// the constraint (a^T delta = 0). For the nonlinear constraint such as |x| = 1, returned point
+/// should be projected onto the constraint (e.g. normalized).
+/// Return updated point `x + delta`. `x` is returned if the KKT system can not be solved.
+pub fn constrained_newton_step(
+    gradient: &na::DVector<f64>,
+    hessian: &na::DMatrix<f64>,
+    constraint_grad: &na::DVector<f64>,
+    x: &na::DVector<f64>,
+) -> na::DVector<f64> {
+    let n = x.nrows();
+    let mut kkt = na::DMatrix::<f64>::zeros(n + 1, n + 1);
+    kkt.slice_mut((0, 0), (n, n)).copy_from(hessian);
+    kkt.slice_mut((0, n), (n, 1)).copy_from(constraint_grad);
+    kkt.slice_mut((n, 0), (1, n))
+        .copy_from(&constraint_grad.transpose());
+    let mut rhs = na::DVector::<f64>::zeros(n + 1);
+    rhs.rows_mut(0, n).copy_from(&(-gradient));
+
+    let solution = match kkt.clone().lu().solve(&rhs) {
+        Some(solution) => solution,
+        None => match pseudo_inverse(&kkt) {
+            Ok(inv) => inv * rhs,
+            Err(_) => return x.clone(),
+        },
+    };
+    x + solution.rows(0, n)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use super::*;
+
+    #[test]
+    fn test_kkt_conditions() {
+        // f(x) = x^T Q x / 2 + c^T x subject to a^T x = b
+        #[rustfmt::skip]
+        let q = na::DMatrix::from_row_slice(3, 3, &[
+            4.0, 1.0, 0.5,
+            1.0, 3.0, -0.2,
+            0.5, -0.2, 2.0,
+        ]);
+        let c = na::DVector::from_column_slice(&[1.0, -2.0, 0.5]);
+        let a = na::DVector::from_column_slice(&[1.0, 2.0, -1.0]);
+        let b = 3.0;
+        let x = na::DVector::from_column_slice(&[1.0, 2.0, 2.0]); // a^T x = b
+
+        let res = constrained_newton_step(&(&q * &x + &c), &q, &a, &x);
+        // primal feasibility
+        assert!((a.dot(&res) - b).abs() < 1e-12);
+        // stationarity : gradient of f is parallel to a (grad f + lambda * a = 0)
+        let grad = &q * &res + &c;
+        let lambda = -grad.dot(&a) / a.norm_squared();
+        assert!((grad + lambda * &a).norm() < 1e-12);
+    }
+
+    #[test]
+    fn test_unit_norm_constraint() {
+        // minimize x^T M x subject to |x| = 1. Solution is the eigenvector of the minimum
+        // eigenvalue of M.
+        let mut rng = rand::thread_rng();
+        let m = na::DMatrix::from_fn(4, 4, |_, _| rng.gen::<f64>());
+        let m = &m * m.transpose() + na::DMatrix::identity(4, 4);
+        let eigen = m.clone().symmetric_eigen();
+        let (idx, _) = eigen.eigenvalues.argmin();
+        let gt = eigen.eigenvectors.column(idx).clone_owned();
+
+        // start from the perturbed solution
+        let noise = na::DVector::from_fn(4, |_, _| rng.gen::<f64>() - 0.5);
+        let mut x = (&gt + 0.05 * noise).normalize();
+        (0..20).for_each(|_| {
+            // hessian of the Lagrangian x^T M x - mu (|x|^2 - 1)
+            let mu = x.dot(&(&m * &x));
+            let hessian = 2.0 * (&m - mu * na::DMatrix::identity(4, 4));
+            x = constrained_newton_step(&(2.0 * &m * &x), &hessian, &x, &x).normalize();
+        });
+        assert!((x.dot(&gt).abs() - 1.0).abs() < 1e-9, "x = {}", x);
+    }
+}
@@ -1,18 +1,28 @@
 //! Implementation of FNS (Fundamental Numerial Scheme)
-use anyhow::Result;
 use nalgebra as na;
 
-use crate::linalg::{get_zero_mat, matrix::lstsq};
+use crate::{
+    error::Result,
+    linalg::{get_zero_mat, matrix::lstsq},
+};
 
-use super::ObservedData;
+use super::{check_rank, ObservedData, OptimizeResult, ProgressCallback};
 
 const MAX_ITERATION: usize = 5;
 const STOP_THRESHOLD: f64 = 1e-7;
 
-pub fn fns<'a, DataClass: ObservedData<'a>>(
+/// Estimate parameters by FNS (minimize Sampson error).
+pub fn fns<'a, DataClass: ObservedData<'a>>(data: &'a [na::Point2<f64>]) -> Result<OptimizeResult> {
+    fns_with_progress::<DataClass>(data, None)
+}
+
+/// Same as `fns`, but `progress` is called at the start of each iteration.
+pub fn fns_with_progress<'a, DataClass: ObservedData<'a>>(
     data: &'a [na::Point2<f64>],
-) -> Result<na::DVector<f64>> {
+    progress: Option<ProgressCallback>,
+) -> Result<OptimizeResult> {
     let data_container = DataClass::new(data);
+    check_rank(&data_container)?;
     let mut previous = na::DVector::<f64>::from_vec(vec![0.0; data_container.vec_size()]);
     let mut params = minimize_sampson_error(&data_container, &previous)?;
     // calculate residual (for avoiding instability caused by SVD)
@@ -22,28 +32,50 @@ pub fn fns<'a, DataClass: ObservedData<'a>>(
             * data_container.num_equation().pow(2)
     ]);
     let mut residual = params.dot(&(&default_matrix * &params));
+    let mut history = vec![residual];
+    let mut iterations = 1;
+    let mut converged = false;
 
-    for _ in 0..MAX_ITERATION {
+    for step in 1..=MAX_ITERATION {
+        if let Some(progress) = &progress {
+            progress(step, MAX_ITERATION);
+        }
         if previous[0] * params[0] < 0.0 {
             params *= -1.0;
         }
         if (params.clone() - previous.clone()).norm() < STOP_THRESHOLD {
+            converged = true;
             break;
         }
         previous = params.clone();
         let updated = minimize_sampson_error(&data_container, &params)?;
+        iterations += 1;
         // check whether residual is decreasing
         {
             let res = updated.dot(&(&default_matrix * &updated));
+            history.push(res);
             if res > residual * 10.0 {
-                println!("Residual is not decreasing. Break iteration.");
+                log::warn!("Residual is not decreasing. Break iteration.");
                 break;
             }
             residual = res;
         }
         params = updated;
     }
-    Ok(params)
+    Ok(OptimizeResult {
+        params,
+        iterations,
+        residual,
+        converged,
+        history,
+    })
+}
+
+/// Same as `fns`, but return only the estimated parameters.
+pub fn fns_params<'a, DataClass: ObservedData<'a>>(
+    data: &'a [na::Point2<f64>],
+) -> Result<na::DVector<f64>> {
+    Ok(fns::<DataClass>(data)?.params)
 }
 
 pub fn minimize_sampson_error<'a, DataClass: ObservedData<'a>>(
@@ -1,6 +1,7 @@
-use anyhow::Result;
 use nalgebra as na;
 
+use crate::error::Result;
+
 use super::{fns::minimize_sampson_error, ObservedData};
 
 const MAX_ITERATION: usize = 5;
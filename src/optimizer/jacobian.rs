@@ -0,0 +1,75 @@
+//! Numerical differentiation utilities.
+use nalgebra as na;
+
+/// Calculate jacobian matrix J (J_ij = df_i / dx_j) of `f` at `x` by the central differences.
+/// - `eps` : step size of the differences.
+pub fn numerical_jacobian<F>(f: F, x: &na::DVector<f64>, eps: f64) -> na::DMatrix<f64>
+where
+    F: Fn(&na::DVector<f64>) -> na::DVector<f64>,
+{
+    let columns: Vec<na::DVector<f64>> = (0..x.nrows())
+        .map(|idx| {
+            let mut forward = x.clone();
+            let mut backward = x.clone();
+            forward[idx] += eps;
+            backward[idx] -= eps;
+            (f(&forward) - f(&backward)) / (2.0 * eps)
+        })
+        .collect();
+    na::DMatrix::from_columns(&columns)
+}
+
+/// Return true if the analytical jacobian `j` of `f` matches the numerical jacobian at `x`.
+/// Jacobians match if the maximum absolute difference of the elements is not larger than
+/// `tolerance`.
+/// - `eps` : step size of the numerical differentiation.
+pub fn check_jacobian<F, J>(f: F, j: J, x: &na::DVector<f64>, eps: f64, tolerance: f64) -> bool
+where
+    F: Fn(&na::DVector<f64>) -> na::DVector<f64>,
+    J: Fn(&na::DVector<f64>) -> na::DMatrix<f64>,
+{
+    let numerical = numerical_jacobian(f, x, eps);
+    let analytical = j(x);
+    if numerical.shape() != analytical.shape() {
+        return false;
+    }
+    (analytical - numerical).amax() <= tolerance
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_numerical_jacobian() {
+        let eps = 1e-6;
+        let a = na::DMatrix::from_row_slice(3, 2, &[1.0, -2.0, 0.5, 3.0, 4.0, 0.0]);
+        let x = na::DVector::from_column_slice(&[0.3, -1.2]);
+        let jacobian = numerical_jacobian(|x| &a * x, &x, eps);
+        assert!((&jacobian - &a).amax() < eps, "jacobian = {}", jacobian);
+    }
+
+    #[test]
+    fn test_check_jacobian() {
+        // f(x) = (x0^2 * x1, sin(x1))
+        let f = |x: &na::DVector<f64>| {
+            na::DVector::from_column_slice(&[x[0] * x[0] * x[1], x[1].sin()])
+        };
+        let j = |x: &na::DVector<f64>| {
+            na::DMatrix::from_row_slice(2, 2, &[2.0 * x[0] * x[1], x[0] * x[0], 0.0, x[1].cos()])
+        };
+        let wrong = |x: &na::DVector<f64>| {
+            na::DMatrix::from_row_slice(2, 2, &[x[0] * x[1], x[0] * x[0], 0.0, x[1].cos()])
+        };
+        let x = na::DVector::from_column_slice(&[1.5, 0.7]);
+        assert!(check_jacobian(f, j, &x, 1e-6, 1e-6));
+        assert!(!check_jacobian(f, wrong, &x, 1e-6, 1e-6));
+        assert!(!check_jacobian(
+            f,
+            |_| na::DMatrix::zeros(2, 3),
+            &x,
+            1e-6,
+            1e-6
+        ));
+    }
+}
@@ -1,23 +1,39 @@
 //! Implementation of least square minimization algorithm.
-use anyhow::Result;
 use nalgebra as na;
 
-use crate::linalg::matrix::lstsq;
+use crate::{
+    ensure,
+    error::Result,
+    linalg::matrix::{lstsq, lstsq_with_condition},
+};
 
-use super::ObservedData;
+use super::{
+    robust_loss::{robust_weight, RobustLoss},
+    ObservedData, ProgressCallback,
+};
 
 const MAX_ITERATION: usize = 4;
 const STOP_THRESHOLD: f64 = 1e-5;
+const CONDITION_NUMBER_WARNING: f64 = 1e8;
 
 pub fn least_square_fitting<'a, DataClass: ObservedData<'a>>(
     data: &'a [na::Point2<f64>],
 ) -> Result<na::DVector<f64>> {
     let data_container = DataClass::new(data);
     let weights: Vec<f64> = vec![1.0; data_container.len() * data_container.num_equation().pow(2)];
-    least_square_fitting_with_weight::<DataClass>(data, &weights)
+    let mat = data_container.matrix(&weights);
+    // the solution is the null vector of `mat`, which is excluded from the condition number.
+    let (params, cond) = lstsq_with_condition(&mat)?;
+    if cond > CONDITION_NUMBER_WARNING {
+        log::warn!(
+            "Data matrix is ill-conditioned (condition number = {:e}).",
+            cond
+        );
+    }
+    Ok(params)
 }
 
-fn least_square_fitting_with_weight<'a, DataClass: ObservedData<'a>>(
+pub fn least_square_fitting_with_weight<'a, DataClass: ObservedData<'a>>(
     data: &'a [na::Point2<f64>],
     weights: &[f64],
 ) -> Result<na::DVector<f64>> {
@@ -28,25 +44,82 @@ fn least_square_fitting_with_weight<'a, DataClass: ObservedData<'a>>(
 
 pub fn iterative_reweight<'a, DataClass: ObservedData<'a>>(
     data: &'a [na::Point2<f64>],
+) -> Result<na::DVector<f64>> {
+    iterative_reweight_with_loss::<DataClass>(data, None)
+}
+
+/// Same as `iterative_reweight`, but `progress` is called at the start of each iteration.
+pub fn iterative_reweight_with_progress<'a, DataClass: ObservedData<'a>>(
+    data: &'a [na::Point2<f64>],
+    progress: Option<ProgressCallback>,
+) -> Result<na::DVector<f64>> {
+    iterative_reweight_impl::<DataClass>(data, None, None, progress.as_ref())
+}
+
+/// Iteratively reweighted least squares with the robust loss function.
+/// Weights of each data are multiplied by `robust_weight` of its residual
+/// sqrt(sum_kl W_kl (xi_k, theta) (xi_l, theta)).
+/// - `loss` : robust loss function. L2 loss is used if `None` (same as `iterative_reweight`).
+pub fn iterative_reweight_with_loss<'a, DataClass: ObservedData<'a>>(
+    data: &'a [na::Point2<f64>],
+    loss: Option<&RobustLoss>,
+) -> Result<na::DVector<f64>> {
+    iterative_reweight_impl::<DataClass>(data, None, loss, None)
+}
+
+/// Iteratively reweighted least squares using only the data whose `mask` is true.
+/// - `mask` : flags of each data (length is `ObservedData::len`).
+pub fn iterative_reweight_with_mask<'a, DataClass: ObservedData<'a>>(
+    data: &'a [na::Point2<f64>],
+    mask: &[bool],
+) -> Result<na::DVector<f64>> {
+    iterative_reweight_impl::<DataClass>(data, Some(mask), None, None)
+}
+
+fn iterative_reweight_impl<'a, DataClass: ObservedData<'a>>(
+    data: &'a [na::Point2<f64>],
+    mask: Option<&[bool]>,
+    loss: Option<&RobustLoss>,
+    progress: Option<&ProgressCallback>,
 ) -> Result<na::DVector<f64>> {
     let data_container = DataClass::new(data);
+    if let Some(mask) = mask {
+        ensure!(
+            mask.len() == data_container.len(),
+            "Length of mask ({}) does not match with data ({})",
+            mask.len(),
+            data_container.len()
+        );
+    }
     // calculate first iteration
-    let default_weights: Vec<f64> =
+    let mut default_weights: Vec<f64> =
         vec![1.0; data_container.len() * data_container.num_equation().pow(2)];
+    if let Some(mask) = mask {
+        apply_mask(&data_container, &mut default_weights, mask);
+    }
     let mut params = least_square_fitting_with_weight::<DataClass>(data, &default_weights)?;
     let mut previous: na::DVector<f64> =
         na::DVector::<f64>::from_iterator(params.len(), (0..params.len()).map(|_| 0.0));
     // calculate residual (for avoiding instability caused by DVD)
     let mut residual = params.dot(&(&data_container.matrix(&default_weights) * &params));
 
-    for _ in 0..MAX_ITERATION {
+    for step in 1..=MAX_ITERATION {
+        if let Some(progress) = progress {
+            progress(step, MAX_ITERATION);
+        }
         if previous[0] * params[0] < 0.0 {
             params *= -1.0;
         }
         if (&params - &previous).norm() < STOP_THRESHOLD {
             break;
         }
-        let weights = data_container.weights(&params);
+        let mut weights = data_container.weights(&params);
+        if let Some(loss) = loss {
+            apply_robust_weights(&data_container, &params, &mut weights, loss);
+        }
+        if let Some(mask) = mask {
+            apply_mask(&data_container, &mut weights, mask);
+        }
         previous = params.clone();
         let mat = data_container.matrix(&weights);
         let updated = lstsq(&mat)?;
@@ -63,3 +136,40 @@ pub fn iterative_reweight<'a, DataClass: ObservedData<'a>>(
     }
     Ok(params)
 }
+
+/// Set weights of the data whose `mask` is false to zero.
+fn apply_mask<'a, DataClass: ObservedData<'a>>(
+    data_container: &DataClass,
+    weights: &mut [f64],
+    mask: &[bool],
+) {
+    let n_eqs = data_container.num_equation();
+    weights
+        .chunks_mut(n_eqs * n_eqs)
+        .zip(mask.iter())
+        .filter(|(_, flag)| !**flag)
+        .for_each(|(block, _)| block.iter_mut().for_each(|val| *val = 0.0));
+}
+
+/// Multiply weights of each data by the robust weight of its residual.
+fn apply_robust_weights<'a, DataClass: ObservedData<'a>>(
+    data_container: &DataClass,
+    params: &na::DVector<f64>,
+    weights: &mut [f64],
+    loss: &RobustLoss,
+) {
+    let n_eqs = data_container.num_equation();
+    weights
+        .chunks_mut(n_eqs * n_eqs)
+        .enumerate()
+        .for_each(|(idx, block)| {
+            let dots: Vec<f64> = (0..n_eqs)
+                .map(|k| data_container.vector(idx * n_eqs + k).dot(params))
+                .collect();
+            let squared: f64 = (0..n_eqs * n_eqs)
+                .map(|i| block[i] * dots[i / n_eqs] * dots[i % n_eqs])
+                .sum();
+            let weight = robust_weight(loss, squared.max(0.0).sqrt());
+            block.iter_mut().for_each(|val| *val *= weight);
+        });
+}
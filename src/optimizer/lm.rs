@@ -0,0 +1,177 @@
+//! Levenberg-Marquardt method for the nonlinear least squares problems.
+use nalgebra as na;
+
+use crate::{
+    ensure,
+    error::{ImprocError, Result},
+};
+
+use super::jacobian::numerical_jacobian;
+
+const INITIAL_DAMPING: f64 = 1e-3;
+const GRADIENT_THRESHOLD: f64 = 1e-15;
+const STEP_THRESHOLD: f64 = 1e-15;
+const NUMERICAL_DIFF_EPS: f64 = 1e-7;
+
+/// Cost function of the nonlinear least squares problem.
+/// Cost of the parameters `x` is |r(x)|^2 / 2 where r(x) is `residuals`.
+pub trait CostFunction {
+    fn residuals(&self, x: &na::DVector<f64>) -> na::DVector<f64>;
+
+    /// Return jacobian of the residuals (J_ij = dr_i / dx_j).
+    /// Numerical jacobian is used by default.
+    fn jacobian(&self, x: &na::DVector<f64>) -> na::DMatrix<f64> {
+        numerical_jacobian(|x| self.residuals(x), x, NUMERICAL_DIFF_EPS)
+    }
+}
+
+/// Result of `levenberg_marquardt`.
+/// - `x` : optimized parameters.
+/// - `cost` : |r(x)|^2 / 2 of the optimized parameters.
+/// - `iterations` : number of the iterations (including the rejected steps).
+/// - `converged` : false if the iteration is stopped by `max_iter`.
+#[derive(Clone, Debug)]
+pub struct LMResult {
+    pub x: na::DVector<f64>,
+    pub cost: f64,
+    pub iterations: usize,
+    pub converged: bool,
+}
+
+/// Minimize `cost` starting from `x0` by the Levenberg-Marquardt method.
+/// Step h is calculated by (J^T J + lambda * diag(J^T J)) h = -J^T r (Marquardt's formula) and
+/// accepted if the gain ratio rho (= actual reduction / predicted reduction) is positive.
+/// `lambda` is updated by lambda *= max(1/3, 1 - (2 rho - 1)^3) on accept and lambda *= nu (nu is
+/// doubled) on reject (K. Madsen et al., "Methods for Non-Linear Least Squares Problems", 2004).
+pub fn levenberg_marquardt(
+    cost: &dyn CostFunction,
+    x0: na::DVector<f64>,
+    max_iter: usize,
+) -> Result<LMResult> {
+    let mut x = x0;
+    let mut residuals = cost.residuals(&x);
+    let mut current = residuals.norm_squared() / 2.0;
+    let mut lambda = INITIAL_DAMPING;
+    let mut nu = 2.0;
+
+    for iter in 0..max_iter {
+        let jacobian = cost.jacobian(&x);
+        ensure!(
+            jacobian.shape() == (residuals.nrows(), x.nrows()),
+            ImprocError::ShapeMismatch {
+                expected: (residuals.nrows(), x.nrows()),
+                got: jacobian.shape()
+            }
+        );
+        let jtj = jacobian.transpose() * &jacobian;
+        let gradient = jacobian.transpose() * &residuals;
+        if gradient.amax() < GRADIENT_THRESHOLD {
+            return Ok(LMResult {
+                x,
+                cost: current,
+                iterations: iter,
+                converged: true,
+            });
+        }
+        // diagonal elements are clamped to avoid the singular matrix.
+        let diag = jtj.diagonal().map(|val| val.max(f64::EPSILON));
+        let step = (&jtj + lambda * na::DMatrix::from_diagonal(&diag))
+            .lu()
+            .solve(&(-&gradient))
+            .ok_or(ImprocError::SingularMatrix)?;
+        if step.norm() < STEP_THRESHOLD * (x.norm() + STEP_THRESHOLD) {
+            return Ok(LMResult {
+                x,
+                cost: current,
+                iterations: iter,
+                converged: true,
+            });
+        }
+
+        let updated = &x + &step;
+        let updated_residuals = cost.residuals(&updated);
+        let updated_cost = updated_residuals.norm_squared() / 2.0;
+        // L(0) - L(h) = h^T (lambda * diag * h - g) / 2
+        let predicted = step.dot(&(lambda * diag.component_mul(&step) - &gradient)) / 2.0;
+        let rho = (current - updated_cost) / predicted;
+        let accepted = predicted > 0.0 && rho > 0.0;
+        if accepted {
+            x = updated;
+            residuals = updated_residuals;
+            current = updated_cost;
+        }
+        let (next_lambda, next_nu) = update_damping(lambda, nu, accepted, rho);
+        lambda = next_lambda;
+        nu = next_nu;
+    }
+    Ok(LMResult {
+        x,
+        cost: current,
+        iterations: max_iter,
+        converged: false,
+    })
+}
+
+/// Return updated damping parameter `lambda` and its multiplier `nu` of the LM method.
+/// - `accepted` : whether the step is accepted.
+/// - `rho` : gain ratio (actual reduction / predicted reduction) of the step.
+pub fn update_damping(lambda: f64, nu: f64, accepted: bool, rho: f64) -> (f64, f64) {
+    if accepted {
+        (
+            lambda * (1.0f64 / 3.0).max(1.0 - (2.0 * rho - 1.0).powi(3)),
+            2.0,
+        )
+    } else {
+        (lambda * nu, nu * 2.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Rosenbrock function f(x, y) = (1 - x)^2 + 100 (y - x^2)^2. Minimum is at (1, 1).
+    struct Rosenbrock;
+
+    impl CostFunction for Rosenbrock {
+        fn residuals(&self, x: &na::DVector<f64>) -> na::DVector<f64> {
+            na::DVector::from_column_slice(&[1.0 - x[0], 10.0 * (x[1] - x[0] * x[0])])
+        }
+
+        fn jacobian(&self, x: &na::DVector<f64>) -> na::DMatrix<f64> {
+            na::DMatrix::from_row_slice(2, 2, &[-1.0, 0.0, -20.0 * x[0], 10.0])
+        }
+    }
+
+    /// Rosenbrock function with the numerical jacobian.
+    struct NumericalRosenbrock;
+
+    impl CostFunction for NumericalRosenbrock {
+        fn residuals(&self, x: &na::DVector<f64>) -> na::DVector<f64> {
+            Rosenbrock.residuals(x)
+        }
+    }
+
+    #[test]
+    fn test_rosenbrock() {
+        let gt = na::DVector::from_column_slice(&[1.0, 1.0]);
+        [(-1.2, 1.0), (2.0, -3.0), (-3.0, -4.0)]
+            .iter()
+            .for_each(|(x, y)| {
+                let x0 = na::DVector::from_column_slice(&[*x, *y]);
+                let res = levenberg_marquardt(&Rosenbrock, x0.clone(), 200).unwrap();
+                assert!(res.converged);
+                assert!((&res.x - &gt).norm() < 1e-10, "x = {}", res.x);
+                assert!(res.cost < 1e-20);
+
+                let res = levenberg_marquardt(&NumericalRosenbrock, x0, 200).unwrap();
+                assert!((&res.x - &gt).norm() < 1e-6, "x = {}", res.x);
+            });
+
+        // not converged within the iterations
+        let x0 = na::DVector::from_column_slice(&[-1.2, 1.0]);
+        let res = levenberg_marquardt(&Rosenbrock, x0, 2).unwrap();
+        assert!(!res.converged);
+        assert_eq!(res.iterations, 2);
+    }
+}
@@ -0,0 +1,410 @@
+//! Implementation of RANSAC for the data contaminated by outliers.
+use nalgebra as na;
+use rand::seq::index::sample;
+
+use crate::{
+    ensure,
+    error::{ImprocError, Result},
+    linalg::ransac::{RANSACConfig, RANSAC},
+};
+
+use super::{
+    least_square::{iterative_reweight_with_mask, least_square_fitting_with_weight},
+    ObservedData,
+};
+
+/// Robustly fit parameters to `data` by RANSAC.
+/// In each iteration, `min_sample` data (e.g. point pairs for `FundamentalMatrixData`) are
+/// randomly sampled and fitted by least square fitting. A data is regarded as inlier if
+/// the algebraic error (|(xi, theta)|) is smaller than `threshold`.
+/// The parameters with the most inliers are refined with all inliers.
+pub fn ransac<'a, D: ObservedData<'a>>(
+    data: &'a [na::Point2<f64>],
+    min_sample: usize,
+    iterations: usize,
+    threshold: f64,
+) -> Result<na::DVector<f64>> {
+    let data_container = D::new(data);
+    let n_data = data_container.len();
+    ensure!(min_sample > 0, "min_sample must be positive");
+    ensure!(
+        n_data >= min_sample,
+        ImprocError::InsufficientData {
+            expected: min_sample,
+            got: n_data
+        }
+    );
+
+    LeastSquareRansac {
+        data,
+        data_container,
+        min_sample,
+        threshold,
+    }
+    .run(&RANSACConfig::new(iterations))
+}
+
+/// RANSAC estimator of the parameters fitted by `least_square_fitting_with_weight`.
+struct LeastSquareRansac<'a, D: ObservedData<'a>> {
+    data: &'a [na::Point2<f64>],
+    data_container: D,
+    min_sample: usize,
+    threshold: f64,
+}
+
+impl<'a, D: ObservedData<'a>> RANSAC<na::DVector<f64>, usize> for LeastSquareRansac<'a, D> {
+    fn min_sample(&self) -> usize {
+        self.min_sample
+    }
+
+    fn estimate_from_random_sample(&self) -> Result<na::DVector<f64>> {
+        let samples = sample(
+            &mut rand::thread_rng(),
+            self.data_container.len(),
+            self.min_sample,
+        );
+        self.estimate(&samples.into_vec())
+    }
+
+    fn get_inliers(&self, params: &na::DVector<f64>) -> Vec<usize> {
+        get_inliers(&self.data_container, params, self.threshold)
+    }
+
+    fn estimate(&self, inputs: &[usize]) -> Result<na::DVector<f64>> {
+        least_square_fitting_with_weight::<D>(
+            self.data,
+            &sample_weights(&self.data_container, inputs),
+        )
+    }
+}
+
+/// Robustly fit parameters to `data` by PROSAC (progressive sample consensus).
+/// `data` is assumed to be sorted by the quality (e.g. descriptor distance of the matches) in
+/// descending order and `sorted_quality` is the quality of each data (non-increasing).
+/// Instead of sampling uniformly from all data as `ransac`, samples are drawn from the subset of
+/// the top-quality data which grows according to the growth function of
+/// O. Chum and J. Matas, "Matching with PROSAC - Progressive Sample Consensus", CVPR 2005.
+/// Inliers are determined and refined in the same way as `ransac`.
+pub fn prosac<'a, D: ObservedData<'a>>(
+    data: &'a [na::Point2<f64>],
+    sorted_quality: &[f64],
+    min_sample: usize,
+    iterations: usize,
+    threshold: f64,
+) -> Result<na::DVector<f64>> {
+    let data_container = D::new(data);
+    let n_data = data_container.len();
+    ensure!(min_sample > 0, "min_sample must be positive");
+    ensure!(
+        n_data >= min_sample,
+        ImprocError::InsufficientData {
+            expected: min_sample,
+            got: n_data
+        }
+    );
+    ensure!(
+        sorted_quality.len() == n_data,
+        "Length of quality ({}) does not match with data ({})",
+        sorted_quality.len(),
+        n_data
+    );
+    ensure!(
+        sorted_quality.windows(2).all(|w| w[0] >= w[1]),
+        "Quality is not sorted in descending order"
+    );
+
+    let mut rng = rand::thread_rng();
+    let mut best_inliers: Vec<usize> = vec![];
+    // average number of the samples drawn only from the top `n` data (T_n in the paper).
+    let mut t_n = (0..min_sample).fold(iterations as f64, |acc, i| {
+        acc * (min_sample - i) as f64 / (n_data - i) as f64
+    });
+    let mut t_n_prime = 1; // number of the iterations until the subset is grown
+    let mut n = min_sample; // size of the subset
+    for t in 1..=iterations {
+        while t > t_n_prime && n < n_data {
+            let t_next = t_n * (n + 1) as f64 / (n + 1 - min_sample) as f64;
+            t_n_prime += (t_next - t_n).ceil() as usize;
+            t_n = t_next;
+            n += 1;
+        }
+        let samples = if t > t_n_prime {
+            sample(&mut rng, n, min_sample).into_vec()
+        } else {
+            // n-th data is always included in the samples.
+            let mut samples = sample(&mut rng, n - 1, min_sample - 1).into_vec();
+            samples.push(n - 1);
+            samples
+        };
+        let params = least_square_fitting_with_weight::<D>(
+            data,
+            &sample_weights(&data_container, &samples),
+        )?;
+        let inliers = get_inliers(&data_container, &params, threshold);
+        if inliers.len() > best_inliers.len() {
+            best_inliers = inliers;
+        }
+    }
+    ensure!(
+        best_inliers.len() >= min_sample,
+        "Failed to find enough inliers : {}",
+        best_inliers.len()
+    );
+
+    // refine parameters with all inliers
+    least_square_fitting_with_weight::<D>(data, &sample_weights(&data_container, &best_inliers))
+}
+
+/// Robustly fit parameters to `data` by LO-RANSAC (locally optimized RANSAC).
+/// Hypotheses are sampled in the same way as `ransac`, but each time a new best hypothesis is
+/// found, its inliers are refitted by `iterative_reweight_with_mask` and re-scored up to
+/// `lo_iterations` times while the number of the inliers increases
+/// (O. Chum et al., "Locally Optimized RANSAC", DAGM 2003).
+/// The parameters with the most inliers are refined with all inliers in the same way.
+pub fn loransac<'a, D: ObservedData<'a>>(
+    data: &'a [na::Point2<f64>],
+    min_sample: usize,
+    iterations: usize,
+    threshold: f64,
+    lo_iterations: usize,
+) -> Result<na::DVector<f64>> {
+    let data_container = D::new(data);
+    let n_data = data_container.len();
+    ensure!(min_sample > 0, "min_sample must be positive");
+    ensure!(
+        n_data >= min_sample,
+        ImprocError::InsufficientData {
+            expected: min_sample,
+            got: n_data
+        }
+    );
+
+    let mut rng = rand::thread_rng();
+    let mut best_inliers: Vec<usize> = vec![];
+    for _ in 0..iterations {
+        let samples = sample(&mut rng, n_data, min_sample).into_vec();
+        let params = least_square_fitting_with_weight::<D>(
+            data,
+            &sample_weights(&data_container, &samples),
+        )?;
+        let inliers = get_inliers(&data_container, &params, threshold);
+        if inliers.len() > best_inliers.len() {
+            best_inliers = inliers;
+            // local optimization
+            for _ in 0..lo_iterations {
+                if best_inliers.len() < min_sample {
+                    break;
+                }
+                let params =
+                    iterative_reweight_with_mask::<D>(data, &inlier_mask(n_data, &best_inliers))?;
+                let inliers = get_inliers(&data_container, &params, threshold);
+                if inliers.len() <= best_inliers.len() {
+                    break;
+                }
+                best_inliers = inliers;
+            }
+        }
+    }
+    ensure!(
+        best_inliers.len() >= min_sample,
+        "Failed to find enough inliers : {}",
+        best_inliers.len()
+    );
+
+    // refine parameters with all inliers
+    iterative_reweight_with_mask::<D>(data, &inlier_mask(n_data, &best_inliers))
+}
+
+/// Return flags of the data which is true if the index of the data is in `indices`.
+fn inlier_mask(n_data: usize, indices: &[usize]) -> Vec<bool> {
+    let mut mask = vec![false; n_data];
+    indices.iter().for_each(|idx| mask[*idx] = true);
+    mask
+}
+
+/// Create weights which select only the data specified by `indices`.
+fn sample_weights<'a, D: ObservedData<'a>>(data_container: &D, indices: &[usize]) -> Vec<f64> {
+    let n_eqs = data_container.num_equation();
+    let mut weights = vec![0.0; data_container.len() * n_eqs * n_eqs];
+    indices.iter().for_each(|idx| {
+        (0..n_eqs).for_each(|i| weights[(idx * n_eqs + i) * n_eqs + i] = 1.0);
+    });
+    weights
+}
+
+/// Return indices of the data whose algebraic error is smaller than `threshold`.
+fn get_inliers<'a, D: ObservedData<'a>>(
+    data_container: &D,
+    params: &na::DVector<f64>,
+    threshold: f64,
+) -> Vec<usize> {
+    let n_eqs = data_container.num_equation();
+    (0..data_container.len())
+        .filter(|idx| {
+            let error: f64 = (0..n_eqs)
+                .map(|i| data_container.vector(idx * n_eqs + i).dot(params).powi(2))
+                .sum();
+            error.sqrt() < threshold
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use crate::epipolar::{
+        essential_matrix::{normalize_points, tests::create_test_data},
+        fundamental_matrix::FundamentalMatrixData,
+        sampson_error_total,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_ransac_fundamental_matrix() {
+        let mut rng = rand::thread_rng();
+        let (intrinsics, _, _, data) = create_test_data();
+        let mut data = normalize_points(&data, &intrinsics).unwrap();
+        let is_inlier: Vec<bool> = (0..data.len() / 2)
+            .map(|idx| {
+                if idx % 10 < 3 {
+                    data[idx * 2 + 1] = na::Point2::new(
+                        (rng.gen::<f64>() - 0.5) * 0.8,
+                        (rng.gen::<f64>() - 0.5) * 0.8,
+                    );
+                    false
+                } else {
+                    true
+                }
+            })
+            .collect();
+
+        let res = ransac::<FundamentalMatrixData>(&data, 8, 300, 1e-5).unwrap();
+        let fund_mat = na::DMatrix::from_row_slice(3, 3, res.as_slice());
+        let inlier_data: Vec<na::Point2<f64>> = (0..data.len() / 2)
+            .filter(|idx| is_inlier[*idx])
+            .flat_map(|idx| vec![data[idx * 2], data[idx * 2 + 1]])
+            .collect();
+        let error = sampson_error_total(&fund_mat, &inlier_data) / (inlier_data.len() / 2) as f64;
+        assert!(error < 1e-4, "sampson error = {}", error);
+    }
+
+    #[test]
+    fn test_prosac_fundamental_matrix() {
+        let mut rng = rand::thread_rng();
+        let (intrinsics, _, _, data) = create_test_data();
+        let mut data = normalize_points(&data, &intrinsics).unwrap();
+        // only the top 30% quality data are inliers.
+        let n_data = data.len() / 2;
+        let n_inliers = n_data * 3 / 10;
+        (n_inliers..n_data).for_each(|idx| {
+            data[idx * 2 + 1] = na::Point2::new(
+                (rng.gen::<f64>() - 0.5) * 0.8,
+                (rng.gen::<f64>() - 0.5) * 0.8,
+            );
+        });
+        let quality: Vec<f64> = (0..n_data).map(|idx| (n_data - idx) as f64).collect();
+        let inlier_data = &data[..n_inliers * 2];
+        let is_success = |res: Result<na::DVector<f64>>| -> bool {
+            res.map(|res| {
+                let fund_mat = na::DMatrix::from_row_slice(3, 3, res.as_slice());
+                sampson_error_total(&fund_mat, inlier_data) / (n_inliers as f64) < 1e-4
+            })
+            .unwrap_or(false)
+        };
+
+        // minimum number of the iterations required to find the inliers.
+        let budgets = [1, 2, 4, 8, 16, 32];
+        let prosac_iterations = budgets
+            .iter()
+            .find(|&&iter| {
+                is_success(prosac::<FundamentalMatrixData>(
+                    &data, &quality, 8, iter, 1e-5,
+                ))
+            })
+            .copied();
+        let ransac_iterations = budgets
+            .iter()
+            .find(|&&iter| is_success(ransac::<FundamentalMatrixData>(&data, 8, iter, 1e-5)))
+            .copied()
+            .unwrap_or(usize::MAX);
+        assert_eq!(prosac_iterations, Some(1));
+        assert!(ransac_iterations > 1);
+
+        // converge to the same solution as RANSAC with enough iterations.
+        assert!(is_success(prosac::<FundamentalMatrixData>(
+            &data, &quality, 8, 300, 1e-5
+        )));
+    }
+
+    #[test]
+    fn test_prosac_invalid_quality() {
+        let (_, _, _, data) = create_test_data();
+        let n_data = data.len() / 2;
+        let ascending: Vec<f64> = (0..n_data).map(|idx| idx as f64).collect();
+        assert!(prosac::<FundamentalMatrixData>(&data, &ascending, 8, 10, 1e-3).is_err());
+        assert!(prosac::<FundamentalMatrixData>(&data, &ascending[..10], 8, 10, 1e-3).is_err());
+    }
+
+    #[test]
+    fn test_loransac_fundamental_matrix() {
+        let mut rng = rand::thread_rng();
+        let rot = na::Rotation3::from_scaled_axis(na::Vector3::new(0.1, -0.2, 0.15));
+        let trans = na::Vector3::new(-1.0, 0.4, 0.3);
+        let n_trials = 20;
+        let n_wins = (0..n_trials)
+            .filter(|_| {
+                // normalized image coordinates with noise. 30% of the data are outliers.
+                let mut inlier_data = vec![];
+                let data: Vec<na::Point2<f64>> = (0..100)
+                    .flat_map(|idx| {
+                        let x0 = na::Point3::new(
+                            (rng.gen::<f64>() - 0.5) * 4.0,
+                            (rng.gen::<f64>() - 0.5) * 4.0,
+                            rng.gen::<f64>() * 4.0 + 4.0,
+                        );
+                        let x1 = rot * x0 + trans;
+                        let pt0 = na::Point2::new(x0[0] / x0[2], x0[1] / x0[2]);
+                        if idx % 10 < 3 {
+                            return vec![
+                                pt0,
+                                na::Point2::new(
+                                    (rng.gen::<f64>() - 0.5) * 0.8,
+                                    (rng.gen::<f64>() - 0.5) * 0.8,
+                                ),
+                            ];
+                        }
+                        let pt1 = na::Point2::new(
+                            x1[0] / x1[2] + (rng.gen::<f64>() - 0.5) * 1e-4,
+                            x1[1] / x1[2] + (rng.gen::<f64>() - 0.5) * 1e-4,
+                        );
+                        inlier_data.push(pt0);
+                        inlier_data.push(pt1);
+                        vec![pt0, pt1]
+                    })
+                    .collect();
+                let error = |params: na::DVector<f64>| -> f64 {
+                    let fund_mat = na::DMatrix::from_row_slice(3, 3, params.as_slice());
+                    sampson_error_total(&fund_mat, &inlier_data)
+                };
+                let ransac_res = ransac::<FundamentalMatrixData>(&data, 8, 50, 3e-4).unwrap();
+                let loransac_res =
+                    loransac::<FundamentalMatrixData>(&data, 8, 50, 3e-4, 5).unwrap();
+                error(loransac_res) < error(ransac_res)
+            })
+            .count();
+        assert!(
+            n_wins > n_trials / 2,
+            "LO-RANSAC wins : {} / {}",
+            n_wins,
+            n_trials
+        );
+    }
+
+    #[test]
+    fn test_ransac_not_enough_data() {
+        let (_, _, _, data) = create_test_data();
+        assert!(ransac::<FundamentalMatrixData>(&data[..10], 8, 10, 1e-3).is_err());
+    }
+}
@@ -0,0 +1,83 @@
+//! Robust loss functions (M-estimators) for the iteratively reweighted least squares.
+
+/// Robust loss function rho(r). Parameter of each variant is the scale of the residual.
+/// - `Huber(k)` : rho(r) = r^2 / 2 (|r| <= k), k|r| - k^2 / 2 (otherwise).
+/// - `Tukey(c)` : rho(r) = c^2 / 6 * (1 - (1 - (r / c)^2)^3) (|r| <= c), c^2 / 6 (otherwise).
+/// - `Cauchy(c)` : rho(r) = c^2 / 2 * log(1 + (r / c)^2).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RobustLoss {
+    Huber(f64),
+    Tukey(f64),
+    Cauchy(f64),
+}
+
+/// Return the weight rho'(r) / r of the iteratively reweighted least squares.
+/// Weight of the L2 loss (rho(r) = r^2 / 2) is 1.
+pub fn robust_weight(loss: &RobustLoss, residual: f64) -> f64 {
+    let r = residual.abs();
+    match *loss {
+        RobustLoss::Huber(k) => {
+            if r <= k {
+                1.0
+            } else {
+                k / r
+            }
+        }
+        RobustLoss::Tukey(c) => {
+            if r <= c {
+                (1.0 - (r / c).powi(2)).powi(2)
+            } else {
+                0.0
+            }
+        }
+        RobustLoss::Cauchy(c) => 1.0 / (1.0 + (r / c).powi(2)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_robust_weight() {
+        let huber = RobustLoss::Huber(2.0);
+        assert_eq!(robust_weight(&huber, 0.0), 1.0);
+        assert_eq!(robust_weight(&huber, -1.5), 1.0);
+        assert!((robust_weight(&huber, 4.0) - 0.5).abs() < 1e-12);
+        assert!((robust_weight(&huber, -8.0) - 0.25).abs() < 1e-12);
+
+        let tukey = RobustLoss::Tukey(2.0);
+        assert_eq!(robust_weight(&tukey, 0.0), 1.0);
+        assert!((robust_weight(&tukey, 1.0) - 0.5625).abs() < 1e-12);
+        assert_eq!(robust_weight(&tukey, 2.5), 0.0);
+
+        let cauchy = RobustLoss::Cauchy(2.0);
+        assert_eq!(robust_weight(&cauchy, 0.0), 1.0);
+        assert!((robust_weight(&cauchy, -2.0) - 0.5).abs() < 1e-12);
+
+        // weight is equal to rho'(r) / r
+        let eps = 1e-6;
+        let rho = |loss: &RobustLoss, r: f64| -> f64 {
+            match *loss {
+                RobustLoss::Huber(k) if r.abs() <= k => r * r / 2.0,
+                RobustLoss::Huber(k) => k * r.abs() - k * k / 2.0,
+                RobustLoss::Tukey(c) if r.abs() <= c => {
+                    c * c / 6.0 * (1.0 - (1.0 - (r / c).powi(2)).powi(3))
+                }
+                RobustLoss::Tukey(c) => c * c / 6.0,
+                RobustLoss::Cauchy(c) => c * c / 2.0 * (1.0 + (r / c).powi(2)).ln(),
+            }
+        };
+        [huber, tukey, cauchy].iter().for_each(|loss| {
+            [0.3, 1.7, 3.1, -5.0].iter().for_each(|r| {
+                let derivative = (rho(loss, r + eps) - rho(loss, r - eps)) / (2.0 * eps);
+                assert!(
+                    (derivative / r - robust_weight(loss, *r)).abs() < 1e-6,
+                    "loss = {:?}, r = {}",
+                    loss,
+                    r
+                );
+            });
+        });
+    }
+}
@@ -1,26 +1,69 @@
 //! Implementation for Taubin method and renormalization.
-use anyhow::Result;
 use nalgebra as na;
 
-use crate::linalg::{get_zero_mat, matrix::constrained_lstsq};
+use crate::{
+    error::Result,
+    linalg::{get_zero_mat, matrix::constrained_lstsq},
+};
 
-use super::ObservedData;
+use super::{check_rank, ObservedData, OptimizeResult, ProgressCallback};
 
 const MAX_ITERATION: usize = 100;
 const STOP_THRESHOLD: f64 = 1e-7;
 
+/// Estimate parameters by Taubin method. Taubin method is not iterative, so that `iterations` of
+/// the returned result is 1 and `converged` is always true.
 pub fn taubin<'a, DataClass: ObservedData<'a>>(
     data: &'a [na::Point2<f64>],
-) -> Result<na::DVector<f64>> {
+) -> Result<OptimizeResult> {
+    taubin_with_progress::<DataClass>(data, None)
+}
+
+/// Same as `taubin`, but `progress` is called once with (1, 1).
+pub fn taubin_with_progress<'a, DataClass: ObservedData<'a>>(
+    data: &'a [na::Point2<f64>],
+    progress: Option<ProgressCallback>,
+) -> Result<OptimizeResult> {
+    if let Some(progress) = &progress {
+        progress(1, 1);
+    }
     let data_container = DataClass::new(data);
+    check_rank(&data_container)?;
     let weights = vec![1.0; data_container.len() * data_container.num_equation().pow(2)];
-    taubin_with_weight::<DataClass>(data, &weights)
+    let params = taubin_with_weight::<DataClass>(data, &weights)?;
+    let residual = params.dot(&(&data_container.matrix(&weights) * &params));
+    Ok(OptimizeResult {
+        params,
+        iterations: 1,
+        residual,
+        converged: true,
+        history: vec![residual],
+    })
 }
 
-pub fn renormalization<'a, DataClass: ObservedData<'a>>(
+/// Same as `taubin`, but return only the estimated parameters.
+pub fn taubin_params<'a, DataClass: ObservedData<'a>>(
     data: &'a [na::Point2<f64>],
 ) -> Result<na::DVector<f64>> {
-    let mut params = taubin::<DataClass>(data)?;
+    Ok(taubin::<DataClass>(data)?.params)
+}
+
+/// Estimate parameters by renormalization starting from the result of `taubin`.
+pub fn renormalization<'a, DataClass: ObservedData<'a>>(
+    data: &'a [na::Point2<f64>],
+) -> Result<OptimizeResult> {
+    renormalization_with_progress::<DataClass>(data, MAX_ITERATION, None)
+}
+
+/// Same as `renormalization`, but the iteration stops after `max_iteration` iterations at most
+/// and `progress` is called with (step, `max_iteration`) at the start of each iteration
+/// (the initial estimation by `taubin` is not counted).
+pub fn renormalization_with_progress<'a, DataClass: ObservedData<'a>>(
+    data: &'a [na::Point2<f64>],
+    max_iteration: usize,
+    progress: Option<ProgressCallback>,
+) -> Result<OptimizeResult> {
+    let mut params = taubin::<DataClass>(data)?.params;
     let mut previous: na::DVector<f64> =
         na::DVector::<f64>::from_iterator(params.len(), (0..params.len()).map(|_| 0.0));
     let data_container = DataClass::new(data);
@@ -30,30 +73,53 @@ pub fn renormalization<'a, DataClass: ObservedData<'a>>(
         data_container.len()
             * data_container.num_equation().pow(2)
     ]);
-    let mut residual = &params.transpose() * &default_matrix * &params;
+    let mut residual = params.dot(&(&default_matrix * &params));
+    let mut history = vec![residual];
+    let mut iterations = 1;
+    let mut converged = false;
 
-    for _ in 1..MAX_ITERATION {
+    for step in 1..=max_iteration {
+        if let Some(progress) = &progress {
+            progress(step, max_iteration);
+        }
         if previous[0] * params[0] < 0.0 {
             previous *= -1.0;
         }
         if (params.clone() - previous).norm() < STOP_THRESHOLD {
+            converged = true;
             break;
         }
         let weights = data_container.weights(&params);
         previous = params.clone();
         let updated = taubin_with_weight::<DataClass>(data, &weights)?;
-        // check whether residual is decreasing
+        iterations += 1;
+        // check whether residual is decreasing. Residual of the exact data can be a tiny
+        // negative value by the rounding error, so that magnitudes are compared.
         {
-            let res = &updated.transpose() * &default_matrix * &updated;
-            if res > residual * 10.0 {
-                println!("Residual is not decreasing. Break iteration.");
+            let res = updated.dot(&(&default_matrix * &updated));
+            history.push(res);
+            if res.abs() > residual.abs() * 10.0 {
+                log::warn!("Residual is not decreasing. Break iteration.");
                 break;
             }
             residual = res;
         }
         params = updated;
     }
-    Ok(params)
+    Ok(OptimizeResult {
+        params,
+        iterations,
+        residual,
+        converged,
+        history,
+    })
+}
+
+/// Same as `renormalization`, but return only the estimated parameters.
+pub fn renormalization_params<'a, DataClass: ObservedData<'a>>(
+    data: &'a [na::Point2<f64>],
+) -> Result<na::DVector<f64>> {
+    Ok(renormalization::<DataClass>(data)?.params)
 }
 
 fn taubin_with_weight<'a, DataClass: ObservedData<'a>>(
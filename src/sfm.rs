@@ -1,4 +1,12 @@
 pub mod affine_self_calibration;
+pub mod distortion;
+pub mod export;
+pub mod intrinsics;
+pub mod pipeline;
 pub mod plane_self_calibration;
+pub mod pnp;
 pub mod projective_self_calibration;
+pub mod scale_estimation;
 pub mod self_calibration;
+pub mod similarity;
+pub mod track;
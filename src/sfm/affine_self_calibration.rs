@@ -1,7 +1,8 @@
-use anyhow::{ensure, Context, Result};
 use nalgebra as na;
 
 use crate::{
+    ensure,
+    error::{Context, ImprocError, Result},
     linalg::{get_zero_mat, matrix::lstsq},
     PrintDebug,
 };
@@ -83,7 +84,7 @@ pub fn affine_self_calibration(
     let affine_inv = affine
         .clone()
         .try_inverse()
-        .context("Failed to calculate inverse matrix")?;
+        .ok_or(ImprocError::SingularMatrix)?;
 
     let motion_mat = motion_mat * &affine;
     let shape_mat = &affine_inv * shape_mat;
@@ -0,0 +1,112 @@
+//! Lens distortion model.
+use nalgebra as na;
+
+const STOP_THRESHOLD: f64 = 1e-24;
+
+/// Radial (`k1`, `k2`) and tangential (`p1`, `p2`) lens distortion (Brown-Conrady model).
+/// Points are expressed in the normalized image coordinates (K^-1 * [x, y, 1]^T).
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DistortionModel {
+    pub k1: f64,
+    pub k2: f64,
+    pub p1: f64,
+    pub p2: f64,
+}
+
+impl DistortionModel {
+    pub fn new(k1: f64, k2: f64, p1: f64, p2: f64) -> Self {
+        DistortionModel { k1, k2, p1, p2 }
+    }
+
+    /// Map undistorted point `pt` to the distorted point.
+    pub fn distort(&self, pt: &na::Point2<f64>) -> na::Point2<f64> {
+        let (x, y) = (pt[0], pt[1]);
+        let r2 = x * x + y * y;
+        let radial = 1.0 + self.k1 * r2 + self.k2 * r2 * r2;
+        na::Point2::new(
+            x * radial + 2.0 * self.p1 * x * y + self.p2 * (r2 + 2.0 * x * x),
+            y * radial + self.p1 * (r2 + 2.0 * y * y) + 2.0 * self.p2 * x * y,
+        )
+    }
+
+    /// Map distorted point `pt` to the undistorted point.
+    /// Inverse of `distort` is calculated by Newton's method starting from `pt`.
+    /// - `max_iter` : maximum number of the iterations.
+    pub fn undistort(&self, pt: &na::Point2<f64>, max_iter: usize) -> na::Point2<f64> {
+        let mut x = *pt;
+        for _ in 0..max_iter {
+            let residual = self.distort(&x) - pt;
+            if residual.norm_squared() < STOP_THRESHOLD {
+                break;
+            }
+            let delta = match self.jacobian(&x).try_inverse() {
+                Some(inv) => inv * residual,
+                None => break,
+            };
+            x -= delta;
+        }
+        x
+    }
+
+    /// Jacobian of `distort` at `pt`.
+    fn jacobian(&self, pt: &na::Point2<f64>) -> na::Matrix2<f64> {
+        let (x, y) = (pt[0], pt[1]);
+        let r2 = x * x + y * y;
+        let radial = 1.0 + self.k1 * r2 + self.k2 * r2 * r2;
+        let d_radial = self.k1 + 2.0 * self.k2 * r2; // d(radial) / d(r2)
+        na::Matrix2::new(
+            radial + 2.0 * x * x * d_radial + 2.0 * self.p1 * y + 6.0 * self.p2 * x,
+            2.0 * x * y * d_radial + 2.0 * self.p1 * x + 2.0 * self.p2 * y,
+            2.0 * x * y * d_radial + 2.0 * self.p1 * x + 2.0 * self.p2 * y,
+            radial + 2.0 * y * y * d_radial + 6.0 * self.p1 * y + 2.0 * self.p2 * x,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use super::*;
+
+    #[test]
+    fn test_distort_and_undistort() {
+        let mut rng = rand::thread_rng();
+        let model = DistortionModel::new(-0.28, 0.07, 1e-3, -5e-4);
+        (0..100).for_each(|_| {
+            let pt = na::Point2::new(
+                (rng.gen::<f64>() - 0.5) * 1.2,
+                (rng.gen::<f64>() - 0.5) * 0.9,
+            );
+            let res = model.distort(&model.undistort(&pt, 9));
+            assert!((res - pt).norm() < 1e-6, "pt = {}, res = {}", pt, res);
+            let res = model.undistort(&model.distort(&pt), 9);
+            assert!((res - pt).norm() < 1e-6, "pt = {}, res = {}", pt, res);
+        });
+
+        // no distortion
+        let pt = na::Point2::new(0.3, -0.2);
+        assert_eq!(DistortionModel::default().distort(&pt), pt);
+        assert_eq!(DistortionModel::default().undistort(&pt, 10), pt);
+    }
+
+    #[test]
+    fn test_jacobian() {
+        let model = DistortionModel::new(-0.28, 0.07, 1e-3, -5e-4);
+        let pt = na::Point2::new(0.4, -0.3);
+        let jacobian = model.jacobian(&pt);
+        let eps = 1e-6;
+        (0..2).for_each(|i| {
+            let mut delta = na::Vector2::zeros();
+            delta[i] = eps;
+            let numerical =
+                (model.distort(&(pt + delta)) - model.distort(&(pt - delta))) / (2.0 * eps);
+            assert!(
+                (jacobian.column(i) - numerical).norm() < 1e-6,
+                "{} vs {}",
+                jacobian.column(i),
+                numerical
+            );
+        });
+    }
+}
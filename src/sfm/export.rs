@@ -0,0 +1,177 @@
+//! Export reconstructed point clouds.
+use std::{
+    fs::{self, File},
+    io::{prelude::*, BufWriter},
+    path::Path,
+};
+
+use nalgebra as na;
+
+use crate::{ensure, error::Result};
+
+/// Data format of PLY file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlyMode {
+    Ascii,
+    BinaryLittleEndian,
+}
+
+impl PlyMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PlyMode::Ascii => "ascii",
+            PlyMode::BinaryLittleEndian => "binary_little_endian",
+        }
+    }
+}
+
+/// Write `points` to PLY file (ASCII format). See `write_ply_with_mode`.
+pub fn write_ply(
+    path: &Path,
+    points: &[na::Vector3<f64>],
+    colors: Option<&[(u8, u8, u8)]>,
+) -> Result<()> {
+    write_ply_with_mode(path, points, colors, PlyMode::Ascii)
+}
+
+/// Write `points` to PLY file which can be opened by MeshLab, CloudCompare, etc.
+/// Coordinates are written as double and colors are written as uchar (red, green, blue).
+/// - `colors` : color of each point. Length must be the same as `points`.
+pub fn write_ply_with_mode(
+    path: &Path,
+    points: &[na::Vector3<f64>],
+    colors: Option<&[(u8, u8, u8)]>,
+    mode: PlyMode,
+) -> Result<()> {
+    if let Some(colors) = colors {
+        ensure!(
+            colors.len() == points.len(),
+            "Number of colors is different from points : {} vs {}",
+            colors.len(),
+            points.len()
+        );
+    }
+    if let Some(outdir) = path.parent() {
+        fs::create_dir_all(outdir)?;
+    }
+    let mut writer = BufWriter::new(File::create(path)?);
+
+    writeln!(writer, "ply")?;
+    writeln!(writer, "format {} 1.0", mode.as_str())?;
+    writeln!(writer, "element vertex {}", points.len())?;
+    for axis in ["x", "y", "z"] {
+        writeln!(writer, "property double {}", axis)?;
+    }
+    if colors.is_some() {
+        for channel in ["red", "green", "blue"] {
+            writeln!(writer, "property uchar {}", channel)?;
+        }
+    }
+    writeln!(writer, "end_header")?;
+
+    for (idx, pt) in points.iter().enumerate() {
+        let color = colors.map(|colors| colors[idx]);
+        match mode {
+            PlyMode::Ascii => {
+                write!(writer, "{} {} {}", pt[0], pt[1], pt[2])?;
+                if let Some((r, g, b)) = color {
+                    write!(writer, " {} {} {}", r, g, b)?;
+                }
+                writeln!(writer)?;
+            }
+            PlyMode::BinaryLittleEndian => {
+                for val in pt.iter() {
+                    writer.write_all(&val.to_le_bytes())?;
+                }
+                if let Some((r, g, b)) = color {
+                    writer.write_all(&[r, g, b])?;
+                }
+            }
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COLORS: [(u8, u8, u8); 2] = [(255, 0, 0), (0, 128, 255)];
+
+    fn test_points() -> Vec<na::Vector3<f64>> {
+        vec![
+            na::Vector3::new(0.0, 1.0, 2.0),
+            na::Vector3::new(-1.5, 0.25, 3.0),
+        ]
+    }
+
+    #[test]
+    fn test_write_ply_ascii() {
+        let (points, colors) = (test_points(), COLORS);
+        let path = std::env::temp_dir().join("improc_test_write_ply_ascii.ply");
+        write_ply(&path, &points, Some(&colors)).unwrap();
+        let content = String::from_utf8(fs::read(&path).unwrap()).unwrap();
+        let expected = "ply\n\
+                        format ascii 1.0\n\
+                        element vertex 2\n\
+                        property double x\n\
+                        property double y\n\
+                        property double z\n\
+                        property uchar red\n\
+                        property uchar green\n\
+                        property uchar blue\n\
+                        end_header\n\
+                        0 1 2 255 0 0\n\
+                        -1.5 0.25 3 0 128 255\n";
+        assert_eq!(content, expected);
+
+        // without colors
+        write_ply(&path, &points, None).unwrap();
+        let content = String::from_utf8(fs::read(&path).unwrap()).unwrap();
+        assert!(!content.contains("red"));
+        assert!(content.ends_with("end_header\n0 1 2\n-1.5 0.25 3\n"));
+        fs::remove_file(&path).unwrap();
+
+        assert!(write_ply(&path, &points, Some(&colors[..1])).is_err());
+    }
+
+    #[test]
+    fn test_write_ply_binary() {
+        let (points, colors) = (test_points(), COLORS);
+        let path = std::env::temp_dir().join("improc_test_write_ply_binary.ply");
+        write_ply_with_mode(&path, &points, Some(&colors), PlyMode::BinaryLittleEndian).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let header = "ply\n\
+                      format binary_little_endian 1.0\n\
+                      element vertex 2\n\
+                      property double x\n\
+                      property double y\n\
+                      property double z\n\
+                      property uchar red\n\
+                      property uchar green\n\
+                      property uchar blue\n\
+                      end_header\n";
+        assert_eq!(&bytes[..header.len()], header.as_bytes());
+        let body = &bytes[header.len()..];
+        assert_eq!(body.len(), points.len() * (8 * 3 + 3));
+        points
+            .iter()
+            .zip(colors.iter())
+            .enumerate()
+            .for_each(|(idx, (pt, color))| {
+                let offset = idx * 27;
+                (0..3).for_each(|i| {
+                    let mut buf = [0u8; 8];
+                    buf.copy_from_slice(&body[offset + i * 8..offset + (i + 1) * 8]);
+                    assert_eq!(f64::from_le_bytes(buf), pt[i]);
+                });
+                assert_eq!(
+                    &body[offset + 24..offset + 27],
+                    &[color.0, color.1, color.2]
+                );
+            });
+    }
+}
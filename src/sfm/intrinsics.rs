@@ -0,0 +1,122 @@
+//! Intrinsic parameters of the camera.
+use std::{
+    fs::{self, File},
+    io::{BufReader, BufWriter},
+    path::Path,
+};
+
+use nalgebra as na;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Context, Result};
+
+use super::distortion::DistortionModel;
+
+/// Intrinsic parameters of the pinhole camera with lens distortion.
+/// - `fx`, `fy` : focal length in pixels.
+/// - `cx`, `cy` : principal point.
+/// - `k1`, `k2` : radial distortion coefficients.
+/// - `p1`, `p2` : tangential distortion coefficients.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct CameraIntrinsics {
+    pub fx: f64,
+    pub fy: f64,
+    pub cx: f64,
+    pub cy: f64,
+    pub k1: f64,
+    pub k2: f64,
+    pub p1: f64,
+    pub p2: f64,
+}
+
+impl CameraIntrinsics {
+    /// Return intrinsic matrix K. Distortion coefficients are ignored.
+    pub fn to_matrix(&self) -> na::Matrix3<f64> {
+        #[rustfmt::skip]
+        let k = na::Matrix3::new(
+            self.fx, 0.0, self.cx,
+            0.0, self.fy, self.cy,
+            0.0, 0.0, 1.0,
+        );
+        k
+    }
+
+    /// Return distortion parameters of the camera.
+    pub fn distortion_model(&self) -> DistortionModel {
+        DistortionModel::new(self.k1, self.k2, self.p1, self.p2)
+    }
+
+    /// Create parameters from intrinsic matrix K. Distortion coefficients are set to 0.
+    pub fn from_matrix(k: &na::Matrix3<f64>) -> Self {
+        let k = k / k[(2, 2)];
+        CameraIntrinsics {
+            fx: k[(0, 0)],
+            fy: k[(1, 1)],
+            cx: k[(0, 2)],
+            cy: k[(1, 2)],
+            ..Default::default()
+        }
+    }
+}
+
+/// Save `cam` to `path` as JSON.
+pub fn save_intrinsics(path: &Path, cam: &CameraIntrinsics) -> Result<()> {
+    if let Some(outdir) = path.parent() {
+        fs::create_dir_all(outdir)?;
+    }
+    let file = File::create(path).with_context(|| format!("Failed to create {:?}", path))?;
+    serde_json::to_writer_pretty(BufWriter::new(file), cam)
+        .with_context(|| format!("Failed to write {:?}", path))?;
+    Ok(())
+}
+
+/// Load intrinsic parameters from JSON file saved by `save_intrinsics`.
+pub fn load_intrinsics(path: &Path) -> Result<CameraIntrinsics> {
+    let file = File::open(path).with_context(|| format!("Failed to open {:?}", path))?;
+    let cam = serde_json::from_reader(BufReader::new(file))
+        .with_context(|| format!("Failed to parse {:?}", path))?;
+    Ok(cam)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_save_and_load_intrinsics() {
+        let cam = CameraIntrinsics {
+            fx: 812.345678901234,
+            fy: 810.0000000001,
+            cx: 319.5,
+            cy: 239.25,
+            k1: -0.123456789,
+            k2: 0.0123456789,
+            p1: 1e-4,
+            p2: -3.3e-5,
+        };
+        let path = std::env::temp_dir().join("improc_test_intrinsics.json");
+        save_intrinsics(&path, &cam).unwrap();
+        let loaded = load_intrinsics(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, cam);
+
+        assert!(load_intrinsics(&std::env::temp_dir().join("improc_not_exist.json")).is_err());
+    }
+
+    #[test]
+    fn test_matrix_conversion() {
+        #[rustfmt::skip]
+        let k = na::Matrix3::new(
+            800.0, 0.0, 320.0,
+            0.0, 700.0, 240.0,
+            0.0, 0.0, 1.0,
+        );
+        let cam = CameraIntrinsics::from_matrix(&k);
+        assert_eq!(cam.fx, 800.0);
+        assert_eq!(cam.fy, 700.0);
+        assert_eq!(cam.cx, 320.0);
+        assert_eq!(cam.cy, 240.0);
+        assert_eq!(cam.k1, 0.0);
+        assert_eq!(cam.to_matrix(), k);
+    }
+}
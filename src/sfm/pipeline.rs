@@ -0,0 +1,302 @@
+//! Minimal two-view SfM pipeline (motion recovery -> triangulation -> refinement).
+use nalgebra as na;
+
+use crate::{
+    ensure,
+    epipolar::{
+        fundamental_matrix::FundamentalMatrixData, homography::normalized_dlt,
+        rank_correction::svd_rank_correction, triangulation::triangulate_dlt,
+    },
+    error::{Context, ImprocError, Result},
+    optimizer::{bundle_adjustment::bundle_adjustment, least_square::least_square_fitting},
+};
+
+use super::{plane_self_calibration::plane_self_calibration, self_calibration::self_calibration};
+
+/// Result of the reconstruction.
+/// - `camera_matrices` : 3 x 4 camera matrices. Image point `x` of the point `X` is calculated as
+///   `x = (P X)[0..2] / (P X)[2]`.
+/// - `points` : reconstructed 3D points. `points[i]` corresponds to the i-th point pair of `data`.
+#[derive(Clone, Debug)]
+pub struct SfmResult {
+    pub camera_matrices: Vec<na::DMatrix<f64>>,
+    pub points: Vec<na::Vector3<f64>>,
+}
+
+impl SfmResult {
+    /// Create result by triangulating all point pairs of `data` with the two camera matrices.
+    fn from_cameras(p0: na::DMatrix<f64>, p1: na::DMatrix<f64>, data: &[na::Point2<f64>]) -> Self {
+        let points = (0..data.len() / 2)
+            .map(|idx| {
+                let pt = triangulate_dlt(&p0, &p1, &data[idx * 2], &data[idx * 2 + 1]);
+                na::Vector3::new(pt[0], pt[1], pt[2])
+            })
+            .collect();
+        SfmResult {
+            camera_matrices: vec![p0, p1],
+            points,
+        }
+    }
+
+    /// Return RMS of the reprojection error of each observed point in `data`.
+    pub fn reprojection_error(&self, data: &[na::Point2<f64>]) -> f64 {
+        let n_pts = data.len() / 2;
+        let sum = (0..n_pts).fold(0.0, |acc, idx| {
+            acc + self
+                .camera_matrices
+                .iter()
+                .enumerate()
+                .fold(0.0, |acc, (i, p)| {
+                    let (u, v) = project(p, &self.points[idx]);
+                    let x = data[idx * 2 + i];
+                    acc + (u - x[0]).powi(2) + (v - x[1]).powi(2)
+                })
+        });
+        (sum / (n_pts * self.camera_matrices.len()) as f64).sqrt()
+    }
+
+    /// Return number of the points which are in front of all cameras.
+    fn count_points_in_front(&self) -> usize {
+        self.points
+            .iter()
+            .filter(|pt| {
+                let homo = pt.insert_row(3, 1.0);
+                self.camera_matrices
+                    .iter()
+                    .all(|p| (p.row(2) * homo)[0] > 0.0)
+            })
+            .count()
+    }
+}
+
+/// Recover camera matrices of the general (non-planar) scene.
+/// Fundamental matrix is estimated by the eight-point algorithm and camera matrices are
+/// calculated by `self_calibration`. The principal point is assumed to be the origin.
+/// - `data` : observed points in the two images. [image0_pt0, image1_pt0, image0_pt1, ....].
+/// - `f0` : scale constant.
+pub fn motion_recovery8(data: &[na::Point2<f64>], f0: f64) -> Result<SfmResult> {
+    ensure!(
+        data.len() >= 16,
+        ImprocError::InsufficientData {
+            expected: 16,
+            got: data.len()
+        }
+    );
+    let params = least_square_fitting::<FundamentalMatrixData>(data)?;
+    let fund_mat = svd_rank_correction(na::DMatrix::from_row_slice(3, 3, params.as_slice()))?;
+    let (p0, p1) = self_calibration(&fund_mat, data, f0)?;
+
+    // convert to the camera matrices which project points to the image coordinates.
+    let scale = na::DMatrix::from_diagonal(&na::DVector::from_vec(vec![1.0, 1.0, 1.0 / f0]));
+    Ok(SfmResult::from_cameras(&scale * p0, &scale * p1, data))
+}
+
+/// Recover camera matrices of the planar scene.
+/// Homography is estimated by `normalized_dlt` and decomposed by `plane_self_calibration`.
+/// The candidate with the most points in front of the both cameras is selected.
+/// - `data` : observed points in the two images. [image0_pt0, image1_pt0, image0_pt1, ....].
+/// - `focal_length0` : focal length of the first camera.
+/// - `focal_length1` : focal length of the second camera.
+/// - `f0` : scale constant.
+pub fn motion_recovery4(
+    data: &[na::Point2<f64>],
+    focal_length0: f64,
+    focal_length1: f64,
+    f0: f64,
+) -> Result<SfmResult> {
+    let mut homography = normalized_dlt(data)?;
+    if homography.determinant() < 0.0 {
+        homography *= -1.0;
+    }
+    // `plane_self_calibration` requires homography of the points scaled by `f0`.
+    let homography =
+        na::DMatrix::from_diagonal(&na::DVector::from_vec(vec![1.0 / f0, 1.0 / f0, 1.0]))
+            * homography
+            * na::DMatrix::from_diagonal(&na::DVector::from_vec(vec![f0, f0, 1.0]));
+    let candidates = plane_self_calibration(&homography, focal_length0, focal_length1, f0)?;
+
+    let k0 = na::DMatrix::from_diagonal(&na::DVector::from_vec(vec![
+        focal_length0,
+        focal_length0,
+        1.0,
+    ]));
+    let k1 = na::DMatrix::from_diagonal(&na::DVector::from_vec(vec![
+        focal_length1,
+        focal_length1,
+        1.0,
+    ]));
+    let p0 = &k0 * na::DMatrix::<f64>::identity(3, 4);
+    candidates
+        .iter()
+        .map(|(rot, trans)| {
+            let rt = -rot.transpose() * trans;
+            let mut motion = na::DMatrix::<f64>::zeros(3, 4);
+            motion.slice_mut((0, 0), (3, 3)).copy_from(&rot.transpose());
+            motion.set_column(3, &rt);
+            SfmResult::from_cameras(p0.clone(), &k1 * motion, data)
+        })
+        .max_by(|lhs, rhs| {
+            lhs.count_points_in_front()
+                .cmp(&rhs.count_points_in_front())
+                .then_with(|| {
+                    rhs.reprojection_error(data)
+                        .partial_cmp(&lhs.reprojection_error(data))
+                        .unwrap_or(std::cmp::Ordering::Equal)
+                })
+        })
+        .context("Failed to decompose homography.")
+}
+
+/// Refine camera matrices and 3D points of `result` by `bundle_adjustment`.
+/// The first camera matrix is fixed.
+/// Return RMS of the reprojection error (of each observed point) after the refinement.
+pub fn refine_reconstruction(
+    result: &mut SfmResult,
+    data: &[na::Point2<f64>],
+    iterations: usize,
+) -> f64 {
+    let n_cams = result.camera_matrices.len();
+    let observations: Vec<(usize, usize, na::Point2<f64>)> = (0..result.points.len())
+        .flat_map(|idx| (0..n_cams).map(move |i| (i, idx, data[idx * n_cams + i])))
+        .collect();
+    bundle_adjustment(
+        &mut result.camera_matrices,
+        &mut result.points,
+        &observations,
+        iterations,
+    )
+}
+
+/// Project `pt` by the camera matrix `p`.
+fn project(p: &na::DMatrix<f64>, pt: &na::Vector3<f64>) -> (f64, f64) {
+    let proj = p * pt.insert_row(3, 1.0);
+    (proj[0] / proj[2], proj[1] / proj[2])
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use crate::linalg::get_rotation_matrix_from_omega;
+
+    use super::*;
+
+    /// Create synthetic data in the same way as `self_calibration::tests::test_self_calibration`.
+    /// Rotation angle and depth of the points are limited so that the optical axes do not
+    /// intersect (focal lengths are not calculated in that case) and all points are in front of
+    /// the both cameras.
+    fn create_test_data() -> Vec<na::Point2<f64>> {
+        let mut rng = rand::thread_rng();
+        let (f, fh) = (2.0, 3.0);
+        let theta: f64 =
+            (0.2 + rng.gen::<f64>() * 0.3) * if rng.gen::<bool>() { 1.0 } else { -1.0 };
+        #[rustfmt::skip]
+        let r = na::DMatrix::from_row_slice(3, 3, &[
+            theta.cos(), 0.0, theta.sin(),
+            0.0, 1.0, 0.0,
+            -theta.sin(), 0.0, theta.cos(),
+        ]);
+        let t = na::DVector::from_vec(vec![1.0, 2.0, 3.0]).normalize();
+        let rt = r.transpose() * &t;
+        #[rustfmt::skip]
+        let p0 = na::DMatrix::from_row_slice(3, 4, &[
+            f, 0.0, 0.0, 0.0,
+            0.0, f, 0.0, 0.0,
+            0.0, 0.0, 1.0, 0.0,
+        ]);
+        #[rustfmt::skip]
+        let p1 = na::DMatrix::from_row_slice(3, 4, &[
+            fh * r[(0, 0)], fh * r[(1, 0)], fh * r[(2, 0)], fh * -rt[0],
+            fh * r[(0, 1)], fh * r[(1, 1)], fh * r[(2, 1)], fh * -rt[1],
+            r[(0, 2)], r[(1, 2)], r[(2, 2)], -rt[2],
+        ]);
+        (0..100)
+            .flat_map(|_| {
+                let gx = na::DVector::from_vec(vec![
+                    (rng.gen::<f64>() - 0.5) * 2.0,
+                    (rng.gen::<f64>() - 0.5) * 2.0,
+                    3.0 + rng.gen::<f64>() * 2.0,
+                    1.0,
+                ]);
+                let x0 = &p0 * &gx;
+                let x1 = &p1 * &gx;
+                vec![
+                    na::Point2::new(x0[0] / x0[2], x0[1] / x0[2]),
+                    na::Point2::new(x1[0] / x1[2], x1[1] / x1[2]),
+                ]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_motion_recovery8() {
+        let data = create_test_data();
+        let mut result = motion_recovery8(&data, 1.0).unwrap();
+        assert_eq!(result.camera_matrices.len(), 2);
+        assert_eq!(result.points.len(), data.len() / 2);
+        let initial_error = result.reprojection_error(&data);
+        assert!(initial_error < 1e-2, "error = {}", initial_error);
+
+        let error = refine_reconstruction(&mut result, &data, 20);
+        assert!(
+            error < 1e-6,
+            "error = {} (initial = {})",
+            error,
+            initial_error
+        );
+    }
+
+    #[test]
+    fn test_refine_reconstruction() {
+        let mut rng = rand::thread_rng();
+        let data = create_test_data();
+        let mut result = motion_recovery8(&data, 1.0).unwrap();
+        result.camera_matrices[1]
+            .iter_mut()
+            .for_each(|val| *val += (rng.gen::<f64>() - 0.5) * 1e-2);
+        result.points.iter_mut().for_each(|pt| {
+            pt.iter_mut()
+                .for_each(|val| *val += (rng.gen::<f64>() - 0.5) * 1e-2);
+        });
+        let initial_error = result.reprojection_error(&data);
+        let error = refine_reconstruction(&mut result, &data, 50);
+        assert!(
+            error < initial_error * 1e-2,
+            "error = {} (initial = {})",
+            error,
+            initial_error
+        );
+    }
+
+    #[test]
+    fn test_motion_recovery4() {
+        let mut rng = rand::thread_rng();
+        let (f, fh) = (1.5, 2.0);
+        let rot = get_rotation_matrix_from_omega(&[0.0, 0.2, 0.0]);
+        let trans = na::DVector::from_vec(vec![1.0, 0.2, 0.1]).normalize();
+        let rt = -rot.transpose() * &trans;
+        let p0 = na::DMatrix::from_diagonal(&na::DVector::from_vec(vec![f, f, 1.0]))
+            * na::DMatrix::<f64>::identity(3, 4);
+        let mut motion = na::DMatrix::<f64>::zeros(3, 4);
+        motion.slice_mut((0, 0), (3, 3)).copy_from(&rot.transpose());
+        motion.set_column(3, &rt);
+        let p1 = na::DMatrix::from_diagonal(&na::DVector::from_vec(vec![fh, fh, 1.0])) * motion;
+
+        // points on the plane z = 4 + 0.2 x
+        let data: Vec<na::Point2<f64>> = (0..50)
+            .flat_map(|_| {
+                let x = (rng.gen::<f64>() - 0.5) * 4.0;
+                let y = (rng.gen::<f64>() - 0.5) * 4.0;
+                let pt = na::Vector3::new(x, y, 4.0 + 0.2 * x);
+                let (u0, v0) = project(&p0, &pt);
+                let (u1, v1) = project(&p1, &pt);
+                vec![na::Point2::new(u0, v0), na::Point2::new(u1, v1)]
+            })
+            .collect();
+
+        let result = motion_recovery4(&data, f, fh, 1.0).unwrap();
+        assert_eq!(result.count_points_in_front(), data.len() / 2);
+        let error = result.reprojection_error(&data);
+        assert!(error < 1e-5, "error = {}", error);
+    }
+}
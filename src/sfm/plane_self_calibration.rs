@@ -1,9 +1,11 @@
 use std::cmp::PartialOrd;
 
-use anyhow::{Context, Result};
 use nalgebra as na;
 
-use crate::linalg::get_identity_mat;
+use crate::{
+    error::{Context, Result},
+    linalg::get_identity_mat,
+};
 
 /// self calibration (calculate camera pose) using homography.
 /// - `homography_mat`
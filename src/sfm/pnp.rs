@@ -0,0 +1,677 @@
+//! Camera pose estimation from 3D-2D point correspondences (Perspective-n-Point).
+use nalgebra as na;
+use rand::seq::index::sample;
+
+use crate::{
+    ensure,
+    error::{Context, ImprocError, Result},
+    linalg::matrix::lstsq,
+};
+
+const GAUSS_NEWTON_ITERATION: usize = 5;
+/// Minimum number of the correspondences for `estimate_pose_dlt`.
+const DLT_MIN_POINTS: usize = 6;
+
+/// Estimate camera pose by EPnP (V. Lepetit et al., "EPnP: An Accurate O(n) Solution to the PnP
+/// Problem", IJCV 2009).
+/// Each object point is expressed as a weighted sum of four control points and the coordinates of
+/// the control points in the camera coordinates are calculated from the null space of the
+/// projection equations. Object points must not be on a plane.
+/// - `object_pts` : points in the world coordinates.
+/// - `image_pts` : observed points in the image. `image_pts[i]` is the projection of `object_pts[i]`.
+/// - `intrinsics` : intrinsic matrix of the camera.
+///
+/// Return tuple of (rotation matrix `R`, translation vector `t`) where x_cam = R * x_world + t.
+pub fn epnp(
+    object_pts: &[na::Point3<f64>],
+    image_pts: &[na::Point2<f64>],
+    intrinsics: &na::Matrix3<f64>,
+) -> Result<(na::DMatrix<f64>, na::DVector<f64>)> {
+    ensure!(
+        object_pts.len() == image_pts.len(),
+        "Number of points is different : {} vs {}",
+        object_pts.len(),
+        image_pts.len()
+    );
+    ensure!(
+        object_pts.len() >= 4,
+        ImprocError::InsufficientData {
+            expected: 4,
+            got: object_pts.len()
+        }
+    );
+    let k_inv = intrinsics
+        .try_inverse()
+        .ok_or(ImprocError::SingularMatrix)?;
+    let normalized: Vec<na::Point2<f64>> = image_pts
+        .iter()
+        .map(|pt| na::Point2::from_homogeneous(k_inv * pt.to_homogeneous()).unwrap())
+        .collect();
+
+    let control_pts = choose_control_points(object_pts);
+    let alphas = compute_barycentric_coordinates(object_pts, &control_pts)?;
+
+    // null space of the projection equations : M * x = 0 (x is 12 dim vector of control points).
+    let mut m = na::DMatrix::<f64>::zeros(object_pts.len() * 2, 12);
+    normalized.iter().enumerate().for_each(|(i, pt)| {
+        (0..4).for_each(|j| {
+            let a = alphas[i][j];
+            m[(i * 2, j * 3)] = a;
+            m[(i * 2, j * 3 + 2)] = -a * pt[0];
+            m[(i * 2 + 1, j * 3 + 1)] = a;
+            m[(i * 2 + 1, j * 3 + 2)] = -a * pt[1];
+        });
+    });
+    let eigen = (m.transpose() * &m).symmetric_eigen();
+    let mut order: Vec<usize> = (0..12).collect();
+    order.sort_by(|l, r| {
+        eigen.eigenvalues[*l]
+            .partial_cmp(&eigen.eigenvalues[*r])
+            .unwrap()
+    });
+    let null_vecs: Vec<na::DVector<f64>> = order[..4]
+        .iter()
+        .map(|idx| eigen.eigenvectors.column(*idx).clone_owned())
+        .collect();
+
+    let l = compute_l_6x10(&null_vecs);
+    let rho = compute_rho(&control_pts);
+
+    let candidates = [
+        approximate_betas_n4(&l, &rho),
+        approximate_betas_n2(&l, &rho),
+        approximate_betas_n3(&l, &rho),
+    ];
+    let (_, rot, trans) = candidates
+        .iter()
+        .flatten()
+        .filter_map(|betas| {
+            let betas = gauss_newton(&l, &rho, betas);
+            let (rot, trans) = compute_pose(&null_vecs, &betas, &alphas, object_pts).ok()?;
+            let error = reprojection_error(&rot, &trans, object_pts, &normalized);
+            Some((error, rot, trans))
+        })
+        .min_by(|l, r| l.0.partial_cmp(&r.0).unwrap())
+        .context("Failed to estimate camera pose.")?;
+
+    Ok((
+        na::DMatrix::from_column_slice(3, 3, rot.as_slice()),
+        na::DVector::from_column_slice(trans.as_slice()),
+    ))
+}
+
+/// Estimate camera pose robustly by RANSAC.
+/// In each iteration, the pose is calculated from randomly sampled 4 correspondences by `epnp`
+/// and a correspondence is regarded as inlier if its reprojection error is smaller than
+/// `threshold_px` (pixels). The pose with the most inliers is refined by `epnp` with all inliers.
+/// See `epnp` for `object_pts`, `image_pts` and `intrinsics`.
+///
+/// Return tuple of (rotation matrix `R`, translation vector `t`, indices of inliers).
+pub fn pnp_ransac(
+    object_pts: &[na::Point3<f64>],
+    image_pts: &[na::Point2<f64>],
+    intrinsics: &na::Matrix3<f64>,
+    iterations: usize,
+    threshold_px: f64,
+) -> Result<(na::DMatrix<f64>, na::DVector<f64>, Vec<usize>)> {
+    ensure!(
+        object_pts.len() == image_pts.len(),
+        "Number of points is different : {} vs {}",
+        object_pts.len(),
+        image_pts.len()
+    );
+    ensure!(
+        object_pts.len() >= 4,
+        "Not enough points : {} (required 4)",
+        object_pts.len()
+    );
+
+    let mut rng = rand::thread_rng();
+    let mut best_inliers: Vec<usize> = vec![];
+    for _ in 0..iterations {
+        let samples = sample(&mut rng, object_pts.len(), 4).into_vec();
+        let sample_object: Vec<na::Point3<f64>> = samples.iter().map(|i| object_pts[*i]).collect();
+        let sample_image: Vec<na::Point2<f64>> = samples.iter().map(|i| image_pts[*i]).collect();
+        let (rot, trans) = match epnp(&sample_object, &sample_image, intrinsics) {
+            Ok(pose) => pose,
+            Err(_) => continue,
+        };
+        let inliers = pixel_inliers(
+            &rot,
+            &trans,
+            object_pts,
+            image_pts,
+            intrinsics,
+            threshold_px,
+        );
+        if inliers.len() > best_inliers.len() {
+            best_inliers = inliers;
+        }
+    }
+    ensure!(
+        best_inliers.len() >= 4,
+        "Failed to find enough inliers : {}",
+        best_inliers.len()
+    );
+
+    // refine pose with all inliers
+    let inlier_object: Vec<na::Point3<f64>> = best_inliers.iter().map(|i| object_pts[*i]).collect();
+    let inlier_image: Vec<na::Point2<f64>> = best_inliers.iter().map(|i| image_pts[*i]).collect();
+    let (rot, trans) = epnp(&inlier_object, &inlier_image, intrinsics)?;
+    let inliers = pixel_inliers(
+        &rot,
+        &trans,
+        object_pts,
+        image_pts,
+        intrinsics,
+        threshold_px,
+    );
+    Ok((rot, trans, inliers))
+}
+
+/// Estimate camera pose by the linear DLT method.
+/// Image points are normalized by `intrinsics` and the 3 x 4 matrix `P` (x ~ P X) is calculated
+/// as the least square solution of the 2n x 12 linear system. The scale and the sign of `P` are
+/// fixed so that det of the left 3 x 3 block is 1, and the block is corrected to the nearest
+/// rotation matrix. At least 6 points not on a plane are required.
+/// See `epnp` for `object_pts`, `image_pts` and `intrinsics`.
+///
+/// Return 3 x 4 matrix [R | t] where x_cam = R * x_world + t.
+pub fn estimate_pose_dlt(
+    object_pts: &[na::Point3<f64>],
+    image_pts: &[na::Point2<f64>],
+    intrinsics: &na::Matrix3<f64>,
+) -> Result<na::DMatrix<f64>> {
+    ensure!(
+        object_pts.len() == image_pts.len(),
+        "Number of points is different : {} vs {}",
+        object_pts.len(),
+        image_pts.len()
+    );
+    ensure!(
+        object_pts.len() >= DLT_MIN_POINTS,
+        ImprocError::InsufficientData {
+            expected: DLT_MIN_POINTS,
+            got: object_pts.len()
+        }
+    );
+    let k_inv = intrinsics
+        .try_inverse()
+        .ok_or(ImprocError::SingularMatrix)?;
+
+    let mut a = na::DMatrix::<f64>::zeros(object_pts.len() * 2, 12);
+    object_pts
+        .iter()
+        .zip(image_pts.iter())
+        .enumerate()
+        .for_each(|(i, (pw, pt))| {
+            let x = na::Point2::from_homogeneous(k_inv * pt.to_homogeneous()).unwrap();
+            let pw = pw.to_homogeneous();
+            (0..4).for_each(|j| {
+                a[(i * 2, j)] = pw[j];
+                a[(i * 2, 8 + j)] = -x[0] * pw[j];
+                a[(i * 2 + 1, 4 + j)] = pw[j];
+                a[(i * 2 + 1, 8 + j)] = -x[1] * pw[j];
+            });
+        });
+    let p = lstsq(&a)?;
+    let p = na::Matrix3x4::from_row_slice(p.as_slice());
+
+    let m = p.fixed_slice::<3, 3>(0, 0).clone_owned();
+    let det = m.determinant();
+    ensure!(det.abs() > f64::EPSILON, ImprocError::SingularMatrix);
+    let p = p / (det.signum() * det.abs().cbrt());
+    let svd = p.fixed_slice::<3, 3>(0, 0).clone_owned().svd(true, true);
+    let rot = svd.u.context("Failed to calc svd.")? * svd.v_t.context("Failed to calc svd.")?;
+
+    let mut pose = na::DMatrix::<f64>::zeros(3, 4);
+    pose.slice_mut((0, 0), (3, 3)).copy_from(&rot);
+    pose.set_column(3, &p.column(3));
+    Ok(pose)
+}
+
+/// Return indices of the correspondences whose reprojection error (pixels) is smaller than
+/// `threshold_px`.
+fn pixel_inliers(
+    rot: &na::DMatrix<f64>,
+    trans: &na::DVector<f64>,
+    object_pts: &[na::Point3<f64>],
+    image_pts: &[na::Point2<f64>],
+    intrinsics: &na::Matrix3<f64>,
+    threshold_px: f64,
+) -> Vec<usize> {
+    let rot = na::Matrix3::from_column_slice(rot.as_slice());
+    let trans = na::Vector3::from_column_slice(trans.as_slice());
+    object_pts
+        .iter()
+        .zip(image_pts.iter())
+        .enumerate()
+        .filter(|(_, (pw, pt))| {
+            let pc = intrinsics * (rot * pw.coords + trans);
+            if pc[2] <= 0.0 {
+                return false;
+            }
+            let error = ((pc[0] / pc[2] - pt[0]).powi(2) + (pc[1] / pc[2] - pt[1]).powi(2)).sqrt();
+            error < threshold_px
+        })
+        .map(|(idx, _)| idx)
+        .collect()
+}
+
+/// Choose control points : centroid of the points and the principal axes.
+fn choose_control_points(pts: &[na::Point3<f64>]) -> [na::Vector3<f64>; 4] {
+    let n = pts.len() as f64;
+    let centroid = pts
+        .iter()
+        .fold(na::Vector3::zeros(), |acc, pt| acc + pt.coords)
+        / n;
+    let cov = pts.iter().fold(na::Matrix3::zeros(), |acc, pt| {
+        let d = pt.coords - centroid;
+        acc + d * d.transpose()
+    });
+    let eigen = cov.symmetric_eigen();
+    let mut control_pts = [centroid; 4];
+    (0..3).for_each(|i| {
+        let scale = (eigen.eigenvalues[i].max(0.0) / n).sqrt();
+        control_pts[i + 1] = centroid + eigen.eigenvectors.column(i) * scale;
+    });
+    control_pts
+}
+
+/// Calculate weights of the control points (sum of weights is 1) for each point.
+fn compute_barycentric_coordinates(
+    pts: &[na::Point3<f64>],
+    control_pts: &[na::Vector3<f64>; 4],
+) -> Result<Vec<[f64; 4]>> {
+    let c = na::Matrix3::from_columns(&[
+        control_pts[1] - control_pts[0],
+        control_pts[2] - control_pts[0],
+        control_pts[3] - control_pts[0],
+    ]);
+    let c_inv = c
+        .try_inverse()
+        .context("Object points are degenerate (on a plane or a line).")?;
+    Ok(pts
+        .iter()
+        .map(|pt| {
+            let a = c_inv * (pt.coords - control_pts[0]);
+            [1.0 - a[0] - a[1] - a[2], a[0], a[1], a[2]]
+        })
+        .collect())
+}
+
+/// Index pairs of the control points used in the distance constraints.
+const PAIRS: [(usize, usize); 6] = [(0, 1), (0, 2), (0, 3), (1, 2), (1, 3), (2, 3)];
+
+/// Calculate coefficients of the distance constraints : L * b = rho, where
+/// b = [b00, b01, b11, b02, b12, b22, b03, b13, b23, b33] (bij = beta_i * beta_j).
+fn compute_l_6x10(null_vecs: &[na::DVector<f64>]) -> na::DMatrix<f64> {
+    let mut l = na::DMatrix::<f64>::zeros(6, 10);
+    PAIRS.iter().enumerate().for_each(|(row, (a, b))| {
+        let dv: Vec<na::Vector3<f64>> = null_vecs
+            .iter()
+            .map(|v| {
+                na::Vector3::new(
+                    v[a * 3] - v[b * 3],
+                    v[a * 3 + 1] - v[b * 3 + 1],
+                    v[a * 3 + 2] - v[b * 3 + 2],
+                )
+            })
+            .collect();
+        let mut col = 0;
+        (0..4).for_each(|j| {
+            (0..=j).for_each(|i| {
+                let scale = if i == j { 1.0 } else { 2.0 };
+                l[(row, col)] = scale * dv[i].dot(&dv[j]);
+                col += 1;
+            });
+        });
+    });
+    l
+}
+
+/// Calculate squared distances between the control points.
+fn compute_rho(control_pts: &[na::Vector3<f64>; 4]) -> na::DVector<f64> {
+    na::DVector::from_iterator(
+        6,
+        PAIRS
+            .iter()
+            .map(|(a, b)| (control_pts[*a] - control_pts[*b]).norm_squared()),
+    )
+}
+
+/// Solve least square problem of the distance constraints using the columns `cols` of `l`.
+fn solve_sub_system(
+    l: &na::DMatrix<f64>,
+    rho: &na::DVector<f64>,
+    cols: &[usize],
+) -> Option<na::DVector<f64>> {
+    let sub = na::DMatrix::from_fn(6, cols.len(), |r, c| l[(r, cols[c])]);
+    sub.svd(true, true).solve(rho, 1e-12).ok()
+}
+
+/// Approximate betas assuming the dimension of the null space is 4.
+fn approximate_betas_n4(l: &na::DMatrix<f64>, rho: &na::DVector<f64>) -> Option<[f64; 4]> {
+    let b = solve_sub_system(l, rho, &[0, 1, 3, 6])?;
+    let sign = if b[0] < 0.0 { -1.0 } else { 1.0 };
+    let beta0 = (sign * b[0]).sqrt();
+    if beta0 < f64::EPSILON {
+        return None;
+    }
+    Some([
+        beta0,
+        sign * b[1] / beta0,
+        sign * b[2] / beta0,
+        sign * b[3] / beta0,
+    ])
+}
+
+/// Approximate betas assuming the dimension of the null space is 2.
+fn approximate_betas_n2(l: &na::DMatrix<f64>, rho: &na::DVector<f64>) -> Option<[f64; 4]> {
+    let b = solve_sub_system(l, rho, &[0, 1, 2])?;
+    let (beta0, beta1) = betas_from_squares(b[0], b[1], b[2]);
+    Some([beta0, beta1, 0.0, 0.0])
+}
+
+/// Approximate betas assuming the dimension of the null space is 3.
+fn approximate_betas_n3(l: &na::DMatrix<f64>, rho: &na::DVector<f64>) -> Option<[f64; 4]> {
+    let b = solve_sub_system(l, rho, &[0, 1, 2, 3, 4])?;
+    let (beta0, beta1) = betas_from_squares(b[0], b[1], b[2]);
+    if beta0.abs() < f64::EPSILON {
+        return None;
+    }
+    Some([beta0, beta1, b[3] / beta0, 0.0])
+}
+
+/// Calculate (beta0, beta1) from (b00, b01, b11).
+fn betas_from_squares(b00: f64, b01: f64, b11: f64) -> (f64, f64) {
+    let (beta0, beta1) = if b00 < 0.0 { (-b00, -b11) } else { (b00, b11) };
+    let beta0 = beta0.sqrt();
+    let beta1 = beta1.max(0.0).sqrt();
+    if b01 < 0.0 {
+        (-beta0, beta1)
+    } else {
+        (beta0, beta1)
+    }
+}
+
+/// Refine betas by Gauss-Newton method minimizing |L * b - rho|.
+fn gauss_newton(l: &na::DMatrix<f64>, rho: &na::DVector<f64>, betas: &[f64; 4]) -> [f64; 4] {
+    let mut betas = *betas;
+    for _ in 0..GAUSS_NEWTON_ITERATION {
+        let mut a = na::Matrix6x4::<f64>::zeros();
+        let mut b = na::Vector6::<f64>::zeros();
+        (0..6).for_each(|i| {
+            let r = l.row(i);
+            let [b0, b1, b2, b3] = betas;
+            a[(i, 0)] = 2.0 * r[0] * b0 + r[1] * b1 + r[3] * b2 + r[6] * b3;
+            a[(i, 1)] = r[1] * b0 + 2.0 * r[2] * b1 + r[4] * b2 + r[7] * b3;
+            a[(i, 2)] = r[3] * b0 + r[4] * b1 + 2.0 * r[5] * b2 + r[8] * b3;
+            a[(i, 3)] = r[6] * b0 + r[7] * b1 + r[8] * b2 + 2.0 * r[9] * b3;
+            let bb = [
+                b0 * b0,
+                b0 * b1,
+                b1 * b1,
+                b0 * b2,
+                b1 * b2,
+                b2 * b2,
+                b0 * b3,
+                b1 * b3,
+                b2 * b3,
+                b3 * b3,
+            ];
+            b[i] = rho[i] - (0..10).map(|j| r[j] * bb[j]).sum::<f64>();
+        });
+        let delta = match a.svd(true, true).solve(&b, 1e-12) {
+            Ok(delta) => delta,
+            Err(_) => break,
+        };
+        (0..4).for_each(|i| betas[i] += delta[i]);
+    }
+    betas
+}
+
+/// Calculate camera pose from the control points in the camera coordinates
+/// (sum of `betas[i]` * `null_vecs[i]`).
+fn compute_pose(
+    null_vecs: &[na::DVector<f64>],
+    betas: &[f64; 4],
+    alphas: &[[f64; 4]],
+    object_pts: &[na::Point3<f64>],
+) -> Result<(na::Matrix3<f64>, na::Vector3<f64>)> {
+    let x = null_vecs
+        .iter()
+        .zip(betas.iter())
+        .fold(na::DVector::<f64>::zeros(12), |acc, (v, beta)| {
+            acc + v * *beta
+        });
+    let control_pts: Vec<na::Vector3<f64>> = (0..4)
+        .map(|j| na::Vector3::new(x[j * 3], x[j * 3 + 1], x[j * 3 + 2]))
+        .collect();
+    let mut camera_pts: Vec<na::Vector3<f64>> = alphas
+        .iter()
+        .map(|a| (0..4).fold(na::Vector3::zeros(), |acc, j| acc + control_pts[j] * a[j]))
+        .collect();
+    // points must be in front of the camera.
+    if camera_pts.iter().map(|pt| pt[2]).sum::<f64>() < 0.0 {
+        camera_pts.iter_mut().for_each(|pt| *pt *= -1.0);
+    }
+
+    // absolute orientation between the world and the camera coordinates.
+    let n = object_pts.len() as f64;
+    let pc = camera_pts
+        .iter()
+        .fold(na::Vector3::zeros(), |acc, pt| acc + pt)
+        / n;
+    let pw = object_pts
+        .iter()
+        .fold(na::Vector3::zeros(), |acc, pt| acc + pt.coords)
+        / n;
+    let h = camera_pts
+        .iter()
+        .zip(object_pts.iter())
+        .fold(na::Matrix3::zeros(), |acc, (c, w)| {
+            acc + (c - pc) * (w.coords - pw).transpose()
+        });
+    let svd = h.svd(true, true);
+    let u = svd.u.context("Failed to calc svd.")?;
+    let v_t = svd.v_t.context("Failed to calc svd.")?;
+    let det = (u * v_t).determinant();
+    let rot = u * na::Matrix3::from_diagonal(&na::Vector3::new(1.0, 1.0, det)) * v_t;
+    let trans = pc - rot * pw;
+    Ok((rot, trans))
+}
+
+/// Calculate mean reprojection error on the normalized image coordinates.
+fn reprojection_error(
+    rot: &na::Matrix3<f64>,
+    trans: &na::Vector3<f64>,
+    object_pts: &[na::Point3<f64>],
+    normalized: &[na::Point2<f64>],
+) -> f64 {
+    object_pts
+        .iter()
+        .zip(normalized.iter())
+        .map(|(pw, pt)| {
+            let pc = rot * pw.coords + trans;
+            ((pc[0] / pc[2] - pt[0]).powi(2) + (pc[1] / pc[2] - pt[1]).powi(2)).sqrt()
+        })
+        .sum::<f64>()
+        / object_pts.len() as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use super::*;
+
+    #[test]
+    fn test_epnp() {
+        let mut rng = rand::thread_rng();
+        #[rustfmt::skip]
+        let intrinsics = na::Matrix3::new(
+            800.0, 0.0, 320.0,
+            0.0, 800.0, 240.0,
+            0.0, 0.0, 1.0,
+        );
+        let axis = na::Vector3::new(rng.gen::<f64>(), rng.gen::<f64>(), rng.gen::<f64>());
+        let rot = na::Rotation3::from_axis_angle(&na::Unit::new_normalize(axis), 0.5).into_inner();
+        let trans = na::Vector3::new(0.3, -0.2, 6.0);
+
+        let object_pts: Vec<na::Point3<f64>> = (0..10)
+            .map(|_| {
+                na::Point3::new(
+                    (rng.gen::<f64>() - 0.5) * 4.0,
+                    (rng.gen::<f64>() - 0.5) * 4.0,
+                    (rng.gen::<f64>() - 0.5) * 4.0,
+                )
+            })
+            .collect();
+        let image_pts: Vec<na::Point2<f64>> = object_pts
+            .iter()
+            .map(|pt| {
+                let pc = intrinsics * (rot * pt.coords + trans);
+                na::Point2::new(pc[0] / pc[2], pc[1] / pc[2])
+            })
+            .collect();
+
+        let (pred_rot, pred_trans) = epnp(&object_pts, &image_pts, &intrinsics).unwrap();
+        let rot = na::DMatrix::from_column_slice(3, 3, rot.as_slice());
+        let trans = na::DVector::from_column_slice(trans.as_slice());
+        assert!((&pred_rot - &rot).norm() < 1e-4, "rot = {}", pred_rot);
+        assert!(
+            (&pred_trans - &trans).norm() < 1e-4,
+            "trans = {}",
+            pred_trans
+        );
+
+        assert!(epnp(&object_pts[..3], &image_pts[..3], &intrinsics).is_err());
+        assert!(epnp(&object_pts, &image_pts[..5], &intrinsics).is_err());
+    }
+
+    #[test]
+    fn test_estimate_pose_dlt() {
+        let mut rng = rand::thread_rng();
+        #[rustfmt::skip]
+        let intrinsics = na::Matrix3::new(
+            800.0, 0.0, 320.0,
+            0.0, 800.0, 240.0,
+            0.0, 0.0, 1.0,
+        );
+        let axis = na::Vector3::new(rng.gen::<f64>(), rng.gen::<f64>(), rng.gen::<f64>());
+        let rot = na::Rotation3::from_axis_angle(&na::Unit::new_normalize(axis), 0.5).into_inner();
+        let trans = na::Vector3::new(0.3, -0.2, 6.0);
+        let mut expect = na::DMatrix::<f64>::zeros(3, 4);
+        expect
+            .slice_mut((0, 0), (3, 3))
+            .copy_from(&na::DMatrix::from_column_slice(3, 3, rot.as_slice()));
+        expect.set_column(3, &na::DVector::from_column_slice(trans.as_slice()));
+
+        let object_pts: Vec<na::Point3<f64>> = (0..20)
+            .map(|_| {
+                na::Point3::new(
+                    (rng.gen::<f64>() - 0.5) * 4.0,
+                    (rng.gen::<f64>() - 0.5) * 4.0,
+                    (rng.gen::<f64>() - 0.5) * 4.0,
+                )
+            })
+            .collect();
+        let project = |pt: &na::Point3<f64>| {
+            let pc = intrinsics * (rot * pt.coords + trans);
+            na::Point2::new(pc[0] / pc[2], pc[1] / pc[2])
+        };
+        let image_pts: Vec<na::Point2<f64>> = object_pts.iter().map(project).collect();
+
+        // exact correspondences
+        let pose = estimate_pose_dlt(&object_pts[..6], &image_pts[..6], &intrinsics).unwrap();
+        assert!((&pose - &expect).amax() < 1e-5, "pose = {}", pose);
+
+        // correspondences with noise of +-0.5 pixel
+        let noisy: Vec<na::Point2<f64>> = image_pts
+            .iter()
+            .map(|pt| {
+                na::Point2::new(
+                    pt[0] + rng.gen::<f64>() - 0.5,
+                    pt[1] + rng.gen::<f64>() - 0.5,
+                )
+            })
+            .collect();
+        let pose = estimate_pose_dlt(&object_pts, &noisy, &intrinsics).unwrap();
+        let pred_rot = na::Matrix3::from_fn(|r, c| pose[(r, c)]);
+        let pred_trans = na::Vector3::new(pose[(0, 3)], pose[(1, 3)], pose[(2, 3)]);
+        object_pts
+            .iter()
+            .zip(image_pts.iter())
+            .for_each(|(pw, pt)| {
+                let pc = intrinsics * (pred_rot * pw.coords + pred_trans);
+                let error =
+                    ((pc[0] / pc[2] - pt[0]).powi(2) + (pc[1] / pc[2] - pt[1]).powi(2)).sqrt();
+                assert!(error < 2.0, "reprojection error = {}", error);
+            });
+
+        assert!(estimate_pose_dlt(&object_pts[..5], &image_pts[..5], &intrinsics).is_err());
+        assert!(estimate_pose_dlt(&object_pts, &image_pts[..10], &intrinsics).is_err());
+    }
+
+    #[test]
+    fn test_pnp_ransac() {
+        let mut rng = rand::thread_rng();
+        #[rustfmt::skip]
+        let intrinsics = na::Matrix3::new(
+            800.0, 0.0, 320.0,
+            0.0, 800.0, 240.0,
+            0.0, 0.0, 1.0,
+        );
+        let axis = na::Vector3::new(rng.gen::<f64>(), rng.gen::<f64>(), rng.gen::<f64>());
+        let rot = na::Rotation3::from_axis_angle(&na::Unit::new_normalize(axis), 0.5).into_inner();
+        let trans = na::Vector3::new(0.3, -0.2, 6.0);
+
+        let n_pts = 100;
+        let object_pts: Vec<na::Point3<f64>> = (0..n_pts)
+            .map(|_| {
+                na::Point3::new(
+                    (rng.gen::<f64>() - 0.5) * 4.0,
+                    (rng.gen::<f64>() - 0.5) * 4.0,
+                    (rng.gen::<f64>() - 0.5) * 4.0,
+                )
+            })
+            .collect();
+        // 40 % of the correspondences are outliers. Inliers have noise of +-0.5 pixel.
+        let image_pts: Vec<na::Point2<f64>> = object_pts
+            .iter()
+            .enumerate()
+            .map(|(idx, pt)| {
+                if idx % 10 < 4 {
+                    return na::Point2::new(rng.gen::<f64>() * 640.0, rng.gen::<f64>() * 480.0);
+                }
+                let pc = intrinsics * (rot * pt.coords + trans);
+                na::Point2::new(
+                    pc[0] / pc[2] + rng.gen::<f64>() - 0.5,
+                    pc[1] / pc[2] + rng.gen::<f64>() - 0.5,
+                )
+            })
+            .collect();
+
+        let (pred_rot, pred_trans, inliers) =
+            pnp_ransac(&object_pts, &image_pts, &intrinsics, 1000, 2.0).unwrap();
+        let pred_rot = na::Matrix3::from_column_slice(pred_rot.as_slice());
+        let pred_trans = na::Vector3::from_column_slice(pred_trans.as_slice());
+        let angle = na::Rotation3::from_matrix_unchecked(pred_rot.transpose() * rot).angle();
+        assert!(
+            angle.to_degrees() < 0.5,
+            "rotation error = {} deg",
+            angle.to_degrees()
+        );
+        let trans_error = (pred_trans - trans).norm() / trans.norm();
+        assert!(trans_error < 0.01, "translation error = {}", trans_error);
+        assert!(
+            inliers.len() >= n_pts * 6 / 10 - 2,
+            "inliers = {}",
+            inliers.len()
+        );
+        assert!(inliers.iter().filter(|idx| *idx % 10 < 4).count() <= 2);
+
+        assert!(pnp_ransac(&object_pts[..3], &image_pts[..3], &intrinsics, 10, 2.0).is_err());
+    }
+}
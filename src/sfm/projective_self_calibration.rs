@@ -1,7 +1,10 @@
-use anyhow::{Context, Result};
 use nalgebra as na;
 
-/// - observed_pts : Observed points. (2d vector : [index of camera][index of point])
+use crate::error::{Context, ImprocError, Result};
+
+const MAX_ITERATION: usize = 100;
+
+// - observed_pts : Observed points. (2d vector : [index of camera][index of point])
 // pub fn projective_self_calibration(
 //     observed_points: &[Vec<na::Point2<f64>>],
 // ) -> Result<(na::DMatrix<f64>, na::DMatrix<f64>)> {
@@ -11,10 +14,10 @@ use nalgebra as na;
 
 // fn projective_reconstruction() {}
 
-///
+/// Projective reconstruction by the primary method of the factorization.
 /// - observed_pts : Observed points. (2d vector : [index of camera][index of point])
 /// - return tuple of (cameras' motion matrix, shape matrix)
-fn primary_method(
+pub fn primary_method(
     observed_points: &[Vec<na::Point2<f64>>],
 ) -> Result<(na::DMatrix<f64>, na::DMatrix<f64>)> {
     let epsilon = 10.0; // unit : pixel
@@ -22,14 +25,15 @@ fn primary_method(
     let n_points = observed_points[0].len();
     let mut zs = na::DMatrix::from_element(n_cameras, n_points, 1.0);
 
-    let inner_product = |pt: &na::Point2<f64>, mat: &na::DMatrix<f64>, row: usize, col: usize| {
-        pt.x * mat[(row, col)] + pt.y * mat[(row, col + 1)] + mat[(row, col + 2)]
+    // inner product of the point and the `cam`-th block of the `col`-th column of `mat`.
+    let inner_product = |pt: &na::Point2<f64>, mat: &na::DMatrix<f64>, col: usize, cam: usize| {
+        pt.x * mat[(3 * cam, col)] + pt.y * mat[(3 * cam + 1, col)] + mat[(3 * cam + 2, col)]
     };
     let point_norm = |pt: &na::Point2<f64>| (pt.x * pt.x + pt.y * pt.y + 1.0).sqrt();
 
-    loop {
+    for _ in 0..MAX_ITERATION {
         let observed_mat = get_observed_matrix(observed_points, &zs);
-        let svd = observed_mat.svd(true, true);
+        let mut svd = observed_mat.svd(true, true);
         svd.sort_by_singular_values();
         let (motion_mat, shape_mat) = get_motion_and_shape_from_svd(&svd)?;
 
@@ -37,14 +41,14 @@ fn primary_method(
             return Ok((motion_mat, shape_mat));
         }
 
-        (0..n_points).map(|ip| {
+        (0..n_points).for_each(|ip| {
             let a: na::DMatrix<f64> = na::DMatrix::from_fn(n_cameras, n_cameras, |r, c| {
                 let rpt: na::Point2<f64> = observed_points[r][ip];
                 let cpt: na::Point2<f64> = observed_points[c][ip];
                 let nume = (0..4).fold(0.0, |accum, idx| {
                     accum
-                        + inner_product(&rpt, &motion_mat, idx, 3 * r)
-                            * inner_product(&cpt, &motion_mat, idx, 3 * c)
+                        + inner_product(&rpt, &motion_mat, idx, r)
+                            * inner_product(&cpt, &motion_mat, idx, c)
                 });
                 let deno = point_norm(&rpt) * point_norm(&cpt);
                 nume / deno
@@ -56,12 +60,12 @@ fn primary_method(
                 .for_each(|ic| zs[(ic, ip)] = xi[ic] / point_norm(&observed_points[ic][ip]));
         });
     }
+    Err(ImprocError::Other(format!(
+        "Primary method did not converge in {} iterations",
+        MAX_ITERATION
+    )))
 }
 
-fn dual_method() {}
-
-fn euclide_reconstruction() {}
-
 fn get_observed_matrix(
     observed_points: &[Vec<na::Point2<f64>>],
     zs: &na::DMatrix<f64>,
@@ -72,18 +76,141 @@ fn get_observed_matrix(
     na::DMatrix::from_fn(n_cameras * 3, n_points, |r, c| {
         let cam_idx = r / 3;
         let coord_idx = r % 3;
-        observed_points[cam_idx][c][coord_idx] * zs[(cam_idx, c)]
+        let val = if coord_idx < 2 {
+            observed_points[cam_idx][c][coord_idx]
+        } else {
+            1.0
+        };
+        val * zs[(cam_idx, c)]
     })
 }
 
-// fn get_motion_and_shape_from_svd(
-//     svd: &na::SVD<f64, na::Dynamic, na::Dynamic>,
-// ) -> Result<(na::DMatrix<f64>, na::DMatrix<f64>)> {
-// }
+/// Decompose the observed matrix to the motion matrix (3M x 4) and the shape matrix (4 x N)
+/// by the rank 4 approximation. Singular values of `svd` must be sorted in descending order.
+/// Columns of the motion matrix are orthonormal.
+fn get_motion_and_shape_from_svd(
+    svd: &na::SVD<f64, na::Dynamic, na::Dynamic>,
+) -> Result<(na::DMatrix<f64>, na::DMatrix<f64>)> {
+    let u = svd
+        .u
+        .as_ref()
+        .context("Left singular vectors are not computed")?;
+    let v_t = svd
+        .v_t
+        .as_ref()
+        .context("Right singular vectors are not computed")?;
+    crate::ensure!(
+        svd.singular_values.len() >= 4,
+        ImprocError::InsufficientData {
+            expected: 4,
+            got: svd.singular_values.len(),
+        }
+    );
+    let motion_mat = u.columns(0, 4).into_owned();
+    let shape_mat = na::DMatrix::from_diagonal(&svd.singular_values.rows(0, 4)) * v_t.rows(0, 4);
+    Ok((motion_mat, shape_mat))
+}
 
-// fn calculate_reprojection_error(
-//     observed_points: &[Vec<na::Point2<f64>>],
-//     motion_mat: &na::DMatrix<f64>,
-//     shape_mat: &na::DMatrix<f64>,
-// ) -> f64 {
-// }
+/// Return RMS of the reprojection errors of the points reconstructed by the motion matrix
+/// and the shape matrix.
+fn calculate_reprojection_error(
+    observed_points: &[Vec<na::Point2<f64>>],
+    motion_mat: &na::DMatrix<f64>,
+    shape_mat: &na::DMatrix<f64>,
+) -> f64 {
+    let projected = motion_mat * shape_mat;
+    let n_points = observed_points[0].len();
+    let sum: f64 = observed_points
+        .iter()
+        .enumerate()
+        .map(|(ic, pts)| {
+            pts.iter()
+                .enumerate()
+                .map(|(ip, pt)| {
+                    let z = projected[(3 * ic + 2, ip)];
+                    let dx = projected[(3 * ic, ip)] / z - pt.x;
+                    let dy = projected[(3 * ic + 1, ip)] / z - pt.y;
+                    dx * dx + dy * dy
+                })
+                .sum::<f64>()
+        })
+        .sum();
+    (sum / (observed_points.len() * n_points) as f64).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha8Rng;
+
+    use super::*;
+
+    /// Return points observed by 3 cameras with the focal length `focal` and their true depths.
+    fn create_test_data(focal: f64) -> (Vec<Vec<na::Point2<f64>>>, na::DMatrix<f64>) {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let n_cameras = 3;
+        let n_points = 20;
+        let cameras: Vec<na::Matrix3x4<f64>> = (0..n_cameras)
+            .map(|ic| {
+                let rot = na::Rotation3::from_euler_angles(0.0, 0.1 * ic as f64, 0.0);
+                let mut cam = na::Matrix3x4::zeros();
+                cam.fixed_slice_mut::<3, 3>(0, 0).copy_from(rot.matrix());
+                cam[(0, 3)] = -(ic as f64);
+                na::Matrix3::new(focal, 0.0, 0.0, 0.0, focal, 0.0, 0.0, 0.0, 1.0) * cam
+            })
+            .collect();
+        let points: Vec<na::Vector4<f64>> = (0..n_points)
+            .map(|_| {
+                na::Vector4::new(
+                    rng.gen_range(-1.0..1.0),
+                    rng.gen_range(-1.0..1.0),
+                    rng.gen_range(4.0..8.0),
+                    1.0,
+                )
+            })
+            .collect();
+        let projected: Vec<Vec<na::Vector3<f64>>> = cameras
+            .iter()
+            .map(|cam| points.iter().map(|pt| cam * pt).collect())
+            .collect();
+        let observed: Vec<Vec<na::Point2<f64>>> = projected
+            .iter()
+            .map(|pts| {
+                pts.iter()
+                    .map(|pt| na::Point2::new(pt[0] / pt[2], pt[1] / pt[2]))
+                    .collect()
+            })
+            .collect();
+        let zs = na::DMatrix::from_fn(n_cameras, n_points, |ic, ip| projected[ic][ip][2]);
+        (observed, zs)
+    }
+
+    #[test]
+    fn test_motion_and_shape_from_svd() {
+        // true depths make the observed matrix rank 4.
+        let (observed, zs) = create_test_data(1.0);
+        let (n_cameras, n_points) = zs.shape();
+        let mut svd = get_observed_matrix(&observed, &zs).svd(true, true);
+        svd.sort_by_singular_values();
+        let (motion_mat, shape_mat) = get_motion_and_shape_from_svd(&svd).unwrap();
+        assert_eq!(motion_mat.shape(), (3 * n_cameras, 4));
+        assert_eq!(shape_mat.shape(), (4, n_points));
+        assert!(calculate_reprojection_error(&observed, &motion_mat, &shape_mat) < 1e-9);
+
+        // unit depths do not reproduce the observed points.
+        let zs = na::DMatrix::from_element(n_cameras, n_points, 1.0);
+        let mut svd = get_observed_matrix(&observed, &zs).svd(true, true);
+        svd.sort_by_singular_values();
+        let (motion_mat, shape_mat) = get_motion_and_shape_from_svd(&svd).unwrap();
+        assert!(calculate_reprojection_error(&observed, &motion_mat, &shape_mat) > 1e-6);
+    }
+
+    #[test]
+    fn test_primary_method() {
+        let (observed, _) = create_test_data(500.0);
+        let (motion_mat, shape_mat) = primary_method(&observed).unwrap();
+        assert_eq!(motion_mat.shape(), (9, 4));
+        assert_eq!(shape_mat.shape(), (4, 20));
+        assert!(calculate_reprojection_error(&observed, &motion_mat, &shape_mat) < 10.0);
+    }
+}
@@ -0,0 +1,149 @@
+use nalgebra as na;
+
+use crate::{ensure, error::Result};
+
+/// Estimate relative scale between consecutive frame pairs of monocular visual odometry.
+/// Translation recovered from each frame pair is only determined up to scale.
+/// `ScaleEstimator` aligns the scale of the pair (i, i+1) to that of the pair (i-1, i)
+/// using the points triangulated in both pairs.
+pub struct ScaleEstimator {
+    min_common_points: usize,
+}
+
+impl ScaleEstimator {
+    /// - `min_common_points` : minimum number of common points required to estimate scale.
+    pub fn new(min_common_points: usize) -> Self {
+        ScaleEstimator {
+            min_common_points: min_common_points.max(1),
+        }
+    }
+
+    /// Calculate scale ratio of the pair (i, i+1) relative to the pair (i-1, i).
+    /// Return median of depth ratios (prev / next) of the common points.
+    /// Both points must be expressed in the camera coordinates of the shared frame i
+    /// and `prev_points[k]` and `next_points[k]` must be the same point.
+    /// - `prev_points` : points triangulated from the pair (i-1, i).
+    /// - `next_points` : points triangulated from the pair (i, i+1).
+    pub fn estimate_scale(
+        &self,
+        prev_points: &[na::Vector3<f64>],
+        next_points: &[na::Vector3<f64>],
+    ) -> Result<f64> {
+        ensure!(
+            prev_points.len() == next_points.len(),
+            "Number of points is different : {} vs {}",
+            prev_points.len(),
+            next_points.len()
+        );
+        let mut ratios: Vec<f64> = prev_points
+            .iter()
+            .zip(next_points.iter())
+            .filter(|(prev, next)| prev[2] > 0.0 && next[2] > 0.0)
+            .map(|(prev, next)| prev[2] / next[2])
+            .collect();
+        ensure!(
+            ratios.len() >= self.min_common_points,
+            "Not enough common points : {} (required {})",
+            ratios.len(),
+            self.min_common_points
+        );
+        ratios.sort_by(|lhs, rhs| lhs.partial_cmp(rhs).unwrap());
+        let n = ratios.len();
+        let median = if n % 2 == 1 {
+            ratios[n / 2]
+        } else {
+            (ratios[n / 2 - 1] + ratios[n / 2]) / 2.0
+        };
+        Ok(median)
+    }
+
+    /// Rescale translation of the pair (i, i+1) (`trans`) to the scale of the pair (i-1, i).
+    /// See `estimate_scale` for `prev_points` and `next_points`.
+    pub fn rescale_translation(
+        &self,
+        prev_points: &[na::Vector3<f64>],
+        next_points: &[na::Vector3<f64>],
+        trans: &na::DVector<f64>,
+    ) -> Result<na::DVector<f64>> {
+        let scale = self.estimate_scale(prev_points, next_points)?;
+        Ok(trans * scale)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use super::*;
+
+    #[test]
+    fn test_estimate_scale() {
+        let mut rng = rand::thread_rng();
+        let estimator = ScaleEstimator::new(5);
+        let prev: Vec<na::Vector3<f64>> = (0..20)
+            .map(|_| na::Vector3::new(rng.gen::<f64>(), rng.gen::<f64>(), rng.gen::<f64>() + 1.0))
+            .collect();
+        let next: Vec<na::Vector3<f64>> = prev.iter().map(|pt| pt * 0.25).collect();
+        let scale = estimator.estimate_scale(&prev, &next).unwrap();
+        assert!((scale - 4.0).abs() < 1e-10, "scale = {}", scale);
+
+        assert!(estimator.estimate_scale(&prev[..4], &next[..4]).is_err());
+        assert!(estimator.estimate_scale(&prev, &next[..4]).is_err());
+    }
+
+    #[test]
+    fn test_three_frame_sequence() {
+        let mut rng = rand::thread_rng();
+        // Camera poses (world to camera) : x_cam = r * x_world + t
+        let theta: f64 = 0.1;
+        #[rustfmt::skip]
+        let r1 = na::Matrix3::new(
+            theta.cos(), 0.0, theta.sin(),
+            0.0, 1.0, 0.0,
+            -theta.sin(), 0.0, theta.cos(),
+        );
+        let r2 = r1 * r1;
+        let t1 = na::Vector3::new(-1.0, 0.1, 0.0);
+        let t2 = na::Vector3::new(-3.0, 0.2, 0.5);
+
+        let world: Vec<na::Vector3<f64>> = (0..50)
+            .map(|_| {
+                na::Vector3::new(
+                    (rng.gen::<f64>() - 0.5) * 4.0,
+                    (rng.gen::<f64>() - 0.5) * 4.0,
+                    rng.gen::<f64>() * 5.0 + 5.0,
+                )
+            })
+            .collect();
+
+        // relative motion from frame i to frame j : x_j = r_ij * x_i + t_ij
+        let t01 = t1;
+        let r12 = r2 * r1.transpose();
+        let t12 = t2 - r12 * t1;
+
+        // Each pair is reconstructed with the unit length translation and arbitrary scale.
+        let s01 = 1.0 / t01.norm();
+        let s12 = 1.0 / t12.norm();
+        let prev: Vec<na::Vector3<f64>> = world.iter().map(|x| (r1 * x + t1) * s01).collect();
+        let next: Vec<na::Vector3<f64>> = world.iter().map(|x| (r1 * x + t1) * s12).collect();
+        let trans01 = na::DVector::from_column_slice((t01 * s01).as_slice());
+        let trans12 = na::DVector::from_column_slice((t12 * s12).as_slice());
+        assert!((trans01.norm() - 1.0).abs() < 1e-10);
+        assert!((trans12.norm() - 1.0).abs() < 1e-10);
+
+        let estimator = ScaleEstimator::new(10);
+        let rescaled = estimator
+            .rescale_translation(&prev, &next, &trans12)
+            .unwrap();
+
+        // Ratio of the rescaled translations must match the ratio of the true translations.
+        let true_ratio = t12.norm() / t01.norm();
+        let pred_ratio = rescaled.norm() / trans01.norm();
+        assert!(
+            (true_ratio - pred_ratio).abs() < 1e-8,
+            "true = {}, pred = {}",
+            true_ratio,
+            pred_ratio
+        );
+    }
+}
@@ -1,7 +1,10 @@
-use anyhow::{ensure, Context, Result};
 use nalgebra as na;
 
-use crate::linalg::{scalar_triple_product, vector_cross_matrix};
+use crate::{
+    ensure,
+    error::{Context, Result},
+    linalg::{scalar_triple_product, vector_cross_matrix},
+};
 
 /// Self-calibration from two image.
 /// `data` is observed points.
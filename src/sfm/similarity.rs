@@ -0,0 +1,124 @@
+//! Similarity transformation (rotation, translation and scale) between two point sets.
+use nalgebra as na;
+
+use crate::{
+    ensure,
+    error::{Context, ImprocError, Result},
+};
+
+const EPS: f64 = 1e-12;
+
+/// Estimate similarity transformation (rotation `R`, translation `t`, scale `s`) which minimizes
+/// sum of |dst_i - (s * R * src_i + t)|^2 by the Umeyama algorithm (S. Umeyama, "Least-squares
+/// estimation of transformation parameters between two point patterns", TPAMI 1991).
+/// `src[i]` and `dst[i]` are the same point. At least 2 points are required and the rotation is
+/// unique only if there are 3 or more points not on a line.
+///
+/// Return tuple of (3 x 3 rotation matrix, translation vector, scale).
+pub fn estimate_similarity(
+    src: &[na::Point3<f64>],
+    dst: &[na::Point3<f64>],
+) -> Result<(na::DMatrix<f64>, na::DVector<f64>, f64)> {
+    ensure!(
+        src.len() == dst.len(),
+        "Number of points is different : {} vs {}",
+        src.len(),
+        dst.len()
+    );
+    ensure!(
+        src.len() >= 2,
+        ImprocError::InsufficientData {
+            expected: 2,
+            got: src.len()
+        }
+    );
+    let n = src.len() as f64;
+    let mean_src = src.iter().map(|pt| pt.coords).sum::<na::Vector3<f64>>() / n;
+    let mean_dst = dst.iter().map(|pt| pt.coords).sum::<na::Vector3<f64>>() / n;
+    let var_src = src
+        .iter()
+        .map(|pt| (pt.coords - mean_src).norm_squared())
+        .sum::<f64>()
+        / n;
+    ensure!(var_src > EPS, "All source points are the same.");
+    let cov = src
+        .iter()
+        .zip(dst)
+        .map(|(s, d)| (d.coords - mean_dst) * (s.coords - mean_src).transpose())
+        .sum::<na::Matrix3<f64>>()
+        / n;
+
+    let svd = cov.svd(true, true);
+    let u = svd.u.context("Failed to calc svd.")?;
+    let v_t = svd.v_t.context("Failed to calc svd.")?;
+    let mut sign = na::Vector3::new(1.0, 1.0, 1.0);
+    if u.determinant() * v_t.determinant() < 0.0 {
+        // singular values are sorted in descending order.
+        sign[2] = -1.0;
+    }
+    let rot = u * na::Matrix3::from_diagonal(&sign) * v_t;
+    let scale = svd.singular_values.dot(&sign) / var_src;
+    let trans = mean_dst - scale * rot * mean_src;
+    Ok((
+        na::DMatrix::from_column_slice(3, 3, rot.as_slice()),
+        na::DVector::from_column_slice(trans.as_slice()),
+        scale,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use super::*;
+
+    fn create_points(n: usize) -> Vec<na::Point3<f64>> {
+        let mut rng = rand::thread_rng();
+        (0..n)
+            .map(|_| {
+                na::Point3::new(
+                    (rng.gen::<f64>() - 0.5) * 10.0,
+                    (rng.gen::<f64>() - 0.5) * 10.0,
+                    (rng.gen::<f64>() - 0.5) * 10.0,
+                )
+            })
+            .collect()
+    }
+
+    fn assert_similarity(rot: na::Matrix3<f64>, trans: na::Vector3<f64>, scale: f64) {
+        let src = create_points(20);
+        let dst: Vec<na::Point3<f64>> = src.iter().map(|pt| scale * (rot * pt) + trans).collect();
+        let (pred_rot, pred_trans, pred_scale) = estimate_similarity(&src, &dst).unwrap();
+        let rot = na::DMatrix::from_column_slice(3, 3, rot.as_slice());
+        let trans = na::DVector::from_column_slice(trans.as_slice());
+        assert!((pred_rot - rot).amax() < 1e-8);
+        assert!((pred_trans - trans).amax() < 1e-8);
+        assert!((pred_scale - scale).abs() < 1e-8);
+    }
+
+    #[test]
+    fn test_estimate_similarity() {
+        let rot = na::Rotation3::from_euler_angles(0.3, -0.2, 1.2).into_inner();
+        assert_similarity(rot, na::Vector3::new(1.0, -5.0, 3.0), 0.4);
+    }
+
+    #[test]
+    fn test_pure_scale() {
+        assert_similarity(na::Matrix3::identity(), na::Vector3::zeros(), 2.5);
+    }
+
+    #[test]
+    fn test_pure_rotation() {
+        let rot = na::Rotation3::from_euler_angles(-1.0, 0.5, 2.0).into_inner();
+        assert_similarity(rot, na::Vector3::zeros(), 1.0);
+    }
+
+    #[test]
+    fn test_invalid_points() {
+        let src = create_points(5);
+        assert!(estimate_similarity(&src[..1], &src[..1]).is_err());
+        assert!(estimate_similarity(&src, &src[..4]).is_err());
+        let same = vec![na::Point3::new(1.0, 2.0, 3.0); 5];
+        assert!(estimate_similarity(&same, &src).is_err());
+    }
+}
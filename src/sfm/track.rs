@@ -0,0 +1,162 @@
+//! Tracks of the keypoints which link the observations in the frames to the same 3D point.
+use nalgebra as na;
+
+use crate::{
+    ensure,
+    error::{Context, Result},
+    feat::keypoints::KeyPoint,
+};
+
+/// Observations of the same 3D point in the multiple frames.
+#[derive(Clone, Debug, Default)]
+pub struct Track {
+    point_3d: Option<na::Vector3<f64>>,
+    observations: Vec<(usize, KeyPoint)>, // (frame_id, keypoint)
+}
+
+impl Track {
+    /// Return triangulated position of the point. `None` if the track is not triangulated yet.
+    pub fn point_3d(&self) -> Option<&na::Vector3<f64>> {
+        self.point_3d.as_ref()
+    }
+
+    /// Return list of (frame_id, keypoint).
+    pub fn observations(&self) -> &[(usize, KeyPoint)] {
+        &self.observations
+    }
+}
+
+/// Container of the tracks. Index of `tracks` is used as track id.
+#[derive(Clone, Debug, Default)]
+pub struct TrackManager {
+    tracks: Vec<Track>,
+}
+
+impl TrackManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn tracks(&self) -> &[Track] {
+        &self.tracks
+    }
+
+    pub fn track(&self, track_id: usize) -> Option<&Track> {
+        self.tracks.get(track_id)
+    }
+
+    /// Add empty track and return its id.
+    pub fn add_track(&mut self) -> usize {
+        self.tracks.push(Track::default());
+        self.tracks.len() - 1
+    }
+
+    /// Add observation of the track `track_id` in the frame `frame_id`.
+    pub fn add_observation(
+        &mut self,
+        track_id: usize,
+        frame_id: usize,
+        kpt: KeyPoint,
+    ) -> Result<()> {
+        let track = self
+            .tracks
+            .get_mut(track_id)
+            .with_context(|| format!("Invalid track id : {}", track_id))?;
+        track.observations.push((frame_id, kpt));
+        Ok(())
+    }
+
+    /// Merge track `b` into track `a`.
+    /// Observations of `b` are moved to `a` and `b` becomes empty (track ids are not changed).
+    /// Triangulated point of `a` is reset since the observations are changed.
+    pub fn merge(&mut self, a: usize, b: usize) -> Result<()> {
+        ensure!(a != b, "Failed to merge the same track : {}", a);
+        ensure!(
+            a < self.tracks.len() && b < self.tracks.len(),
+            "Invalid track id : ({}, {}) (number of tracks = {})",
+            a,
+            b,
+            self.tracks.len()
+        );
+        let merged = std::mem::take(&mut self.tracks[b]);
+        let track = &mut self.tracks[a];
+        track.observations.extend(merged.observations);
+        track.point_3d = None;
+        Ok(())
+    }
+
+    /// Triangulate all tracks observed in two or more frames by the linear (DLT) method.
+    /// `cameras[frame_id]` is 3 x 4 camera matrix of the frame `frame_id`.
+    /// Return the number of triangulated tracks.
+    pub fn triangulate_all(&mut self, cameras: &[na::DMatrix<f64>]) -> Result<usize> {
+        let mut count = 0;
+        for track in self.tracks.iter_mut() {
+            if track.observations.len() < 2 {
+                continue;
+            }
+            let mut a = na::DMatrix::<f64>::zeros(track.observations.len() * 2, 4);
+            for (i, (frame_id, kpt)) in track.observations.iter().enumerate() {
+                let p = cameras
+                    .get(*frame_id)
+                    .with_context(|| format!("Camera matrix is not found : {}", frame_id))?;
+                a.set_row(i * 2, &(kpt.x() as f64 * p.row(2) - p.row(0)));
+                a.set_row(i * 2 + 1, &(kpt.y() as f64 * p.row(2) - p.row(1)));
+            }
+            let svd = a.svd(false, true);
+            let (idx, _) = svd.singular_values.argmin();
+            let x = svd.v_t.context("Failed to calc svd.")?.row(idx).transpose();
+            track.point_3d = Some(na::Vector3::new(x[0] / x[3], x[1] / x[3], x[2] / x[3]));
+            count += 1;
+        }
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_and_triangulate() {
+        #[rustfmt::skip]
+        let k = na::DMatrix::from_row_slice(3, 3, &[
+            100.0, 0.0, 50.0,
+            0.0, 100.0, 50.0,
+            0.0, 0.0, 1.0,
+        ]);
+        #[rustfmt::skip]
+        let motion1 = na::DMatrix::from_row_slice(3, 4, &[
+            1.0, 0.0, 0.0, -1.0,
+            0.0, 1.0, 0.0, 0.0,
+            0.0, 0.0, 1.0, 0.0,
+        ]);
+        let cameras = vec![&k * na::DMatrix::<f64>::identity(3, 4), &k * motion1];
+        // (1, 2, 10) is projected to (60, 70) and (50, 70).
+        let gt = na::Vector3::new(1.0, 2.0, 10.0);
+
+        let mut manager = TrackManager::new();
+        let t0 = manager.add_track();
+        let t1 = manager.add_track();
+        manager
+            .add_observation(t0, 0, KeyPoint::new(60, 70, 1.0, 0, 0.0))
+            .unwrap();
+        manager
+            .add_observation(t1, 1, KeyPoint::new(50, 70, 1.0, 0, 0.0))
+            .unwrap();
+        assert!(manager
+            .add_observation(2, 1, KeyPoint::new(0, 0, 1.0, 0, 0.0))
+            .is_err());
+        assert!(manager.merge(t0, t0).is_err());
+
+        // not triangulated before merge
+        assert_eq!(manager.triangulate_all(&cameras).unwrap(), 0);
+        manager.merge(t0, t1).unwrap();
+        assert!(manager.track(t1).unwrap().observations().is_empty());
+        assert_eq!(manager.track(t0).unwrap().observations().len(), 2);
+
+        assert_eq!(manager.triangulate_all(&cameras).unwrap(), 1);
+        let pt = manager.track(t0).unwrap().point_3d().unwrap();
+        assert!((pt - gt).norm() < 1e-3, "pt = {:?}", pt);
+        assert!(manager.track(t1).unwrap().point_3d().is_none());
+    }
+}
@@ -1 +1,172 @@
+use std::collections::HashMap;
 
+use crate::feat::{descriptors::Descriptor, Distance};
+
+use super::DescType;
+
+const WORD_RADIUS: f32 = 40.0; // descriptors within this hamming distance are assigned to the same word
+const MIN_SCORE: f32 = 0.3;
+
+/// Bag of words : (word id) -> (weight). Sum of the weights is 1.
+type BowVector = HashMap<usize, f32>;
+
+/// Loop closure detector by the bag of words.
+/// Vocabulary is a flat list of cluster centers (words) which is incrementally built from the
+/// descriptors of the added keyframes.
+pub struct LoopCloser {
+    vocabulary: Vec<DescType>,
+    keyframes: Vec<(usize, BowVector)>, // (keyframe id, bag of words)
+    min_score: f32,
+}
+
+impl Default for LoopCloser {
+    fn default() -> Self {
+        Self::new(MIN_SCORE)
+    }
+}
+
+impl LoopCloser {
+    /// `min_score` : minimum similarity score ([0, 1]) of the detected keyframe.
+    pub fn new(min_score: f32) -> Self {
+        LoopCloser {
+            vocabulary: Vec::new(),
+            keyframes: Vec::new(),
+            min_score,
+        }
+    }
+
+    pub fn vocabulary(&self) -> &[DescType] {
+        &self.vocabulary
+    }
+
+    /// Add keyframe to the database.
+    /// Descriptors which are far from all words are added to the vocabulary as new words.
+    pub fn add_keyframe(&mut self, kf_id: usize, descs: &[Descriptor<DescType>]) {
+        let words: Vec<usize> = descs
+            .iter()
+            .map(|desc| match self.find_word(&desc.value) {
+                Some(word) => word,
+                None => {
+                    self.vocabulary.push(desc.value.clone());
+                    self.vocabulary.len() - 1
+                }
+            })
+            .collect();
+        self.keyframes.push((kf_id, bow_vector(&words)));
+    }
+
+    /// Return id of the keyframe which is the most similar to `descs`.
+    /// None if no keyframe has the similarity score not less than `min_score`.
+    /// If several keyframes have the same score, the earliest added one is returned.
+    pub fn detect(&self, descs: &[Descriptor<DescType>]) -> Option<usize> {
+        let words: Vec<usize> = descs
+            .iter()
+            .filter_map(|desc| self.find_word(&desc.value))
+            .collect();
+        if words.is_empty() {
+            return None;
+        }
+        let query = bow_vector(&words);
+        self.keyframes
+            .iter()
+            .map(|(kf_id, bow)| (*kf_id, score(&query, bow)))
+            .filter(|(_, score)| *score >= self.min_score)
+            .fold(
+                None,
+                |best: Option<(usize, f32)>, (kf_id, score)| match best {
+                    Some((_, best_score)) if best_score >= score => best,
+                    _ => Some((kf_id, score)),
+                },
+            )
+            .map(|(kf_id, _)| kf_id)
+    }
+
+    /// Return the nearest word of `desc`. None if the distance is larger than `WORD_RADIUS`.
+    fn find_word(&self, desc: &DescType) -> Option<usize> {
+        self.vocabulary
+            .iter()
+            .enumerate()
+            .map(|(idx, word)| (idx, desc.distance(word)))
+            .filter(|(_, dist)| *dist <= WORD_RADIUS)
+            .min_by(|lhs, rhs| lhs.1.partial_cmp(&rhs.1).unwrap())
+            .map(|(idx, _)| idx)
+    }
+}
+
+/// Create L1 normalized bag of words from the word ids.
+fn bow_vector(words: &[usize]) -> BowVector {
+    let weight = 1.0 / words.len().max(1) as f32;
+    let mut bow = BowVector::new();
+    words
+        .iter()
+        .for_each(|word| *bow.entry(*word).or_insert(0.0) += weight);
+    bow
+}
+
+/// L1 similarity score of the bag of words (D. Galvez-Lopez et al., "Bags of Binary Words for Fast
+/// Place Recognition in Image Sequences", 2012). Score is 1 if `lhs` and `rhs` are the same and 0
+/// if they have no common words.
+fn score(lhs: &BowVector, rhs: &BowVector) -> f32 {
+    let diff: f32 = lhs
+        .iter()
+        .map(|(word, val)| (val - rhs.get(word).unwrap_or(&0.0)).abs())
+        .sum::<f32>()
+        + rhs
+            .iter()
+            .filter(|(word, _)| !lhs.contains_key(word))
+            .map(|(_, val)| val)
+            .sum::<f32>();
+    1.0 - 0.5 * diff
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use super::*;
+    use crate::feat::{descriptors::BriefDescriptor, keypoints::KeyPoint};
+
+    fn random_descriptors(n: usize) -> Vec<Descriptor<DescType>> {
+        let mut rng = rand::thread_rng();
+        (0..n)
+            .map(|_| {
+                let mut value = BriefDescriptor::new(256);
+                (0..256).for_each(|_| value.push(rng.gen::<bool>()));
+                Descriptor {
+                    kpt: KeyPoint::new(rng.gen_range(0..640), rng.gen_range(0..480), 1.0, 0, 0.0),
+                    value,
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_detect() {
+        let descs0 = random_descriptors(200);
+        let descs1 = random_descriptors(200);
+        let mut closer = LoopCloser::default();
+        assert!(closer.detect(&descs0).is_none());
+
+        closer.add_keyframe(3, &descs0);
+        closer.add_keyframe(7, &descs1);
+        // identical descriptor set is added again
+        closer.add_keyframe(10, &descs0);
+        assert_eq!(closer.vocabulary().len(), 400);
+
+        assert_eq!(closer.detect(&descs0), Some(3));
+        assert_eq!(closer.detect(&descs1), Some(7));
+        assert_eq!(closer.detect(&descs1[..120]), Some(7));
+        assert!(closer.detect(&random_descriptors(200)).is_none());
+        assert!(closer.detect(&[]).is_none());
+    }
+
+    #[test]
+    fn test_score() {
+        let lhs = bow_vector(&[0, 1, 1, 2]);
+        let rhs = bow_vector(&[3, 4]);
+        assert!((score(&lhs, &lhs) - 1.0).abs() < 1e-6);
+        assert!(score(&lhs, &rhs).abs() < 1e-6);
+        assert!((score(&lhs, &bow_vector(&[1])) - 0.5).abs() < 1e-6);
+        assert!((score(&lhs, &rhs) - score(&rhs, &lhs)).abs() < 1e-6);
+    }
+}
@@ -1,5 +1,162 @@
-use super::keyframe::KeyFrame;
+use std::collections::{BTreeSet, HashMap};
 
+/// Covisibility graph of the keyframes.
+/// Each node is a keyframe and two keyframes are connected if they observe the same map points.
+/// Weight of the edge is the number of the shared map points.
+#[derive(Clone, Debug, Default)]
 pub struct CovisibilityGraph {
-    linked_list: Vec<Vec<(usize, usize)>>, // (keyframe_idx, weights)
+    keyframes: BTreeSet<usize>,
+    edges: HashMap<(usize, usize), usize>, // (smaller keyframe id, larger keyframe id) -> weight
+}
+
+impl CovisibilityGraph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_keyframe(&mut self, kf_id: usize) {
+        self.keyframes.insert(kf_id);
+    }
+
+    /// Set the weight of the edge between `kf1` and `kf2` to `shared_points`.
+    /// Keyframes are added if they are not in the graph. The edge is removed if `shared_points` is 0.
+    pub fn update_edge(&mut self, kf1: usize, kf2: usize, shared_points: usize) {
+        if kf1 == kf2 {
+            return;
+        }
+        self.add_keyframe(kf1);
+        self.add_keyframe(kf2);
+        let key = (kf1.min(kf2), kf1.max(kf2));
+        if shared_points == 0 {
+            self.edges.remove(&key);
+        } else {
+            self.edges.insert(key, shared_points);
+        }
+    }
+
+    /// Return weight of the edge between `kf1` and `kf2` (0 if they are not connected).
+    pub fn weight(&self, kf1: usize, kf2: usize) -> usize {
+        *self.edges.get(&(kf1.min(kf2), kf1.max(kf2))).unwrap_or(&0)
+    }
+
+    /// Return keyframes connected to `kf_id` with the weight not less than `min_weight`.
+    /// Returned keyframes are sorted in descending order of the weight.
+    pub fn connected_keyframes(&self, kf_id: usize, min_weight: usize) -> Vec<usize> {
+        let mut connected: Vec<(usize, usize)> = self
+            .edges
+            .iter()
+            .filter(|(_, weight)| **weight >= min_weight)
+            .filter_map(|((kf1, kf2), weight)| {
+                if *kf1 == kf_id {
+                    Some((*kf2, *weight))
+                } else if *kf2 == kf_id {
+                    Some((*kf1, *weight))
+                } else {
+                    None
+                }
+            })
+            .collect();
+        connected.sort_by(|lhs, rhs| rhs.1.cmp(&lhs.1).then(lhs.0.cmp(&rhs.0)));
+        connected.into_iter().map(|(kf, _)| kf).collect()
+    }
+
+    /// Return edges of the maximum spanning tree (Kruskal's algorithm), which connects each
+    /// keyframe with the strongest covisibility. If the graph is not connected, spanning forest
+    /// is returned.
+    pub fn spanning_tree(&self) -> Vec<(usize, usize)> {
+        let ids: Vec<usize> = self.keyframes.iter().cloned().collect();
+        let index: HashMap<usize, usize> = ids.iter().enumerate().map(|(i, id)| (*id, i)).collect();
+        let mut parents: Vec<usize> = (0..ids.len()).collect();
+
+        let mut edges: Vec<(&(usize, usize), &usize)> = self.edges.iter().collect();
+        edges.sort_by(|lhs, rhs| rhs.1.cmp(lhs.1).then(lhs.0.cmp(rhs.0)));
+        edges
+            .into_iter()
+            .filter_map(|((kf1, kf2), _)| {
+                let root1 = find_root(&mut parents, index[kf1]);
+                let root2 = find_root(&mut parents, index[kf2]);
+                if root1 == root2 {
+                    return None;
+                }
+                parents[root2] = root1;
+                Some((*kf1, *kf2))
+            })
+            .collect()
+    }
+}
+
+/// Find root of the union-find tree with path compression.
+fn find_root(parents: &mut [usize], idx: usize) -> usize {
+    let mut root = idx;
+    while parents[root] != root {
+        root = parents[root];
+    }
+    let mut cur = idx;
+    while parents[cur] != root {
+        let next = parents[cur];
+        parents[cur] = root;
+        cur = next;
+    }
+    root
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_graph() -> CovisibilityGraph {
+        let mut graph = CovisibilityGraph::new();
+        (0..5).for_each(|kf| graph.add_keyframe(kf));
+        graph.update_edge(0, 1, 100);
+        graph.update_edge(1, 2, 80);
+        graph.update_edge(0, 2, 30);
+        graph.update_edge(2, 3, 50);
+        graph.update_edge(3, 1, 10);
+        graph.update_edge(4, 3, 20);
+        graph
+    }
+
+    #[test]
+    fn test_edges() {
+        let mut graph = create_graph();
+        assert_eq!(graph.weight(0, 1), 100);
+        assert_eq!(graph.weight(1, 0), 100);
+        assert_eq!(graph.weight(1, 3), 10);
+        assert_eq!(graph.weight(0, 4), 0);
+
+        assert_eq!(graph.connected_keyframes(2, 0), vec![1, 3, 0]);
+        assert_eq!(graph.connected_keyframes(2, 50), vec![1, 3]);
+        assert_eq!(graph.connected_keyframes(4, 30), Vec::<usize>::new());
+
+        // update and remove edges
+        graph.update_edge(2, 0, 90);
+        assert_eq!(graph.connected_keyframes(2, 0), vec![0, 1, 3]);
+        graph.update_edge(0, 2, 0);
+        assert_eq!(graph.connected_keyframes(2, 0), vec![1, 3]);
+        graph.update_edge(2, 2, 10);
+        assert_eq!(graph.weight(2, 2), 0);
+    }
+
+    #[test]
+    fn test_spanning_tree() {
+        let mut graph = create_graph();
+        let tree = graph.spanning_tree();
+        assert_eq!(tree, vec![(0, 1), (1, 2), (2, 3), (3, 4)]);
+
+        // tree has (n - 1) edges, no cycles and connects all keyframes.
+        graph.add_keyframe(5);
+        graph.update_edge(5, 0, 5);
+        graph.update_edge(5, 4, 5);
+        let tree = graph.spanning_tree();
+        assert_eq!(tree.len(), 5);
+        let mut parents: Vec<usize> = (0..6).collect();
+        tree.iter().for_each(|(kf1, kf2)| {
+            assert!(graph.weight(*kf1, *kf2) > 0);
+            let (root1, root2) = (find_root(&mut parents, *kf1), find_root(&mut parents, *kf2));
+            assert_ne!(root1, root2, "cycle is found : ({}, {})", kf1, kf2);
+            parents[root2] = root1;
+        });
+        let root = find_root(&mut parents, 0);
+        assert!((0..6).all(|kf| find_root(&mut parents, kf) == root));
+    }
 }
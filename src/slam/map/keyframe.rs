@@ -1,4 +1,4 @@
-use nalgebra::{Matrix3, Matrix4};
+use nalgebra::{Matrix3, Matrix4, Point3};
 
 // pub struct KeyFrames<T> {
 //     frames: Vec<KeyFrames<T>>,
@@ -8,4 +8,39 @@ pub struct KeyFrame<T> {
     camera_pose: Matrix4<f32>,
     camera_intrinsic: Matrix3<f32>,
     descriptors: Vec<T>,
+    map_points: Vec<Point3<f64>>, // position of the map point observed by `descriptors[i]`
+}
+
+impl<T> KeyFrame<T> {
+    /// `map_points[i]` is the position (in the world coordinates) of the point of `descriptors[i]`.
+    pub fn new(
+        camera_pose: Matrix4<f32>,
+        camera_intrinsic: Matrix3<f32>,
+        descriptors: Vec<T>,
+        map_points: Vec<Point3<f64>>,
+    ) -> Self {
+        assert_eq!(descriptors.len(), map_points.len());
+        KeyFrame {
+            camera_pose,
+            camera_intrinsic,
+            descriptors,
+            map_points,
+        }
+    }
+
+    pub fn camera_pose(&self) -> &Matrix4<f32> {
+        &self.camera_pose
+    }
+
+    pub fn camera_intrinsic(&self) -> &Matrix3<f32> {
+        &self.camera_intrinsic
+    }
+
+    pub fn descriptors(&self) -> &[T] {
+        &self.descriptors
+    }
+
+    pub fn map_points(&self) -> &[Point3<f64>] {
+        &self.map_points
+    }
 }
@@ -12,3 +12,41 @@ where
     dmax: f32, //maximum distance at which the point can be observed
     dmin: f32, //minimum distance at which the point can be observed
 }
+
+impl<T> MapPoint<T>
+where
+    T: Distance + Clone,
+{
+    pub fn new(
+        pt: Vector3<f32>,
+        n: Vector3<f32>,
+        desc: Descriptor<T>,
+        dmax: f32,
+        dmin: f32,
+    ) -> Self {
+        MapPoint {
+            pt,
+            n,
+            desc,
+            dmax,
+            dmin,
+        }
+    }
+
+    pub fn position(&self) -> &Vector3<f32> {
+        &self.pt
+    }
+
+    pub fn viewing_direction(&self) -> &Vector3<f32> {
+        &self.n
+    }
+
+    pub fn descriptor(&self) -> &Descriptor<T> {
+        &self.desc
+    }
+
+    /// Return (minimum, maximum) distance at which the point can be observed.
+    pub fn distance_range(&self) -> (f32, f32) {
+        (self.dmin, self.dmax)
+    }
+}
@@ -1,17 +1,42 @@
-use std::ops::Deref;
+use std::{
+    fs::{self, File},
+    io::{BufReader, BufWriter},
+    ops::Deref,
+    path::Path,
+};
 
+use anyhow::{ensure, Context, Result};
 use image::{ImageBuffer, Pixel};
-use nalgebra::Matrix3;
+use nalgebra::{DMatrix, Point2, Vector3};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
-use crate::feat::{descriptors::Descriptor, matcher::Match};
+use crate::{
+    epipolar::{
+        fundamental_matrix::FundamentalMatrixData, homography::homography_ransac, sampson_distance,
+    },
+    feat::{
+        descriptors::{BriefDescriptor, Descriptor},
+        keypoints::KeyPoint,
+        matcher::{brute_force::BruteForceMathcer, Match, Matcher},
+    },
+    optimizer::ransac::ransac,
+    sfm::pipeline::{motion_recovery4, motion_recovery8, refine_reconstruction, SfmResult},
+};
 
-use super::{extract_orb, DescType};
+use super::{extract_orb, DescType, OrbExtractor};
 
 pub mod covisibility_graph;
 pub mod essential_graph;
 pub mod keyframe;
 pub mod map_point;
 
+const F0: f64 = 1.0;
+const BA_ITERATIONS: usize = 10;
+const MIN_INITIAL_MATCHES: usize = 30;
+const MAX_MATCH_DISTANCE: f32 = 64.0; // hamming distance of 256 bits descriptor
+const RANSAC_ITERATIONS: usize = 200;
+const RANSAC_THRESHOLD_PX: f64 = 3.0;
+
 pub struct Map<P, Container>
 where
     P: Pixel + 'static,
@@ -20,6 +45,9 @@ where
 {
     ref_frame: ImageBuffer<P, Container>, // reference frame
     ref_frame_descs: Vec<Descriptor<DescType>>,
+    focal_length: Option<f64>,        // focal length in pixel
+    matched_points: Vec<Point2<f64>>, // [ref_frame_pt0, cur_frame_pt0, ref_frame_pt1, ...]
+    result: Option<SfmResult>,
 }
 
 impl<P, Container> Map<P, Container>
@@ -32,42 +60,549 @@ where
         let mut map = Map {
             ref_frame: image,
             ref_frame_descs: Vec::new(),
+            focal_length: None,
+            matched_points: Vec::new(),
+            result: None,
         };
         map.ref_frame_descs = extract_orb(&map.ref_frame, 1, 1.0);
         map
     }
 
-    pub fn initialize_map(mut self, cur_img: &ImageBuffer<P, Container>) -> Self {
-        let descs = extract_orb(cur_img, 1, 1.0);
-        let matches = self.calc_match(&descs);
+    /// Same as `new`, but the focal length (in pixel) of the camera is given.
+    /// Focal length is needed to initialize the map from the planar scene.
+    pub fn with_focal_length(image: ImageBuffer<P, Container>, focal_length: f64) -> Self {
+        let mut map = Self::new(image);
+        map.focal_length = Some(focal_length);
+        map
+    }
 
-        let (h, s_h) = self.find_homography(&matches);
-        let (f, s_f) = self.find_fundamental_matrix(&matches);
+    /// Initialize the map from the reference frame and `cur_img`.
+    /// Motion of the camera is recovered from the homography (planar scene) or the fundamental
+    /// matrix (general scene), which is selected by the ratio of the inliers of the models.
+    /// Return `Err` if the matched points are not enough or the motion is not recovered.
+    pub fn initialize_map(mut self, cur_img: &ImageBuffer<P, Container>) -> Result<Self> {
+        // descriptors extracted by the different extractors can not be compared.
+        let extractor = OrbExtractor::new(1, 1.0);
+        self.ref_frame_descs = extractor.extract(&self.ref_frame);
+        let descs = extractor.extract(cur_img);
+        let matches = self.calc_match(&descs);
+        ensure!(
+            matches.len() >= MIN_INITIAL_MATCHES,
+            "Number of the matched points ({}) is less than {}.",
+            matches.len(),
+            MIN_INITIAL_MATCHES
+        );
+        // principal point is assumed to be the center of the image.
+        let cx = self.ref_frame.width() as f64 / 2.0;
+        let cy = self.ref_frame.height() as f64 / 2.0;
+        self.matched_points = matches
+            .iter()
+            .flat_map(|m| {
+                let (lhs, rhs) = (&m.matche.0.kpt, &m.matche.1.kpt);
+                vec![
+                    Point2::new(lhs.x() as f64 - cx, lhs.y() as f64 - cy),
+                    Point2::new(rhs.x() as f64 - cx, rhs.y() as f64 - cy),
+                ]
+            })
+            .collect();
 
+        let h_inliers = self.homography_inliers();
+        let f_inliers = self.fundamental_matrix_inliers();
+        let (s_h, s_f) = (h_inliers.len() as f64, f_inliers.len() as f64);
+        ensure!(
+            s_h + s_f > 0.0,
+            "Neither homography nor fundamental matrix is estimated."
+        );
+        // homography is selected for the planar scene or the small parallax.
+        // Only the inliers of the selected model are used for the reconstruction.
         if s_h / (s_h + s_f) > 0.45 {
-            self.motion_recovery8();
+            self.keep_matched_points(&h_inliers);
+            self.motion_recovery4()?;
         } else {
-            self.motion_recovery4();
+            self.keep_matched_points(&f_inliers);
+            self.motion_recovery8()?;
         }
         self.run_bundle_adjustment();
-        self
+        Ok(self)
+    }
+
+    fn calc_match(&self, descs: &[Descriptor<DescType>]) -> Vec<Match<DescType>> {
+        BruteForceMathcer::new(self.ref_frame_descs.clone(), descs.to_vec(), false)
+            .run()
+            .into_iter()
+            .filter(|m| m.matche.0.distance(&m.matche.1) < MAX_MATCH_DISTANCE)
+            .collect()
+    }
+
+    /// Return indices of the point pairs which are inliers of the homography estimated by
+    /// RANSAC (empty if not estimated).
+    fn homography_inliers(&self) -> Vec<usize> {
+        homography_ransac(&self.matched_points, RANSAC_ITERATIONS, RANSAC_THRESHOLD_PX)
+            .map_or(Vec::new(), |(_, inliers)| inliers)
+    }
+
+    /// Return indices of the point pairs which are inliers of the fundamental matrix estimated by
+    /// RANSAC (empty if not estimated). A point pair is inlier if its Sampson distance is smaller
+    /// than `RANSAC_THRESHOLD_PX`.
+    fn fundamental_matrix_inliers(&self) -> Vec<usize> {
+        // points are scaled to avoid the ill-conditioned algebraic error.
+        let scale = self.ref_frame.width().max(self.ref_frame.height()) as f64;
+        let threshold = RANSAC_THRESHOLD_PX / scale;
+        let data: Vec<Point2<f64>> = self.matched_points.iter().map(|pt| pt / scale).collect();
+        match ransac::<FundamentalMatrixData>(&data, 8, RANSAC_ITERATIONS, threshold) {
+            Ok(theta) => {
+                let f = DMatrix::from_row_slice(3, 3, theta.as_slice());
+                (0..data.len() / 2)
+                    .filter(|idx| {
+                        sampson_distance(&f, &data[idx * 2], &data[idx * 2 + 1]) < threshold.powi(2)
+                    })
+                    .collect()
+            }
+            Err(_) => Vec::new(),
+        }
+    }
+
+    /// Keep only the point pairs of `indices` in the matched points.
+    fn keep_matched_points(&mut self, indices: &[usize]) {
+        self.matched_points = indices
+            .iter()
+            .flat_map(|idx| {
+                [
+                    self.matched_points[idx * 2],
+                    self.matched_points[idx * 2 + 1],
+                ]
+            })
+            .collect();
+    }
+
+    /// Return reconstructed camera matrices and 3D points if the map is initialized.
+    pub fn result(&self) -> Option<&SfmResult> {
+        self.result.as_ref()
     }
 
-    fn calc_match(&self, descs: &Vec<Descriptor<DescType>>) -> Vec<Match<DescType>> {
-        Vec::new()
+    /// Return number of the map points.
+    pub fn point_count(&self) -> usize {
+        self.result.as_ref().map_or(0, |res| res.points.len())
     }
 
-    fn find_homography(&self, matches: &Vec<Match<DescType>>) -> (Matrix3<f32>, f32) {
-        (nalgebra::one::<Matrix3<f32>>(), 0.0)
+    /// Remove map points which are observed by fewer than `min_observations` cameras or whose RMS
+    /// reprojection error is larger than `max_reprojection_error`. Observations of the removed
+    /// points are also removed.
+    /// A point is observed by `cameras[k]` if it is in front of the camera. The observed position
+    /// in `cameras[k]` is the k-th point of each point tuple of the matched points
+    /// (i.e. `cameras` are [reference frame, current frame]).
+    /// Return `Err` if the number of the matched points is not (number of the map points) x
+    /// (number of `cameras`) or a camera matrix is not 3 x 4.
+    pub fn cull_map_points(
+        &mut self,
+        min_observations: usize,
+        max_reprojection_error: f64,
+        cameras: &[DMatrix<f64>],
+    ) -> Result<()> {
+        let matched_points = &self.matched_points;
+        let result = match self.result.as_mut() {
+            Some(result) => result,
+            None => return Ok(()),
+        };
+        let n_views = cameras.len();
+        ensure!(
+            matched_points.len() == result.points.len() * n_views,
+            "Number of the matched points ({}) does not match with {} map points x {} cameras.",
+            matched_points.len(),
+            result.points.len(),
+            n_views
+        );
+        ensure!(
+            cameras.iter().all(|p| p.shape() == (3, 4)),
+            "Camera matrix must be 3 x 4."
+        );
+        let keep: Vec<bool> = result
+            .points
+            .iter()
+            .enumerate()
+            .map(|(idx, pt)| {
+                let homo = pt.insert_row(3, 1.0);
+                let errors: Vec<f64> = cameras
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(k, p)| {
+                        let proj = p * homo;
+                        if proj[2] <= 0.0 {
+                            return None;
+                        }
+                        let x = matched_points[idx * n_views + k];
+                        Some(
+                            (proj[0] / proj[2] - x[0]).powi(2) + (proj[1] / proj[2] - x[1]).powi(2),
+                        )
+                    })
+                    .collect();
+                !errors.is_empty()
+                    && errors.len() >= min_observations
+                    && (errors.iter().sum::<f64>() / errors.len() as f64).sqrt()
+                        <= max_reprojection_error
+            })
+            .collect();
+
+        result.points = keep
+            .iter()
+            .zip(result.points.iter())
+            .filter(|(k, _)| **k)
+            .map(|(_, pt)| *pt)
+            .collect();
+        self.matched_points = self
+            .matched_points
+            .chunks(n_views)
+            .zip(keep.iter())
+            .filter(|(_, k)| **k)
+            .flat_map(|(pts, _)| pts.to_vec())
+            .collect();
+        Ok(())
+    }
+
+    fn motion_recovery8(&mut self) -> Result<()> {
+        self.result = Some(motion_recovery8(&self.matched_points, F0)?);
+        Ok(())
+    }
+
+    fn motion_recovery4(&mut self) -> Result<()> {
+        let focal_length = self
+            .focal_length
+            .context("Focal length is needed to recover the motion of the planar scene.")?;
+        self.result = Some(motion_recovery4(
+            &self.matched_points,
+            focal_length,
+            focal_length,
+            F0,
+        )?);
+        Ok(())
+    }
+
+    fn run_bundle_adjustment(&mut self) {
+        if let Some(result) = self.result.as_mut() {
+            refine_reconstruction(result, &self.matched_points, BA_ITERATIONS);
+        }
     }
+}
+
+/// Serialized form of the descriptor.
+#[derive(Serialize, Deserialize)]
+struct DescriptorData {
+    x: f32,
+    y: f32,
+    cornerness: f32,
+    direction: f32,
+    #[serde(default)]
+    level: u32,
+    n_bits: usize,
+    bits: Vec<u64>,
+}
 
-    fn find_fundamental_matrix(&self, matches: &Vec<Match<DescType>>) -> (Matrix3<f32>, f32) {
-        (nalgebra::one::<Matrix3<f32>>(), 0.0)
+impl DescriptorData {
+    fn new(desc: &Descriptor<DescType>) -> Self {
+        DescriptorData {
+            x: desc.kpt.x(),
+            y: desc.kpt.y(),
+            cornerness: desc.kpt.crf(),
+            direction: desc.kpt.direction(),
+            level: desc.kpt.level(),
+            n_bits: desc.value.len(),
+            bits: desc.value.bits.clone(),
+        }
     }
 
-    fn motion_recovery8(&self) {}
+    fn to_descriptor(&self) -> Result<Descriptor<DescType>> {
+        ensure!(
+            self.bits.len() * 64 >= self.n_bits,
+            "Descriptor has {} bits, but only {} words are saved.",
+            self.n_bits,
+            self.bits.len()
+        );
+        let kpt = KeyPoint::new(
+            self.x as usize,
+            self.y as usize,
+            self.cornerness,
+            self.level,
+            self.direction,
+        );
+        let mut value = BriefDescriptor::new(self.n_bits);
+        (0..self.n_bits).for_each(|i| value.push((self.bits[i / 64] >> (i % 64)) & 1 == 1));
+        Ok(Descriptor { kpt, value })
+    }
+}
 
-    fn motion_recovery4(&self) {}
+/// Serialized form of the `Map`.
+/// Only raw data and dimensions of the reference frame are saved.
+/// `keyframe_poses` are 3 x 4 camera matrices in row-major order.
+#[derive(Serialize, Deserialize)]
+struct MapData<S> {
+    width: u32,
+    height: u32,
+    ref_frame: Vec<S>,
+    ref_frame_descs: Vec<DescriptorData>,
+    focal_length: Option<f64>,
+    matched_points: Vec<[f64; 2]>,
+    keyframe_poses: Option<Vec<Vec<f64>>>,
+    map_points: Option<Vec<[f64; 3]>>,
+}
 
-    fn run_bundle_adjustment(&self) {}
+impl<P> Map<P, Vec<P::Subpixel>>
+where
+    P: Pixel + 'static,
+    P::Subpixel: 'static + Serialize + DeserializeOwned,
+{
+    /// Save the map to `path` as JSON.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let data = MapData {
+            width: self.ref_frame.width(),
+            height: self.ref_frame.height(),
+            ref_frame: self.ref_frame.as_raw().clone(),
+            ref_frame_descs: self
+                .ref_frame_descs
+                .iter()
+                .map(DescriptorData::new)
+                .collect(),
+            focal_length: self.focal_length,
+            matched_points: self.matched_points.iter().map(|pt| [pt.x, pt.y]).collect(),
+            keyframe_poses: self.result.as_ref().map(|res| {
+                res.camera_matrices
+                    .iter()
+                    .map(|mat| mat.transpose().iter().cloned().collect())
+                    .collect()
+            }),
+            map_points: self
+                .result
+                .as_ref()
+                .map(|res| res.points.iter().map(|pt| [pt.x, pt.y, pt.z]).collect()),
+        };
+        if let Some(outdir) = path.parent() {
+            fs::create_dir_all(outdir)?;
+        }
+        let file = File::create(path).with_context(|| format!("Failed to create {:?}", path))?;
+        serde_json::to_writer(BufWriter::new(file), &data)?;
+        Ok(())
+    }
+
+    /// Load the map saved by `save`.
+    pub fn load(path: &Path) -> Result<Self> {
+        let file = File::open(path).with_context(|| format!("Failed to open {:?}", path))?;
+        let data: MapData<P::Subpixel> = serde_json::from_reader(BufReader::new(file))
+            .with_context(|| format!("Failed to parse {:?}", path))?;
+
+        let ref_frame = ImageBuffer::from_raw(data.width, data.height, data.ref_frame)
+            .context("Size of the reference frame is mismatched.")?;
+        let result = match (data.keyframe_poses, data.map_points) {
+            (Some(poses), Some(points)) => {
+                ensure!(
+                    poses.iter().all(|pose| pose.len() == 12),
+                    "Keyframe pose must have 12 elements."
+                );
+                Some(SfmResult {
+                    camera_matrices: poses
+                        .iter()
+                        .map(|pose| DMatrix::from_row_slice(3, 4, pose))
+                        .collect(),
+                    points: points
+                        .iter()
+                        .map(|pt| Vector3::new(pt[0], pt[1], pt[2]))
+                        .collect(),
+                })
+            }
+            _ => None,
+        };
+        Ok(Map {
+            ref_frame,
+            ref_frame_descs: data
+                .ref_frame_descs
+                .iter()
+                .map(DescriptorData::to_descriptor)
+                .collect::<Result<_>>()?,
+            focal_length: data.focal_length,
+            matched_points: data
+                .matched_points
+                .iter()
+                .map(|pt| Point2::new(pt[0], pt[1]))
+                .collect(),
+            result,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::GrayImage;
+    use nalgebra::{Matrix3, Rotation3};
+    use rand::Rng;
+
+    use super::*;
+    use crate::{imgproc::warp_perspective, slam::tracking::tests::create_textured_image};
+
+    #[test]
+    fn test_cull_map_points() {
+        let mut rng = rand::thread_rng();
+        let mut map = Map::new(GrayImage::new(32, 24));
+        assert_eq!(map.point_count(), 0);
+
+        let p0 = DMatrix::<f64>::identity(3, 4);
+        #[rustfmt::skip]
+        let p1 = DMatrix::from_row_slice(3, 4, &[
+            1.0, 0.0, 0.0, -1.0,
+            0.0, 1.0, 0.0, 0.0,
+            0.0, 0.0, 1.0, 0.0,
+        ]);
+        let points: Vec<Vector3<f64>> = (0..10)
+            .map(|_| {
+                Vector3::new(
+                    rng.gen::<f64>() - 0.5,
+                    rng.gen::<f64>() - 0.5,
+                    rng.gen::<f64>() * 5.0 + 2.0,
+                )
+            })
+            .collect();
+        map.matched_points = points
+            .iter()
+            .enumerate()
+            .flat_map(|(idx, pt)| {
+                [&p0, &p1]
+                    .iter()
+                    .map(|p| {
+                        let proj = *p * pt.insert_row(3, 1.0);
+                        // points [0, 3) have large reprojection error.
+                        let offset = if idx < 3 { 0.1 } else { 0.0 };
+                        Point2::new(proj[0] / proj[2] + offset, proj[1] / proj[2])
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        map.result = Some(SfmResult {
+            camera_matrices: vec![p0.clone(), p1.clone()],
+            points,
+        });
+        assert_eq!(map.point_count(), 10);
+
+        let cameras = [p0, p1];
+        assert!(map.cull_map_points(2, 1e-3, &cameras[..1]).is_err());
+        assert_eq!(map.point_count(), 10);
+        map.cull_map_points(2, 1e-3, &cameras).unwrap();
+        assert_eq!(map.point_count(), 7);
+        assert_eq!(map.matched_points.len(), 14);
+        let result = map.result().unwrap();
+        assert!(result.reprojection_error(&map.matched_points) < 1e-10);
+
+        // all points are behind the flipped camera and observed only once.
+        let mut flipped = cameras[1].clone();
+        flipped.set_row(2, &(-cameras[1].row(2)));
+        let cameras = [cameras[0].clone(), flipped];
+        map.cull_map_points(1, 1e-3, &cameras).unwrap();
+        assert_eq!(map.point_count(), 7);
+        map.cull_map_points(2, 1e-3, &cameras).unwrap();
+        assert_eq!(map.point_count(), 0);
+    }
+
+    #[test]
+    fn test_initialize_map() {
+        let (width, height) = (240, 180);
+        let frame = create_textured_image(width, height);
+        let focal_length = 300.0;
+        // camera moves in front of the plane z = 5.
+        #[rustfmt::skip]
+        let k = Matrix3::new(
+            focal_length, 0.0, width as f64 / 2.0,
+            0.0, focal_length, height as f64 / 2.0,
+            0.0, 0.0, 1.0,
+        );
+        let rot = Rotation3::from_euler_angles(0.0, 0.02, 0.0);
+        let trans = Vector3::new(-0.3, 0.05, 0.0);
+        let normal = Vector3::new(0.0, 0.0, 1.0);
+        let homography =
+            k * (rot.matrix() - trans * normal.transpose() / 5.0) * k.try_inverse().unwrap();
+        let cur = GrayImage::from_raw(
+            width,
+            height,
+            warp_perspective(&frame, &homography.cast::<f32>()),
+        )
+        .unwrap();
+
+        let map = Map::with_focal_length(frame.clone(), focal_length)
+            .initialize_map(&cur)
+            .unwrap();
+        assert!(map.point_count() > 0);
+        let result = map.result().unwrap();
+        assert!(result.reprojection_error(&map.matched_points) < RANSAC_THRESHOLD_PX);
+
+        // focal length is needed for the planar scene.
+        assert!(Map::new(frame.clone()).initialize_map(&cur).is_err());
+        // no matched points
+        assert!(Map::new(frame)
+            .initialize_map(&GrayImage::new(width, height))
+            .is_err());
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let mut rng = rand::thread_rng();
+        let mut map = Map::with_focal_length(create_textured_image(160, 120), 300.0);
+        map.matched_points = (0..20)
+            .map(|_| Point2::new(rng.gen::<f64>() * 160.0, rng.gen::<f64>() * 120.0))
+            .collect();
+        map.result = Some(SfmResult {
+            camera_matrices: (0..2)
+                .map(|_| DMatrix::from_fn(3, 4, |_, _| rng.gen::<f64>()))
+                .collect(),
+            points: (0..10)
+                .map(|_| Vector3::new(rng.gen(), rng.gen(), rng.gen::<f64>() * 10.0))
+                .collect(),
+        });
+        assert!(!map.ref_frame_descs.is_empty());
+
+        let path = std::env::temp_dir().join("improc_test_map.json");
+        map.save(&path).unwrap();
+        let loaded: Map<image::Luma<u8>, Vec<u8>> = Map::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.ref_frame, map.ref_frame);
+        assert_eq!(loaded.focal_length, map.focal_length);
+        assert_eq!(loaded.matched_points, map.matched_points);
+        assert_eq!(loaded.ref_frame_descs.len(), map.ref_frame_descs.len());
+        loaded
+            .ref_frame_descs
+            .iter()
+            .zip(map.ref_frame_descs.iter())
+            .for_each(|(lhs, rhs)| {
+                assert_eq!(lhs.distance(rhs), 0.0);
+                assert_eq!(lhs.value.len(), rhs.value.len());
+                assert_eq!(lhs.kpt.x(), rhs.kpt.x());
+                assert_eq!(lhs.kpt.y(), rhs.kpt.y());
+                assert_eq!(lhs.kpt.level(), rhs.kpt.level());
+            });
+        let (lhs, rhs) = (loaded.result().unwrap(), map.result().unwrap());
+        assert_eq!(lhs.camera_matrices, rhs.camera_matrices);
+        assert_eq!(lhs.points, rhs.points);
+
+        // map which is not initialized
+        let map = Map::new(GrayImage::new(32, 24));
+        map.save(&path).unwrap();
+        let loaded: Map<image::Luma<u8>, Vec<u8>> = Map::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(loaded.result().is_none());
+        assert!(Map::<image::Luma<u8>, Vec<u8>>::load(&path).is_err());
+    }
+
+    #[test]
+    fn test_truncated_descriptor() {
+        let mut data = DescriptorData {
+            x: 10.0,
+            y: 20.0,
+            cornerness: 1.0,
+            direction: 0.5,
+            level: 2,
+            n_bits: 256,
+            bits: vec![0x5555_5555_5555_5555; 4],
+        };
+        let desc = data.to_descriptor().unwrap();
+        assert_eq!(desc.kpt.level(), 2);
+        assert_eq!(desc.value.len(), 256);
+
+        data.bits.truncate(3);
+        assert!(data.to_descriptor().is_err());
+    }
 }
@@ -0,0 +1,229 @@
+//! Accuracy metrics of the estimated trajectory (J. Sturm et al., "A Benchmark for the Evaluation
+//! of RGB-D SLAM Systems", IROS 2012).
+//! Each pose is the transformation from the camera coordinates to the world coordinates, i.e.
+//! the translation of the pose is the camera position.
+use nalgebra as na;
+
+use crate::{ensure, error::Result, sfm::similarity::estimate_similarity};
+
+/// Return absolute trajectory error (RMS of the position errors) after aligning `estimated` to
+/// `ground_truth` by the similarity transformation.
+/// Return error if the lengths of the trajectories are different.
+pub fn absolute_trajectory_error(
+    estimated: &[na::Isometry3<f64>],
+    ground_truth: &[na::Isometry3<f64>],
+) -> Result<f64> {
+    ensure!(
+        estimated.len() == ground_truth.len(),
+        "Length of estimated trajectory ({}) does not match with ground truth ({})",
+        estimated.len(),
+        ground_truth.len()
+    );
+    if estimated.is_empty() {
+        return Ok(0.0);
+    }
+    let aligned = align_trajectory(estimated, ground_truth)?;
+    let sum: f64 = aligned
+        .iter()
+        .zip(ground_truth)
+        .map(|(est, gt)| (est.translation.vector - gt.translation.vector).norm_squared())
+        .sum();
+    Ok((sum / estimated.len() as f64).sqrt())
+}
+
+/// Return relative pose error (mean of the translational errors of the relative motions between
+/// the consecutive frames) after aligning `estimated` to `ground_truth` by the similarity
+/// transformation.
+/// Return error if the lengths of the trajectories are different.
+pub fn relative_pose_error(
+    estimated: &[na::Isometry3<f64>],
+    ground_truth: &[na::Isometry3<f64>],
+) -> Result<f64> {
+    ensure!(
+        estimated.len() == ground_truth.len(),
+        "Length of estimated trajectory ({}) does not match with ground truth ({})",
+        estimated.len(),
+        ground_truth.len()
+    );
+    if estimated.len() < 2 {
+        return Ok(0.0);
+    }
+    let aligned = align_trajectory(estimated, ground_truth)?;
+    let sum: f64 = (0..aligned.len() - 1)
+        .map(|idx| {
+            let rel_est = aligned[idx].inverse() * aligned[idx + 1];
+            let rel_gt = ground_truth[idx].inverse() * ground_truth[idx + 1];
+            (rel_gt.inverse() * rel_est).translation.vector.norm()
+        })
+        .sum();
+    Ok(sum / (aligned.len() - 1) as f64)
+}
+
+/// Estimate the similarity transformation which aligns the camera positions of `estimated` to
+/// those of `ground_truth` by the Umeyama algorithm (`sfm::similarity::estimate_similarity`).
+/// If the transformation can not be estimated (e.g. all positions of `estimated` are the same),
+/// only the translation between the centroids is returned.
+/// Return error if the lengths of the trajectories are different.
+pub fn align_trajectories(
+    estimated: &[na::Isometry3<f64>],
+    ground_truth: &[na::Isometry3<f64>],
+) -> Result<na::Similarity3<f64>> {
+    ensure!(
+        estimated.len() == ground_truth.len(),
+        "Length of estimated trajectory ({}) does not match with ground truth ({})",
+        estimated.len(),
+        ground_truth.len()
+    );
+    let to_points = |poses: &[na::Isometry3<f64>]| -> Vec<na::Point3<f64>> {
+        poses
+            .iter()
+            .map(|pose| na::Point3::from(pose.translation.vector))
+            .collect()
+    };
+    let src = to_points(estimated);
+    let dst = to_points(ground_truth);
+    let sim = match estimate_similarity(&src, &dst) {
+        Ok((rot, trans, scale)) => na::Similarity3::from_parts(
+            na::Translation3::new(trans[0], trans[1], trans[2]),
+            na::UnitQuaternion::from_matrix(&na::Matrix3::from_column_slice(rot.as_slice())),
+            scale,
+        ),
+        Err(_) => {
+            let n = src.len().max(1) as f64;
+            let mean_src = src.iter().map(|pt| pt.coords).sum::<na::Vector3<f64>>() / n;
+            let mean_dst = dst.iter().map(|pt| pt.coords).sum::<na::Vector3<f64>>() / n;
+            na::Similarity3::from_parts(
+                na::Translation3::from(mean_dst - mean_src),
+                na::UnitQuaternion::identity(),
+                1.0,
+            )
+        }
+    };
+    Ok(sim)
+}
+
+/// Transform `estimated` by the similarity transformation returned by `align_trajectories`.
+fn align_trajectory(
+    estimated: &[na::Isometry3<f64>],
+    ground_truth: &[na::Isometry3<f64>],
+) -> Result<Vec<na::Isometry3<f64>>> {
+    let sim = align_trajectories(estimated, ground_truth)?;
+    let aligned = estimated
+        .iter()
+        .map(|pose| {
+            na::Isometry3::from_parts(
+                na::Translation3::from(sim.transform_point(&pose.translation.vector.into()).coords),
+                sim.isometry.rotation * pose.rotation,
+            )
+        })
+        .collect();
+    Ok(aligned)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use super::*;
+
+    /// Camera moves on the circle in the xy plane.
+    fn create_trajectory(n: usize) -> Vec<na::Isometry3<f64>> {
+        (0..n)
+            .map(|idx| {
+                let theta = 2.0 * PI * idx as f64 / n as f64;
+                na::Isometry3::new(
+                    na::Vector3::new(20.0 * theta.cos(), 20.0 * theta.sin(), 0.0),
+                    na::Vector3::new(0.0, 0.0, theta),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_aligned_trajectory() {
+        let gt = create_trajectory(20);
+        assert!(absolute_trajectory_error(&gt, &gt).unwrap() < 1e-9);
+        assert!(relative_pose_error(&gt, &gt).unwrap() < 1e-9);
+
+        // similarity transformation is removed by the alignment.
+        let rot = na::UnitQuaternion::from_euler_angles(0.3, -0.2, 1.2);
+        let trans = na::Vector3::new(1.0, -5.0, 3.0);
+        let scale = 0.4;
+        let estimated: Vec<_> = gt
+            .iter()
+            .map(|pose| {
+                na::Isometry3::from_parts(
+                    na::Translation3::from(scale * (rot * pose.translation.vector) + trans),
+                    rot * pose.rotation,
+                )
+            })
+            .collect();
+        assert!(absolute_trajectory_error(&estimated, &gt).unwrap() < 1e-9);
+        assert!(relative_pose_error(&estimated, &gt).unwrap() < 1e-9);
+    }
+
+    #[test]
+    fn test_align_trajectories() {
+        let gt = create_trajectory(20);
+        let rot = na::UnitQuaternion::from_euler_angles(-0.5, 0.1, 2.0);
+        let trans = na::Vector3::new(-3.0, 2.0, 7.0);
+        let scale = 2.5;
+        let estimated: Vec<_> = gt
+            .iter()
+            .map(|pose| {
+                na::Isometry3::from_parts(
+                    na::Translation3::from(scale * (rot * pose.translation.vector) + trans),
+                    rot * pose.rotation,
+                )
+            })
+            .collect();
+        let sim = align_trajectories(&estimated, &gt).unwrap();
+        assert!((sim.scaling() - 1.0 / scale).abs() < 1e-9);
+        assert!(sim.isometry.rotation.angle_to(&rot.inverse()) < 1e-9);
+
+        let aligned: Vec<_> = estimated
+            .iter()
+            .map(|pose| {
+                na::Isometry3::from_parts(
+                    na::Translation3::from(
+                        sim.transform_point(&pose.translation.vector.into()).coords,
+                    ),
+                    sim.isometry.rotation * pose.rotation,
+                )
+            })
+            .collect();
+        assert!(absolute_trajectory_error(&aligned, &gt).unwrap() < 1e-9);
+        aligned.iter().zip(&gt).for_each(|(est, gt)| {
+            assert!((est.translation.vector - gt.translation.vector).norm() < 1e-9);
+            assert!(est.rotation.angle_to(&gt.rotation) < 1e-9);
+        });
+    }
+
+    #[test]
+    fn test_shifted_trajectory() {
+        // each position is shifted by 1m, alternately up and down, which is not removed by the
+        // alignment except for the small scale correction.
+        let gt = create_trajectory(20);
+        let estimated: Vec<_> = gt
+            .iter()
+            .enumerate()
+            .map(|(idx, pose)| {
+                let shift = if idx % 2 == 0 { 1.0 } else { -1.0 };
+                na::Translation3::new(0.0, 0.0, shift) * pose
+            })
+            .collect();
+        let ate = absolute_trajectory_error(&estimated, &gt).unwrap();
+        assert!((ate - 1.0).abs() < 1e-2, "ate = {}", ate);
+        let rpe = relative_pose_error(&estimated, &gt).unwrap();
+        assert!((rpe - 2.0).abs() < 2e-2, "rpe = {}", rpe);
+    }
+
+    #[test]
+    fn test_length_mismatch() {
+        let gt = create_trajectory(20);
+        let estimated = create_trajectory(19);
+        assert!(absolute_trajectory_error(&estimated, &gt).is_err());
+        assert!(relative_pose_error(&estimated, &gt).is_err());
+        assert!(align_trajectories(&estimated, &gt).is_err());
+    }
+}
@@ -1,37 +1,99 @@
-// use std::ops::Deref;
-
-// use image::{ImageBuffer, Pixel};
-
-// use crate::{
-//     feat::{
-//         descriptors::{steered_brief::SteeredBrief, BriefDescriptor, Descriptor, Extractor},
-//         keypoints::{fast::FASTCornerDetector, KeypointDetector},
-//     },
-//     imgproc::gray,
-// };
-
-// pub mod local_mapping;
-// pub mod loop_closing;
-// pub mod map;
-// pub mod tracking;
-
-// type DescType = BriefDescriptor;
-
-// fn extract_orb<P, Container>(
-//     image: &ImageBuffer<P, Container>,
-//     pyramid_level: u32,
-//     pyramid_scale: f32,
-// ) -> Vec<Descriptor<DescType>>
-// where
-//     P: Pixel + 'static,
-//     P::Subpixel: 'static,
-//     Container: Deref<Target = [P::Subpixel]>,
-// {
-//     let gray = image::GrayImage::from_raw(image.width(), image.height(), gray(image)).unwrap();
-
-//     let fast = FASTCornerDetector::new(3, (50 * 50) as f32, pyramid_level, pyramid_scale, true);
-//     let kpts = fast.detect(&gray, 0);
-
-//     let sb = SteeredBrief::new(31, 5, 256, 12);
-//     sb.compute(&gray, &kpts)
-// }
+use std::ops::Deref;
+
+use image::{ImageBuffer, Pixel};
+use nalgebra::Point2;
+
+use crate::{
+    feat::{
+        descriptors::{steered_brief::SteeredBrief, BriefDescriptor, Descriptor, Extractor},
+        keypoints::{fast::FASTCornerDetector, KeyPoint, KeypointDetector},
+    },
+    imgproc::gray,
+};
+
+pub mod local_mapping;
+pub mod loop_closing;
+pub mod map;
+pub mod metrics;
+pub mod tracking;
+
+type DescType = BriefDescriptor;
+
+fn extract_orb<P, Container>(
+    image: &ImageBuffer<P, Container>,
+    pyramid_level: u32,
+    pyramid_scale: f32,
+) -> Vec<Descriptor<DescType>>
+where
+    P: Pixel + 'static,
+    P::Subpixel: 'static,
+    Container: Deref<Target = [P::Subpixel]>,
+{
+    OrbExtractor::new(pyramid_level, pyramid_scale).extract(image)
+}
+
+/// ORB (FAST + steered BRIEF) feature extractor.
+/// Binary test pairs of BRIEF are randomly generated in the constructor, so the descriptors
+/// extracted by the different extractors can not be compared with each other.
+pub struct OrbExtractor {
+    fast: FASTCornerDetector,
+    brief: SteeredBrief,
+    pyramid_scale: f32,
+}
+
+impl OrbExtractor {
+    pub fn new(pyramid_level: u32, pyramid_scale: f32) -> Self {
+        OrbExtractor {
+            fast: FASTCornerDetector::new(3, (50 * 50) as f32, pyramid_level, pyramid_scale, true),
+            brief: SteeredBrief::new(31, 5, 256, 12),
+            pyramid_scale,
+        }
+    }
+
+    /// Return position of `kpt` in the coordinates of the original image (pyramid level 0).
+    /// Keypoints extracted by the extractor are in the coordinates of their pyramid level.
+    pub fn level0_position(&self, kpt: &KeyPoint) -> Point2<f32> {
+        let scale = self.pyramid_scale.powi(kpt.level() as i32);
+        Point2::new(kpt.x() * scale, kpt.y() * scale)
+    }
+
+    pub fn extract<P, Container>(
+        &self,
+        image: &ImageBuffer<P, Container>,
+    ) -> Vec<Descriptor<DescType>>
+    where
+        P: Pixel + 'static,
+        P::Subpixel: 'static,
+        Container: Deref<Target = [P::Subpixel]>,
+    {
+        let gray = image::GrayImage::from_raw(image.width(), image.height(), gray(image)).unwrap();
+        let kpts = self.fast.detect(&gray, 0);
+        self.brief.compute(&gray, &kpts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{tracking::tests::create_textured_image, *};
+
+    #[test]
+    fn test_level0_position() {
+        let (width, height) = (240, 180);
+        let image = create_textured_image(width, height);
+        let extractor = OrbExtractor::new(2, 2.0);
+        let descs = extractor.extract(&image);
+        let positions: Vec<_> = descs
+            .iter()
+            .filter(|desc| desc.kpt.level() == 1)
+            .map(|desc| extractor.level0_position(&desc.kpt))
+            .collect();
+        assert!(!positions.is_empty());
+        // keypoints of level 1 are in the image of half size.
+        assert!(positions
+            .iter()
+            .all(|pos| pos.x < width as f32 && pos.y < height as f32));
+        assert!(positions
+            .iter()
+            .any(|pos| pos.x >= width as f32 / 2.0 || pos.y >= height as f32 / 2.0));
+    }
+}
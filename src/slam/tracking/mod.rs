@@ -1,25 +1,43 @@
 use std::ops::Deref;
 
-use image::{ImageBuffer, Pixel};
-use nalgebra::{matrix, Matrix3, Matrix3x4, Vector3};
+use image::{GrayImage, ImageBuffer, Pixel};
+use nalgebra::{matrix, Matrix3, Matrix3x4, Point2, Point3, Vector3};
 
-use crate::feat::matcher::Match;
+use crate::{
+    feat::{descriptors::Descriptor, matcher::brute_force::BruteForceMathcer},
+    imgproc::gray,
+    sfm::pnp::pnp_ransac,
+};
 
-use super::{extract_orb, DescType};
+use super::{map::keyframe::KeyFrame, DescType, OrbExtractor};
+
+const MIN_TRACKED_POINTS: usize = 30; // relocalization is performed below this number
+const MIN_RELOCALIZATION_INLIERS: usize = 30;
+const MAX_MATCH_DISTANCE: f32 = 64.0; // hamming distance of 256 bits descriptor
+const RANSAC_ITERATIONS: usize = 300;
+const RANSAC_THRESHOLD_PX: f64 = 3.0;
+const SEARCH_RADIUS_PX: f32 = 15.0; // search window of the guided search
 
 pub struct Tracker {
-    previous_pts: Vec<Vector3<f32>>,
     previous_pose: Matrix3x4<f32>,
     rotate_velocity: Matrix3<f32>,
     trans_velocity: Vector3<f32>,
+    lost: bool, // true if neither tracking nor relocalization succeeded in the last frame
     since_global_reloc: u32, // Number of frames passed from the last global relocalization
     since_last_kf_insertion: u32, // Number of frames passed from the last keyframe insertion
+    extractor: OrbExtractor,
+    keyframes: Vec<KeyFrame<Descriptor<DescType>>>, // descriptor database for relocalization
+}
+
+impl Default for Tracker {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Tracker {
     pub fn new() -> Self {
         Tracker {
-            previous_pts: Vec::new(),
             previous_pose: matrix![
                 1.0, 0.0, 0.0, 0.0;
                 0.0, 1.0, 0.0, 0.0;
@@ -27,30 +45,178 @@ impl Tracker {
             ],
             rotate_velocity: nalgebra::one(),
             trans_velocity: nalgebra::zero(),
+            lost: false,
             since_global_reloc: 0,
             since_last_kf_insertion: 0,
+            extractor: OrbExtractor::new(8, 1.2),
+            keyframes: Vec::new(),
         }
     }
 
-    pub fn process_frame<P, Container>(&mut self, frame: &ImageBuffer<P, Container>)
+    /// Extract ORB descriptors by the extractor of the tracker.
+    /// Descriptors of the keyframes must be extracted by this function.
+    pub fn extract<P, Container>(
+        &self,
+        frame: &ImageBuffer<P, Container>,
+    ) -> Vec<Descriptor<DescType>>
     where
         P: Pixel + 'static,
         P::Subpixel: 'static,
         Container: Deref<Target = [P::Subpixel]>,
     {
-        let descs = extract_orb(frame, 8, 1.2);
-        let matches = self.guided_search(frame);
-        self.track_local_map();
-        if self.judge_use_as_keyframe() {}
+        self.extractor.extract(frame)
     }
 
-    fn guided_search<P, Container>(&self, frame: &ImageBuffer<P, Container>) -> Vec<Match<DescType>>
+    /// Add keyframe to the descriptor database used for relocalization.
+    pub fn add_keyframe(&mut self, keyframe: KeyFrame<Descriptor<DescType>>) {
+        self.keyframes.push(keyframe);
+        self.since_last_kf_insertion = 0;
+    }
+
+    pub fn process_frame<P, Container>(&mut self, frame: &ImageBuffer<P, Container>)
     where
         P: Pixel + 'static,
         P::Subpixel: 'static,
         Container: Deref<Target = [P::Subpixel]>,
     {
-        Vec::new()
+        self.since_global_reloc += 1;
+        self.since_last_kf_insertion += 1;
+        // the motion model is not reliable once tracking is lost.
+        if self.lost || !self.track_frame(&self.extract(frame)) {
+            let gray = GrayImage::from_raw(frame.width(), frame.height(), gray(frame)).unwrap();
+            if !self.relocalize(&gray) {
+                self.lost = true;
+                return;
+            }
+        }
+        self.track_local_map();
+        if self.judge_use_as_keyframe() {}
+    }
+
+    /// Global relocalization.
+    /// ORB features of `frame` are matched against the descriptors of each keyframe and the
+    /// camera pose is estimated from the matched map points by PnP RANSAC.
+    /// Return true if the pose is estimated with enough inliers.
+    pub fn relocalize(&mut self, frame: &GrayImage) -> bool {
+        let descs = self.extract(frame);
+        if descs.len() < MIN_RELOCALIZATION_INLIERS {
+            return false;
+        }
+        // search from the latest keyframe
+        for keyframe in self.keyframes.iter().rev() {
+            let matcher =
+                BruteForceMathcer::new(keyframe.descriptors().to_vec(), descs.clone(), false);
+            let indices: Vec<(usize, usize)> = matcher
+                .run_indices()
+                .into_iter()
+                .filter(|(li, ri)| {
+                    keyframe.descriptors()[*li].distance(&descs[*ri]) < MAX_MATCH_DISTANCE
+                })
+                .collect();
+            if indices.len() < MIN_RELOCALIZATION_INLIERS {
+                continue;
+            }
+            let object_pts: Vec<_> = indices
+                .iter()
+                .map(|(li, _)| keyframe.map_points()[*li])
+                .collect();
+            let image_pts: Vec<_> = indices
+                .iter()
+                .map(|(_, ri)| {
+                    self.extractor
+                        .level0_position(&descs[*ri].kpt)
+                        .cast::<f64>()
+                })
+                .collect();
+            let pose = match estimate_pose(&object_pts, &image_pts, keyframe.camera_intrinsic()) {
+                Some(pose) => pose,
+                None => continue,
+            };
+
+            self.previous_pose = pose;
+            self.rotate_velocity = nalgebra::one();
+            self.trans_velocity = nalgebra::zero();
+            self.lost = false;
+            self.since_global_reloc = 0;
+            return true;
+        }
+        false
+    }
+
+    /// Predict pose of the current frame by the constant velocity motion model.
+    /// Velocity is applied to the previous pose as `R = R_v * R_prev`, `t = R_v * t_prev + t_v`.
+    pub fn predict_pose(&self) -> Matrix3x4<f32> {
+        let rot = self.rotate_velocity * self.previous_pose.fixed_slice::<3, 3>(0, 0);
+        let trans = self.rotate_velocity * self.previous_pose.column(3) + self.trans_velocity;
+        let mut pose = Matrix3x4::zeros();
+        pose.fixed_slice_mut::<3, 3>(0, 0).copy_from(&rot);
+        pose.set_column(3, &trans);
+        pose
+    }
+
+    /// Update the velocity by the pose of the current frame and set the pose as the previous pose.
+    fn update_pose(&mut self, pose: Matrix3x4<f32>) {
+        let rot = pose.fixed_slice::<3, 3>(0, 0);
+        self.rotate_velocity = rot * self.previous_pose.fixed_slice::<3, 3>(0, 0).transpose();
+        self.trans_velocity = pose.column(3) - self.rotate_velocity * self.previous_pose.column(3);
+        self.previous_pose = pose;
+    }
+
+    /// Track the current frame from the previous frame.
+    /// Return false if the pose of the current frame is not estimated.
+    fn track_frame(&mut self, descs: &[Descriptor<DescType>]) -> bool {
+        let intrinsics = match self.keyframes.last() {
+            Some(keyframe) => *keyframe.camera_intrinsic(),
+            None => return false,
+        };
+        let (object_pts, image_pts) = self.guided_search(descs);
+        if object_pts.len() < MIN_TRACKED_POINTS {
+            return false;
+        }
+        match estimate_pose(&object_pts, &image_pts, &intrinsics) {
+            Some(pose) => {
+                self.update_pose(pose);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Search correspondences between the map points of the latest keyframe and `descs`.
+    /// Map points are projected by the predicted pose and matched with the descriptors in the
+    /// window around the projected point.
+    /// Return matched (map points, image points).
+    fn guided_search(
+        &self,
+        descs: &[Descriptor<DescType>],
+    ) -> (Vec<Point3<f64>>, Vec<Point2<f64>>) {
+        let keyframe = match self.keyframes.last() {
+            Some(keyframe) => keyframe,
+            None => return (Vec::new(), Vec::new()),
+        };
+        let projection = keyframe.camera_intrinsic() * self.predict_pose();
+        keyframe
+            .map_points()
+            .iter()
+            .zip(keyframe.descriptors())
+            .filter_map(|(pt, kf_desc)| {
+                let proj = projection * pt.cast::<f32>().to_homogeneous();
+                if proj[2] <= 0.0 {
+                    return None;
+                }
+                let (u, v) = (proj[0] / proj[2], proj[1] / proj[2]);
+                descs
+                    .iter()
+                    .map(|desc| (desc, self.extractor.level0_position(&desc.kpt)))
+                    .filter(|(_, pos)| {
+                        (pos.x - u).abs() < SEARCH_RADIUS_PX && (pos.y - v).abs() < SEARCH_RADIUS_PX
+                    })
+                    .map(|(desc, pos)| (pos, kf_desc.distance(desc)))
+                    .filter(|(_, dist)| *dist < MAX_MATCH_DISTANCE)
+                    .min_by(|lhs, rhs| lhs.1.partial_cmp(&rhs.1).unwrap())
+                    .map(|(pos, _)| (*pt, pos.cast::<f64>()))
+            })
+            .unzip()
     }
 
     fn track_local_map(&self) {}
@@ -59,3 +225,196 @@ impl Tracker {
         true
     }
 }
+
+/// Estimate camera pose [R | t] by PnP RANSAC.
+/// Return None if the number of the inliers is not enough.
+fn estimate_pose(
+    object_pts: &[Point3<f64>],
+    image_pts: &[Point2<f64>],
+    intrinsics: &Matrix3<f32>,
+) -> Option<Matrix3x4<f32>> {
+    let (rot, trans, inliers) = pnp_ransac(
+        object_pts,
+        image_pts,
+        &intrinsics.cast::<f64>(),
+        RANSAC_ITERATIONS,
+        RANSAC_THRESHOLD_PX,
+    )
+    .ok()?;
+    if inliers.len() < MIN_RELOCALIZATION_INLIERS {
+        return None;
+    }
+    Some(Matrix3x4::from_fn(|row, col| {
+        if col < 3 {
+            rot[(row, col)] as f32
+        } else {
+            trans[row] as f32
+        }
+    }))
+}
+
+#[cfg(test)]
+pub mod tests {
+    use nalgebra::{Matrix4, Rotation3};
+    use rand::Rng;
+
+    use super::*;
+
+    pub const FOCAL_LENGTH: f32 = 300.0;
+
+    /// Create image which has random rectangles (texture for feature extraction).
+    pub fn create_textured_image(width: u32, height: u32) -> GrayImage {
+        let mut rng = rand::thread_rng();
+        let rects: Vec<(u32, u32, u32, u32, u8)> = (0..150)
+            .map(|_| {
+                (
+                    rng.gen_range(0..width),
+                    rng.gen_range(0..height),
+                    rng.gen_range(4..24),
+                    rng.gen_range(4..24),
+                    rng.gen::<u8>(),
+                )
+            })
+            .collect();
+        GrayImage::from_fn(width, height, |x, y| {
+            let val = rects
+                .iter()
+                .rev()
+                .find(|(rx, ry, w, h, _)| x >= *rx && x < rx + w && y >= *ry && y < ry + h)
+                .map_or(128, |rect| rect.4);
+            image::Luma([val])
+        })
+    }
+
+    pub fn intrinsics(width: u32, height: u32) -> Matrix3<f32> {
+        matrix![
+            FOCAL_LENGTH, 0.0, width as f32 / 2.0;
+            0.0, FOCAL_LENGTH, height as f32 / 2.0;
+            0.0, 0.0, 1.0;
+        ]
+    }
+
+    /// Create keyframe at the origin of the world coordinates from `frame`.
+    /// Depth of each keypoint is determined from its position.
+    pub fn create_keyframe(tracker: &Tracker, frame: &GrayImage) -> KeyFrame<Descriptor<DescType>> {
+        let k = intrinsics(frame.width(), frame.height());
+        let descs = tracker.extract(frame);
+        let points = descs
+            .iter()
+            .map(|desc| {
+                let pos = tracker.extractor.level0_position(&desc.kpt);
+                let (u, v) = (pos.x as f64, pos.y as f64);
+                let depth = 4.0 + ((u + 2.0 * v) as usize % 7) as f64 * 0.3;
+                Point3::new(
+                    (u - k[(0, 2)] as f64) / FOCAL_LENGTH as f64 * depth,
+                    (v - k[(1, 2)] as f64) / FOCAL_LENGTH as f64 * depth,
+                    depth,
+                )
+            })
+            .collect();
+        KeyFrame::new(Matrix4::identity(), k, descs, points)
+    }
+
+    #[test]
+    fn test_relocalize() {
+        let (width, height) = (240, 180);
+        let frame = create_textured_image(width, height);
+        let black = GrayImage::new(width, height);
+
+        let mut tracker = Tracker::new();
+        let keyframe = create_keyframe(&tracker, &frame);
+        assert!(keyframe.descriptors().len() >= MIN_RELOCALIZATION_INLIERS);
+        tracker.add_keyframe(keyframe);
+
+        // tracking is broken by the black frame.
+        tracker.process_frame(&black);
+        assert!(!tracker.relocalize(&black));
+        assert!(tracker.since_global_reloc > 0);
+
+        // relocalization on the next real frame
+        tracker.process_frame(&frame);
+        assert_eq!(tracker.since_global_reloc, 0);
+        assert!(tracker.relocalize(&frame));
+        let identity: Matrix3x4<f32> = Matrix3x4::identity();
+        assert!(
+            (tracker.previous_pose - identity).norm() < 1e-2,
+            "pose = {}",
+            tracker.previous_pose
+        );
+    }
+
+    #[test]
+    fn test_relocalize_with_velocity() {
+        let (width, height) = (240, 180);
+        let frame = create_textured_image(width, height);
+        let black = GrayImage::new(width, height);
+
+        let mut tracker = Tracker::new();
+        let keyframe = create_keyframe(&tracker, &frame);
+        tracker.add_keyframe(keyframe);
+        // camera moved by 0.2 along x axis in the last frame.
+        let mut pose: Matrix3x4<f32> = Matrix3x4::identity();
+        pose[(0, 3)] = 0.2;
+        tracker.update_pose(pose);
+        assert!((tracker.predict_pose()[(0, 3)] - 0.4).abs() < 1e-6);
+
+        // tracking is lost and the next frame is relocalized instead of tracked by the motion
+        // model, which is reset by the relocalization.
+        tracker.process_frame(&black);
+        assert!(tracker.lost);
+        tracker.process_frame(&frame);
+        assert!(!tracker.lost);
+        assert_eq!(tracker.since_global_reloc, 0);
+        let identity: Matrix3x4<f32> = Matrix3x4::identity();
+        assert!((tracker.previous_pose - identity).norm() < 1e-2);
+        assert!((tracker.predict_pose() - identity).norm() < 1e-2);
+
+        // tracked by the guided search after the relocalization.
+        tracker.process_frame(&frame);
+        assert_eq!(tracker.since_global_reloc, 1);
+        assert!((tracker.previous_pose - identity).norm() < 1e-2);
+    }
+
+    #[test]
+    fn test_predict_pose() {
+        // camera moves by the same rigid motion in each frame.
+        let motion_rot = Rotation3::from_euler_angles(0.02, -0.05, 0.01).into_inner();
+        let motion_trans = Vector3::new(0.1, -0.02, 0.05);
+        let apply_motion = |pose: &Matrix3x4<f32>| {
+            let mut res = motion_rot * pose;
+            res.set_column(3, &(res.column(3) + motion_trans));
+            res
+        };
+        let pose1 = apply_motion(&Matrix3x4::identity());
+        let pose2 = apply_motion(&pose1);
+
+        let mut tracker = Tracker::new();
+        assert_eq!(tracker.predict_pose(), Matrix3x4::identity());
+        tracker.update_pose(pose1);
+        let predicted = tracker.predict_pose();
+        assert!(
+            (predicted - pose2).norm() < pose2.norm() * 0.05,
+            "predicted = {}, gt = {}",
+            predicted,
+            pose2
+        );
+    }
+
+    #[test]
+    fn test_track_frame() {
+        let (width, height) = (240, 180);
+        let frame = create_textured_image(width, height);
+
+        let mut tracker = Tracker::new();
+        let keyframe = create_keyframe(&tracker, &frame);
+        tracker.add_keyframe(keyframe);
+
+        // tracked by the guided search without relocalization.
+        tracker.process_frame(&frame);
+        tracker.process_frame(&frame);
+        assert_eq!(tracker.since_global_reloc, 2);
+        let identity: Matrix3x4<f32> = Matrix3x4::identity();
+        assert!((tracker.previous_pose - identity).norm() < 1e-2);
+        assert!((tracker.predict_pose() - identity).norm() < 1e-2);
+    }
+}
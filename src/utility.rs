@@ -1,14 +1,172 @@
+//! Utility macros and functions.
+use std::{convert::TryFrom, fs::File, ops::Deref, path::Path};
+
+use anyhow::{ensure, Context, Result};
+use image::{ImageBuffer, Luma};
+use memmap2::Mmap;
+
+/// Evaluate `$target` and print the elapsed time as "`$label`: xx.xxms".
+/// Return the value of `$target`.
 #[macro_export]
 macro_rules! timer {
-    ($str:expr, $target:expr) => {{
-        let start = Instant::now();
+    ($label:expr, $target:expr) => {{
+        let start = std::time::Instant::now();
         let result = $target;
         println!(
-            "{} : Elapsed time = {}.{:03}",
-            $str,
-            start.elapsed().as_secs(),
-            start.elapsed().subsec_nanos() / 1_000_000
+            "{}: {:.2}ms",
+            $label,
+            start.elapsed().as_secs_f64() * 1000.0
         );
         result
     }};
 }
+
+/// Pixel data of the memory-mapped file. Deref to the `len` pixels after the header.
+pub struct MmapBuffer {
+    mmap: Mmap,
+    offset: usize,
+    len: usize,
+}
+
+impl Deref for MmapBuffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.mmap[self.offset..self.offset + self.len]
+    }
+}
+
+/// Gray scale image whose pixels are not copied to heap but read from the mapped file.
+pub type MmapImage = ImageBuffer<Luma<u8>, MmapBuffer>;
+
+/// Read the next token (separated by whitespace) of the pgm header from `pos`.
+fn read_header_token(data: &[u8], pos: &mut usize) -> Result<usize> {
+    while *pos < data.len() && (data[*pos].is_ascii_whitespace() || data[*pos] == b'#') {
+        if data[*pos] == b'#' {
+            while *pos < data.len() && data[*pos] != b'\n' {
+                *pos += 1;
+            }
+        } else {
+            *pos += 1;
+        }
+    }
+    let start = *pos;
+    while *pos < data.len() && data[*pos].is_ascii_digit() {
+        *pos += 1;
+    }
+    ensure!(start < *pos, "Invalid pgm header at {}", start);
+    Ok(std::str::from_utf8(&data[start..*pos])?.parse()?)
+}
+
+/// Memory map the raw pixel file of 8bit single channel image of size (`width`, `height`).
+/// The file has no header and the pixels are stored in row-major order.
+/// The returned image can be passed to the `imgproc` functions without copying whole pixels.
+pub fn open_mmap(path: &Path, width: u32, height: u32) -> Result<MmapImage> {
+    let mmap = map_file(path)?;
+    create_mmap_image(mmap, 0, width as usize, height as usize)
+}
+
+/// Memory map the binary pgm (P5) file of 8bit single channel image.
+/// Same as `open_mmap`, but the image size is read from the header.
+pub fn open_pgm_mmap(path: &Path) -> Result<MmapImage> {
+    let mmap = map_file(path)?;
+    ensure!(
+        mmap.starts_with(b"P5"),
+        "{:?} is not a binary pgm file",
+        path
+    );
+    let mut pos = 2;
+    let width = read_header_token(&mmap, &mut pos)?;
+    let height = read_header_token(&mmap, &mut pos)?;
+    let max_value = read_header_token(&mmap, &mut pos)?;
+    ensure!(
+        max_value < 256,
+        "Only 8bit pgm is supported : max value = {}",
+        max_value
+    );
+    // single whitespace after the header
+    create_mmap_image(mmap, pos + 1, width, height)
+}
+
+fn map_file(path: &Path) -> Result<Mmap> {
+    let file = File::open(path).with_context(|| format!("Failed to open {:?}", path))?;
+    // Safety : the file must not be modified while the image is alive.
+    Ok(unsafe { Mmap::map(&file)? })
+}
+
+fn create_mmap_image(mmap: Mmap, offset: usize, width: usize, height: usize) -> Result<MmapImage> {
+    let len = width
+        .checked_mul(height)
+        .with_context(|| format!("Image size overflows : {} x {}", width, height))?;
+    let end = offset
+        .checked_add(len)
+        .with_context(|| format!("Image size overflows : {} x {}", width, height))?;
+    ensure!(
+        end <= mmap.len(),
+        "File size is too small : expected {}, got {}",
+        end,
+        mmap.len()
+    );
+    let buffer = MmapBuffer { mmap, offset, len };
+    ImageBuffer::from_raw(u32::try_from(width)?, u32::try_from(height)?, buffer)
+        .context("Failed to create image")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timer() {
+        let x = timer!("test", { 42usize });
+        assert_eq!(x, 42);
+        let y = timer!("test", (0..10).sum::<i32>());
+        assert_eq!(y, 45);
+    }
+
+    #[test]
+    fn test_open_mmap_too_small() {
+        let path = std::env::temp_dir().join("improc_test_open_mmap_too_small.raw");
+        std::fs::write(&path, [0u8; 100]).unwrap();
+        assert!(open_mmap(&path, 10, 10).is_ok());
+        assert!(open_mmap(&path, 10, 11).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_open_pgm_mmap() {
+        let (width, height) = (30usize, 20usize);
+        let pixels: Vec<u8> = (0..width * height).map(|i| (i % 251) as u8).collect();
+        let path = std::env::temp_dir().join("improc_test_open_mmap.pgm");
+        let mut data = format!("P5\n# comment\n{} {}\n255\n", width, height).into_bytes();
+        data.extend_from_slice(&pixels);
+        data.extend_from_slice(&[0; 16]);
+        std::fs::write(&path, &data).unwrap();
+
+        let image = open_pgm_mmap(&path).unwrap();
+        assert_eq!(image.dimensions(), (width as u32, height as u32));
+        assert_eq!(image.get_pixel(7, 3)[0], pixels[3 * width + 7]);
+        assert_eq!(image.as_raw().len(), width * height);
+        assert_eq!(
+            crate::imgproc::gaussian(&image, 3, 1.0),
+            crate::imgproc::gaussian(
+                &image::GrayImage::from_raw(width as u32, height as u32, pixels).unwrap(),
+                3,
+                1.0
+            )
+        );
+        drop(image);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_open_pgm_mmap_overflow() {
+        let path = std::env::temp_dir().join("improc_test_open_mmap_overflow.pgm");
+        let size = 1usize << (usize::BITS / 2);
+        let mut data = format!("P5\n{} {}\n255\n", size, size).into_bytes();
+        data.extend_from_slice(&[0; 16]);
+        std::fs::write(&path, &data).unwrap();
+        assert!(open_pgm_mmap(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
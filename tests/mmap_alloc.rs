@@ -0,0 +1,64 @@
+//! `open_mmap` must not copy the pixels to heap. This is checked by counting the allocated bytes
+//! with the global allocator, which is replaced only in this test binary.
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+};
+
+use improc::utility::open_mmap;
+
+/// Count the bytes allocated by the current thread (other tests run in parallel).
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATED: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATED.try_with(|allocated| allocated.set(allocated.get() + layout.size()));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+fn allocated_bytes() -> usize {
+    ALLOCATED.with(|allocated| allocated.get())
+}
+
+#[test]
+fn test_open_mmap() {
+    let (width, height) = (1000usize, 800usize);
+    let pixels: Vec<u8> = (0..width * height).map(|i| (i % 251) as u8).collect();
+    let path = std::env::temp_dir().join("improc_test_open_mmap.raw");
+    let mut data = pixels.clone();
+    // trailing bytes are not the pixels.
+    data.extend_from_slice(&[0; 16]);
+    std::fs::write(&path, &data).unwrap();
+
+    // copying the pixels is detected.
+    let before = allocated_bytes();
+    std::hint::black_box(data.clone());
+    assert!(allocated_bytes() - before >= width * height);
+
+    let before = allocated_bytes();
+    let image = open_mmap(&path, width as u32, height as u32).unwrap();
+    let pixel = image.get_pixel(7, 3)[0];
+    // pixels are not copied to heap but read from the mapped region.
+    let allocated = allocated_bytes() - before;
+    assert!(allocated < 1024, "allocated = {}", allocated);
+    assert_eq!(pixel, pixels[3 * width + 7]);
+    assert_eq!(image.dimensions(), (width as u32, height as u32));
+    assert_eq!(image.as_raw().len(), width * height);
+    assert_eq!(&image.as_raw()[..], &pixels[..]);
+
+    assert!(open_mmap(&path, width as u32, height as u32 + 1).is_err());
+    drop(image);
+    std::fs::remove_file(&path).unwrap();
+}